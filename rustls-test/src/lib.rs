@@ -19,8 +19,8 @@ use std::sync::{Arc, OnceLock};
 
 use rustls::client::danger::{HandshakeSignatureValid, ServerCertVerified, ServerCertVerifier};
 use rustls::client::{
-    AlwaysResolvesClientRawPublicKeys, ServerCertVerifierBuilder, UnbufferedClientConnection,
-    WebPkiServerVerifier,
+    AlwaysResolvesClientRawPublicKeys, ResolvesClientCert, ServerCertVerifierBuilder,
+    UnbufferedClientConnection, WebPkiServerVerifier,
 };
 use rustls::crypto::cipher::{InboundOpaqueMessage, MessageDecrypter, MessageEncrypter};
 use rustls::crypto::{
@@ -1110,6 +1110,7 @@ pub struct MockServerVerifier {
     expected_ocsp_response: Option<Vec<u8>>,
     requires_raw_public_keys: bool,
     raw_public_key_algorithms: Option<WebPkiSupportedAlgorithms>,
+    asynchronous: bool,
 }
 
 impl ServerCertVerifier for MockServerVerifier {
@@ -1178,6 +1179,10 @@ impl ServerCertVerifier for MockServerVerifier {
     fn requires_raw_public_keys(&self) -> bool {
         self.requires_raw_public_keys
     }
+
+    fn is_async(&self) -> bool {
+        self.asynchronous
+    }
 }
 
 impl MockServerVerifier {
@@ -1188,6 +1193,13 @@ impl MockServerVerifier {
         }
     }
 
+    pub fn accepts_anything_asynchronously() -> Self {
+        Self {
+            asynchronous: true,
+            ..Default::default()
+        }
+    }
+
     pub fn expects_ocsp_response(response: &[u8]) -> Self {
         Self {
             expected_ocsp_response: Some(response.to_vec()),
@@ -1249,6 +1261,7 @@ impl Default for MockServerVerifier {
             expected_ocsp_response: None,
             requires_raw_public_keys: false,
             raw_public_key_algorithms: None,
+            asynchronous: false,
         }
     }
 }
@@ -1259,6 +1272,7 @@ pub struct MockClientVerifier {
     pub subjects: Arc<[DistinguishedName]>,
     pub mandatory: bool,
     pub offered_schemes: Option<Vec<SignatureScheme>>,
+    pub asynchronous: bool,
     expect_raw_public_keys: bool,
     raw_public_key_algorithms: Option<WebPkiSupportedAlgorithms>,
     parent: Arc<dyn ClientCertVerifier>,
@@ -1278,6 +1292,7 @@ impl MockClientVerifier {
             subjects: Arc::from(get_client_root_store(kt).subjects()),
             mandatory: true,
             offered_schemes: None,
+            asynchronous: false,
             expect_raw_public_keys: false,
             raw_public_key_algorithms: Some(provider.signature_verification_algorithms),
         }
@@ -1348,6 +1363,32 @@ impl ClientCertVerifier for MockClientVerifier {
     fn requires_raw_public_keys(&self) -> bool {
         self.expect_raw_public_keys
     }
+
+    fn is_async(&self) -> bool {
+        self.asynchronous
+    }
+}
+
+/// A client certificate resolver whose certificates are chosen by the application.
+#[derive(Debug)]
+pub struct AsyncClientCertResolver;
+
+impl ResolvesClientCert for AsyncClientCertResolver {
+    fn resolve(
+        &self,
+        _root_hint_subjects: &[&[u8]],
+        _sigschemes: &[SignatureScheme],
+    ) -> Option<Arc<CertifiedKey>> {
+        unreachable!("asynchronous resolvers are not called")
+    }
+
+    fn has_certs(&self) -> bool {
+        true
+    }
+
+    fn is_async(&self) -> bool {
+        true
+    }
 }
 
 /// This allows injection/receipt of raw messages into a post-handshake connection.
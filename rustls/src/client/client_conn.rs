@@ -1,3 +1,4 @@
+use alloc::vec;
 use alloc::vec::Vec;
 use core::marker::PhantomData;
 use core::ops::{Deref, DerefMut};
@@ -13,13 +14,17 @@ use crate::builder::ConfigBuilder;
 use crate::client::{EchMode, EchStatus};
 use crate::common_state::{CommonState, Protocol, Side};
 use crate::conn::{ConnectionCore, UnbufferedConnectionCommon};
+#[cfg(doc)]
+use crate::crypto;
 use crate::crypto::{CryptoProvider, SupportedKxGroup};
-use crate::enums::{CipherSuite, ProtocolVersion, SignatureScheme};
+use crate::enums::{CipherSuite, MaxFragmentLength, ProtocolVersion, SignatureScheme};
 use crate::error::Error;
 use crate::kernel::KernelConnection;
 use crate::log::trace;
+use crate::msgs::base::Payload;
+use crate::msgs::codec::Codec;
 use crate::msgs::enums::NamedGroup;
-use crate::msgs::handshake::ClientExtensionsInput;
+use crate::msgs::handshake::{ClientExtensionsInput, ClientHelloPayload, DistinguishedName};
 use crate::msgs::persist;
 use crate::suites::{ExtractedSecrets, SupportedCipherSuite};
 use crate::sync::Arc;
@@ -27,9 +32,7 @@ use crate::sync::Arc;
 use crate::time_provider::DefaultTimeProvider;
 use crate::time_provider::TimeProvider;
 use crate::unbuffered::{EncryptError, TransmitTlsData};
-#[cfg(doc)]
-use crate::{DistinguishedName, crypto};
-use crate::{KeyLog, WantsVersions, compress, sign, verify, versions};
+use crate::{ExternalPsk, KeyLog, PskKexMode, WantsVersions, compress, sign, verify, versions};
 
 /// A trait for the ability to store client session data, so that sessions
 /// can be resumed in future connections.
@@ -53,6 +56,19 @@ pub trait ClientSessionStore: fmt::Debug + Send + Sync {
     /// to the server.
     fn kx_hint(&self, server_name: &ServerName<'_>) -> Option<NamedGroup>;
 
+    /// Return the groups to send TLS1.3 key shares for, when connecting to `server_name`.
+    ///
+    /// This allows the initial key shares to be chosen per server: for example, to avoid
+    /// a `HelloRetryRequest` from a server known not to support a post-quantum group.
+    /// Groups not enabled for TLS1.3 in the [`ClientConfig`] are ignored.
+    ///
+    /// If this returns `None` (the default) or no usable groups, key shares are instead
+    /// sent for the [`ClientSessionStore::kx_hint()`] group, followed by enough of the
+    /// configured groups to make [`ClientConfig::initial_key_shares`] in total.
+    fn key_share_groups(&self, _server_name: &ServerName<'_>) -> Option<Vec<NamedGroup>> {
+        None
+    }
+
     /// Remember a TLS1.2 session.
     ///
     /// At most one of these can be remembered at a time, per `server_name`.
@@ -130,6 +146,62 @@ pub trait ResolvesClientCert: fmt::Debug + Send + Sync {
 
     /// Return true if any certificates at all are available.
     fn has_certs(&self) -> bool;
+
+    /// Whether client certificates are chosen by the application, asynchronously.
+    ///
+    /// This allows a certificate to be loaded without blocking: for example after
+    /// prompting the user, or from a remote wallet.  If this returns `true`, handshakes
+    /// do not call [`Self::resolve()`]: instead they pause with a [`ClientCertRequest`]
+    /// for the application to answer, and continue with the certificate the application
+    /// chooses.  Buffered connections report this via [`IoState::awaiting_client_cert()`],
+    /// and unbuffered connections by yielding [`ConnectionState::ClientCertRequired`].
+    ///
+    /// Post-handshake client authentication always calls [`Self::resolve()`].
+    ///
+    /// The default implementation returns `false`.
+    ///
+    /// [`IoState::awaiting_client_cert()`]: crate::IoState::awaiting_client_cert
+    /// [`ConnectionState::ClientCertRequired`]: crate::unbuffered::ConnectionState::ClientCertRequired
+    fn is_async(&self) -> bool {
+        false
+    }
+}
+
+/// A server's request for a client certificate, for the application to answer.
+///
+/// The application should choose a certificate as [`ResolvesClientCert::resolve()`] would,
+/// then provide it to the connection.
+///
+/// See [`ResolvesClientCert::is_async()`].
+#[derive(Debug)]
+pub struct ClientCertRequest {
+    root_hint_subjects: Vec<DistinguishedName>,
+    sigschemes: Vec<SignatureScheme>,
+}
+
+impl ClientCertRequest {
+    pub(crate) fn new(
+        root_hint_subjects: Vec<DistinguishedName>,
+        sigschemes: Vec<SignatureScheme>,
+    ) -> Self {
+        Self {
+            root_hint_subjects,
+            sigschemes,
+        }
+    }
+
+    /// The subject distinguished names of the certificate authorities the server accepts.
+    ///
+    /// If this is empty, the client should send whatever certificate it has.  See
+    /// [`ResolvesClientCert::resolve()`] for more information.
+    pub fn root_hint_subjects(&self) -> &[DistinguishedName] {
+        &self.root_hint_subjects
+    }
+
+    /// The [`SignatureScheme`]s the server supports.
+    pub fn sigschemes(&self) -> &[SignatureScheme] {
+        &self.sigschemes
+    }
 }
 
 /// Common configuration for (typically) all connections made by a program.
@@ -151,6 +223,8 @@ pub trait ResolvesClientCert: fmt::Debug + Send + Sync {
 /// # Defaults
 ///
 /// * [`ClientConfig::max_fragment_size`]: the default is `None` (meaning 16kB).
+/// * [`ClientConfig::record_size_limit`]: the default is `None`: the extension is not sent.
+/// * [`ClientConfig::max_fragment_length`]: the default is `None`: the extension is not sent.
 /// * [`ClientConfig::resumption`]: supports resumption with up to 256 server names, using session
 ///   ids or tickets, with a max of eight tickets per server.
 /// * [`ClientConfig::alpn_protocols`]: the default is empty -- no ALPN protocol is negotiated.
@@ -158,6 +232,8 @@ pub trait ResolvesClientCert: fmt::Debug + Send + Sync {
 /// * [`ClientConfig::cert_decompressors`]: depends on the crate features, see [`compress::default_cert_decompressors()`].
 /// * [`ClientConfig::cert_compressors`]: depends on the crate features, see [`compress::default_cert_compressors()`].
 /// * [`ClientConfig::cert_compression_cache`]: caches the most recently used 4 compressions
+/// * [`ClientConfig::external_psks`]: the default is empty -- no external PSKs are offered.
+/// * [`ClientConfig::psk_kex_modes`]: the default is [`PskKexMode::PskWithDhe`] only.
 ///
 /// [`RootCertStore`]: crate::RootCertStore
 #[derive(Clone, Debug)]
@@ -198,6 +274,44 @@ pub struct ClientConfig {
     /// [ClientConnection::new]: crate::client::ClientConnection::new
     pub max_fragment_size: Option<usize>,
 
+    /// The largest record this client is willing to receive, advertised to
+    /// the server with the `record_size_limit` extension of [RFC 8449].
+    ///
+    /// This is a limit on the size of a record's plaintext.  In TLS1.3 it includes
+    /// the content type, so the largest payload is one byte smaller.
+    ///
+    /// If the server supports the extension, it should send no larger records,
+    /// and larger records are rejected.  The server's limit in return is honoured
+    /// for records sent to it; see [`CommonState::peer_record_size_limit()`].
+    ///
+    /// The value must be at least 64.  Out of range values are reported as
+    /// errors from [ClientConnection::new].  The default is `None`: the extension
+    /// is not sent.  It is never sent for QUIC connections.
+    ///
+    /// [RFC 8449]: https://datatracker.ietf.org/doc/html/rfc8449
+    /// [ClientConnection::new]: crate::client::ClientConnection::new
+    /// [`CommonState::peer_record_size_limit()`]: crate::CommonState::peer_record_size_limit
+    pub record_size_limit: Option<u16>,
+
+    /// Request that the server sends no record larger than this, using the
+    /// `max_fragment_length` extension of [RFC 6066].
+    ///
+    /// This is intended for constrained peers that do not support
+    /// [`ClientConfig::record_size_limit`].  If the server agrees, the limit applies
+    /// to records in both directions, and larger records are rejected; see
+    /// [`CommonState::max_fragment_length()`].  Servers that do not support the
+    /// extension ignore it.
+    ///
+    /// Servers that support `record_size_limit` ignore this extension if both
+    /// are sent.  Unknown values are reported as errors from [ClientConnection::new].
+    /// The default is `None`: the extension is not sent.  It is never sent for QUIC
+    /// connections.
+    ///
+    /// [RFC 6066]: https://datatracker.ietf.org/doc/html/rfc6066#section-4
+    /// [ClientConnection::new]: crate::client::ClientConnection::new
+    /// [`CommonState::max_fragment_length()`]: crate::CommonState::max_fragment_length
+    pub max_fragment_length: Option<MaxFragmentLength>,
+
     /// How to decide what client auth certificate/keys to use.
     pub client_auth_cert_resolver: Arc<dyn ResolvesClientCert>,
 
@@ -221,6 +335,63 @@ pub struct ClientConfig {
     /// The default is false.
     pub enable_early_data: bool,
 
+    /// Whether to allow the server to request a client certificate after
+    /// the handshake, in TLS1.3 connections.
+    ///
+    /// If the server does so, [`ClientConfig::client_auth_cert_resolver`] is
+    /// consulted for a certificate, as it would be during the handshake.
+    /// This is never offered for QUIC connections.
+    ///
+    /// The default is false.
+    pub enable_post_handshake_auth: bool,
+
+    /// Whether to accept delegated credentials from the server, as described
+    /// in [RFC 9345], in TLS1.3 connections.
+    ///
+    /// If enabled, the client advertises the TLS1.3 signature schemes of the
+    /// server certificate verifier for use with delegated credentials.  A server may
+    /// then authenticate with a short-lived key delegated by its certificate, which
+    /// is checked with [`ServerCertVerifier::verify_delegated_credential()`].
+    ///
+    /// The default is false.
+    ///
+    /// [RFC 9345]: https://datatracker.ietf.org/doc/html/rfc9345
+    /// [`ServerCertVerifier::verify_delegated_credential()`]: crate::client::danger::ServerCertVerifier::verify_delegated_credential
+    pub enable_delegated_credentials: bool,
+
+    /// Whether to send GREASE values, as described in [RFC 8701].
+    ///
+    /// If enabled, reserved values are added to the offered cipher suites,
+    /// supported groups (with a placeholder key share), signature algorithms,
+    /// supported versions and ALPN protocols, and reserved extensions are sent.
+    /// Servers are required to ignore these, so this checks their tolerance of
+    /// unknown values and makes the `ClientHello` harder to fingerprint.
+    ///
+    /// The default is false.
+    ///
+    /// [RFC 8701]: https://datatracker.ietf.org/doc/html/rfc8701
+    pub enable_grease: bool,
+
+    /// How many TLS1.3 key shares to send in the initial `ClientHello`.
+    ///
+    /// Key shares are sent for the group the server chose last time (see
+    /// [`ClientSessionStore::kx_hint()`]), followed by the configured key exchange
+    /// groups in order.  Where a hybrid group is sent, its classical component is
+    /// also offered separately, without counting towards this number.
+    ///
+    /// Each key share costs a key generation and increases the size of the
+    /// `ClientHello`, but a server that supports none of the offered groups
+    /// makes an extra round trip with a `HelloRetryRequest`.
+    /// [`ClientSessionStore::key_share_groups()`] can override this per server.
+    ///
+    /// The default is 1.  Zero is treated as 1.
+    pub initial_key_shares: usize,
+
+    /// How to pad the `ClientHello`.
+    ///
+    /// The default is [`ClientHelloPadding::Disabled`].
+    pub padding: ClientHelloPadding,
+
     /// If set to `true`, requires the server to support the extended
     /// master secret extraction method defined in [RFC 7627].
     ///
@@ -246,7 +417,7 @@ pub struct ClientConfig {
     pub(super) versions: versions::EnabledVersions,
 
     /// How to verify the server certificate chain.
-    pub(super) verifier: Arc<dyn verify::ServerCertVerifier>,
+    pub(crate) verifier: Arc<dyn verify::ServerCertVerifier>,
 
     /// How to decompress the server's certificate chain.
     ///
@@ -280,6 +451,25 @@ pub struct ClientConfig {
 
     /// How to offer Encrypted Client Hello (ECH). The default is to not offer ECH.
     pub(super) ech_mode: Option<EchMode>,
+
+    /// External pre-shared keys (PSKs) to offer to the server.
+    ///
+    /// These are offered in TLS1.3 handshakes, after any resumption ticket.  If the
+    /// server selects one of them, it authenticates both peers: the server sends no
+    /// certificate, and none is verified.  A PSK is only offered if its hash function
+    /// matches that of a cipher suite this client supports.
+    pub external_psks: Vec<Arc<ExternalPsk>>,
+
+    /// The key exchange modes to offer for PSKs.
+    ///
+    /// This applies to both external PSKs and resumption, but rustls servers
+    /// only resume sessions using [`PskKexMode::PskWithDhe`].
+    ///
+    /// If this does not include [`PskKexMode::PskWithDhe`] and there are
+    /// [`ClientConfig::external_psks`], no key shares are sent in the initial
+    /// `ClientHello` (the `key_share` extension is empty): a server that does not
+    /// accept the PSK must then ask for one with a `HelloRetryRequest`.
+    pub psk_kex_modes: Vec<PskKexMode>,
 }
 
 impl ClientConfig {
@@ -392,7 +582,28 @@ impl ClientConfig {
     }
 
     pub(super) fn needs_key_share(&self) -> bool {
-        self.supports_version(ProtocolVersion::TLSv1_3)
+        self.supports_version(ProtocolVersion::TLSv1_3) && !self.offers_psk_ke_only()
+    }
+
+    /// A client that only offers external PSKs with PSK_KE sends no key shares,
+    /// unless asked for one by a HelloRetryRequest.
+    pub(super) fn offers_psk_ke_only(&self) -> bool {
+        !self.external_psks.is_empty()
+            && !self
+                .psk_kex_modes
+                .contains(&PskKexMode::PskWithDhe)
+    }
+
+    /// The signature schemes offered for delegated credentials, if they are enabled.
+    pub(super) fn delegated_credential_schemes(&self) -> Option<Vec<SignatureScheme>> {
+        self.enable_delegated_credentials
+            .then(|| {
+                self.verifier
+                    .supported_verify_schemes()
+                    .into_iter()
+                    .filter(|scheme| scheme.supported_in_tls13())
+                    .collect()
+            })
     }
 
     /// We support a given TLS version if it's quoted in the configured
@@ -439,7 +650,7 @@ impl ClientConfig {
             .copied()
     }
 
-    pub(super) fn current_time(&self) -> Result<UnixTime, Error> {
+    pub(crate) fn current_time(&self) -> Result<UnixTime, Error> {
         self.time_provider
             .current_time()
             .ok_or(Error::FailedToGetCurrentTime)
@@ -529,6 +740,82 @@ pub enum Tls12Resumption {
     SessionIdOrTickets,
 }
 
+/// How to pad the `ClientHello` message, using the [RFC 7685] padding extension.
+///
+/// The sizes here are of the whole `ClientHello` handshake message, including its
+/// four byte header.  When offering ECH, this applies to the outer `ClientHello`:
+/// the inner `ClientHello` is padded separately, as required by ECH.
+///
+/// [RFC 7685]: https://datatracker.ietf.org/doc/html/rfc7685
+#[non_exhaustive]
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ClientHelloPadding {
+    /// Do not pad the `ClientHello`.
+    Disabled,
+    /// Pad the `ClientHello` to at least the given length.
+    ToLength(u16),
+    /// Pad the `ClientHello` to a multiple of the given length.
+    ///
+    /// This hides small variations in the size of the `ClientHello`, such as
+    /// from the choice of key shares, within buckets of this size.
+    ToMultipleOf(u16),
+    /// Pad a `ClientHello` of between 256 and 511 bytes to 512 bytes.
+    ///
+    /// Some F5 load balancers (and other middleboxes) fail to process a
+    /// `ClientHello` of this size; this is the workaround described in RFC 7685.
+    F5Workaround,
+}
+
+impl ClientHelloPadding {
+    /// Add a padding extension to `hello`, if this policy calls for one.
+    ///
+    /// This must be done once all other extensions are present, but before
+    /// any PSK binders or ECH payload are computed over the encoding.
+    pub(super) fn apply(self, hello: &mut ClientHelloPayload) {
+        hello.padding = None;
+
+        // the handshake message header is a one byte type and three byte length
+        let unpadded = 4 + hello.get_encoding().len();
+        if let Some(len) = self.padding_len(unpadded) {
+            hello.padding = Some(Payload::new(vec![0; len]));
+        }
+    }
+
+    /// The body length of the padding extension for a `ClientHello` of `unpadded` bytes.
+    pub(super) fn padding_len(self, unpadded: usize) -> Option<usize> {
+        let (target, step) = match self {
+            Self::Disabled | Self::ToMultipleOf(0) => return None,
+            Self::ToLength(len) => (usize::from(len), 0),
+            Self::ToMultipleOf(step) => {
+                let step = usize::from(step);
+                (unpadded.div_ceil(step) * step, step)
+            }
+            Self::F5Workaround => match unpadded {
+                0x100..=0x1ff => (0x200, 0),
+                _ => return None,
+            },
+        };
+
+        let mut needed = target.checked_sub(unpadded)?;
+        if needed == 0 {
+            return None;
+        }
+
+        // The extension needs four bytes for its type and length.  Always include
+        // at least one byte of body, as some servers are intolerant of the last
+        // extension being empty.  This may overshoot `target`, except for
+        // `ToMultipleOf` where we move up to a further multiple.
+        while needed < 5 {
+            match step {
+                0 => needed = 5,
+                _ => needed += step,
+            }
+        }
+
+        Some(needed - 4)
+    }
+}
+
 /// Container for unsafe APIs
 pub(super) mod danger {
     use super::ClientConfig;
@@ -635,16 +922,19 @@ mod connection {
     use core::ops::{Deref, DerefMut};
     use std::io;
 
-    use pki_types::ServerName;
+    use pki_types::{CertificateDer, ServerName, UnixTime};
 
-    use super::{ClientConnectionData, ClientExtensionsInput};
+    use super::{ClientCertRequest, ClientConnectionData, ClientExtensionsInput};
     use crate::ClientConfig;
     use crate::client::EchStatus;
-    use crate::common_state::Protocol;
+    use crate::common_state::{IoState, Protocol};
     use crate::conn::{ConnectionCommon, ConnectionCore};
     use crate::error::Error;
+    use crate::exported_authenticator::{self, AuthenticatorRequest};
+    use crate::sign::CertifiedKey;
     use crate::suites::ExtractedSecrets;
     use crate::sync::Arc;
+    use crate::verify::ServerCertVerifier;
 
     /// Allows writing of early data in resumed TLS 1.3 connections.
     ///
@@ -785,6 +1075,108 @@ mod connection {
             self.inner.tls13_tickets_received
         }
 
+        /// Returns the client certificate request the handshake is waiting for, if any.
+        ///
+        /// See [`IoState::awaiting_client_cert()`].
+        pub fn pending_client_cert(&self) -> Option<&ClientCertRequest> {
+            self.inner
+                .core
+                .state
+                .as_ref()
+                .ok()?
+                .pending_client_cert()
+        }
+
+        /// Continues the handshake with the certificate chosen for [`Self::pending_client_cert()`].
+        ///
+        /// If `certified_key` is `None`, or the server supports none of its signature
+        /// schemes, the handshake continues without client authentication.  Any packets
+        /// received while the handshake was paused are then processed, as by
+        /// [`ConnectionCommon::process_new_packets()`].
+        pub fn complete_client_cert(
+            &mut self,
+            certified_key: Option<Arc<CertifiedKey>>,
+        ) -> Result<IoState, Error> {
+            self.inner
+                .core
+                .process_client_cert(certified_key)?;
+            self.inner.process_new_packets()
+        }
+
+        /// Make a request for the server to prove possession of another identity.
+        ///
+        /// This is an exported authenticator request, described in [RFC 9261].  The
+        /// application sends [`AuthenticatorRequest::as_bytes()`] to the server, which
+        /// answers with [`ServerConnection::exported_authenticator()`].  The reply is
+        /// then checked with [`ClientConnection::validate_exported_authenticator()`].
+        ///
+        /// `context` must be unique for each request on this connection, and should be
+        /// unpredictable to the server (for example, randomly generated).  It may be
+        /// at most 255 bytes.  The request asks for the signature schemes and trust
+        /// anchors of `verifier`.
+        ///
+        /// This is only possible once the handshake is complete, and only for TLS1.3.
+        ///
+        /// [RFC 9261]: https://datatracker.ietf.org/doc/html/rfc9261
+        /// [`ServerConnection::exported_authenticator()`]: crate::ServerConnection::exported_authenticator
+        pub fn exported_authenticator_request(
+            &self,
+            context: Vec<u8>,
+            verifier: &dyn ServerCertVerifier,
+        ) -> Result<AuthenticatorRequest, Error> {
+            AuthenticatorRequest::new(
+                &self.inner.core.common_state,
+                context,
+                verifier.supported_verify_schemes(),
+                verifier
+                    .root_hint_subjects()
+                    .map(|hints| hints.to_vec())
+                    .unwrap_or_default(),
+            )
+        }
+
+        /// Make an exported authenticator in reply to a `request` from the server.
+        ///
+        /// The authenticator proves possession of `certified_key`, and is sent to the
+        /// server by the application.  If `certified_key` is `None`, the authenticator
+        /// is empty, declining the request.
+        ///
+        /// See [`ServerConnection::exported_authenticator_request()`].
+        ///
+        /// [`ServerConnection::exported_authenticator_request()`]: crate::ServerConnection::exported_authenticator_request
+        pub fn exported_authenticator(
+            &self,
+            request: &AuthenticatorRequest,
+            certified_key: Option<&CertifiedKey>,
+        ) -> Result<Vec<u8>, Error> {
+            exported_authenticator::make_authenticator(&self.inner.core, request, certified_key)
+        }
+
+        /// Check an exported authenticator the server sent in reply to `request`.
+        ///
+        /// `request` must have come from [`ClientConnection::exported_authenticator_request()`].
+        /// The server's certificate chain is checked with `verifier` to be valid for
+        /// `server_name` at time `now`.
+        ///
+        /// Returns the server's certificate chain, or `None` if the server declined the request.
+        pub fn validate_exported_authenticator(
+            &self,
+            request: &AuthenticatorRequest,
+            authenticator: &[u8],
+            server_name: &ServerName<'_>,
+            verifier: &dyn ServerCertVerifier,
+            now: UnixTime,
+        ) -> Result<Option<Vec<CertificateDer<'static>>>, Error> {
+            exported_authenticator::validate_server_authenticator(
+                &self.inner.core,
+                request,
+                authenticator,
+                server_name,
+                verifier,
+                now,
+            )
+        }
+
         /// Return true if the connection was made with a `ClientConfig` that is FIPS compatible.
         ///
         /// This is different from [`crate::crypto::CryptoProvider::fips()`]:
@@ -852,6 +1244,10 @@ impl ConnectionCore<ClientConnectionData> {
     ) -> Result<Self, Error> {
         let mut common_state = CommonState::new(Side::Client);
         common_state.set_max_fragment_size(config.max_fragment_size)?;
+        common_state.set_record_size_limit(config.record_size_limit)?;
+        if let Some(MaxFragmentLength::Unknown(_)) = config.max_fragment_length {
+            return Err(Error::BadMaxFragmentSize);
+        }
         common_state.protocol = proto;
         common_state.enable_secret_extraction = config.enable_secret_extraction;
         common_state.fips = config.fips();
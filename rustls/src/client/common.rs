@@ -1,35 +1,81 @@
 use alloc::boxed::Box;
 use alloc::vec::Vec;
 
-use super::ResolvesClientCert;
+use pki_types::UnixTime;
+
+use super::client_conn::ClientConnectionData;
+use super::hs::{ClientContext, NextState, NextStateOrError};
+use super::{ClientCertRequest, ResolvesClientCert};
+use crate::check::inappropriate_message;
+use crate::common_state::State;
+use crate::crypto::SecureRandom;
+use crate::delegated_credential::DelegatedCredential;
+use crate::enums::{CipherSuite, ProtocolVersion};
+use crate::error::Error;
 use crate::log::{debug, trace};
-use crate::msgs::enums::ExtensionType;
+use crate::msgs::enums::{ExtensionType, NamedGroup};
 use crate::msgs::handshake::{CertificateChain, DistinguishedName, ProtocolName, ServerExtensions};
+use crate::msgs::message::Message;
+use crate::rand::GetRandomFailed;
+use crate::sct::{self, SctList};
 use crate::sync::Arc;
+use crate::verify::ServerCertVerifier;
 use crate::{SignatureScheme, compress, sign};
 
 #[derive(Debug)]
 pub(super) struct ServerCertDetails<'a> {
     pub(super) cert_chain: CertificateChain<'a>,
     pub(super) ocsp_response: Vec<u8>,
+    pub(super) scts: Option<SctList>,
+    pub(super) delegated_credential: Option<DelegatedCredential>,
 }
 
 impl<'a> ServerCertDetails<'a> {
-    pub(super) fn new(cert_chain: CertificateChain<'a>, ocsp_response: Vec<u8>) -> Self {
+    pub(super) fn new(
+        cert_chain: CertificateChain<'a>,
+        ocsp_response: Vec<u8>,
+        scts: Option<SctList>,
+    ) -> Self {
         Self {
             cert_chain,
             ocsp_response,
+            scts,
+            delegated_credential: None,
         }
     }
 
+    /// Verify the SCTs received for the end-entity certificate, if `verifier` wants them.
+    ///
+    /// The certificate chain must already have been verified.
+    pub(super) fn verify_scts(
+        &self,
+        verifier: &dyn ServerCertVerifier,
+        now: UnixTime,
+    ) -> Result<(), Error> {
+        if !verifier.request_scts() {
+            return Ok(());
+        }
+
+        let (end_entity, intermediates) = self
+            .cert_chain
+            .split_first()
+            .ok_or(Error::NoCertificatesPresented)?;
+        let scts = sct::received_scts(end_entity, self.scts.as_ref(), &self.ocsp_response);
+        verifier.verify_signed_certificate_timestamps(end_entity, intermediates, &scts, now)
+    }
+
     pub(super) fn into_owned(self) -> ServerCertDetails<'static> {
         let Self {
             cert_chain,
             ocsp_response,
+            scts,
+            delegated_credential,
         } = self;
         ServerCertDetails {
             cert_chain: cert_chain.into_owned(),
             ocsp_response,
+            scts,
+            delegated_credential,
         }
     }
 }
@@ -39,15 +85,21 @@ pub(super) struct ClientHelloDetails {
     pub(super) sent_extensions: Vec<ExtensionType>,
     pub(super) extension_order_seed: u16,
     pub(super) offered_cert_compression: bool,
+    pub(super) grease: Option<GreaseValues>,
 }
 
 impl ClientHelloDetails {
-    pub(super) fn new(alpn_protocols: Vec<ProtocolName>, extension_order_seed: u16) -> Self {
+    pub(super) fn new(
+        alpn_protocols: Vec<ProtocolName>,
+        extension_order_seed: u16,
+        grease: Option<GreaseValues>,
+    ) -> Self {
         Self {
             alpn_protocols,
             sent_extensions: Vec::new(),
             extension_order_seed,
             offered_cert_compression: false,
+            grease,
         }
     }
 
@@ -75,6 +127,51 @@ impl ClientHelloDetails {
     }
 }
 
+/// GREASE values ([RFC8701]) to send in a `ClientHello`.
+///
+/// These are chosen once per connection, so that a `ClientHello` sent in
+/// response to a `HelloRetryRequest` repeats them.
+///
+/// [RFC8701]: https://datatracker.ietf.org/doc/html/rfc8701
+#[derive(Clone, Copy, Debug)]
+pub(super) struct GreaseValues {
+    pub(super) cipher_suite: CipherSuite,
+    pub(super) group: NamedGroup,
+    pub(super) signature_scheme: SignatureScheme,
+    pub(super) version: ProtocolVersion,
+    pub(super) alpn: u16,
+    pub(super) extensions: [ExtensionType; 2],
+}
+
+impl GreaseValues {
+    pub(super) fn new(secure_random: &dyn SecureRandom) -> Result<Self, GetRandomFailed> {
+        let mut bytes = [0u8; 7];
+        secure_random.fill(&mut bytes)?;
+
+        // GREASE values are 0x0a0a, 0x1a1a, ..., 0xfafa.
+        let value = |byte: u8| {
+            let byte = (byte & 0xf0) | 0x0a;
+            u16::from_be_bytes([byte, byte])
+        };
+
+        // the two extensions must differ
+        let first = value(bytes[5]);
+        let mut second = value(bytes[6]);
+        if first == second {
+            second ^= 0x1010;
+        }
+
+        Ok(Self {
+            cipher_suite: CipherSuite::from(value(bytes[0])),
+            group: NamedGroup::from(value(bytes[1])),
+            signature_scheme: SignatureScheme::from(value(bytes[2])),
+            version: ProtocolVersion::from(value(bytes[3])),
+            alpn: value(bytes[4]),
+            extensions: [ExtensionType::from(first), ExtensionType::from(second)],
+        })
+    }
+}
+
 pub(super) enum ClientAuthDetails {
     /// Send an empty `Certificate` and no `CertificateVerify`.
     Empty { auth_context_tls13: Option<Vec<u8>> },
@@ -101,7 +198,21 @@ impl ClientAuthDetails {
             .map(|p| p.as_ref())
             .collect::<Vec<&[u8]>>();
 
-        if let Some(certkey) = resolver.resolve(&acceptable_issuers, sigschemes) {
+        Self::new(
+            resolver.resolve(&acceptable_issuers, sigschemes),
+            sigschemes,
+            auth_context_tls13,
+            compressor,
+        )
+    }
+
+    fn new(
+        certkey: Option<Arc<sign::CertifiedKey>>,
+        sigschemes: &[SignatureScheme],
+        auth_context_tls13: Option<Vec<u8>>,
+        compressor: Option<&'static dyn compress::CertCompressor>,
+    ) -> Self {
+        if let Some(certkey) = certkey {
             if let Some(signer) = certkey.key.choose_scheme(sigschemes) {
                 debug!("Attempting client auth");
                 return Self::Verify {
@@ -117,3 +228,100 @@ impl ClientAuthDetails {
         Self::Empty { auth_context_tls13 }
     }
 }
+
+/// The handshake, from the point the client's certificate has been chosen.
+pub(super) trait ResumeWithClientAuth: Send + Sync + 'static {
+    fn resume(
+        self,
+        cx: &mut ClientContext<'_>,
+        client_auth: ClientAuthDetails,
+    ) -> NextStateOrError<'static>;
+}
+
+/// Waits for the application to choose a client certificate, then continues with `next`.
+pub(super) struct ExpectClientCert<T> {
+    request: ClientCertRequest,
+    auth_context_tls13: Option<Vec<u8>>,
+    compressor: Option<&'static dyn compress::CertCompressor>,
+    next: T,
+}
+
+impl<T: ResumeWithClientAuth> ExpectClientCert<T> {
+    /// Resolve the client's certificate with `resolver`, then continue with `next`.
+    ///
+    /// If `resolver` is asynchronous, instead wait for the application to choose one.
+    pub(super) fn resolve(
+        resolver: &dyn ResolvesClientCert,
+        canames: Option<&[DistinguishedName]>,
+        sigschemes: &[SignatureScheme],
+        auth_context_tls13: Option<Vec<u8>>,
+        compressor: Option<&'static dyn compress::CertCompressor>,
+        next: T,
+        cx: &mut ClientContext<'_>,
+    ) -> NextStateOrError<'static> {
+        if !resolver.is_async() {
+            let client_auth = ClientAuthDetails::resolve(
+                resolver,
+                canames,
+                sigschemes,
+                auth_context_tls13,
+                compressor,
+            );
+            return next.resume(cx, client_auth);
+        }
+
+        debug!("Waiting for the application to choose a client certificate");
+        cx.common.awaiting_client_cert = true;
+        Ok(Box::new(Self {
+            request: ClientCertRequest::new(
+                canames.unwrap_or_default().to_vec(),
+                sigschemes.to_vec(),
+            ),
+            auth_context_tls13,
+            compressor,
+            next,
+        }))
+    }
+}
+
+impl<T: ResumeWithClientAuth> State<ClientConnectionData> for ExpectClientCert<T> {
+    fn handle<'m>(
+        self: Box<Self>,
+        _cx: &mut ClientContext<'_>,
+        message: Message<'m>,
+    ) -> NextStateOrError<'m>
+    where
+        Self: 'm,
+    {
+        Err(inappropriate_message(&message.payload, &[]))
+    }
+
+    fn pending_client_cert(&self) -> Option<&ClientCertRequest> {
+        Some(&self.request)
+    }
+
+    fn handle_client_cert(
+        self: Box<Self>,
+        cx: &mut ClientContext<'_>,
+        certified_key: Option<Arc<sign::CertifiedKey>>,
+    ) -> NextStateOrError<'static> {
+        cx.common.awaiting_client_cert = false;
+        let Self {
+            request,
+            auth_context_tls13,
+            compressor,
+            next,
+        } = *self;
+        let client_auth = ClientAuthDetails::new(
+            certified_key,
+            request.sigschemes(),
+            auth_context_tls13,
+            compressor,
+        );
+        next.resume(cx, client_auth)
+    }
+
+    fn into_owned(self: Box<Self>) -> NextState<'static> {
+        self
+    }
+}
@@ -6,7 +6,7 @@ use pki_types::{DnsName, EchConfigListBytes, ServerName};
 use subtle::ConstantTimeEq;
 
 use crate::CipherSuite::TLS_EMPTY_RENEGOTIATION_INFO_SCSV;
-use crate::client::tls13;
+use crate::client::{ClientHelloPadding, tls13};
 use crate::crypto::SecureRandom;
 use crate::crypto::hash::Hash;
 use crate::crypto::hpke::{EncapsulatedSecret, Hpke, HpkePublicKey, HpkeSealer, HpkeSuite};
@@ -24,11 +24,12 @@ use crate::msgs::handshake::{
 use crate::msgs::message::{Message, MessagePayload};
 use crate::msgs::persist;
 use crate::msgs::persist::Retrieved;
+use crate::sync::Arc;
 use crate::tls13::key_schedule::{
     KeyScheduleEarly, KeyScheduleHandshakeStart, server_ech_hrr_confirmation_secret,
 };
 use crate::{
-    AlertDescription, ClientConfig, CommonState, EncryptedClientHelloError, Error,
+    AlertDescription, ClientConfig, CommonState, EncryptedClientHelloError, Error, ExternalPsk,
     PeerIncompatible, PeerMisbehaved, ProtocolVersion, Tls13CipherSuite,
 };
 
@@ -253,7 +254,7 @@ impl EchGreaseConfig {
 
         // Construct an inner hello using the outer hello - this allows us to know the size of
         // dummy payload we should use for the GREASE extension.
-        let encoded_inner_hello = grease_state.encode_inner_hello(outer_hello, None, &None);
+        let encoded_inner_hello = grease_state.encode_inner_hello(outer_hello, None, &None, &[]);
 
         // Generate a payload of random data equivalent in length to a real inner hello.
         let payload_len = encoded_inner_hello.len()
@@ -382,11 +383,15 @@ impl EchState {
     /// If `retry_req` is `Some`, then the outer hello will be constructed for a hello retry request.
     ///
     /// If `resuming` is `Some`, then the inner hello will be constructed for a resumption handshake.
+    ///
+    /// Binders for `external_psks` are filled in the inner hello alongside any resumption binder.
     pub(crate) fn ech_hello(
         &mut self,
         mut outer_hello: ClientHelloPayload,
+        padding: ClientHelloPadding,
         retry_req: Option<&HelloRetryRequest>,
         resuming: &Option<Retrieved<&persist::Tls13ClientSessionValue>>,
+        external_psks: &[Arc<ExternalPsk>],
     ) -> Result<ClientHelloPayload, Error> {
         trace!(
             "Preparing ECH offer {}",
@@ -394,7 +399,8 @@ impl EchState {
         );
 
         // Construct the encoded inner hello and update the transcript.
-        let encoded_inner_hello = self.encode_inner_hello(&outer_hello, retry_req, resuming);
+        let encoded_inner_hello =
+            self.encode_inner_hello(&outer_hello, retry_req, resuming, external_psks);
 
         // Complete the ClientHelloOuterAAD with an ech extension, the payload should be a placeholder
         // of size L, all zeroes. L == length of encrypting encoded client hello inner w/ the selected
@@ -435,6 +441,10 @@ impl EchState {
         outer_hello.encrypted_client_hello =
             Some(outer_hello_ext(self, enc.clone(), vec![0; payload_len]));
 
+        // The placeholder has the same length as the real extension, so the outer
+        // hello can be padded now.  Padding is part of the AAD like everything else.
+        padding.apply(&mut outer_hello);
+
         // Next we compute the proper extension payload.
         let payload = self
             .sender
@@ -566,6 +576,7 @@ impl EchState {
         outer_hello: &ClientHelloPayload,
         retryreq: Option<&HelloRetryRequest>,
         resuming: &Option<Retrieved<&persist::Tls13ClientSessionValue>>,
+        external_psks: &[Arc<ExternalPsk>],
     ) -> Vec<u8> {
         // Start building an inner hello using the outer_hello as a template.
         let mut inner_hello = ClientHelloPayload {
@@ -653,18 +664,21 @@ impl EchState {
         // always have the ECH cover name in SNI).
         self.sent_extensions = inner_hello.collect_used();
 
-        // If we're resuming, we need to update the PSK binder in the inner hello.
-        if let Some(resuming) = resuming.as_ref() {
+        // If we're offering PSKs, we need to update the PSK binders in the inner hello.
+        if resuming.is_some() || !external_psks.is_empty() {
             let mut chp = HandshakeMessagePayload(HandshakePayload::ClientHello(inner_hello));
 
-            // Retain the early key schedule we get from processing the binder.
-            self.early_data_key_schedule = Some(tls13::fill_in_psk_binder(
-                resuming,
+            // Retain the early key schedule we get from processing the resumption binder.
+            self.early_data_key_schedule = tls13::fill_in_psk_binders(
+                resuming
+                    .as_ref()
+                    .map(|resuming| resuming.value),
+                external_psks,
                 &self.inner_hello_transcript,
                 &mut chp,
-            ));
+            );
 
-            // fill_in_psk_binder works on an owned HandshakeMessagePayload, so we need to
+            // fill_in_psk_binders works on an owned HandshakeMessagePayload, so we need to
             // extract our inner hello back out of it to retain ownership.
             inner_hello = match chp.0 {
                 HandshakePayload::ClientHello(chp) => chp,
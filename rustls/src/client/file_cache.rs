@@ -0,0 +1,507 @@
+use alloc::collections::VecDeque;
+use alloc::format;
+use alloc::string::String;
+use alloc::vec::Vec;
+use core::fmt;
+use core::mem::ManuallyDrop;
+use core::sync::atomic::{AtomicUsize, Ordering};
+use core::time::Duration;
+use std::ffi::OsString;
+use std::fs::{self, OpenOptions};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::thread;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use pki_types::{ServerName, UnixTime};
+
+use crate::NamedGroup;
+use crate::client::{ClientConfig, ClientSessionStore, ResolvesClientCert};
+use crate::crypto::CryptoProvider;
+use crate::log::warn;
+use crate::msgs::codec::{Codec, Reader};
+use crate::msgs::persist::{self, ClientSessionContext, Retrieved};
+use crate::sync::Arc;
+use crate::time_provider::TimeProvider;
+use crate::verify::ServerCertVerifier;
+
+/// The version of the file format.  This is independent of the version of the
+/// session encoding (see [`persist::Tls13ClientSessionValue::encode()`]).
+const FILE_FORMAT_VERSION: u8 = 1;
+
+const MAX_TLS13_TICKETS_PER_SERVER: usize = 8;
+
+/// How long to wait for another process to finish updating the file.
+const LOCK_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// A lock file older than this is assumed to have been left by a process that exited
+/// while holding it.
+const STALE_LOCK_AGE: Duration = Duration::from_secs(30);
+
+/// An implementer of `ClientSessionStore` that keeps everything in a file,
+/// so that sessions can be resumed by later processes.
+///
+/// The file holds the key exchange hints, TLS1.2 sessions and TLS1.3 tickets
+/// for up to a given number of servers, most recently used first.  Sessions and
+/// tickets are dropped once they expire, according to the `ClientConfig`'s
+/// [`TimeProvider`].
+///
+/// The file is updated under a lock file (the same path, with `.lock` appended), and
+/// is replaced atomically.  So, it may be shared between concurrent processes:
+/// in particular, each TLS1.3 ticket is still used at most once.
+///
+/// The file contains the secrets needed to resume sessions, and so must be
+/// protected like any other key material: on unix, it is created readable only by
+/// its owner.  Any problems reading or writing the file are logged, and otherwise
+/// treated as if no sessions were stored.
+pub struct ClientSessionFileCache {
+    path: PathBuf,
+    lock_path: PathBuf,
+    max_servers: usize,
+    provider: Arc<CryptoProvider>,
+    server_cert_verifier: Arc<dyn ServerCertVerifier>,
+    client_creds: Arc<dyn ResolvesClientCert>,
+    time_provider: Arc<dyn TimeProvider>,
+}
+
+impl ClientSessionFileCache {
+    /// Make a new `ClientSessionFileCache`, storing sessions for up to `max_servers`
+    /// servers in the file at `path`.
+    ///
+    /// Stored sessions can be used by `config` and any clones of it.  A session
+    /// stored by another process is only resumed if `config` supports its cipher
+    /// suite, and `config`'s server certificate verifier accepts the server
+    /// certificate chain it was established with.  Sessions which `config` cannot
+    /// use are left in the file for other processes until they expire.
+    pub fn new(path: impl Into<PathBuf>, max_servers: usize, config: &ClientConfig) -> Self {
+        let path = path.into();
+        let mut lock_path = OsString::from(path.clone());
+        lock_path.push(".lock");
+
+        Self {
+            path,
+            lock_path: lock_path.into(),
+            max_servers,
+            provider: config.crypto_provider().clone(),
+            server_cert_verifier: config.verifier.clone(),
+            client_creds: config.client_auth_cert_resolver.clone(),
+            time_provider: config.time_provider.clone(),
+        }
+    }
+
+    fn context(&self) -> ClientSessionContext<'_> {
+        ClientSessionContext {
+            provider: &self.provider,
+            server_cert_verifier: &self.server_cert_verifier,
+            client_creds: &self.client_creds,
+        }
+    }
+
+    /// Read the current contents of the file.
+    fn load(&self) -> Vec<ServerData> {
+        match fs::read(&self.path) {
+            Ok(bytes) => ServerData::read_file(&bytes).unwrap_or_else(|| {
+                warn!("ignoring malformed client session file {:?}", self.path);
+                Vec::new()
+            }),
+            Err(err) if err.kind() == io::ErrorKind::NotFound => Vec::new(),
+            Err(err) => {
+                warn!("unable to read client session file {:?}: {err}", self.path);
+                Vec::new()
+            }
+        }
+    }
+
+    /// Update the data for `server_name` with `f`, and save the result.
+    ///
+    /// `f` is not called if the file cannot be locked.  If the file cannot be saved,
+    /// this returns `None`: anything `f` took from the data remains in the file, so
+    /// must not be used.
+    fn edit<T>(
+        &self,
+        server_name: &ServerName<'_>,
+        f: impl FnOnce(&mut ServerData) -> Option<T>,
+    ) -> Option<T> {
+        let _lock = match FileLock::acquire(&self.lock_path) {
+            Ok(lock) => lock,
+            Err(err) => {
+                warn!("unable to lock client session file {:?}: {err}", self.path);
+                return None;
+            }
+        };
+
+        let mut servers = self.load();
+        let mut data = match servers
+            .iter()
+            .position(|sd| sd.server_name == *server_name)
+        {
+            Some(index) => servers.remove(index),
+            None => ServerData::new(server_name.to_owned()),
+        };
+        let result = f(&mut data);
+
+        // Most recently used first.
+        servers.insert(0, data);
+        servers.retain(|sd| !sd.is_empty());
+        servers.truncate(self.max_servers);
+        if let Some(now) = self.time_provider.current_time() {
+            for sd in servers.iter_mut() {
+                sd.remove_expired(now);
+            }
+        }
+
+        match self.save(&servers) {
+            Ok(()) => result,
+            Err(err) => {
+                warn!("unable to write client session file {:?}: {err}", self.path);
+                None
+            }
+        }
+    }
+
+    fn save(&self, servers: &[ServerData]) -> io::Result<()> {
+        let mut tmp_path = OsString::from(self.path.clone());
+        tmp_path.push(".tmp");
+
+        // Any temporary file left by a failed update is ours, as we hold the lock.
+        // Remove it so the new one is created with our permissions.
+        if let Err(err) = fs::remove_file(&tmp_path) {
+            if err.kind() != io::ErrorKind::NotFound {
+                return Err(err);
+            }
+        }
+
+        let mut options = OpenOptions::new();
+        options.write(true).create_new(true);
+        #[cfg(unix)]
+        std::os::unix::fs::OpenOptionsExt::mode(&mut options, 0o600);
+        let mut file = options.open(&tmp_path)?;
+
+        let written = file
+            .write_all(&ServerData::encode_file(servers))
+            .and_then(|()| file.sync_all())
+            .and_then(|()| fs::rename(&tmp_path, &self.path));
+        if written.is_err() {
+            let _ = fs::remove_file(&tmp_path);
+        }
+        written
+    }
+}
+
+impl ClientSessionStore for ClientSessionFileCache {
+    fn set_kx_hint(&self, server_name: ServerName<'static>, group: NamedGroup) {
+        self.edit(&server_name, |data| {
+            data.kx_hint = Some(group);
+            Some(())
+        });
+    }
+
+    fn kx_hint(&self, server_name: &ServerName<'_>) -> Option<NamedGroup> {
+        self.load()
+            .into_iter()
+            .find(|sd| sd.server_name == *server_name)
+            .and_then(|sd| sd.kx_hint)
+    }
+
+    fn set_tls12_session(
+        &self,
+        server_name: ServerName<'static>,
+        value: persist::Tls12ClientSessionValue,
+    ) {
+        self.edit(&server_name, |data| {
+            data.tls12 = Some(value.encode());
+            Some(())
+        });
+    }
+
+    fn tls12_session(
+        &self,
+        server_name: &ServerName<'_>,
+    ) -> Option<persist::Tls12ClientSessionValue> {
+        let now = self.time_provider.current_time()?;
+        let encoded = self
+            .load()
+            .into_iter()
+            .find(|sd| sd.server_name == *server_name)?
+            .tls12?;
+        decode_unexpired(&encoded, now, |bytes| {
+            persist::Tls12ClientSessionValue::decode_for(bytes, server_name, now, &self.context())
+        })
+    }
+
+    fn remove_tls12_session(&self, server_name: &ServerName<'static>) {
+        self.edit(server_name, |data| data.tls12.take());
+    }
+
+    fn insert_tls13_ticket(
+        &self,
+        server_name: ServerName<'static>,
+        value: persist::Tls13ClientSessionValue,
+    ) {
+        self.edit(&server_name, |data| {
+            if data.tls13.len() == MAX_TLS13_TICKETS_PER_SERVER {
+                data.tls13.pop_front();
+            }
+            data.tls13.push_back(value.encode());
+            Some(())
+        });
+    }
+
+    fn take_tls13_ticket(
+        &self,
+        server_name: &ServerName<'static>,
+    ) -> Option<persist::Tls13ClientSessionValue> {
+        let now = self.time_provider.current_time()?;
+        // Removing the ticket from the file under the lock ensures no other
+        // process can take it.  Tickets this config cannot use are left for others.
+        self.edit(server_name, |data| {
+            let (index, value) = data
+                .tls13
+                .iter()
+                .enumerate()
+                .rev()
+                .find_map(|(index, encoded)| {
+                    let value = decode_unexpired(encoded, now, |bytes| {
+                        persist::Tls13ClientSessionValue::decode_for(
+                            bytes,
+                            server_name,
+                            now,
+                            &self.context(),
+                        )
+                    })?;
+                    Some((index, value))
+                })?;
+            data.tls13.remove(index);
+            Some(value)
+        })
+    }
+}
+
+impl fmt::Debug for ClientSessionFileCache {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ClientSessionFileCache")
+            .field("path", &self.path)
+            .field("max_servers", &self.max_servers)
+            .finish_non_exhaustive()
+    }
+}
+
+/// Decode a stored session, if it is usable and has not expired at `now`.
+fn decode_unexpired<T: core::ops::Deref<Target = persist::ClientSessionCommon>>(
+    encoded: &[u8],
+    now: UnixTime,
+    decode: impl FnOnce(&[u8]) -> Result<T, crate::Error>,
+) -> Option<T> {
+    let retrieved = Retrieved::new(decode(encoded).ok()?, now);
+    match retrieved.has_expired() {
+        true => None,
+        false => Some(retrieved.value),
+    }
+}
+
+struct ServerData {
+    server_name: ServerName<'static>,
+    kx_hint: Option<NamedGroup>,
+
+    // Zero or one encoded TLS1.2 sessions.
+    tls12: Option<Vec<u8>>,
+
+    // Up to MAX_TLS13_TICKETS_PER_SERVER encoded TLS1.3 tickets, oldest first.
+    tls13: VecDeque<Vec<u8>>,
+}
+
+impl ServerData {
+    fn new(server_name: ServerName<'static>) -> Self {
+        Self {
+            server_name,
+            kx_hint: None,
+            tls12: None,
+            tls13: VecDeque::new(),
+        }
+    }
+
+    fn is_empty(&self) -> bool {
+        self.kx_hint.is_none() && self.tls12.is_none() && self.tls13.is_empty()
+    }
+
+    /// Remove sessions that have expired, or are malformed.
+    ///
+    /// Sessions are kept regardless of whether this process's config can use them,
+    /// as the file may be shared with processes whose config can.
+    fn remove_expired(&mut self, now: UnixTime) {
+        let unexpired =
+            |encoded: &[u8]| persist::encoded_client_session_has_expired(encoded, now) == Ok(false);
+
+        if self
+            .tls12
+            .as_deref()
+            .is_some_and(|encoded| !unexpired(encoded))
+        {
+            self.tls12 = None;
+        }
+        self.tls13
+            .retain(|encoded| unexpired(encoded));
+    }
+
+    fn encode_file(servers: &[Self]) -> Vec<u8> {
+        let mut bytes = Vec::new();
+        FILE_FORMAT_VERSION.encode(&mut bytes);
+        (servers.len() as u32).encode(&mut bytes);
+
+        for sd in servers {
+            encode_blob(sd.server_name.to_str().as_bytes(), &mut bytes);
+            match sd.kx_hint {
+                Some(group) => {
+                    1u8.encode(&mut bytes);
+                    group.encode(&mut bytes);
+                }
+                None => 0u8.encode(&mut bytes),
+            }
+            match &sd.tls12 {
+                Some(encoded) => {
+                    1u8.encode(&mut bytes);
+                    encode_blob(encoded, &mut bytes);
+                }
+                None => 0u8.encode(&mut bytes),
+            }
+            (sd.tls13.len() as u8).encode(&mut bytes);
+            for encoded in &sd.tls13 {
+                encode_blob(encoded, &mut bytes);
+            }
+        }
+
+        bytes
+    }
+
+    /// Read the file contents, or `None` if it is malformed or of an unknown version.
+    fn read_file(bytes: &[u8]) -> Option<Vec<Self>> {
+        let r = &mut Reader::init(bytes);
+        if u8::read(r).ok()? != FILE_FORMAT_VERSION {
+            return None;
+        }
+
+        let count = u32::read(r).ok()?;
+        let mut servers = Vec::new();
+        for _ in 0..count {
+            let name = String::from_utf8(read_blob(r)?.to_vec()).ok()?;
+            let mut sd = Self::new(ServerName::try_from(name).ok()?);
+            if u8::read(r).ok()? == 1 {
+                sd.kx_hint = Some(NamedGroup::read(r).ok()?);
+            }
+            if u8::read(r).ok()? == 1 {
+                sd.tls12 = Some(read_blob(r)?.to_vec());
+            }
+            for _ in 0..u8::read(r).ok()? {
+                sd.tls13
+                    .push_back(read_blob(r)?.to_vec());
+            }
+            servers.push(sd);
+        }
+
+        r.expect_empty("ClientSessionFileCache")
+            .ok()?;
+        Some(servers)
+    }
+}
+
+fn encode_blob(blob: &[u8], bytes: &mut Vec<u8>) {
+    (blob.len() as u32).encode(bytes);
+    bytes.extend_from_slice(blob);
+}
+
+fn read_blob<'a>(r: &mut Reader<'a>) -> Option<&'a [u8]> {
+    let len = u32::read(r).ok()?;
+    r.take(len as usize)
+}
+
+/// Exclusive access to the session file, held by creating a lock file.
+///
+/// The lock file holds a token unique to this lock.  It is only removed, when
+/// released or found to be stale, if it still holds the expected token: so a
+/// lock taken over by another process is left in place.
+struct FileLock<'a> {
+    path: &'a Path,
+    token: String,
+}
+
+impl<'a> FileLock<'a> {
+    fn acquire(path: &'a Path) -> io::Result<Self> {
+        static COUNTER: AtomicUsize = AtomicUsize::new(0);
+        let token = format!(
+            "{}.{}.{}",
+            std::process::id(),
+            SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .unwrap_or_default()
+                .as_nanos(),
+            COUNTER.fetch_add(1, Ordering::Relaxed)
+        );
+        // Until the lock file is created, there is nothing for `Drop` to release.
+        let lock = ManuallyDrop::new(Self { path, token });
+
+        let started = SystemTime::now();
+        loop {
+            match OpenOptions::new()
+                .write(true)
+                .create_new(true)
+                .open(path)
+            {
+                Ok(mut file) => {
+                    if let Err(err) = file.write_all(lock.token.as_bytes()) {
+                        let _ = fs::remove_file(path);
+                        return Err(err);
+                    }
+                    return Ok(ManuallyDrop::into_inner(lock));
+                }
+                Err(err) if err.kind() == io::ErrorKind::AlreadyExists => {}
+                Err(err) => return Err(err),
+            }
+
+            let stale = fs::metadata(path)
+                .and_then(|meta| meta.modified())
+                .ok()
+                .and_then(|modified| modified.elapsed().ok())
+                .is_some_and(|age| age > STALE_LOCK_AGE);
+            // If the stale lock file cannot be removed, keep waiting until the timeout.
+            if stale {
+                if let Ok(stale_token) = fs::read(path) {
+                    lock.remove_if_holding(&stale_token);
+                }
+            }
+
+            if started.elapsed().unwrap_or_default() > LOCK_TIMEOUT {
+                return Err(io::Error::new(
+                    io::ErrorKind::TimedOut,
+                    "timed out waiting for lock file",
+                ));
+            }
+            thread::sleep(Duration::from_millis(1));
+        }
+    }
+
+    /// Remove the lock file if it holds `token`.
+    ///
+    /// The lock file is first moved aside, so it cannot be replaced between being
+    /// checked and removed.  If it turns out to hold another token, it is moved back,
+    /// unless a new lock file has been created meanwhile.
+    fn remove_if_holding(&self, token: &[u8]) {
+        let mut aside = OsString::from(self.path);
+        aside.push(".");
+        aside.push(&self.token);
+        let aside = PathBuf::from(aside);
+
+        if fs::rename(self.path, &aside).is_err() {
+            return;
+        }
+        if !fs::read(&aside).is_ok_and(|contents| contents == token) {
+            let _ = fs::hard_link(&aside, self.path);
+        }
+        let _ = fs::remove_file(&aside);
+    }
+}
+
+impl Drop for FileLock<'_> {
+    fn drop(&mut self) {
+        self.remove_if_holding(self.token.as_bytes());
+    }
+}
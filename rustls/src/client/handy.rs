@@ -34,6 +34,7 @@ impl client::ClientSessionStore for NoClientSessionStorage {
 #[cfg(any(feature = "std", feature = "hashbrown"))]
 mod cache {
     use alloc::collections::VecDeque;
+    use alloc::vec::Vec;
     use core::fmt;
 
     use pki_types::ServerName;
@@ -46,6 +47,7 @@ mod cache {
 
     struct ServerData {
         kx_hint: Option<NamedGroup>,
+        key_share_groups: Option<Vec<NamedGroup>>,
 
         // Zero or one TLS1.2 sessions.
         tls12: Option<persist::Tls12ClientSessionValue>,
@@ -58,6 +60,7 @@ mod cache {
         fn default() -> Self {
             Self {
                 kx_hint: None,
+                key_share_groups: None,
                 tls12: None,
                 tls13: VecDeque::with_capacity(MAX_TLS13_TICKETS_PER_SERVER),
             }
@@ -94,6 +97,24 @@ mod cache {
                 servers: Mutex::new::<M>(limited_cache::LimitedCache::new(max_servers)),
             }
         }
+
+        /// Set the groups to send TLS1.3 key shares for, when connecting to `server_name`.
+        ///
+        /// See [`ClientSessionStore::key_share_groups()`].
+        ///
+        /// [`ClientSessionStore::key_share_groups()`]: super::client::ClientSessionStore::key_share_groups
+        pub fn set_key_share_groups(
+            &self,
+            server_name: ServerName<'static>,
+            groups: Vec<NamedGroup>,
+        ) {
+            self.servers
+                .lock()
+                .unwrap()
+                .get_or_insert_default_and_edit(server_name, |data| {
+                    data.key_share_groups = Some(groups)
+                });
+        }
     }
 
     impl super::client::ClientSessionStore for ClientSessionMemoryCache {
@@ -112,6 +133,14 @@ mod cache {
                 .and_then(|sd| sd.kx_hint)
         }
 
+        fn key_share_groups(&self, server_name: &ServerName<'_>) -> Option<Vec<NamedGroup>> {
+            self.servers
+                .lock()
+                .unwrap()
+                .get(server_name)
+                .and_then(|sd| sd.key_share_groups.clone())
+        }
+
         fn set_tls12_session(
             &self,
             _server_name: ServerName<'static>,
@@ -12,14 +12,15 @@ use crate::SupportedCipherSuite;
 use crate::bs_debug;
 use crate::check::inappropriate_handshake_message;
 use crate::client::client_conn::ClientConnectionData;
-use crate::client::common::ClientHelloDetails;
+use crate::client::common::{ClientHelloDetails, GreaseValues};
 use crate::client::ech::EchState;
 use crate::client::{ClientConfig, EchMode, EchStatus, tls13};
 use crate::common_state::{CommonState, HandshakeKind, KxState, State};
 use crate::conn::ConnectionRandoms;
 use crate::crypto::{ActiveKeyExchange, KeyExchangeAlgorithm};
 use crate::enums::{
-    AlertDescription, CertificateType, CipherSuite, ContentType, HandshakeType, ProtocolVersion,
+    AlertDescription, CertificateType, CipherSuite, ContentType, HandshakeType, MaxFragmentLength,
+    ProtocolVersion,
 };
 use crate::error::{Error, PeerIncompatible, PeerMisbehaved};
 use crate::hash_hs::HandshakeHashBuffer;
@@ -37,6 +38,7 @@ use crate::msgs::persist;
 use crate::sync::Arc;
 use crate::tls13::key_schedule::KeyScheduleEarly;
 use crate::verify::ServerCertVerifier;
+use crate::{ExternalPsk, PskKexMode};
 
 pub(super) type NextState<'a> = Box<dyn State<ClientConnectionData> + 'a>;
 pub(super) type NextStateOrError<'a> = Result<NextState<'a>, Error>;
@@ -53,7 +55,7 @@ struct ExpectServerHello {
     //
     // If this is `None` then we do not support early data.
     early_data_key_schedule: Option<KeyScheduleEarly>,
-    offered_key_share: Option<Box<dyn ActiveKeyExchange>>,
+    offered_key_shares: Vec<Box<dyn ActiveKeyExchange>>,
     suite: Option<SupportedCipherSuite>,
     ech_state: Option<EchState>,
 }
@@ -72,6 +74,8 @@ pub(crate) struct ClientHelloInput {
     pub(super) session_id: SessionId,
     pub(super) server_name: ServerName<'static>,
     pub(super) prev_ech_ext: Option<EncryptedClientHello>,
+    /// The external PSKs offered in the most recent `ClientHello`.
+    pub(super) external_psks: Vec<Arc<ExternalPsk>>,
 }
 
 impl ClientHelloInput {
@@ -119,6 +123,10 @@ impl ClientHelloInput {
                 .clone()
                 .unwrap_or_default(),
             crate::rand::random_u16(config.provider.secure_random)?,
+            match config.enable_grease {
+                true => Some(GreaseValues::new(config.provider.secure_random)?),
+                false => None,
+            },
         );
 
         Ok(Self {
@@ -129,6 +137,7 @@ impl ClientHelloInput {
             session_id,
             server_name,
             prev_ech_ext: None,
+            external_psks: Vec::new(),
             config,
         })
     }
@@ -147,14 +156,10 @@ impl ClientHelloInput {
             transcript_buffer.set_client_auth_enabled();
         }
 
-        let key_share = if self.config.needs_key_share() {
-            Some(tls13::initial_key_share(
-                &self.config,
-                &self.server_name,
-                &mut cx.common.kx_state,
-            )?)
+        let key_shares = if self.config.needs_key_share() {
+            tls13::initial_key_shares(&self.config, &self.server_name, &mut cx.common.kx_state)?
         } else {
-            None
+            Vec::new()
         };
 
         let ech_state = match self.config.ech_mode.as_ref() {
@@ -167,7 +172,7 @@ impl ClientHelloInput {
         emit_client_hello_for_retry(
             transcript_buffer,
             None,
-            key_share,
+            key_shares,
             extra_exts,
             None,
             self,
@@ -185,7 +190,7 @@ impl ClientHelloInput {
 fn emit_client_hello_for_retry(
     mut transcript_buffer: HandshakeHashBuffer,
     retryreq: Option<&HelloRetryRequest>,
-    key_share: Option<Box<dyn ActiveKeyExchange>>,
+    key_shares: Vec<Box<dyn ActiveKeyExchange>>,
     extra_exts: ClientExtensionsInput<'static>,
     suite: Option<SupportedCipherSuite>,
     mut input: ClientHelloInput,
@@ -200,6 +205,7 @@ fn emit_client_hello_for_retry(
     let supported_versions = SupportedProtocolVersions {
         tls12: config.supports_version(ProtocolVersion::TLSv1_2) && !forbids_tls12,
         tls13: config.supports_version(ProtocolVersion::TLSv1_3),
+        grease: None,
     };
 
     // should be unreachable thanks to config builder
@@ -231,6 +237,10 @@ fn emit_client_hello_for_retry(
             true => Some(CertificateStatusRequest::build_ocsp()),
             false => None,
         },
+        signed_certificate_timestamp_request: match config.verifier.request_scts() {
+            true => Some(()),
+            false => None,
+        },
         protocols: extra_exts.protocols.clone(),
         ..Default::default()
     });
@@ -243,6 +253,13 @@ fn emit_client_hello_for_retry(
         if let Some(cas_extension) = config.verifier.root_hint_subjects() {
             exts.certificate_authority_names = Some(cas_extension.to_vec());
         }
+
+        // QUIC does not allow post-handshake authentication (RFC9001 section 4.4).
+        if config.enable_post_handshake_auth && !cx.common.is_quic() {
+            exts.post_handshake_auth = Some(());
+        }
+
+        exts.delegated_credential_schemes = config.delegated_credential_schemes();
     }
 
     // Send the ECPointFormat extension only if we are proposing ECDHE
@@ -255,6 +272,13 @@ fn emit_client_hello_for_retry(
         exts.ec_point_formats = Some(SupportedEcPointFormats::default());
     }
 
+    // QUIC packets are not TLS records, so these limits do not apply (and RFC9001
+    // section 8.4 forbids sending max_fragment_length).
+    if !cx.common.is_quic() {
+        exts.record_size_limit = cx.common.record_size_limit;
+        exts.max_fragment_length = config.max_fragment_length;
+    }
+
     exts.server_name = match (ech_state.as_ref(), config.enable_sni) {
         // If we have ECH state we have a "cover name" to send in the outer hello
         // as the SNI domain name. This happens unconditionally so we ignore the
@@ -273,32 +297,49 @@ fn emit_client_hello_for_retry(
         (None, false) => None,
     };
 
-    if let Some(key_share) = &key_share {
+    if !key_shares.is_empty() {
         debug_assert!(supported_versions.tls13);
-        let mut shares = vec![KeyShareEntry::new(key_share.group(), key_share.pub_key())];
+        let mut shares = key_shares
+            .iter()
+            .map(|key_share| KeyShareEntry::new(key_share.group(), key_share.pub_key()))
+            .collect::<Vec<_>>();
 
         if !retryreq
             .map(|rr| rr.key_share.is_some())
             .unwrap_or_default()
         {
             // Only for the initial client hello, or a HRR that does not specify a kx group,
-            // see if we can send a second KeyShare for "free".  We only do this if the same
+            // see if we can send extra KeyShares for "free".  We only do this if the same
             // algorithm is also supported separately by our provider for this version
-            // (`find_kx_group` looks that up).
-            if let Some((component_group, component_share)) =
-                key_share
+            // (`find_kx_group` looks that up), and we're not already sending it.
+            for key_share in &key_shares {
+                if let Some((component_group, component_share)) = key_share
                     .hybrid_component()
                     .filter(|(group, _)| {
                         config
                             .find_kx_group(*group, ProtocolVersion::TLSv1_3)
                             .is_some()
+                            && !shares
+                                .iter()
+                                .any(|share| share.group == *group)
                     })
-            {
-                shares.push(KeyShareEntry::new(component_group, component_share));
+                {
+                    shares.push(KeyShareEntry::new(component_group, component_share));
+                }
+            }
+
+            // RFC8701: a GREASE key share is a single zero byte.
+            if let Some(grease) = &input.hello.grease {
+                shares.insert(0, KeyShareEntry::new(grease.group, &[0][..]));
             }
         }
 
         exts.key_shares = Some(shares);
+    } else if supported_versions.tls13 && config.offers_psk_ke_only() {
+        // "Clients MAY send an empty client_shares vector in order to request
+        //  group selection from the server, at the cost of an additional round
+        //  trip" - RFC8446 4.2.8
+        exts.key_shares = Some(Vec::new());
     }
 
     if let Some(cookie) = retryreq.and_then(|hrr| hrr.cookie.as_ref()) {
@@ -306,11 +347,13 @@ fn emit_client_hello_for_retry(
     }
 
     if supported_versions.tls13 {
-        // We could support PSK_KE here too. Such connections don't
-        // have forward secrecy, and are similar to TLS1.2 resumption.
         exts.preshared_key_modes = Some(PskKeyExchangeModes {
-            psk: false,
-            psk_dhe: true,
+            psk: config
+                .psk_kex_modes
+                .contains(&PskKexMode::PskOnly),
+            psk_dhe: config
+                .psk_kex_modes
+                .contains(&PskKexMode::PskWithDhe),
         });
     }
 
@@ -354,6 +397,12 @@ fn emit_client_hello_for_retry(
     // Do we have a SessionID or ticket cached for this host?
     let tls13_session = prepare_resumption(&input.resuming, &mut exts, suite, cx, config);
 
+    // Offer any external PSKs after the resumption ticket.
+    input.external_psks = match supported_versions.tls13 {
+        true => tls13::prepare_external_psks(config, &mut exts, suite.and_then(|cs| cs.tls13())),
+        false => Vec::new(),
+    };
+
     // Extensions MAY be randomized
     // but they also need to keep the same order as the previous ClientHello
     exts.order_seed = input.hello.extension_order_seed;
@@ -373,6 +422,24 @@ fn emit_client_hello_for_retry(
         cipher_suites.push(CipherSuite::TLS_EMPTY_RENEGOTIATION_INFO_SCSV);
     }
 
+    if let Some(grease) = &input.hello.grease {
+        // GREASE values are sent first in each list.
+        cipher_suites.insert(0, grease.cipher_suite);
+        if let Some(groups) = &mut exts.named_groups {
+            groups.insert(0, grease.group);
+        }
+        if let Some(schemes) = &mut exts.signature_schemes {
+            schemes.insert(0, grease.signature_scheme);
+        }
+        if let Some(versions) = &mut exts.supported_versions {
+            versions.grease = Some(grease.version);
+        }
+        if let Some(protocols) = &mut exts.protocols {
+            protocols.insert(0, ProtocolName::from(grease.alpn.to_be_bytes().to_vec()));
+        }
+        exts.grease_extensions = grease.extensions.to_vec();
+    }
+
     let mut chp_payload = ClientHelloPayload {
         client_version: ProtocolVersion::TLSv1_2,
         random: input.random,
@@ -399,7 +466,13 @@ fn emit_client_hello_for_retry(
         // we need to replace the client hello payload with an ECH client hello payload.
         (EchStatus::NotOffered | EchStatus::Offered, Some(ech_state)) => {
             // Replace the client hello payload with an ECH client hello payload.
-            chp_payload = ech_state.ech_hello(chp_payload, retryreq, &tls13_session)?;
+            chp_payload = ech_state.ech_hello(
+                chp_payload,
+                config.padding,
+                retryreq,
+                &tls13_session,
+                &input.external_psks,
+            )?;
             cx.data.ech_status = EchStatus::Offered;
             // Store the ECH extension in case we need to carry it forward in a subsequent hello.
             input.prev_ech_ext = chp_payload
@@ -418,8 +491,9 @@ fn emit_client_hello_for_retry(
                 // subsequent hello.
                 input.prev_ech_ext = Some(grease_ext);
             }
+            config.padding.apply(&mut chp_payload);
         }
-        _ => {}
+        _ => config.padding.apply(&mut chp_payload),
     }
 
     // Note what extensions we sent.
@@ -435,12 +509,15 @@ fn emit_client_hello_for_retry(
             .take()
             .map(|schedule| (tls13_session.suite(), schedule)),
 
-        // When we're not doing ECH and resuming, then the PSK binder need to be filled in as
-        // normal.
-        (_, Some(tls13_session)) => Some((
-            tls13_session.suite(),
-            tls13::fill_in_psk_binder(&tls13_session, &transcript_buffer, &mut chp),
-        )),
+        // When we're not doing ECH, then any PSK binders need to be filled in as normal.
+        (None, tls13_session) => {
+            let resuming = tls13_session
+                .as_ref()
+                .map(|session| session.value);
+            tls13::fill_in_psk_binders(resuming, &input.external_psks, &transcript_buffer, &mut chp)
+                .zip(resuming)
+                .map(|(schedule, resuming)| (resuming.suite(), schedule))
+        }
 
         // No early key schedule in other cases.
         _ => None,
@@ -506,7 +583,7 @@ fn emit_client_hello_for_retry(
         input,
         transcript_buffer,
         early_data_key_schedule,
-        offered_key_share: key_share,
+        offered_key_shares: key_shares,
         suite,
         ech_state,
     };
@@ -626,6 +703,30 @@ pub(super) fn process_alpn_protocol(
     Ok(())
 }
 
+pub(super) fn process_max_fragment_length(
+    common: &mut CommonState,
+    offered: Option<MaxFragmentLength>,
+    selected: Option<MaxFragmentLength>,
+) -> Result<(), Error> {
+    let Some(selected) = selected else {
+        return Ok(());
+    };
+
+    // "If a client receives a maximum fragment length negotiation response that
+    // differs from the length it requested, it MUST also abort the handshake with an
+    // "illegal_parameter" alert." - RFC6066 section 4.
+    if offered != Some(selected) {
+        return Err(common.send_fatal_alert(
+            AlertDescription::IllegalParameter,
+            PeerMisbehaved::SelectedUnofferedMaxFragmentLength,
+        ));
+    }
+
+    debug!("Max fragment length is {selected:?}");
+    common.set_max_fragment_length(selected);
+    Ok(())
+}
+
 pub(super) fn process_server_cert_type_extension(
     common: &mut CommonState,
     config: &ClientConfig,
@@ -747,6 +848,20 @@ impl State<ClientConnectionData> for ExpectServerHello {
             )?;
         }
 
+        // In TLS1.3 these are instead sent in EncryptedExtensions.
+        if !cx.common.is_tls13() {
+            if let Some(limit) = server_hello.record_size_limit {
+                cx.common
+                    .set_peer_record_size_limit(limit)?;
+            }
+
+            process_max_fragment_length(
+                cx.common,
+                config.max_fragment_length,
+                server_hello.max_fragment_length,
+            )?;
+        }
+
         // If ECPointFormats extension is supplied by the server, it must contain
         // Uncompressed.  But it's allowed to be omitted.
         if let Some(point_fmts) = &server_hello.ec_point_formats {
@@ -812,8 +927,7 @@ impl State<ClientConnectionData> for ExpectServerHello {
                     suite,
                     transcript,
                     self.early_data_key_schedule,
-                    // We always send a key share when TLS 1.3 is enabled.
-                    self.offered_key_share.unwrap(),
+                    self.offered_key_shares,
                     &m,
                     self.ech_state,
                     self.input,
@@ -859,21 +973,27 @@ impl ExpectServerHelloOrHelloRetryRequest {
         cx.common.check_aligned_handshake()?;
 
         // We always send a key share when TLS 1.3 is enabled.
-        let offered_key_share = self.next.offered_key_share.unwrap();
+        let offered_key_shares = self.next.offered_key_shares;
 
         // A retry request is illegal if it contains no cookie and asks for
         // retry of a group we already sent.
         let config = &self.next.input.config;
 
         if let (None, Some(req_group)) = (&hrr.cookie, hrr.key_share) {
-            let offered_hybrid = offered_key_share
-                .hybrid_component()
-                .and_then(|(group_name, _)| {
-                    config.find_kx_group(group_name, ProtocolVersion::TLSv1_3)
-                })
-                .map(|skxg| skxg.name());
+            let offered = offered_key_shares
+                .iter()
+                .any(|key_share| {
+                    let offered_hybrid = key_share
+                        .hybrid_component()
+                        .and_then(|(group_name, _)| {
+                            config.find_kx_group(group_name, ProtocolVersion::TLSv1_3)
+                        })
+                        .map(|skxg| skxg.name());
+
+                    req_group == key_share.group() || Some(req_group) == offered_hybrid
+                });
 
-            if req_group == offered_key_share.group() || Some(req_group) == offered_hybrid {
+            if offered {
                 return Err({
                     cx.common.send_fatal_alert(
                         AlertDescription::IllegalParameter,
@@ -1001,8 +1121,8 @@ impl ExpectServerHelloOrHelloRetryRequest {
             cx.data.early_data.rejected();
         }
 
-        let key_share = match hrr.key_share {
-            Some(group) if group != offered_key_share.group() => {
+        let key_shares = match hrr.key_share {
+            Some(group) => {
                 let Some(skxg) = config.find_kx_group(group, ProtocolVersion::TLSv1_3) else {
                     return Err(cx.common.send_fatal_alert(
                         AlertDescription::IllegalParameter,
@@ -1011,15 +1131,23 @@ impl ExpectServerHelloOrHelloRetryRequest {
                 };
 
                 cx.common.kx_state = KxState::Start(skxg);
-                skxg.start()?
+                // Reuse the share for this group, if we sent one.
+                let key_share = match offered_key_shares
+                    .into_iter()
+                    .find(|key_share| key_share.group() == group)
+                {
+                    Some(key_share) => key_share,
+                    None => skxg.start()?,
+                };
+                vec![key_share]
             }
-            _ => offered_key_share,
+            None => offered_key_shares,
         };
 
         emit_client_hello_for_retry(
             transcript_buffer,
             Some(hrr),
-            Some(key_share),
+            key_shares,
             self.extra_exts,
             Some(cs),
             self.next.input,
@@ -687,6 +687,38 @@ fn hybrid_kx_component_share_not_offered_unless_supported_separately() {
     assert_eq!(key_shares[0].group, NamedGroup::X25519MLKEM768);
 }
 
+#[test]
+fn client_hello_padding_lengths() {
+    use crate::client::ClientHelloPadding;
+
+    assert_eq!(ClientHelloPadding::Disabled.padding_len(300), None);
+
+    let to_length = ClientHelloPadding::ToLength(512);
+    assert_eq!(to_length.padding_len(300), Some(208));
+    assert_eq!(to_length.padding_len(507), Some(1));
+    // too close to fit the extension: overshoot by as little as possible
+    assert_eq!(to_length.padding_len(510), Some(1));
+    assert_eq!(to_length.padding_len(512), None);
+    assert_eq!(to_length.padding_len(600), None);
+
+    let multiple = ClientHelloPadding::ToMultipleOf(256);
+    assert_eq!(multiple.padding_len(300), Some(208));
+    assert_eq!(multiple.padding_len(512), None);
+    // too close to fit the extension: move to the next multiple
+    assert_eq!(multiple.padding_len(510), Some(254));
+    assert_eq!(
+        ClientHelloPadding::ToMultipleOf(2).padding_len(511),
+        Some(1)
+    );
+    assert_eq!(ClientHelloPadding::ToMultipleOf(0).padding_len(300), None);
+
+    let f5 = ClientHelloPadding::F5Workaround;
+    assert_eq!(f5.padding_len(255), None);
+    assert_eq!(f5.padding_len(256), Some(252));
+    assert_eq!(f5.padding_len(510), Some(1));
+    assert_eq!(f5.padding_len(512), None);
+}
+
 fn client_hello_sent_for_config(config: ClientConfig) -> Result<ClientHelloPayload, Error> {
     let mut conn =
         ClientConnection::new(config.into(), ServerName::try_from("localhost").unwrap())?;
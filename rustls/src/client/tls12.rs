@@ -3,7 +3,7 @@ use alloc::boxed::Box;
 use alloc::vec;
 use alloc::vec::Vec;
 
-use pki_types::ServerName;
+use pki_types::{ServerName, UnixTime};
 pub(crate) use server_hello::{TLS12_HANDLER, Tls12Handler};
 use subtle::ConstantTimeEq;
 
@@ -11,12 +11,18 @@ use super::client_conn::ClientConnectionData;
 use super::hs::ClientContext;
 use crate::ConnectionTrafficSecrets;
 use crate::check::{inappropriate_handshake_message, inappropriate_message};
-use crate::client::common::{ClientAuthDetails, ServerCertDetails};
+use crate::client::common::{
+    ClientAuthDetails, ExpectClientCert, ResumeWithClientAuth, ServerCertDetails,
+};
 use crate::client::{ClientConfig, hs};
-use crate::common_state::{CommonState, HandshakeKind, KxState, Side, State};
+use crate::common_state::{
+    CommonState, ExpectSignature, ExpectVerification, HandshakeKind, KxState, ResumeWithSignature,
+    ResumeWithVerification, Side, State,
+};
 use crate::conn::ConnectionRandoms;
 use crate::conn::kernel::{Direction, KernelContext, KernelState};
-use crate::crypto::KeyExchangeAlgorithm;
+use crate::crypto::hash;
+use crate::crypto::{ActiveKeyExchange, KeyExchangeAlgorithm};
 use crate::enums::{AlertDescription, ContentType, HandshakeType, ProtocolVersion};
 use crate::error::{Error, InvalidMessage, PeerIncompatible, PeerMisbehaved};
 use crate::hash_hs::HandshakeHash;
@@ -30,11 +36,11 @@ use crate::msgs::handshake::{
 };
 use crate::msgs::message::{Message, MessagePayload};
 use crate::msgs::persist;
-use crate::sign::Signer;
+use crate::sct::SctList;
 use crate::suites::{PartiallyExtractedSecrets, SupportedCipherSuite};
 use crate::sync::Arc;
 use crate::tls12::{self, ConnectionSecrets, Tls12CipherSuite};
-use crate::verify::{self, DigitallySignedStruct};
+use crate::verify::{self, CertificateVerification, DigitallySignedStruct};
 
 mod server_hello {
     use core::fmt;
@@ -219,6 +225,9 @@ mod server_hello {
                 transcript,
                 suite,
                 may_send_cert_status,
+                server_scts: server_hello
+                    .signed_certificate_timestamps
+                    .clone(),
                 must_issue_new_ticket,
             }))
         }
@@ -250,6 +259,7 @@ struct ExpectCertificate {
     transcript: HandshakeHash,
     suite: &'static Tls12CipherSuite,
     may_send_cert_status: bool,
+    server_scts: Option<SctList>,
     must_issue_new_ticket: bool,
 }
 
@@ -280,10 +290,11 @@ impl State<ClientConnectionData> for ExpectCertificate {
                 transcript: self.transcript,
                 suite: self.suite,
                 server_cert_chain,
+                server_scts: self.server_scts,
                 must_issue_new_ticket: self.must_issue_new_ticket,
             }))
         } else {
-            let server_cert = ServerCertDetails::new(server_cert_chain, vec![]);
+            let server_cert = ServerCertDetails::new(server_cert_chain, vec![], self.server_scts);
 
             Ok(Box::new(ExpectServerKx {
                 config: self.config,
@@ -315,6 +326,7 @@ struct ExpectCertificateStatusOrServerKx<'m> {
     transcript: HandshakeHash,
     suite: &'static Tls12CipherSuite,
     server_cert_chain: CertificateChain<'m>,
+    server_scts: Option<SctList>,
     must_issue_new_ticket: bool,
 }
 
@@ -340,7 +352,11 @@ impl State<ClientConnectionData> for ExpectCertificateStatusOrServerKx<'_> {
                 using_ems: self.using_ems,
                 transcript: self.transcript,
                 suite: self.suite,
-                server_cert: ServerCertDetails::new(self.server_cert_chain, vec![]),
+                server_cert: ServerCertDetails::new(
+                    self.server_cert_chain,
+                    vec![],
+                    self.server_scts,
+                ),
                 must_issue_new_ticket: self.must_issue_new_ticket,
             })
             .handle(cx, m),
@@ -357,6 +373,7 @@ impl State<ClientConnectionData> for ExpectCertificateStatusOrServerKx<'_> {
                 transcript: self.transcript,
                 suite: self.suite,
                 server_cert_chain: self.server_cert_chain,
+                server_scts: self.server_scts,
                 must_issue_new_ticket: self.must_issue_new_ticket,
             })
             .handle(cx, m),
@@ -382,6 +399,7 @@ impl State<ClientConnectionData> for ExpectCertificateStatusOrServerKx<'_> {
             transcript: self.transcript,
             suite: self.suite,
             server_cert_chain: self.server_cert_chain.into_owned(),
+            server_scts: self.server_scts,
             must_issue_new_ticket: self.must_issue_new_ticket,
         })
     }
@@ -397,6 +415,7 @@ struct ExpectCertificateStatus<'a> {
     transcript: HandshakeHash,
     suite: &'static Tls12CipherSuite,
     server_cert_chain: CertificateChain<'a>,
+    server_scts: Option<SctList>,
     must_issue_new_ticket: bool,
 }
 
@@ -422,7 +441,11 @@ impl State<ClientConnectionData> for ExpectCertificateStatus<'_> {
             &server_cert_ocsp_response
         );
 
-        let server_cert = ServerCertDetails::new(self.server_cert_chain, server_cert_ocsp_response);
+        let server_cert = ServerCertDetails::new(
+            self.server_cert_chain,
+            server_cert_ocsp_response,
+            self.server_scts,
+        );
 
         Ok(Box::new(ExpectServerKx {
             config: self.config,
@@ -449,6 +472,7 @@ impl State<ClientConnectionData> for ExpectCertificateStatus<'_> {
             transcript: self.transcript,
             suite: self.suite,
             server_cert_chain: self.server_cert_chain.into_owned(),
+            server_scts: self.server_scts,
             must_issue_new_ticket: self.must_issue_new_ticket,
         })
     }
@@ -587,17 +611,9 @@ fn emit_client_kx(
 
 fn emit_certverify(
     transcript: &mut HandshakeHash,
-    signer: &dyn Signer,
+    body: DigitallySignedStruct,
     common: &mut CommonState,
-) -> Result<(), Error> {
-    let message = transcript
-        .take_handshake_buf()
-        .ok_or_else(|| Error::General("Expected transcript".to_owned()))?;
-
-    let scheme = signer.scheme();
-    let sig = signer.sign(&message)?;
-    let body = DigitallySignedStruct::new(scheme, sig);
-
+) {
     let m = Message {
         version: ProtocolVersion::TLSv1_2,
         payload: MessagePayload::handshake(HandshakeMessagePayload(
@@ -607,7 +623,6 @@ fn emit_certverify(
 
     transcript.add_message(&m);
     common.send_msg(m, false);
-    Ok(())
 }
 
 fn emit_ccs(common: &mut CommonState) {
@@ -755,7 +770,7 @@ struct ExpectCertificateRequest<'a> {
 impl State<ClientConnectionData> for ExpectCertificateRequest<'_> {
     fn handle<'m>(
         mut self: Box<Self>,
-        _cx: &mut ClientContext<'_>,
+        cx: &mut ClientContext<'_>,
         m: Message<'m>,
     ) -> hs::NextStateOrError<'m>
     where
@@ -777,17 +792,11 @@ impl State<ClientConnectionData> for ExpectCertificateRequest<'_> {
 
         const NO_CONTEXT: Option<Vec<u8>> = None; // TLS 1.2 doesn't use a context.
         let no_compression = None; // or compression
-        let client_auth = ClientAuthDetails::resolve(
-            self.config
-                .client_auth_cert_resolver
-                .as_ref(),
-            Some(&certreq.canames),
-            &certreq.sigschemes,
-            NO_CONTEXT,
-            no_compression,
-        );
-
-        Ok(Box::new(ExpectServerDone {
+        let resolver = self
+            .config
+            .client_auth_cert_resolver
+            .clone();
+        let next = ExpectServerDone {
             config: self.config,
             resuming_session: self.resuming_session,
             session_id: self.session_id,
@@ -796,11 +805,21 @@ impl State<ClientConnectionData> for ExpectCertificateRequest<'_> {
             using_ems: self.using_ems,
             transcript: self.transcript,
             suite: self.suite,
-            server_cert: self.server_cert,
+            server_cert: self.server_cert.into_owned(),
             server_kx: self.server_kx,
-            client_auth: Some(client_auth),
+            client_auth: None,
             must_issue_new_ticket: self.must_issue_new_ticket,
-        }))
+        };
+
+        ExpectClientCert::resolve(
+            resolver.as_ref(),
+            Some(&certreq.canames),
+            &certreq.sigschemes,
+            NO_CONTEXT,
+            no_compression,
+            next,
+            cx,
+        )
     }
 
     fn into_owned(self: Box<Self>) -> hs::NextState<'static> {
@@ -866,8 +885,6 @@ impl State<ClientConnectionData> for ExpectServerDone<'_> {
         trace!("Server cert is {:?}", st.server_cert.cert_chain);
         debug!("Server DNS name is {:?}", st.server_name);
 
-        let suite = st.suite;
-
         // 1. Verify the cert chain.
         // 2. Verify that the top certificate signed their kx.
         // 3. If doing client auth, send our Certificate.
@@ -889,6 +906,21 @@ impl State<ClientConnectionData> for ExpectServerDone<'_> {
 
         let now = st.config.current_time()?;
 
+        if st.config.verifier.is_async() {
+            let pending = CertificateVerification::new(
+                end_entity,
+                intermediates,
+                Some(st.server_name.clone()),
+                st.server_cert.ocsp_response.clone(),
+                now,
+            );
+            let next = AcceptServerCert {
+                state: st.into_static(),
+                now,
+            };
+            return Ok(ExpectVerification::new(pending, next, cx.common));
+        }
+
         let cert_verified = st
             .config
             .verifier
@@ -903,18 +935,58 @@ impl State<ClientConnectionData> for ExpectServerDone<'_> {
                 cx.common
                     .send_cert_verify_error_alert(err)
             })?;
+        st.complete(cx, now, cert_verified)
+    }
+
+    fn into_owned(self: Box<Self>) -> hs::NextState<'static> {
+        Box::new(self.into_static())
+    }
+}
+
+impl ResumeWithClientAuth for ExpectServerDone<'static> {
+    fn resume(
+        mut self,
+        _cx: &mut ClientContext<'_>,
+        client_auth: ClientAuthDetails,
+    ) -> hs::NextStateOrError<'static> {
+        self.client_auth = Some(client_auth);
+        Ok(Box::new(self))
+    }
+}
+
+impl ExpectServerDone<'_> {
+    /// Continue the handshake once the server's certificate chain is verified.
+    fn complete(
+        mut self,
+        cx: &mut ClientContext<'_>,
+        now: UnixTime,
+        cert_verified: verify::ServerCertVerified,
+    ) -> hs::NextStateOrError<'static> {
+        let suite = self.suite;
+        let (end_entity, _) = self
+            .server_cert
+            .cert_chain
+            .split_first()
+            .ok_or(Error::NoCertificatesPresented)?;
+
+        self.server_cert
+            .verify_scts(self.config.verifier.as_ref(), now)
+            .map_err(|err| {
+                cx.common
+                    .send_cert_verify_error_alert(err)
+            })?;
 
         // 2.
         // Build up the contents of the signed message.
         // It's ClientHello.random || ServerHello.random || ServerKeyExchange.params
         let sig_verified = {
             let mut message = Vec::new();
-            message.extend_from_slice(&st.randoms.client);
-            message.extend_from_slice(&st.randoms.server);
-            message.extend_from_slice(&st.server_kx.kx_params);
+            message.extend_from_slice(&self.randoms.client);
+            message.extend_from_slice(&self.randoms.server);
+            message.extend_from_slice(&self.server_kx.kx_params);
 
             // Check the signature is compatible with the ciphersuite.
-            let sig = &st.server_kx.kx_sig;
+            let sig = &self.server_kx.kx_sig;
             if !SupportedCipherSuite::from(suite)
                 .usable_for_signature_algorithm(sig.scheme.algorithm())
             {
@@ -926,7 +998,7 @@ impl State<ClientConnectionData> for ExpectServerDone<'_> {
                 return Err(PeerMisbehaved::SignedKxWithWrongAlgorithm.into());
             }
 
-            st.config
+            self.config
                 .verifier
                 .verify_tls12_signature(&message, end_entity, sig)
                 .map_err(|err| {
@@ -934,33 +1006,33 @@ impl State<ClientConnectionData> for ExpectServerDone<'_> {
                         .send_cert_verify_error_alert(err)
                 })?
         };
-        cx.common.peer_certificates = Some(st.server_cert.cert_chain.into_owned());
+        cx.common.peer_certificates = Some(self.server_cert.cert_chain.into_owned());
 
         // 3.
-        if let Some(client_auth) = &st.client_auth {
+        if let Some(client_auth) = &self.client_auth {
             let certs = match client_auth {
                 ClientAuthDetails::Empty { .. } => CertificateChain::default(),
                 ClientAuthDetails::Verify { certkey, .. } => {
                     CertificateChain(certkey.cert_chain.clone())
                 }
             };
-            emit_certificate(&mut st.transcript, certs, cx.common);
+            emit_certificate(&mut self.transcript, certs, cx.common);
         }
 
         // 4a.
         let kx_params = tls12::decode_kx_params::<ServerKeyExchangeParams>(
-            st.suite.kx,
+            self.suite.kx,
             cx.common,
-            &st.server_kx.kx_params,
+            &self.server_kx.kx_params,
         )?;
         let maybe_skxg = match &kx_params {
-            ServerKeyExchangeParams::Ecdh(ecdh) => st
+            ServerKeyExchangeParams::Ecdh(ecdh) => self
                 .config
                 .find_kx_group(ecdh.curve_params.named_group, ProtocolVersion::TLSv1_2),
             ServerKeyExchangeParams::Dh(dh) => {
                 let ffdhe_group = dh.as_ffdhe_group();
 
-                st.config
+                self.config
                     .provider
                     .kx_groups
                     .iter()
@@ -978,27 +1050,112 @@ impl State<ClientConnectionData> for ExpectServerDone<'_> {
         let kx = skxg.start()?;
 
         // 4b.
-        let mut transcript = st.transcript;
-        emit_client_kx(&mut transcript, st.suite.kx, cx.common, kx.pub_key());
+        let mut transcript = self.transcript;
+        emit_client_kx(&mut transcript, self.suite.kx, cx.common, kx.pub_key());
         // Note: EMS handshake hash only runs up to ClientKeyExchange.
-        let ems_seed = st
+        let ems_seed = self
             .using_ems
             .then(|| transcript.current_hash());
 
         // 4c.
-        if let Some(ClientAuthDetails::Verify { signer, .. }) = &st.client_auth {
-            emit_certverify(&mut transcript, signer.as_ref(), cx.common)?;
+        let next = CompleteClientFlight {
+            config: self.config,
+            resuming_session: self.resuming_session,
+            session_id: self.session_id,
+            server_name: self.server_name,
+            randoms: self.randoms,
+            using_ems: self.using_ems,
+            transcript,
+            suite,
+            kx,
+            peer_pub_key: kx_params.pub_key().to_vec(),
+            ems_seed,
+            must_issue_new_ticket: self.must_issue_new_ticket,
+            cert_verified,
+            sig_verified,
+        };
+
+        match &self.client_auth {
+            Some(ClientAuthDetails::Verify { signer, .. }) => {
+                let mut next = next;
+                let message = next
+                    .transcript
+                    .take_handshake_buf()
+                    .ok_or_else(|| Error::General("Expected transcript".to_owned()))?;
+                ExpectSignature::sign(signer.as_ref(), message, next, cx)
+            }
+            _ => next.emit(cx, None),
+        }
+    }
+
+    fn into_static(self) -> ExpectServerDone<'static> {
+        ExpectServerDone {
+            config: self.config,
+            resuming_session: self.resuming_session,
+            session_id: self.session_id,
+            server_name: self.server_name,
+            randoms: self.randoms,
+            using_ems: self.using_ems,
+            transcript: self.transcript,
+            suite: self.suite,
+            server_cert: self.server_cert.into_owned(),
+            server_kx: self.server_kx,
+            client_auth: self.client_auth,
+            must_issue_new_ticket: self.must_issue_new_ticket,
+        }
+    }
+}
+
+/// Continues the handshake once the application has verified the server's certificates.
+struct AcceptServerCert {
+    state: ExpectServerDone<'static>,
+    now: UnixTime,
+}
+
+impl ResumeWithVerification<ClientConnectionData> for AcceptServerCert {
+    fn resume(self, cx: &mut ClientContext<'_>) -> hs::NextStateOrError<'static> {
+        self.state
+            .complete(cx, self.now, verify::ServerCertVerified::assertion())
+    }
+}
+
+/// Our first flight, from the optional CertificateVerify onwards.
+struct CompleteClientFlight {
+    config: Arc<ClientConfig>,
+    resuming_session: Option<persist::Tls12ClientSessionValue>,
+    session_id: SessionId,
+    server_name: ServerName<'static>,
+    randoms: ConnectionRandoms,
+    using_ems: bool,
+    transcript: HandshakeHash,
+    suite: &'static Tls12CipherSuite,
+    kx: Box<dyn ActiveKeyExchange>,
+    peer_pub_key: Vec<u8>,
+    ems_seed: Option<hash::Output>,
+    must_issue_new_ticket: bool,
+    cert_verified: verify::ServerCertVerified,
+    sig_verified: verify::HandshakeSignatureValid,
+}
+
+impl CompleteClientFlight {
+    fn emit(
+        mut self,
+        cx: &mut ClientContext<'_>,
+        certificate_verify: Option<DigitallySignedStruct>,
+    ) -> hs::NextStateOrError<'static> {
+        if let Some(cv) = certificate_verify {
+            emit_certverify(&mut self.transcript, cv, cx.common);
         }
 
         // 4d. Derive secrets.
         // An alert at this point will be sent in plaintext.  That must happen
         // prior to the CCS, or else the peer will try to decrypt it.
         let secrets = ConnectionSecrets::from_key_exchange(
-            kx,
-            kx_params.pub_key(),
-            ems_seed,
-            st.randoms,
-            suite,
+            self.kx,
+            &self.peer_pub_key,
+            self.ems_seed,
+            self.randoms,
+            self.suite,
         )
         .map_err(|err| {
             cx.common
@@ -1010,7 +1167,7 @@ impl State<ClientConnectionData> for ExpectServerDone<'_> {
         emit_ccs(cx.common);
 
         // 4f. Now commit secrets.
-        st.config.key_log.log(
+        self.config.key_log.log(
             "CLIENT_RANDOM",
             &secrets.randoms.client,
             secrets.master_secret(),
@@ -1022,53 +1179,46 @@ impl State<ClientConnectionData> for ExpectServerDone<'_> {
             .start_encrypting();
 
         // 5.
-        emit_finished(&secrets, &mut transcript, cx.common);
+        emit_finished(&secrets, &mut self.transcript, cx.common);
 
-        if st.must_issue_new_ticket {
+        if self.must_issue_new_ticket {
             Ok(Box::new(ExpectNewTicket {
-                config: st.config,
+                config: self.config,
                 secrets,
-                resuming_session: st.resuming_session,
-                session_id: st.session_id,
-                server_name: st.server_name,
-                using_ems: st.using_ems,
-                transcript,
+                resuming_session: self.resuming_session,
+                session_id: self.session_id,
+                server_name: self.server_name,
+                using_ems: self.using_ems,
+                transcript: self.transcript,
                 resuming: false,
-                cert_verified,
-                sig_verified,
+                cert_verified: self.cert_verified,
+                sig_verified: self.sig_verified,
             }))
         } else {
             Ok(Box::new(ExpectCcs {
-                config: st.config,
+                config: self.config,
                 secrets,
-                resuming_session: st.resuming_session,
-                session_id: st.session_id,
-                server_name: st.server_name,
-                using_ems: st.using_ems,
-                transcript,
+                resuming_session: self.resuming_session,
+                session_id: self.session_id,
+                server_name: self.server_name,
+                using_ems: self.using_ems,
+                transcript: self.transcript,
                 ticket: None,
                 resuming: false,
-                cert_verified,
-                sig_verified,
+                cert_verified: self.cert_verified,
+                sig_verified: self.sig_verified,
             }))
         }
     }
+}
 
-    fn into_owned(self: Box<Self>) -> hs::NextState<'static> {
-        Box::new(ExpectServerDone {
-            config: self.config,
-            resuming_session: self.resuming_session,
-            session_id: self.session_id,
-            server_name: self.server_name,
-            randoms: self.randoms,
-            using_ems: self.using_ems,
-            transcript: self.transcript,
-            suite: self.suite,
-            server_cert: self.server_cert.into_owned(),
-            server_kx: self.server_kx,
-            client_auth: self.client_auth,
-            must_issue_new_ticket: self.must_issue_new_ticket,
-        })
+impl ResumeWithSignature<ClientConnectionData> for CompleteClientFlight {
+    fn resume(
+        self,
+        cx: &mut ClientContext<'_>,
+        signature: DigitallySignedStruct,
+    ) -> hs::NextStateOrError<'static> {
+        self.emit(cx, Some(signature))
     }
 }
 
@@ -3,22 +3,27 @@ use alloc::vec;
 use alloc::vec::Vec;
 use core::fmt;
 
-use pki_types::ServerName;
+use pki_types::{CertificateDer, ServerName, UnixTime};
 use subtle::ConstantTimeEq;
 
 use super::client_conn::ClientConnectionData;
 use super::hs::{ClientContext, ClientHelloInput, ClientSessionValue};
 use crate::check::inappropriate_handshake_message;
-use crate::client::common::{ClientAuthDetails, ClientHelloDetails, ServerCertDetails};
+use crate::client::common::{
+    ClientAuthDetails, ClientHelloDetails, ExpectClientCert, ResumeWithClientAuth,
+    ServerCertDetails,
+};
 use crate::client::ech::{self, EchState, EchStatus};
 use crate::client::{ClientConfig, ClientSessionStore, hs};
 use crate::common_state::{
-    CommonState, HandshakeFlightTls13, HandshakeKind, KxState, Protocol, Side, State,
+    CommonState, ExpectSignature, ExpectVerification, HandshakeFlightTls13, HandshakeKind, KxState,
+    Protocol, ResumeWithSignature, ResumeWithVerification, Side, State,
 };
 use crate::conn::ConnectionRandoms;
 use crate::conn::kernel::{Direction, KernelContext, KernelState};
-use crate::crypto::hash::Hash;
+use crate::crypto::hash::{self, Hash};
 use crate::crypto::{ActiveKeyExchange, SharedSecret};
+use crate::delegated_credential::DelegatedCredential;
 use crate::enums::{
     AlertDescription, ContentType, HandshakeType, ProtocolVersion, SignatureScheme,
 };
@@ -46,10 +51,11 @@ use crate::tls13::key_schedule::{
     KeyScheduleTraffic,
 };
 use crate::tls13::{
-    Tls13CipherSuite, construct_client_verify_message, construct_server_verify_message,
+    Tls13CipherSuite, VerifyMessage, construct_client_verify_message,
+    construct_server_verify_message,
 };
-use crate::verify::{self, DigitallySignedStruct};
-use crate::{ConnectionTrafficSecrets, KeyLog, compress, crypto};
+use crate::verify::{self, CertificateVerification, DigitallySignedStruct};
+use crate::{ConnectionTrafficSecrets, ExternalPsk, KeyLog, PskKexMode, compress, crypto};
 
 // Extensions we expect in plaintext in the ServerHello.
 static ALLOWED_PLAINTEXT_EXTS: &[ExtensionType] = &[
@@ -83,32 +89,41 @@ impl Tls13Handler for Handler {
         suite: &'static Tls13CipherSuite,
         mut transcript: HandshakeHash,
         early_data_key_schedule: Option<KeyScheduleEarly>,
-        our_key_share: Box<dyn ActiveKeyExchange>,
+        our_key_shares: Vec<Box<dyn ActiveKeyExchange>>,
         server_hello_msg: &Message<'_>,
         ech_state: Option<EchState>,
         input: ClientHelloInput,
     ) -> hs::NextStateOrError<'static> {
         validate_server_hello(cx.common, server_hello)?;
 
-        let their_key_share = server_hello
-            .key_share
-            .as_ref()
-            .ok_or_else(|| {
-                cx.common.send_fatal_alert(
-                    AlertDescription::MissingExtension,
-                    PeerMisbehaved::MissingKeyShare,
-                )
-            })?;
-
         let ClientHelloInput {
             config,
             resuming,
             mut sent_tls13_fake_ccs,
             mut hello,
             server_name,
+            external_psks,
             ..
         } = input;
 
+        // The server omits its key share if it chose PSK_KE.
+        let their_key_share = match &server_hello.key_share {
+            Some(share) => Some(share),
+            None if server_hello.preshared_key.is_some()
+                && config
+                    .psk_kex_modes
+                    .contains(&PskKexMode::PskOnly) =>
+            {
+                None
+            }
+            None => {
+                return Err(cx.common.send_fatal_alert(
+                    AlertDescription::MissingExtension,
+                    PeerMisbehaved::MissingKeyShare,
+                ));
+            }
+        };
+
         let mut resuming_session = match resuming {
             Some(Retrieved {
                 value: ClientSessionValue::Tls13(value),
@@ -117,16 +132,60 @@ impl Tls13Handler for Handler {
             _ => None,
         };
 
-        let our_key_share = KeyExchangeChoice::new(&config, cx, our_key_share, their_key_share)
-            .map_err(|_| {
-                cx.common.send_fatal_alert(
-                    AlertDescription::IllegalParameter,
-                    PeerMisbehaved::WrongGroupForKeyShare,
-                )
-            })?;
+        let our_key_share = match their_key_share {
+            Some(their_key_share) => Some(
+                KeyExchangeChoice::new(&config, cx, our_key_shares, their_key_share).map_err(
+                    |_| {
+                        cx.common.send_fatal_alert(
+                            AlertDescription::IllegalParameter,
+                            PeerMisbehaved::WrongGroupForKeyShare,
+                        )
+                    },
+                )?,
+            ),
+            None => None,
+        };
+
+        // PSKs are offered in this order: any resumption ticket, then the external PSKs.
+        let external_psk_offset = usize::from(early_data_key_schedule.is_some());
+        let mut external_psk = false;
 
         let key_schedule_pre_handshake = match (server_hello.preshared_key, early_data_key_schedule)
         {
+            (Some(selected_psk), _)
+                if usize::from(selected_psk) >= external_psk_offset
+                    && !external_psks.is_empty() =>
+            {
+                let Some(psk) = external_psks.get(usize::from(selected_psk) - external_psk_offset)
+                else {
+                    return Err({
+                        cx.common.send_fatal_alert(
+                            AlertDescription::IllegalParameter,
+                            PeerMisbehaved::SelectedInvalidPsk,
+                        )
+                    });
+                };
+
+                if suite
+                    .can_resume_from(psk.suite())
+                    .is_none()
+                {
+                    return Err({
+                        cx.common.send_fatal_alert(
+                            AlertDescription::IllegalParameter,
+                            PeerMisbehaved::SelectedPskWithIncompatibleCipherSuite,
+                        )
+                    });
+                }
+
+                debug!("Using external PSK");
+                // Early data is only offered with a resumption ticket.
+                cx.data.early_data.rejected();
+                cx.common.early_traffic = false;
+                resuming_session.take();
+                external_psk = true;
+                KeySchedulePreHandshake::from(KeyScheduleEarly::new(suite, psk.secret()))
+            }
             (Some(selected_psk), Some(early_key_schedule)) => {
                 match &resuming_session {
                     Some(resuming) => {
@@ -160,7 +219,7 @@ impl Tls13Handler for Handler {
                         }
 
                         debug!("Resuming using PSK");
-                        // The key schedule has been initialized and set in fill_in_psk_binder()
+                        // The key schedule has been initialized and set in fill_in_psk_binders()
                     }
                     _ => {
                         return Err(PeerMisbehaved::SelectedUnofferedPsk.into());
@@ -178,15 +237,23 @@ impl Tls13Handler for Handler {
             }
         };
 
-        cx.common.kx_state.complete();
-        let shared_secret = our_key_share
-            .complete(&their_key_share.payload.0)
-            .map_err(|err| {
-                cx.common
-                    .send_fatal_alert(AlertDescription::IllegalParameter, err)
-            })?;
-
-        let mut key_schedule = key_schedule_pre_handshake.into_handshake(shared_secret);
+        let mut key_schedule = match (our_key_share, their_key_share) {
+            (Some(our_key_share), Some(their_key_share)) => {
+                cx.common.kx_state.complete();
+                let shared_secret = our_key_share
+                    .complete(&their_key_share.payload.0)
+                    .map_err(|err| {
+                        cx.common
+                            .send_fatal_alert(AlertDescription::IllegalParameter, err)
+                    })?;
+                key_schedule_pre_handshake.into_handshake(shared_secret)
+            }
+            _ => {
+                debug!("Server chose PSK_KE: no key exchange");
+                cx.common.kx_state = KxState::None;
+                key_schedule_pre_handshake.into_handshake_without_kx()
+            }
+        };
 
         // If we have ECH state, check that the server accepted our offer.
         if let Some(ech_state) = ech_state {
@@ -225,10 +292,12 @@ impl Tls13Handler for Handler {
         }
 
         // Remember what KX group the server liked for next time.
-        config
-            .resumption
-            .store
-            .set_kx_hint(server_name.clone(), their_key_share.group);
+        if let Some(their_key_share) = their_key_share {
+            config
+                .resumption
+                .store
+                .set_kx_hint(server_name.clone(), their_key_share.group);
+        }
 
         // If we change keying when a subsequent handshake message is being joined,
         // the two halves will have different record layer protections.  Disallow this.
@@ -249,6 +318,7 @@ impl Tls13Handler for Handler {
         Ok(Box::new(ExpectEncryptedExtensions {
             config,
             resuming_session,
+            external_psk,
             server_name,
             randoms,
             suite,
@@ -270,7 +340,7 @@ pub(crate) trait Tls13Handler: fmt::Debug + Sealed + Send + Sync {
         suite: &'static Tls13CipherSuite,
         transcript: HandshakeHash,
         early_data_key_schedule: Option<KeyScheduleEarly>,
-        our_key_share: Box<dyn ActiveKeyExchange>,
+        our_key_shares: Vec<Box<dyn ActiveKeyExchange>>,
         server_hello_msg: &Message<'_>,
         ech_state: Option<EchState>,
         input: ClientHelloInput,
@@ -283,34 +353,39 @@ enum KeyExchangeChoice {
 }
 
 impl KeyExchangeChoice {
-    /// Decide between `our_key_share` or `our_key_share.hybrid_component()`
+    /// Decide between one of `our_key_shares`, or the hybrid component of one,
     /// based on the selection of the server expressed in `their_key_share`.
     fn new(
         config: &Arc<ClientConfig>,
         cx: &mut ClientContext<'_>,
-        our_key_share: Box<dyn ActiveKeyExchange>,
+        mut our_key_shares: Vec<Box<dyn ActiveKeyExchange>>,
         their_key_share: &KeyShareEntry,
     ) -> Result<Self, ()> {
-        if our_key_share.group() == their_key_share.group {
-            return Ok(Self::Whole(our_key_share));
-        }
-
-        let (component_group, _) = our_key_share
-            .hybrid_component()
+        // correct the record for the benefit of accuracy of
+        // `negotiated_key_exchange_group()`
+        let actual_skxg = config
+            .find_kx_group(their_key_share.group, ProtocolVersion::TLSv1_3)
             .ok_or(())?;
 
-        if component_group != their_key_share.group {
-            return Err(());
+        if let Some(index) = our_key_shares
+            .iter()
+            .position(|share| share.group() == their_key_share.group)
+        {
+            cx.common.kx_state = KxState::Start(actual_skxg);
+            return Ok(Self::Whole(our_key_shares.swap_remove(index)));
         }
 
-        // correct the record for the benefit of accuracy of
-        // `negotiated_key_exchange_group()`
-        let actual_skxg = config
-            .find_kx_group(component_group, ProtocolVersion::TLSv1_3)
+        let index = our_key_shares
+            .iter()
+            .position(|share| {
+                share
+                    .hybrid_component()
+                    .is_some_and(|(component, _)| component == their_key_share.group)
+            })
             .ok_or(())?;
-        cx.common.kx_state = KxState::Start(actual_skxg);
 
-        Ok(Self::Component(our_key_share))
+        cx.common.kx_state = KxState::Start(actual_skxg);
+        Ok(Self::Component(our_key_shares.swap_remove(index)))
     }
 
     fn complete(self, peer_pub_key: &[u8]) -> Result<SharedSecret, Error> {
@@ -335,50 +410,109 @@ fn validate_server_hello(
     Ok(())
 }
 
-pub(super) fn initial_key_share(
+pub(super) fn initial_key_shares(
     config: &ClientConfig,
     server_name: &ServerName<'_>,
     kx_state: &mut KxState,
-) -> Result<Box<dyn ActiveKeyExchange>, Error> {
-    let group = config
-        .resumption
-        .store
-        .kx_hint(server_name)
-        .and_then(|group_name| config.find_kx_group(group_name, ProtocolVersion::TLSv1_3))
-        .unwrap_or_else(|| {
-            config
-                .provider
-                .kx_groups
-                .iter()
-                .copied()
-                .next()
-                .expect("No kx groups configured")
-        });
-
-    *kx_state = KxState::Start(group);
-    group.start()
+) -> Result<Vec<Box<dyn ActiveKeyExchange>>, Error> {
+    let store = &config.resumption.store;
+    let usable = |name| config.find_kx_group(name, ProtocolVersion::TLSv1_3);
+
+    let (groups, limit) = match store
+        .key_share_groups(server_name)
+        .map(|names| {
+            names
+                .into_iter()
+                .filter_map(usable)
+                .collect::<Vec<_>>()
+        })
+        .filter(|groups| !groups.is_empty())
+    {
+        Some(groups) => (groups, usize::MAX),
+        None => (
+            store
+                .kx_hint(server_name)
+                .and_then(usable)
+                .into_iter()
+                .chain(
+                    config
+                        .provider
+                        .kx_groups
+                        .iter()
+                        .copied(),
+                )
+                .collect(),
+            config.initial_key_shares.max(1),
+        ),
+    };
+
+    let mut shares: Vec<Box<dyn ActiveKeyExchange>> = Vec::new();
+    for group in groups {
+        if shares.len() == limit {
+            break;
+        }
+
+        // Skip groups we already have a share for, including as the
+        // component of a hybrid share.
+        let name = group.name();
+        if shares.iter().any(|share| {
+            share.group() == name
+                || share
+                    .hybrid_component()
+                    .is_some_and(|(component, _)| component == name)
+        }) {
+            continue;
+        }
+
+        if shares.is_empty() {
+            *kx_state = KxState::Start(group);
+        }
+        shares.push(group.start()?);
+    }
+
+    assert!(!shares.is_empty(), "No kx groups configured");
+    Ok(shares)
 }
 
 /// This implements the horrifying TLS1.3 hack where PSK binders have a
 /// data dependency on the message they are contained within.
-pub(super) fn fill_in_psk_binder(
-    resuming: &persist::Tls13ClientSessionValue,
+///
+/// Binders are filled in for `resuming` (if any) and then `external_psks`,
+/// in the order they were offered.  Returns the key schedule for `resuming`.
+pub(super) fn fill_in_psk_binders(
+    resuming: Option<&persist::Tls13ClientSessionValue>,
+    external_psks: &[Arc<ExternalPsk>],
     transcript: &HandshakeHashBuffer,
     hmp: &mut HandshakeMessagePayload<'_>,
-) -> KeyScheduleEarly {
-    // We need to know the hash function of the suite we're trying to resume into.
-    let suite = resuming.suite();
-    let suite_hash = suite.common.hash_provider;
-
+) -> Option<KeyScheduleEarly> {
     // The binder is calculated over the clienthello, but doesn't include itself or its
     // length, or the length of its container.
     let binder_plaintext = hmp.encoding_for_binder_signing();
-    let handshake_hash = transcript.hash_given(suite_hash, &binder_plaintext);
 
-    // Run a fake key_schedule to simulate what the server will do if it chooses
-    // to resume.
-    let key_schedule = KeyScheduleEarly::new(suite, resuming.secret());
-    let real_binder = key_schedule.resumption_psk_binder_key_and_sign_verify_data(&handshake_hash);
+    // We need to know the hash function of the suite we're trying to resume into.
+    let resumption = resuming.map(|resuming| {
+        let suite = resuming.suite();
+        let handshake_hash = transcript.hash_given(suite.common.hash_provider, &binder_plaintext);
+
+        // Run a fake key_schedule to simulate what the server will do if it chooses
+        // to resume.
+        let key_schedule = KeyScheduleEarly::new(suite, resuming.secret());
+        let binder = key_schedule.resumption_psk_binder_key_and_sign_verify_data(&handshake_hash);
+        (key_schedule, binder)
+    });
+
+    let mut real_binders = Vec::with_capacity(1 + external_psks.len());
+    if let Some((_, binder)) = &resumption {
+        real_binders.push(binder.as_ref().to_vec());
+    }
+
+    for psk in external_psks {
+        let suite = psk.suite();
+        let handshake_hash = transcript.hash_given(suite.common.hash_provider, &binder_plaintext);
+        let binder = KeyScheduleEarly::new(suite, psk.secret())
+            .external_psk_binder_key_and_sign_verify_data(psk.is_imported(), &handshake_hash);
+        real_binders.push(binder.as_ref().to_vec());
+    }
 
     if let HandshakePayload::ClientHello(ch) = &mut hmp.0 {
         if let Some(PresharedKeyOffer {
@@ -386,17 +520,19 @@ pub(super) fn fill_in_psk_binder(
             identities,
         }) = &mut ch.preshared_key_offer
         {
-            // the caller of this function must have set up the desired identity, and a
-            // matching (dummy) binder; or else the binder we compute here will be incorrect.
-            // See `prepare_resumption()`.
-            debug_assert_eq!(identities.len(), 1);
-            debug_assert_eq!(binders.len(), 1);
-            debug_assert_eq!(binders[0].as_ref().len(), real_binder.as_ref().len());
-            binders[0] = PresharedKeyBinder::from(real_binder.as_ref().to_vec());
+            // the caller of this function must have set up the desired identities, and
+            // matching (dummy) binders; or else the binders we compute here will be incorrect.
+            // See `prepare_resumption()` and `prepare_external_psks()`.
+            debug_assert_eq!(identities.len(), real_binders.len());
+            debug_assert_eq!(binders.len(), real_binders.len());
+            for (binder, real_binder) in binders.iter_mut().zip(real_binders) {
+                debug_assert_eq!(binder.as_ref().len(), real_binder.len());
+                *binder = PresharedKeyBinder::from(real_binder);
+            }
         }
     };
 
-    key_schedule
+    resumption.map(|(key_schedule, _)| key_schedule)
 }
 
 pub(super) fn prepare_resumption(
@@ -437,6 +573,67 @@ pub(super) fn prepare_resumption(
     exts.preshared_key_offer = Some(psk_offer);
 }
 
+/// Adds the usable `config.external_psks` to the PSK offer in `exts`, after
+/// any resumption ticket, and returns them.
+///
+/// `suite` is `None` for the initial ClientHello, or `Some` if we're retrying
+/// in response to a HelloRetryRequest: then only PSKs usable with that suite
+/// are offered.
+pub(super) fn prepare_external_psks(
+    config: &ClientConfig,
+    exts: &mut ClientExtensions<'_>,
+    suite: Option<&'static Tls13CipherSuite>,
+) -> Vec<Arc<ExternalPsk>> {
+    let usable = |psk: &ExternalPsk| match suite {
+        Some(suite) => suite
+            .can_resume_from(psk.suite())
+            .is_some(),
+        None => config
+            .provider
+            .cipher_suites
+            .iter()
+            .filter_map(|suite| suite.tls13())
+            .any(|suite| {
+                suite
+                    .can_resume_from(psk.suite())
+                    .is_some()
+            }),
+    };
+
+    let psks = config
+        .external_psks
+        .iter()
+        .filter(|psk| usable(psk))
+        .cloned()
+        .collect::<Vec<_>>();
+
+    for psk in &psks {
+        // "For identities established externally, an obfuscated_ticket_age of 0 SHOULD be
+        //  used" - RFC8446 4.2.11
+        let identity = PresharedKeyIdentity::new(psk.identity().to_vec(), 0);
+        // A dummy binder, which is filled in by `fill_in_psk_binders()`.
+        let binder = vec![
+            0u8;
+            psk.suite()
+                .common
+                .hash_provider
+                .output_len()
+        ];
+
+        match &mut exts.preshared_key_offer {
+            Some(offer) => {
+                offer.identities.push(identity);
+                offer
+                    .binders
+                    .push(PresharedKeyBinder::from(binder));
+            }
+            None => exts.preshared_key_offer = Some(PresharedKeyOffer::new(identity, binder)),
+        }
+    }
+
+    psks
+}
+
 pub(super) fn derive_early_traffic_secret(
     key_log: &dyn KeyLog,
     cx: &mut ClientContext<'_>,
@@ -503,6 +700,7 @@ fn validate_encrypted_extensions(
 struct ExpectEncryptedExtensions {
     config: Arc<ClientConfig>,
     resuming_session: Option<persist::Tls13ClientSessionValue>,
+    external_psk: bool,
     server_name: ServerName<'static>,
     randoms: ConnectionRandoms,
     suite: &'static Tls13CipherSuite,
@@ -547,6 +745,16 @@ impl State<ClientConnectionData> for ExpectEncryptedExtensions {
             exts.server_certificate_type.as_ref(),
         )?;
 
+        if let Some(limit) = exts.record_size_limit {
+            cx.common
+                .set_peer_record_size_limit(limit)?;
+        }
+        hs::process_max_fragment_length(
+            cx.common,
+            self.config.max_fragment_length,
+            exts.max_fragment_length,
+        )?;
+
         let ech_retry_configs = match (cx.data.ech_status, &exts.encrypted_client_hello_ack) {
             // If we didn't offer ECH, or ECH was accepted, but the server sent an ECH encrypted
             // extension with retry configs, we must error.
@@ -620,7 +828,30 @@ impl State<ClientConnectionData> for ExpectEncryptedExtensions {
                     ech_retry_configs,
                 }))
             }
-            _ => {
+            None if self.external_psk => {
+                if exts.early_data_ack.is_some() {
+                    return Err(PeerMisbehaved::EarlyDataExtensionWithoutResumption.into());
+                }
+                cx.common.handshake_kind = Some(HandshakeKind::ExternalPsk);
+
+                // The server authenticated itself with the PSK, so there
+                // is no certificate to verify.
+                let cert_verified = verify::ServerCertVerified::assertion();
+                let sig_verified = verify::HandshakeSignatureValid::assertion();
+                Ok(Box::new(ExpectFinished {
+                    config: self.config,
+                    server_name: self.server_name,
+                    randoms: self.randoms,
+                    suite: self.suite,
+                    transcript: self.transcript,
+                    key_schedule: self.key_schedule,
+                    client_auth: None,
+                    cert_verified,
+                    sig_verified,
+                    ech_retry_configs,
+                }))
+            }
+            None => {
                 if exts.early_data_ack.is_some() {
                     return Err(PeerMisbehaved::EarlyDataExtensionWithoutResumption.into());
                 }
@@ -749,6 +980,17 @@ struct ExpectCertificateOrCompressedCertificate {
     ech_retry_configs: Option<Vec<EchConfigPayload>>,
 }
 
+impl ResumeWithClientAuth for ExpectCertificateOrCompressedCertificate {
+    fn resume(
+        mut self,
+        _cx: &mut ClientContext<'_>,
+        client_auth: ClientAuthDetails,
+    ) -> hs::NextStateOrError<'static> {
+        self.client_auth = Some(client_auth);
+        Ok(Box::new(self))
+    }
+}
+
 impl State<ClientConnectionData> for ExpectCertificateOrCompressedCertificate {
     fn handle<'m>(
         self: Box<Self>,
@@ -941,43 +1183,55 @@ impl State<ClientConnectionData> for ExpectCertificateRequest {
             })
             .cloned();
 
-        let client_auth = ClientAuthDetails::resolve(
-            self.config
-                .client_auth_cert_resolver
-                .as_ref(),
-            certreq
-                .extensions
-                .authority_names
-                .as_deref(),
-            &compat_sigschemes,
-            Some(certreq.context.0.clone()),
-            compat_compressor,
-        );
-
-        Ok(if self.offered_cert_compression {
-            Box::new(ExpectCertificateOrCompressedCertificate {
-                config: self.config,
-                server_name: self.server_name,
-                randoms: self.randoms,
-                suite: self.suite,
-                transcript: self.transcript,
-                key_schedule: self.key_schedule,
-                client_auth: Some(client_auth),
-                ech_retry_configs: self.ech_retry_configs,
-            })
+        let resolver = self
+            .config
+            .client_auth_cert_resolver
+            .clone();
+        let canames = certreq
+            .extensions
+            .authority_names
+            .as_deref();
+        let auth_context = Some(certreq.context.0.clone());
+        if self.offered_cert_compression {
+            ExpectClientCert::resolve(
+                resolver.as_ref(),
+                canames,
+                &compat_sigschemes,
+                auth_context,
+                compat_compressor,
+                ExpectCertificateOrCompressedCertificate {
+                    config: self.config,
+                    server_name: self.server_name,
+                    randoms: self.randoms,
+                    suite: self.suite,
+                    transcript: self.transcript,
+                    key_schedule: self.key_schedule,
+                    client_auth: None,
+                    ech_retry_configs: self.ech_retry_configs,
+                },
+                cx,
+            )
         } else {
-            Box::new(ExpectCertificate {
-                config: self.config,
-                server_name: self.server_name,
-                randoms: self.randoms,
-                suite: self.suite,
-                transcript: self.transcript,
-                key_schedule: self.key_schedule,
-                client_auth: Some(client_auth),
-                message_already_in_transcript: false,
-                ech_retry_configs: self.ech_retry_configs,
-            })
-        })
+            ExpectClientCert::resolve(
+                resolver.as_ref(),
+                canames,
+                &compat_sigschemes,
+                auth_context,
+                compat_compressor,
+                ExpectCertificate {
+                    config: self.config,
+                    server_name: self.server_name,
+                    randoms: self.randoms,
+                    suite: self.suite,
+                    transcript: self.transcript,
+                    key_schedule: self.key_schedule,
+                    client_auth: None,
+                    message_already_in_transcript: false,
+                    ech_retry_configs: self.ech_retry_configs,
+                },
+                cx,
+            )
+        }
     }
 
     fn into_owned(self: Box<Self>) -> hs::NextState<'static> {
@@ -1100,6 +1354,17 @@ struct ExpectCertificate {
     ech_retry_configs: Option<Vec<EchConfigPayload>>,
 }
 
+impl ResumeWithClientAuth for ExpectCertificate {
+    fn resume(
+        mut self,
+        _cx: &mut ClientContext<'_>,
+        client_auth: ClientAuthDetails,
+    ) -> hs::NextStateOrError<'static> {
+        self.client_auth = Some(client_auth);
+        Ok(Box::new(self))
+    }
+}
+
 impl State<ClientConnectionData> for ExpectCertificate {
     fn handle<'m>(
         mut self: Box<Self>,
@@ -1126,13 +1391,36 @@ impl State<ClientConnectionData> for ExpectCertificate {
             ));
         }
 
+        // A delegated credential is only allowed for the end-entity certificate,
+        // and only if we offered to accept one.
+        let delegated_credential = cert_chain.end_entity_delegated_credential();
+        if cert_chain
+            .entries
+            .iter()
+            .skip(1)
+            .any(|e| {
+                e.extensions
+                    .delegated_credential
+                    .is_some()
+            })
+            || (delegated_credential.is_some() && !self.config.enable_delegated_credentials)
+        {
+            return Err(cx.common.send_fatal_alert(
+                AlertDescription::UnsupportedExtension,
+                PeerMisbehaved::UnsolicitedCertExtension,
+            ));
+        }
+
         let end_entity_ocsp = cert_chain.end_entity_ocsp().to_vec();
-        let server_cert = ServerCertDetails::new(
+        let end_entity_scts = cert_chain.end_entity_scts();
+        let mut server_cert = ServerCertDetails::new(
             cert_chain
                 .into_certificate_chain()
                 .into_owned(),
             end_entity_ocsp,
+            end_entity_scts,
         );
+        server_cert.delegated_credential = delegated_credential;
 
         Ok(Box::new(ExpectCertificateVerify {
             config: self.config,
@@ -1167,14 +1455,14 @@ struct ExpectCertificateVerify<'a> {
 
 impl State<ClientConnectionData> for ExpectCertificateVerify<'_> {
     fn handle<'m>(
-        mut self: Box<Self>,
+        self: Box<Self>,
         cx: &mut ClientContext<'_>,
         m: Message<'m>,
     ) -> hs::NextStateOrError<'m>
     where
         Self: 'm,
     {
-        let cert_verify = require_handshake_msg!(
+        require_handshake_msg!(
             m,
             HandshakeType::CertificateVerify,
             HandshakePayload::CertificateVerify
@@ -1191,6 +1479,22 @@ impl State<ClientConnectionData> for ExpectCertificateVerify<'_> {
 
         let now = self.config.current_time()?;
 
+        if self.config.verifier.is_async() {
+            let pending = CertificateVerification::new(
+                end_entity,
+                intermediates,
+                Some(self.server_name.clone()),
+                self.server_cert.ocsp_response.clone(),
+                now,
+            );
+            let next = AcceptServerCert {
+                state: self.into_static(),
+                message: m.into_owned(),
+                now,
+            };
+            return Ok(ExpectVerification::new(pending, next, cx.common));
+        }
+
         let cert_verified = self
             .config
             .verifier
@@ -1205,24 +1509,66 @@ impl State<ClientConnectionData> for ExpectCertificateVerify<'_> {
                 cx.common
                     .send_cert_verify_error_alert(err)
             })?;
+        self.complete(cx, &m, now, cert_verified)
+    }
 
-        // 2. Verify their signature on the handshake.
-        let handshake_hash = self.transcript.current_hash();
-        let sig_verified = self
-            .config
-            .verifier
-            .verify_tls13_signature(
-                construct_server_verify_message(&handshake_hash).as_ref(),
-                end_entity,
-                cert_verify,
-            )
+    fn into_owned(self: Box<Self>) -> hs::NextState<'static> {
+        Box::new(self.into_static())
+    }
+}
+
+impl ExpectCertificateVerify<'_> {
+    /// Continue the handshake once the server's certificate chain is verified.
+    fn complete(
+        mut self,
+        cx: &mut ClientContext<'_>,
+        m: &Message<'_>,
+        now: UnixTime,
+        cert_verified: verify::ServerCertVerified,
+    ) -> hs::NextStateOrError<'static> {
+        let cert_verify = require_handshake_msg!(
+            m,
+            HandshakeType::CertificateVerify,
+            HandshakePayload::CertificateVerify
+        )?;
+        let (end_entity, _) = self
+            .server_cert
+            .cert_chain
+            .split_first()
+            .ok_or(Error::NoCertificatesPresented)?;
+
+        self.server_cert
+            .verify_scts(self.config.verifier.as_ref(), now)
             .map_err(|err| {
                 cx.common
                     .send_cert_verify_error_alert(err)
             })?;
 
+        // 2. Verify their signature on the handshake, made either by the certificate's
+        //    key or the key of a delegated credential.
+        let handshake_hash = self.transcript.current_hash();
+        let message = construct_server_verify_message(&handshake_hash);
+        let sig_verified = match &self.server_cert.delegated_credential {
+            Some(credential) => verify_delegated_signature(
+                &self.config,
+                end_entity,
+                credential,
+                now,
+                &message,
+                cert_verify,
+            ),
+            None => self
+                .config
+                .verifier
+                .verify_tls13_signature(message.as_ref(), end_entity, cert_verify),
+        }
+        .map_err(|err| {
+            cx.common
+                .send_cert_verify_error_alert(err)
+        })?;
+
         cx.common.peer_certificates = Some(self.server_cert.cert_chain.into_owned());
-        self.transcript.add_message(&m);
+        self.transcript.add_message(m);
 
         Ok(Box::new(ExpectFinished {
             config: self.config,
@@ -1238,8 +1584,8 @@ impl State<ClientConnectionData> for ExpectCertificateVerify<'_> {
         }))
     }
 
-    fn into_owned(self: Box<Self>) -> hs::NextState<'static> {
-        Box::new(ExpectCertificateVerify {
+    fn into_static(self) -> ExpectCertificateVerify<'static> {
+        ExpectCertificateVerify {
             config: self.config,
             server_name: self.server_name,
             randoms: self.randoms,
@@ -1249,8 +1595,55 @@ impl State<ClientConnectionData> for ExpectCertificateVerify<'_> {
             server_cert: self.server_cert.into_owned(),
             client_auth: self.client_auth,
             ech_retry_configs: self.ech_retry_configs,
-        })
+        }
+    }
+}
+
+/// Continues the handshake once the application has verified the server's certificates.
+struct AcceptServerCert {
+    state: ExpectCertificateVerify<'static>,
+    message: Message<'static>,
+    now: UnixTime,
+}
+
+impl ResumeWithVerification<ClientConnectionData> for AcceptServerCert {
+    fn resume(self, cx: &mut ClientContext<'_>) -> hs::NextStateOrError<'static> {
+        self.state.complete(
+            cx,
+            &self.message,
+            self.now,
+            verify::ServerCertVerified::assertion(),
+        )
+    }
+}
+
+/// Verify a delegated credential and the server's signature made with its key (RFC9345).
+fn verify_delegated_signature(
+    config: &ClientConfig,
+    end_entity: &CertificateDer<'_>,
+    credential: &DelegatedCredential,
+    now: UnixTime,
+    message: &VerifyMessage,
+    cert_verify: &DigitallySignedStruct,
+) -> Result<verify::HandshakeSignatureValid, Error> {
+    // "the dc_cert_verify_algorithm [must be] one of the signature schemes advertised in the
+    // client's "delegated_credential" extension", and "the CertificateVerify message [must
+    // use] the dc_cert_verify_algorithm" - RFC9345 section 4.2
+    let offered = config
+        .delegated_credential_schemes()
+        .unwrap_or_default();
+    if !offered.contains(&credential.dc_cert_verify_algorithm())
+        || cert_verify.scheme != credential.dc_cert_verify_algorithm()
+    {
+        return Err(PeerMisbehaved::InvalidDelegatedCredential.into());
     }
+
+    config
+        .verifier
+        .verify_delegated_credential(end_entity, credential, now)?;
+    config
+        .verifier
+        .verify_tls13_signature_with_delegated_credential(message.as_ref(), credential, cert_verify)
 }
 
 fn emit_compressed_certificate_tls13(
@@ -1386,6 +1779,7 @@ impl State<ClientConnectionData> for ExpectFinished {
         }
 
         let mut flight = HandshakeFlightTls13::new(&mut st.transcript);
+        let mut certificate_verify = None;
 
         /* Send our authentication/finished messages.  These are still encrypted
          * with our handshake keys. */
@@ -1421,34 +1815,97 @@ impl State<ClientConnectionData> for ExpectFinished {
                     } else {
                         emit_certificate_tls13(&mut flight, Some(&certkey), auth_context);
                     }
-                    emit_certverify_tls13(&mut flight, signer.as_ref())?;
+                    let message =
+                        construct_client_verify_message(&flight.transcript.current_hash());
+                    certificate_verify = Some((signer, message.as_ref().to_vec()));
                 }
             }
         }
 
-        let (key_schedule_pre_finished, verify_data) = st
+        let next = CompleteClientFlight {
+            flight: flight.suspend(),
+            config: st.config,
+            server_name: st.server_name,
+            randoms: st.randoms,
+            suite: st.suite,
+            transcript: st.transcript,
+            key_schedule: st.key_schedule,
+            hash_after_handshake,
+            cert_verified: st.cert_verified,
+            sig_verified: st.sig_verified,
+            fin_verified: fin,
+            ech_retry_configs: st.ech_retry_configs,
+        };
+        match certificate_verify {
+            Some((signer, message)) => ExpectSignature::sign(&*signer, message, next, cx),
+            None => next.emit(cx, None),
+        }
+    }
+
+    fn into_owned(self: Box<Self>) -> hs::NextState<'static> {
+        self
+    }
+}
+
+/// Our final handshake flight, from the CertificateVerify (if any) onwards.
+struct CompleteClientFlight {
+    flight: Vec<u8>,
+    config: Arc<ClientConfig>,
+    server_name: ServerName<'static>,
+    randoms: ConnectionRandoms,
+    suite: &'static Tls13CipherSuite,
+    transcript: HandshakeHash,
+    key_schedule: KeyScheduleHandshake,
+    hash_after_handshake: hash::Output,
+    cert_verified: verify::ServerCertVerified,
+    sig_verified: verify::HandshakeSignatureValid,
+    fin_verified: verify::FinishedMessageVerified,
+    ech_retry_configs: Option<Vec<EchConfigPayload>>,
+}
+
+impl CompleteClientFlight {
+    fn emit(
+        mut self,
+        cx: &mut ClientContext<'_>,
+        certificate_verify: Option<DigitallySignedStruct>,
+    ) -> hs::NextStateOrError<'static> {
+        let mut flight = HandshakeFlightTls13::resume(&mut self.transcript, self.flight);
+        if let Some(cv) = certificate_verify {
+            flight.add(HandshakeMessagePayload(
+                HandshakePayload::CertificateVerify(cv),
+            ));
+        }
+
+        let (key_schedule_pre_finished, verify_data) = self
             .key_schedule
             .into_pre_finished_client_traffic(
-                hash_after_handshake,
+                self.hash_after_handshake,
                 flight.transcript.current_hash(),
-                &*st.config.key_log,
-                &st.randoms.client,
+                &*self.config.key_log,
+                &self.randoms.client,
             );
 
         emit_finished_tls13(&mut flight, &verify_data);
         flight.finish(cx.common);
 
+        // Post-handshake authentication is performed over the transcript so far.
+        let post_handshake_auth_transcript =
+            match self.config.enable_post_handshake_auth && !cx.common.is_quic() {
+                true => Some(self.transcript.clone()),
+                false => None,
+            };
+
         /* We're now sure this server supports TLS1.3.  But if we run out of TLS1.3 tickets
          * when connecting to it again, we definitely don't want to attempt a TLS1.2 resumption. */
-        st.config
+        self.config
             .resumption
             .store
-            .remove_tls12_session(&st.server_name);
+            .remove_tls12_session(&self.server_name);
 
         /* Now move to our application traffic keys. */
         cx.common.check_aligned_handshake()?;
         let (key_schedule, resumption) =
-            key_schedule_pre_finished.into_traffic(cx.common, st.transcript.current_hash());
+            key_schedule_pre_finished.into_traffic(cx.common, self.transcript.current_hash());
         cx.common
             .start_traffic(&mut cx.sendable_plaintext);
 
@@ -1456,19 +1913,20 @@ impl State<ClientConnectionData> for ExpectFinished {
         // sending an alert and returning an error (potentially with retry configs) if the server
         // did not accept our ECH offer.
         if cx.data.ech_status == EchStatus::Rejected {
-            return Err(ech::fatal_alert_required(st.ech_retry_configs, cx.common));
+            return Err(ech::fatal_alert_required(self.ech_retry_configs, cx.common));
         }
 
         let st = ExpectTraffic {
-            config: st.config.clone(),
-            session_storage: st.config.resumption.store.clone(),
-            server_name: st.server_name,
-            suite: st.suite,
+            config: self.config.clone(),
+            session_storage: self.config.resumption.store.clone(),
+            server_name: self.server_name,
+            suite: self.suite,
             key_schedule,
             resumption,
-            _cert_verified: st.cert_verified,
-            _sig_verified: st.sig_verified,
-            _fin_verified: fin,
+            post_handshake_auth_transcript,
+            _cert_verified: self.cert_verified,
+            _sig_verified: self.sig_verified,
+            _fin_verified: self.fin_verified,
         };
 
         Ok(match cx.common.is_quic() {
@@ -1476,9 +1934,15 @@ impl State<ClientConnectionData> for ExpectFinished {
             false => Box::new(st),
         })
     }
+}
 
-    fn into_owned(self: Box<Self>) -> hs::NextState<'static> {
-        self
+impl ResumeWithSignature<ClientConnectionData> for CompleteClientFlight {
+    fn resume(
+        self,
+        cx: &mut ClientContext<'_>,
+        signature: DigitallySignedStruct,
+    ) -> hs::NextStateOrError<'static> {
+        self.emit(cx, Some(signature))
     }
 }
 
@@ -1492,6 +1956,8 @@ struct ExpectTraffic {
     suite: &'static Tls13CipherSuite,
     key_schedule: KeyScheduleTraffic,
     resumption: KeyScheduleResumption,
+    /// The handshake transcript, if we offered post-handshake authentication.
+    post_handshake_auth_transcript: Option<HandshakeHash>,
     _cert_verified: verify::ServerCertVerified,
     _sig_verified: verify::HandshakeSignatureValid,
     _fin_verified: verify::FinishedMessageVerified,
@@ -1586,6 +2052,98 @@ impl ExpectTraffic {
             .update_decrypter(common);
         Ok(())
     }
+
+    fn handle_certificate_request(
+        &mut self,
+        cx: &mut ClientContext<'_>,
+        m: &Message<'_>,
+    ) -> Result<(), Error> {
+        let certreq = require_handshake_msg!(
+            m,
+            HandshakeType::CertificateRequest,
+            HandshakePayload::CertificateRequestTls13
+        )?;
+        debug!("Got post-handshake CertificateRequest {certreq:?}");
+
+        // Each post-handshake authentication starts from the end of the handshake,
+        // rather than following any earlier ones.
+        let Some(mut transcript) = self
+            .post_handshake_auth_transcript
+            .clone()
+        else {
+            return Err(cx.common.send_fatal_alert(
+                AlertDescription::UnexpectedMessage,
+                PeerMisbehaved::UnsolicitedPostHandshakeCertificateRequest,
+            ));
+        };
+
+        // "The certificate_request_context ... MUST be unique within the scope of
+        //  this connection" - RFC8446 4.3.2.  An empty one is only for use in
+        //  the handshake.
+        if certreq.context.0.is_empty() {
+            return Err(cx.common.send_fatal_alert(
+                AlertDescription::DecodeError,
+                InvalidMessage::InvalidCertRequest,
+            ));
+        }
+
+        let compat_sigschemes = certreq
+            .extensions
+            .signature_algorithms
+            .as_deref()
+            .unwrap_or_default()
+            .iter()
+            .cloned()
+            .filter(SignatureScheme::supported_in_tls13)
+            .collect::<Vec<SignatureScheme>>();
+
+        if compat_sigschemes.is_empty() {
+            return Err(cx.common.send_fatal_alert(
+                AlertDescription::HandshakeFailure,
+                PeerIncompatible::NoCertificateRequestSignatureSchemesInCommon,
+            ));
+        }
+
+        let client_auth = ClientAuthDetails::resolve(
+            self.config
+                .client_auth_cert_resolver
+                .as_ref(),
+            certreq
+                .extensions
+                .authority_names
+                .as_deref(),
+            &compat_sigschemes,
+            Some(certreq.context.0.clone()),
+            None,
+        );
+
+        transcript.add_message(m);
+        let mut flight = HandshakeFlightTls13::new(&mut transcript);
+
+        match client_auth {
+            ClientAuthDetails::Empty {
+                auth_context_tls13: auth_context,
+            } => {
+                emit_certificate_tls13(&mut flight, None, auth_context);
+            }
+            ClientAuthDetails::Verify {
+                certkey,
+                signer,
+                auth_context_tls13: auth_context,
+                ..
+            } => {
+                emit_certificate_tls13(&mut flight, Some(&certkey), auth_context);
+                emit_certverify_tls13(&mut flight, signer.as_ref())?;
+            }
+        }
+
+        let verify_data = self
+            .key_schedule
+            .sign_post_handshake_client_finish(&flight.transcript.current_hash());
+        emit_finished_tls13(&mut flight, &verify_data);
+        flight.finish(cx.common);
+        Ok(())
+    }
 }
 
 impl State<ClientConnectionData> for ExpectTraffic {
@@ -1609,6 +2167,10 @@ impl State<ClientConnectionData> for ExpectTraffic {
                 parsed: HandshakeMessagePayload(HandshakePayload::KeyUpdate(key_update)),
                 ..
             } => self.handle_key_update(cx.common, &key_update)?,
+            MessagePayload::Handshake {
+                parsed: HandshakeMessagePayload(HandshakePayload::CertificateRequestTls13(_)),
+                ..
+            } => self.handle_certificate_request(cx, &m)?,
             payload => {
                 return Err(inappropriate_handshake_message(
                     &payload,
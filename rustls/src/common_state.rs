@@ -3,9 +3,15 @@ use alloc::vec::Vec;
 
 use pki_types::CertificateDer;
 
+use crate::check::inappropriate_message;
+use crate::client::ClientCertRequest;
 use crate::conn::kernel::KernelState;
 use crate::crypto::SupportedKxGroup;
-use crate::enums::{AlertDescription, ContentType, HandshakeType, ProtocolVersion};
+use crate::crypto::signer::{CertifiedKey, Signer};
+use crate::enums::{
+    AlertDescription, ContentType, HandshakeType, MaxFragmentLength, ProtocolVersion,
+    SignatureScheme,
+};
 use crate::error::{Error, InvalidMessage, PeerMisbehaved};
 use crate::hash_hs::HandshakeHash;
 use crate::log::{debug, error, warn};
@@ -13,7 +19,7 @@ use crate::msgs::alert::AlertMessagePayload;
 use crate::msgs::base::Payload;
 use crate::msgs::codec::Codec;
 use crate::msgs::enums::{AlertLevel, KeyUpdateRequest};
-use crate::msgs::fragmenter::MessageFragmenter;
+use crate::msgs::fragmenter::{MIN_RECORD_SIZE_LIMIT, MessageFragmenter};
 use crate::msgs::handshake::{CertificateChain, HandshakeMessagePayload, ProtocolName};
 use crate::msgs::message::{
     Message, MessagePayload, OutboundChunks, OutboundOpaqueMessage, OutboundPlainMessage,
@@ -21,9 +27,11 @@ use crate::msgs::message::{
 };
 use crate::record_layer::PreEncryptAction;
 use crate::suites::{PartiallyExtractedSecrets, SupportedCipherSuite};
+use crate::sync::Arc;
 use crate::tls12::ConnectionSecrets;
 use crate::unbuffered::{EncryptError, InsufficientSizeError};
 use crate::vecbuf::ChunkVecBuffer;
+use crate::verify::{CertificateVerification, ClientCertVerifier, DigitallySignedStruct};
 use crate::{quic, record_layer};
 
 /// Connection state common to both client and server connections.
@@ -48,6 +56,12 @@ pub struct CommonState {
     pub(crate) has_seen_eof: bool,
     pub(crate) peer_certificates: Option<CertificateChain<'static>>,
     message_fragmenter: MessageFragmenter,
+    /// The `record_size_limit` we advertise, if any.
+    pub(crate) record_size_limit: Option<u16>,
+    /// The `record_size_limit` the peer advertised, once negotiated.
+    peer_record_size_limit: Option<u16>,
+    /// The negotiated `max_fragment_length`, if any.
+    max_fragment_length: Option<MaxFragmentLength>,
     pub(crate) received_plaintext: ChunkVecBuffer,
     pub(crate) sendable_tls: ChunkVecBuffer,
     queued_key_update_message: Option<Vec<u8>>,
@@ -60,6 +74,12 @@ pub struct CommonState {
     pub(crate) refresh_traffic_keys_pending: bool,
     pub(crate) fips: bool,
     pub(crate) tls13_tickets_received: u32,
+    /// Whether the application can make handshake signatures: true for unbuffered connections.
+    pub(crate) async_signing: bool,
+    /// Whether the handshake is paused until the application verifies the peer's certificates.
+    pub(crate) awaiting_verification: bool,
+    /// Whether the handshake is paused until the application chooses a client certificate.
+    pub(crate) awaiting_client_cert: bool,
 }
 
 impl CommonState {
@@ -83,6 +103,9 @@ impl CommonState {
             has_seen_eof: false,
             peer_certificates: None,
             message_fragmenter: MessageFragmenter::default(),
+            record_size_limit: None,
+            peer_record_size_limit: None,
+            max_fragment_length: None,
             received_plaintext: ChunkVecBuffer::new(Some(DEFAULT_RECEIVED_PLAINTEXT_LIMIT)),
             sendable_tls: ChunkVecBuffer::new(Some(DEFAULT_BUFFER_LIMIT)),
             queued_key_update_message: None,
@@ -93,6 +116,9 @@ impl CommonState {
             refresh_traffic_keys_pending: false,
             fips: false,
             tls13_tickets_received: 0,
+            async_signing: false,
+            awaiting_verification: false,
+            awaiting_client_cert: false,
         }
     }
 
@@ -188,6 +214,27 @@ impl CommonState {
         self.handshake_kind
     }
 
+    /// Retrieves the record size limit advertised by the peer, as described in [RFC 8449].
+    ///
+    /// This is `None` unless both peers sent the `record_size_limit` extension.
+    /// Records sent on this connection are no larger than this limit.
+    ///
+    /// [RFC 8449]: https://datatracker.ietf.org/doc/html/rfc8449
+    pub fn peer_record_size_limit(&self) -> Option<u16> {
+        self.peer_record_size_limit
+    }
+
+    /// Retrieves the maximum fragment length negotiated with the peer, as described in
+    /// [RFC 6066 section 4].
+    ///
+    /// This is `None` unless the client requested a limit, and the server agreed to it.
+    /// Records in both directions are no larger than this limit.
+    ///
+    /// [RFC 6066 section 4]: https://datatracker.ietf.org/doc/html/rfc6066#section-4
+    pub fn max_fragment_length(&self) -> Option<MaxFragmentLength> {
+        self.max_fragment_length
+    }
+
     pub(crate) fn is_tls13(&self) -> bool {
         matches!(self.negotiated_version, Some(ProtocolVersion::TLSv1_3))
     }
@@ -653,6 +700,76 @@ impl CommonState {
             .set_max_fragment_size(new)
     }
 
+    pub(crate) fn set_record_size_limit(&mut self, limit: Option<u16>) -> Result<(), Error> {
+        if limit.is_some_and(|limit| limit < MIN_RECORD_SIZE_LIMIT) {
+            return Err(Error::BadMaxFragmentSize);
+        }
+        self.record_size_limit = limit;
+        Ok(())
+    }
+
+    /// Honour the `record_size_limit` the peer sent.
+    ///
+    /// This must be called once the protocol version is known.
+    pub(crate) fn set_peer_record_size_limit(&mut self, limit: u16) -> Result<(), Error> {
+        if limit < MIN_RECORD_SIZE_LIMIT {
+            return Err(self.send_fatal_alert(
+                AlertDescription::IllegalParameter,
+                PeerMisbehaved::InvalidRecordSizeLimit,
+            ));
+        }
+
+        self.message_fragmenter
+            .limit_payload_len(self.record_payload_len(limit));
+        self.peer_record_size_limit = Some(limit);
+        Ok(())
+    }
+
+    /// Honour the negotiated `max_fragment_length`.
+    ///
+    /// The value must be known, and agreed by both peers.
+    pub(crate) fn set_max_fragment_length(&mut self, max_fragment_length: MaxFragmentLength) {
+        if let Some(len) = max_fragment_length.payload_len() {
+            self.message_fragmenter
+                .limit_payload_len(len);
+            self.max_fragment_length = Some(max_fragment_length);
+        }
+    }
+
+    /// The largest record payload we accept from the peer, in a record that
+    /// was `protected` or not.
+    ///
+    /// This is `None` unless we advertised a `record_size_limit` and the peer
+    /// acknowledged it, or a `max_fragment_length` was negotiated.  The
+    /// `record_size_limit` does not apply to unprotected records (RFC 8449
+    /// section 4), such as the rest of a TLS1.2 handshake.
+    pub(crate) fn max_received_payload_len(&self, protected: bool) -> Option<usize> {
+        let record_size_limit = match self.peer_record_size_limit {
+            Some(_) if protected => self
+                .record_size_limit
+                .map(|limit| self.record_payload_len(limit)),
+            _ => None,
+        };
+        let max_fragment_length = self
+            .max_fragment_length
+            .and_then(|mfl| mfl.payload_len());
+
+        match (record_size_limit, max_fragment_length) {
+            (Some(a), Some(b)) => Some(a.min(b)),
+            (a, b) => a.or(b),
+        }
+    }
+
+    /// The largest payload in a record within `limit`.
+    ///
+    /// In TLS1.3 the limit includes the inner content type (RFC 8449 section 4).
+    fn record_payload_len(&self, limit: u16) -> usize {
+        match self.is_tls13() {
+            true => usize::from(limit) - 1,
+            false => usize::from(limit),
+        }
+    }
+
     pub(crate) fn get_alpn_protocol(&self) -> Option<&[u8]> {
         self.alpn_protocol
             .as_ref()
@@ -674,9 +791,12 @@ impl CommonState {
         // the peer has sent us a close notification.
         //
         // In the handshake case we don't have readable plaintext before the handshake has
-        // completed, but also don't want to read if we still have sendable tls.
+        // completed, but also don't want to read if we still have sendable tls, or while
+        // the application verifies the peer's certificates or chooses a client certificate.
         self.received_plaintext.is_empty()
             && !self.has_received_close_notify
+            && !self.awaiting_verification
+            && !self.awaiting_client_cert
             && (self.may_send_application_data || self.sendable_tls.is_empty())
     }
 
@@ -685,6 +805,8 @@ impl CommonState {
             tls_bytes_to_write: self.sendable_tls.len(),
             plaintext_bytes_to_read: self.received_plaintext.len(),
             peer_has_closed: self.has_received_close_notify,
+            awaiting_verification: self.awaiting_verification,
+            awaiting_client_cert: self.awaiting_client_cert,
         }
     }
 
@@ -800,6 +922,12 @@ pub enum HandshakeKind {
     /// full ones, but can only happen when the peers have previously done a full
     /// handshake together, and then remember data about it.
     Resumed,
+
+    /// A TLS1.3 handshake authenticated with an external pre-shared key.
+    ///
+    /// Neither peer presents a certificate: both prove they know the PSK instead.
+    /// See [`ExternalPsk`](crate::ExternalPsk).
+    ExternalPsk,
 }
 
 /// Values of this structure are returned from [`Connection::process_new_packets`]
@@ -811,6 +939,8 @@ pub struct IoState {
     tls_bytes_to_write: usize,
     plaintext_bytes_to_read: usize,
     peer_has_closed: bool,
+    awaiting_verification: bool,
+    awaiting_client_cert: bool,
 }
 
 impl IoState {
@@ -839,6 +969,33 @@ impl IoState {
     pub fn peer_has_closed(&self) -> bool {
         self.peer_has_closed
     }
+
+    /// True if the handshake is paused until the application verifies the peer's
+    /// certificates.
+    ///
+    /// This happens when the connection's certificate verifier
+    /// [is asynchronous][crate::client::danger::ServerCertVerifier::is_async].
+    /// Make the verification described by [`ConnectionCommon::pending_verification()`], then
+    /// provide its result with [`ConnectionCommon::complete_verification()`].
+    ///
+    /// [`ConnectionCommon::pending_verification()`]: crate::ConnectionCommon::pending_verification
+    /// [`ConnectionCommon::complete_verification()`]: crate::ConnectionCommon::complete_verification
+    pub fn awaiting_verification(&self) -> bool {
+        self.awaiting_verification
+    }
+
+    /// True if the handshake is paused until the application chooses a client certificate.
+    ///
+    /// This happens when the client's certificate resolver
+    /// [is asynchronous][crate::client::ResolvesClientCert::is_async].
+    /// Answer the request described by [`ClientConnection::pending_client_cert()`], then
+    /// provide the chosen certificate with [`ClientConnection::complete_client_cert()`].
+    ///
+    /// [`ClientConnection::pending_client_cert()`]: crate::ClientConnection::pending_client_cert
+    /// [`ClientConnection::complete_client_cert()`]: crate::ClientConnection::complete_client_cert
+    pub fn awaiting_client_cert(&self) -> bool {
+        self.awaiting_client_cert
+    }
 }
 
 pub(crate) trait State<Data>: Send + Sync {
@@ -867,8 +1024,63 @@ pub(crate) trait State<Data>: Send + Sync {
         Err(Error::HandshakeNotComplete)
     }
 
+    #[cfg_attr(not(feature = "std"), allow(dead_code))]
+    fn request_client_certificate(
+        &mut self,
+        _common: &mut CommonState,
+        _verifier: Arc<dyn ClientCertVerifier>,
+    ) -> Result<(), Error> {
+        Err(Error::HandshakeNotComplete)
+    }
+
     fn handle_decrypt_error(&self) {}
 
+    /// The handshake signature this state is waiting for the application to make, if any.
+    fn pending_signature(&self) -> Option<&PendingSignature> {
+        None
+    }
+
+    /// Continue the handshake with the `signature` requested by [`Self::pending_signature()`].
+    fn handle_signature(
+        self: Box<Self>,
+        _cx: &mut Context<'_, Data>,
+        _signature: Vec<u8>,
+    ) -> Result<Box<dyn State<Data>>, Error> {
+        Err(Error::General("no signature is pending".into()))
+    }
+
+    /// The certificate verification this state is waiting for the application to make, if any.
+    fn pending_verification(&self) -> Option<&CertificateVerification> {
+        None
+    }
+
+    /// Continue the handshake with the `result` of [`Self::pending_verification()`].
+    fn handle_verification(
+        self: Box<Self>,
+        _cx: &mut Context<'_, Data>,
+        _result: Result<(), Error>,
+    ) -> Result<Box<dyn State<Data>>, Error> {
+        Err(Error::General(
+            "no certificate verification is pending".into(),
+        ))
+    }
+
+    /// The client certificate request this state is waiting for the application to answer, if any.
+    fn pending_client_cert(&self) -> Option<&ClientCertRequest> {
+        None
+    }
+
+    /// Continue the handshake with the `certified_key` chosen for [`Self::pending_client_cert()`].
+    fn handle_client_cert(
+        self: Box<Self>,
+        _cx: &mut Context<'_, Data>,
+        _certified_key: Option<Arc<CertifiedKey>>,
+    ) -> Result<Box<dyn State<Data>>, Error> {
+        Err(Error::General(
+            "no client certificate request is pending".into(),
+        ))
+    }
+
     fn into_external_state(self: Box<Self>) -> Result<Box<dyn KernelState + 'static>, Error> {
         Err(Error::HandshakeNotComplete)
     }
@@ -876,6 +1088,145 @@ pub(crate) trait State<Data>: Send + Sync {
     fn into_owned(self: Box<Self>) -> Box<dyn State<Data> + 'static>;
 }
 
+/// A handshake signature for the application to make.
+///
+/// See [`Signer::is_async()`].
+pub(crate) struct PendingSignature {
+    pub(crate) scheme: SignatureScheme,
+    /// The message to sign, which is not hashed.
+    pub(crate) message: Vec<u8>,
+}
+
+/// The handshake, from the point a signature has been made.
+pub(crate) trait ResumeWithSignature<Data>: Send + Sync + 'static {
+    fn resume(
+        self,
+        cx: &mut Context<'_, Data>,
+        signature: DigitallySignedStruct,
+    ) -> Result<Box<dyn State<Data>>, Error>;
+}
+
+/// Waits for the application to make a handshake signature, then continues with `next`.
+pub(crate) struct ExpectSignature<T> {
+    pending: PendingSignature,
+    next: T,
+}
+
+impl<T> ExpectSignature<T> {
+    /// Sign `message` with `signer`, and continue the handshake with `next`.
+    ///
+    /// If the application makes this connection's signatures with `signer`, instead
+    /// return a state that waits for it.
+    pub(crate) fn sign<Data>(
+        signer: &dyn Signer,
+        message: Vec<u8>,
+        next: T,
+        cx: &mut Context<'_, Data>,
+    ) -> Result<Box<dyn State<Data>>, Error>
+    where
+        T: ResumeWithSignature<Data>,
+    {
+        let scheme = signer.scheme();
+        if cx.common.async_signing && signer.is_async() {
+            return Ok(Box::new(Self {
+                pending: PendingSignature { scheme, message },
+                next,
+            }));
+        }
+
+        let signature = signer.sign(&message)?;
+        next.resume(cx, DigitallySignedStruct::new(scheme, signature))
+    }
+}
+
+impl<Data, T: ResumeWithSignature<Data>> State<Data> for ExpectSignature<T> {
+    fn handle<'m>(
+        self: Box<Self>,
+        _cx: &mut Context<'_, Data>,
+        message: Message<'m>,
+    ) -> Result<Box<dyn State<Data> + 'm>, Error>
+    where
+        Self: 'm,
+    {
+        Err(inappropriate_message(&message.payload, &[]))
+    }
+
+    fn pending_signature(&self) -> Option<&PendingSignature> {
+        Some(&self.pending)
+    }
+
+    fn handle_signature(
+        self: Box<Self>,
+        cx: &mut Context<'_, Data>,
+        signature: Vec<u8>,
+    ) -> Result<Box<dyn State<Data>>, Error> {
+        let Self { pending, next } = *self;
+        next.resume(cx, DigitallySignedStruct::new(pending.scheme, signature))
+    }
+
+    fn into_owned(self: Box<Self>) -> Box<dyn State<Data> + 'static> {
+        self
+    }
+}
+
+/// The handshake, from the point the peer's certificates have been verified.
+pub(crate) trait ResumeWithVerification<Data>: Send + Sync + 'static {
+    fn resume(self, cx: &mut Context<'_, Data>) -> Result<Box<dyn State<Data>>, Error>;
+}
+
+/// Waits for the application to verify the peer's certificates, then continues with `next`.
+pub(crate) struct ExpectVerification<T> {
+    pending: CertificateVerification,
+    next: T,
+}
+
+impl<T> ExpectVerification<T> {
+    /// Wait for the application to make the `pending` verification.
+    pub(crate) fn new(
+        pending: CertificateVerification,
+        next: T,
+        common: &mut CommonState,
+    ) -> Box<Self> {
+        common.awaiting_verification = true;
+        Box::new(Self { pending, next })
+    }
+}
+
+impl<Data, T: ResumeWithVerification<Data>> State<Data> for ExpectVerification<T> {
+    fn handle<'m>(
+        self: Box<Self>,
+        _cx: &mut Context<'_, Data>,
+        message: Message<'m>,
+    ) -> Result<Box<dyn State<Data> + 'm>, Error>
+    where
+        Self: 'm,
+    {
+        Err(inappropriate_message(&message.payload, &[]))
+    }
+
+    fn pending_verification(&self) -> Option<&CertificateVerification> {
+        Some(&self.pending)
+    }
+
+    fn handle_verification(
+        self: Box<Self>,
+        cx: &mut Context<'_, Data>,
+        result: Result<(), Error>,
+    ) -> Result<Box<dyn State<Data>>, Error> {
+        cx.common.awaiting_verification = false;
+        match result {
+            Ok(()) => self.next.resume(cx),
+            Err(err) => Err(cx
+                .common
+                .send_cert_verify_error_alert(err)),
+        }
+    }
+
+    fn into_owned(self: Box<Self>) -> Box<dyn State<Data> + 'static> {
+        self
+    }
+}
+
 pub(crate) struct Context<'a, Data> {
     pub(crate) common: &'a mut CommonState,
     pub(crate) data: &'a mut Data,
@@ -1020,6 +1371,16 @@ impl<'a, const TLS13: bool> HandshakeFlight<'a, TLS13> {
         }
     }
 
+    /// Continue a flight, whose messages so far were returned by [`Self::suspend()`].
+    pub(crate) fn resume(transcript: &'a mut HandshakeHash, body: Vec<u8>) -> Self {
+        Self { transcript, body }
+    }
+
+    /// Stop adding to this flight for now, returning its messages so far.
+    pub(crate) fn suspend(self) -> Vec<u8> {
+        self.body
+    }
+
     pub(crate) fn add(&mut self, hs: HandshakeMessagePayload<'_>) {
         let start_len = self.body.len();
         hs.encode(&mut self.body);
@@ -1,4 +1,5 @@
 use alloc::boxed::Box;
+use alloc::vec::Vec;
 use core::fmt::Debug;
 use core::mem;
 use core::ops::{Deref, DerefMut, Range};
@@ -7,7 +8,7 @@ use std::io;
 
 use kernel::KernelConnection;
 
-use crate::common_state::{CommonState, Context, DEFAULT_BUFFER_LIMIT, IoState, State};
+use crate::common_state::{CommonState, Context, DEFAULT_BUFFER_LIMIT, IoState, Side, State};
 use crate::enums::{AlertDescription, ContentType, ProtocolVersion};
 use crate::error::{Error, PeerMisbehaved};
 use crate::log::trace;
@@ -17,8 +18,11 @@ use crate::msgs::deframer::handshake::HandshakeDeframer;
 use crate::msgs::handshake::Random;
 use crate::msgs::message::{InboundPlainMessage, Message, MessagePayload};
 use crate::record_layer::Decrypted;
+use crate::sign::CertifiedKey;
 use crate::suites::ExtractedSecrets;
+use crate::sync::Arc;
 use crate::vecbuf::ChunkVecBuffer;
+use crate::verify::CertificateVerification;
 
 // pub so that it can be re-exported from the crate root
 pub mod kernel;
@@ -37,6 +41,7 @@ mod connection {
     use crate::msgs::message::OutboundChunks;
     use crate::suites::ExtractedSecrets;
     use crate::vecbuf::ChunkVecBuffer;
+    use crate::verify::CertificateVerification;
 
     /// A client or server connection.
     #[allow(clippy::exhaustive_enums)]
@@ -92,6 +97,29 @@ mod connection {
             }
         }
 
+        /// Returns the certificate verification the handshake is waiting for, if any.
+        ///
+        /// See [`ConnectionCommon::pending_verification()`] for more information.
+        pub fn pending_verification(&self) -> Option<&CertificateVerification> {
+            match self {
+                Self::Client(conn) => conn.pending_verification(),
+                Self::Server(conn) => conn.pending_verification(),
+            }
+        }
+
+        /// Continues the handshake with the `result` of [`Connection::pending_verification()`].
+        ///
+        /// See [`ConnectionCommon::complete_verification()`] for more information.
+        pub fn complete_verification(
+            &mut self,
+            result: Result<(), Error>,
+        ) -> Result<IoState, Error> {
+            match self {
+                Self::Client(conn) => conn.complete_verification(result),
+                Self::Server(conn) => conn.complete_verification(result),
+            }
+        }
+
         /// Derives key material from the agreed connection secrets.
         ///
         /// See [`ConnectionCommon::export_keying_material()`] for more information.
@@ -405,6 +433,29 @@ impl<Data> ConnectionCommon<Data> {
             .process_new_packets(&mut self.deframer_buffer, &mut self.sendable_plaintext)
     }
 
+    /// Returns the certificate verification the handshake is waiting for, if any.
+    ///
+    /// See [`IoState::awaiting_verification()`].
+    pub fn pending_verification(&self) -> Option<&CertificateVerification> {
+        self.core
+            .state
+            .as_ref()
+            .ok()?
+            .pending_verification()
+    }
+
+    /// Continues the handshake with the `result` of [`Self::pending_verification()`].
+    ///
+    /// If `result` is an error, the connection fails with it, and an alert is queued
+    /// for the peer.  Otherwise, any packets received while the handshake was paused
+    /// are then processed, as by [`Connection::process_new_packets`].
+    ///
+    /// [`Connection::process_new_packets`]: crate::Connection::process_new_packets
+    pub fn complete_verification(&mut self, result: Result<(), Error>) -> Result<IoState, Error> {
+        self.core.process_verification(result)?;
+        self.process_new_packets()
+    }
+
     /// Derives key material from the agreed connection secrets.
     ///
     /// This function fills in `output` with `output.len()` bytes of key
@@ -678,6 +729,14 @@ impl<Data> ConnectionCommon<Data> {
         }
     }
 
+    /// Take any received data that has not yet been processed.
+    pub(crate) fn take_received_tls(&mut self) -> Vec<u8> {
+        let received = self.deframer_buffer.filled().to_vec();
+        self.deframer_buffer
+            .discard(received.len());
+        received
+    }
+
     pub(crate) fn replace_state(&mut self, new: Box<dyn State<Data>>) {
         self.core.state = Ok(new);
     }
@@ -776,7 +835,8 @@ pub struct UnbufferedConnectionCommon<Data> {
 }
 
 impl<Data> From<ConnectionCore<Data>> for UnbufferedConnectionCommon<Data> {
-    fn from(core: ConnectionCore<Data>) -> Self {
+    fn from(mut core: ConnectionCore<Data>) -> Self {
+        core.common_state.async_signing = true;
         Self {
             core,
             wants_write: false,
@@ -791,6 +851,35 @@ impl<Data> UnbufferedConnectionCommon<Data> {
     pub fn dangerous_extract_secrets(self) -> Result<ExtractedSecrets, Error> {
         self.core.dangerous_extract_secrets()
     }
+
+    /// Extract the first handshake message from `incoming_tls`.
+    ///
+    /// This is the unbuffered counterpart of [`ConnectionCommon::first_handshake_message()`].
+    /// Also returns the number of bytes to discard from the front of `incoming_tls`.
+    pub(crate) fn first_handshake_message(
+        &mut self,
+        incoming_tls: &mut [u8],
+    ) -> (usize, Result<Option<Message<'static>>, Error>) {
+        let mut buffer_progress = self.core.hs_deframer.progress();
+
+        let res = self
+            .core
+            .deframe(None, incoming_tls, &mut buffer_progress)
+            .map(|opt| opt.map(|pm| Message::try_from(pm).map(|m| m.into_owned())));
+
+        match res {
+            Ok(Some(Ok(msg))) => (buffer_progress.take_discard(), Ok(Some(msg))),
+            Ok(Some(Err(err))) => (
+                buffer_progress.take_discard(),
+                Err(self
+                    .core
+                    .common_state
+                    .send_fatal_alert(AlertDescription::DecodeError, err)),
+            ),
+            Ok(None) => (0, Ok(None)),
+            Err(err) => (buffer_progress.take_discard(), Err(err)),
+        }
+    }
 }
 
 impl<T> Deref for UnbufferedConnectionCommon<T> {
@@ -839,6 +928,12 @@ impl<Data> ConnectionCore<Data> {
         let mut buffer_progress = self.hs_deframer.progress();
 
         loop {
+            if state.pending_verification().is_some() || state.pending_client_cert().is_some() {
+                // Later messages are processed once the application has done what the
+                // handshake is waiting for.
+                break;
+            }
+
             let res = self.deframe(
                 Some(&*state),
                 deframer_buffer.filled_mut(),
@@ -886,6 +981,57 @@ impl<Data> ConnectionCore<Data> {
         Ok(self.common_state.current_io_state())
     }
 
+    /// Continue the handshake with the application's `signature`.
+    pub(crate) fn process_signature(&mut self, signature: Vec<u8>) -> Result<(), Error> {
+        self.resume(|state, cx| state.handle_signature(cx, signature))
+    }
+
+    /// Continue the handshake with the `result` of the application's certificate verification.
+    pub(crate) fn process_verification(&mut self, result: Result<(), Error>) -> Result<(), Error> {
+        self.resume(|state, cx| state.handle_verification(cx, result))
+    }
+
+    /// Continue the handshake with the client certificate chosen by the application.
+    pub(crate) fn process_client_cert(
+        &mut self,
+        certified_key: Option<Arc<CertifiedKey>>,
+    ) -> Result<(), Error> {
+        self.resume(|state, cx| state.handle_client_cert(cx, certified_key))
+    }
+
+    /// Continue the handshake with `f`, once the application has done what the state needs.
+    fn resume(
+        &mut self,
+        f: impl FnOnce(
+            Box<dyn State<Data>>,
+            &mut Context<'_, Data>,
+        ) -> Result<Box<dyn State<Data>>, Error>,
+    ) -> Result<(), Error> {
+        let state = match mem::replace(&mut self.state, Err(Error::HandshakeNotComplete)) {
+            Ok(state) => state,
+            Err(e) => {
+                self.state = Err(e.clone());
+                return Err(e);
+            }
+        };
+
+        let mut cx = Context {
+            common: &mut self.common_state,
+            data: &mut self.data,
+            sendable_plaintext: None,
+        };
+        match f(state, &mut cx) {
+            Ok(state) => {
+                self.state = Ok(state);
+                Ok(())
+            }
+            Err(e) => {
+                self.state = Err(e.clone());
+                Err(e)
+            }
+        }
+    }
+
     /// Pull a message out of the deframer and send any messages that need to be sent as a result.
     fn deframe<'b>(
         &mut self,
@@ -995,6 +1141,25 @@ impl<Data> ConnectionCore<Data> {
                     self.common_state.send_close_notify();
                 }
 
+                // Early data is sent before the client learns of our record size limit.
+                let early_data = self.common_state.side == Side::Server
+                    && plaintext.typ == ContentType::ApplicationData
+                    && self.common_state.is_handshaking();
+                let protected = self
+                    .common_state
+                    .record_layer
+                    .is_decrypting();
+                match self
+                    .common_state
+                    .max_received_payload_len(protected)
+                {
+                    Some(max_len) if !early_data && plaintext.payload.len() > max_len => {
+                        let err = Error::PeerSentOversizedRecord;
+                        return Err(self.handle_deframe_error(err, state));
+                    }
+                    _ => {}
+                }
+
                 break (plaintext, iter.bytes_consumed());
             };
 
@@ -7,10 +7,14 @@ use core::{fmt, mem};
 use std::error::Error as StdError;
 
 use super::UnbufferedConnectionCommon;
-use crate::Error;
-use crate::client::ClientConnectionData;
+use crate::client::{ClientCertRequest, ClientConnectionData};
+use crate::common_state::PendingSignature;
 use crate::msgs::deframer::buffers::DeframerSliceBuffer;
 use crate::server::ServerConnectionData;
+use crate::sign::CertifiedKey;
+use crate::sync::Arc;
+use crate::verify::CertificateVerification;
+use crate::{Error, SignatureScheme};
 
 impl UnbufferedConnectionCommon<ClientConnectionData> {
     /// Processes the TLS records in `incoming_tls` buffer until a new [`UnbufferedStatus`] is
@@ -80,6 +84,10 @@ impl<Data> UnbufferedConnectionCommon<Data> {
                 );
             }
 
+            if let Some(input) = self.pending_input() {
+                break (buffer.pending_discard(), input.into_state(self));
+            }
+
             let deframer_output = if self
                 .core
                 .common_state
@@ -174,6 +182,46 @@ impl<Data> UnbufferedConnectionCommon<Data> {
             state: Ok(state),
         }
     }
+
+    /// The input the application must provide before the handshake can continue, if any.
+    fn pending_input(&self) -> Option<PendingInput> {
+        let state = self.core.state.as_ref().ok()?;
+        if state.pending_signature().is_some() {
+            Some(PendingInput::Signature)
+        } else if state.pending_verification().is_some() {
+            Some(PendingInput::Verification)
+        } else if state.pending_client_cert().is_some() {
+            Some(PendingInput::ClientCert)
+        } else {
+            None
+        }
+    }
+}
+
+/// Input from the application that the handshake is waiting for.
+#[derive(Clone, Copy)]
+enum PendingInput {
+    Signature,
+    Verification,
+    ClientCert,
+}
+
+impl PendingInput {
+    fn into_state<'c, 'i, Data>(
+        self,
+        conn: &'c mut UnbufferedConnectionCommon<Data>,
+    ) -> ConnectionState<'c, 'i, Data> {
+        // Transmit what we have so far, while the application provides the input.
+        if conn.wants_write {
+            return TransmitTlsData { conn }.into();
+        }
+
+        match self {
+            Self::Signature => SignatureRequired { conn }.into(),
+            Self::Verification => VerificationRequired { conn }.into(),
+            Self::ClientCert => ClientCertRequired { conn }.into(),
+        }
+    }
 }
 
 /// The current status of the `UnbufferedConnection*`
@@ -259,6 +307,37 @@ pub enum ConnectionState<'c, 'i, Data> {
     /// was passed to [`UnbufferedConnectionCommon::process_tls_records`].
     BlockedHandshake,
 
+    /// The application must sign the handshake, because the [`Signer`] in use
+    /// [is asynchronous][Signer::is_async].
+    ///
+    /// Sign [`SignatureRequired::message`] using [`SignatureRequired::scheme`], then call
+    /// [`SignatureRequired::done`] on the enclosed object with the signature.  The connection
+    /// does not make progress until then, but can be set aside while signing.
+    ///
+    /// [`Signer`]: crate::sign::Signer
+    /// [Signer::is_async]: crate::sign::Signer::is_async
+    SignatureRequired(SignatureRequired<'c, Data>),
+
+    /// The application must verify the peer's certificates, because the certificate
+    /// verifier in use [is asynchronous][ServerCertVerifier::is_async].
+    ///
+    /// Verify the certificates described by [`VerificationRequired::verification`], then
+    /// call [`VerificationRequired::done`] on the enclosed object with the result.  The
+    /// connection does not make progress until then, but can be set aside while verifying.
+    ///
+    /// [ServerCertVerifier::is_async]: crate::client::danger::ServerCertVerifier::is_async
+    VerificationRequired(VerificationRequired<'c, Data>),
+
+    /// The application must choose a client certificate, because the client's certificate
+    /// resolver [is asynchronous][ResolvesClientCert::is_async].
+    ///
+    /// Answer the request described by [`ClientCertRequired::request`], then call
+    /// [`ClientCertRequired::done`] on the enclosed object with the chosen certificate.  The
+    /// connection does not make progress until then, but can be set aside meanwhile.
+    ///
+    /// [ResolvesClientCert::is_async]: crate::client::ResolvesClientCert::is_async
+    ClientCertRequired(ClientCertRequired<'c, Data>),
+
     /// The handshake process has been completed.
     ///
     /// [`WriteTraffic::encrypt`] can be called on the enclosed object to encrypt application
@@ -299,6 +378,24 @@ impl<'c, Data> From<TransmitTlsData<'c, Data>> for ConnectionState<'c, '_, Data>
     }
 }
 
+impl<'c, Data> From<SignatureRequired<'c, Data>> for ConnectionState<'c, '_, Data> {
+    fn from(v: SignatureRequired<'c, Data>) -> Self {
+        Self::SignatureRequired(v)
+    }
+}
+
+impl<'c, Data> From<VerificationRequired<'c, Data>> for ConnectionState<'c, '_, Data> {
+    fn from(v: VerificationRequired<'c, Data>) -> Self {
+        Self::VerificationRequired(v)
+    }
+}
+
+impl<'c, Data> From<ClientCertRequired<'c, Data>> for ConnectionState<'c, '_, Data> {
+    fn from(v: ClientCertRequired<'c, Data>) -> Self {
+        Self::ClientCertRequired(v)
+    }
+}
+
 impl<Data> fmt::Debug for ConnectionState<'_, '_, Data> {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         match self {
@@ -320,6 +417,18 @@ impl<Data> fmt::Debug for ConnectionState<'_, '_, Data> {
                 .debug_tuple("BlockedHandshake")
                 .finish(),
 
+            Self::SignatureRequired(..) => f
+                .debug_tuple("SignatureRequired")
+                .finish(),
+
+            Self::VerificationRequired(..) => f
+                .debug_tuple("VerificationRequired")
+                .finish(),
+
+            Self::ClientCertRequired(..) => f
+                .debug_tuple("ClientCertRequired")
+                .finish(),
+
             Self::WriteTraffic(..) => f.debug_tuple("WriteTraffic").finish(),
         }
     }
@@ -552,6 +661,107 @@ impl<Data> TransmitTlsData<'_, Data> {
     }
 }
 
+/// A handshake signature must be made by the application
+pub struct SignatureRequired<'c, Data> {
+    conn: &'c mut UnbufferedConnectionCommon<Data>,
+}
+
+impl<Data> SignatureRequired<'_, Data> {
+    /// Returns the message to sign
+    ///
+    /// The message is not hashed: it must be hashed using the hash function implicit in
+    /// [`Self::scheme()`].
+    pub fn message(&self) -> &[u8] {
+        &self.pending().message
+    }
+
+    /// Returns the scheme to sign the message with
+    ///
+    /// The signature format is also defined by this scheme.
+    pub fn scheme(&self) -> SignatureScheme {
+        self.pending().scheme
+    }
+
+    /// Provides the signature of [`Self::message()`], and continues the handshake
+    ///
+    /// Call [`UnbufferedConnectionCommon::process_tls_records`] afterwards to obtain the
+    /// next state.  If the handshake cannot continue, the error is also returned by
+    /// future [`UnbufferedConnectionCommon::process_tls_records`] calls.
+    pub fn done(self, signature: Vec<u8>) -> Result<(), Error> {
+        self.conn
+            .core
+            .process_signature(signature)
+    }
+
+    fn pending(&self) -> &PendingSignature {
+        self.conn
+            .core
+            .state
+            .as_ref()
+            .ok()
+            .and_then(|state| state.pending_signature())
+            .expect("SignatureRequired is only created while a signature is pending")
+    }
+}
+
+/// The peer's certificates must be verified by the application
+pub struct VerificationRequired<'c, Data> {
+    conn: &'c mut UnbufferedConnectionCommon<Data>,
+}
+
+impl<Data> VerificationRequired<'_, Data> {
+    /// Returns the certificate verification to make
+    pub fn verification(&self) -> &CertificateVerification {
+        self.conn
+            .core
+            .state
+            .as_ref()
+            .ok()
+            .and_then(|state| state.pending_verification())
+            .expect("VerificationRequired is only created while a verification is pending")
+    }
+
+    /// Provides the result of verifying [`Self::verification()`], and continues the handshake
+    ///
+    /// If `result` is an error, the connection fails with it, and an alert is queued for
+    /// the peer.  Call [`UnbufferedConnectionCommon::process_tls_records`] afterwards to
+    /// obtain the next state.
+    pub fn done(self, result: Result<(), Error>) -> Result<(), Error> {
+        self.conn
+            .core
+            .process_verification(result)
+    }
+}
+
+/// A client certificate must be chosen by the application
+pub struct ClientCertRequired<'c, Data> {
+    conn: &'c mut UnbufferedConnectionCommon<Data>,
+}
+
+impl<Data> ClientCertRequired<'_, Data> {
+    /// Returns the server's request for a client certificate
+    pub fn request(&self) -> &ClientCertRequest {
+        self.conn
+            .core
+            .state
+            .as_ref()
+            .ok()
+            .and_then(|state| state.pending_client_cert())
+            .expect("ClientCertRequired is only created while a request is pending")
+    }
+
+    /// Provides the certificate chosen for [`Self::request()`], and continues the handshake
+    ///
+    /// If `certified_key` is `None`, the handshake continues without client authentication.
+    /// Call [`UnbufferedConnectionCommon::process_tls_records`] afterwards to obtain the
+    /// next state.
+    pub fn done(self, certified_key: Option<Arc<CertifiedKey>>) -> Result<(), Error> {
+        self.conn
+            .core
+            .process_client_cert(certified_key)
+    }
+}
+
 /// Errors that may arise when encoding a handshake record
 #[non_exhaustive]
 #[derive(Debug)]
@@ -5,8 +5,8 @@ use core::fmt::{Debug, Formatter};
 use core::sync::atomic::{AtomicUsize, Ordering};
 
 use aws_lc_rs::cipher::{
-    AES_256, AES_256_KEY_LEN, AES_CBC_IV_LEN, DecryptionContext, PaddedBlockDecryptingKey,
-    PaddedBlockEncryptingKey, UnboundCipherKey,
+    self, AES_128, AES_256, AES_256_KEY_LEN, AES_CBC_IV_LEN, DecryptionContext,
+    PaddedBlockDecryptingKey, PaddedBlockEncryptingKey, UnboundCipherKey,
 };
 use aws_lc_rs::{hmac, iv};
 
@@ -19,6 +19,8 @@ use crate::polyfill::try_split_at;
 use crate::rand::GetRandomFailed;
 use crate::server::ProducesTickets;
 use crate::sync::Arc;
+#[cfg(feature = "std")]
+use crate::ticketer::{TicketKey, TicketKeyRing, TicketKeySet};
 
 /// A concrete, safe ticket creation mechanism.
 #[non_exhaustive]
@@ -44,21 +46,93 @@ impl Ticketer {
             make_ticket_generator,
         )?))
     }
+
+    /// Make a `Ticketer` using externally supplied keys.
+    ///
+    /// This is suitable for sharing tickets across servers: see [`TicketKeyRing`].
+    /// Tickets are advertised to clients as lasting for `lifetime` seconds.
+    ///
+    /// Each key's secret must be 64 bytes: a 32 byte HMAC-SHA256 key followed by
+    /// a 32 byte AES 256 key.  Keys of any other length, such as the 32 byte keys
+    /// used by the *ring* provider's `Ticketer`, are rejected.  Tickets are otherwise
+    /// constructed as for [`Ticketer::new()`].
+    ///
+    /// This is the same layout as an 80 byte key file used by nginx and other
+    /// OpenSSL-based servers, less the key name: see [`Ticketer::new_with_key_files()`].
+    #[cfg(feature = "std")]
+    pub fn new_with_keys(lifetime: u32, keys: TicketKeySet) -> Result<Arc<TicketKeyRing>, Error> {
+        Ok(Arc::new(TicketKeyRing::new(
+            lifetime,
+            keys,
+            make_keyed_ticketer,
+        )?))
+    }
+
+    /// Make a `Ticketer` using keys loaded from OpenSSL-style session ticket key
+    /// files, such as those used by nginx and HAProxy.
+    ///
+    /// Each key must come from [`TicketKey::from_key_file()`].  Tickets use the
+    /// same [RFC 5077 §4] layout as those servers, with AES 128 or AES 256
+    /// depending on the key file's length, so keys can be shared and rotated in
+    /// step with them.  As in OpenSSL, the HMAC covers the key name, IV and
+    /// encrypted state, without the length of the encrypted state that RFC 5077
+    /// describes.  Tickets are advertised to clients as lasting for `lifetime`
+    /// seconds.
+    ///
+    /// Note that tickets cannot be resumed across different server implementations,
+    /// as each encrypts its own, incompatible, session state.
+    ///
+    /// [RFC 5077 §4]: https://www.rfc-editor.org/rfc/rfc5077#section-4
+    #[cfg(feature = "std")]
+    pub fn new_with_key_files(
+        lifetime: u32,
+        keys: TicketKeySet,
+    ) -> Result<Arc<TicketKeyRing>, Error> {
+        Ok(Arc::new(TicketKeyRing::new(
+            lifetime,
+            keys,
+            make_key_file_ticketer,
+        )?))
+    }
 }
 
 fn make_ticket_generator() -> Result<Box<dyn ProducesTickets>, Error> {
     Ok(Box::new(Rfc5077Ticketer::new()?))
 }
 
+#[cfg(feature = "std")]
+fn make_keyed_ticketer(key: &TicketKey) -> Result<Box<dyn ProducesTickets>, Error> {
+    // Only key files may use AES 128.
+    if key.secret().len() != 2 * AES_256_KEY_LEN {
+        return Err(Error::General("ticket key must be 64 bytes".into()));
+    }
+    Ok(Box::new(Rfc5077Ticketer::with_key(key, MacInput::Rfc5077)?))
+}
+
+#[cfg(feature = "std")]
+fn make_key_file_ticketer(key: &TicketKey) -> Result<Box<dyn ProducesTickets>, Error> {
+    Ok(Box::new(Rfc5077Ticketer::with_key(key, MacInput::OpenSsl)?))
+}
+
 /// An RFC 5077 "Recommended Ticket Construction" implementation of a [`Ticketer`].
 struct Rfc5077Ticketer {
     aes_encrypt_key: PaddedBlockEncryptingKey,
     aes_decrypt_key: PaddedBlockDecryptingKey,
     hmac_key: hmac::Key,
     key_name: [u8; 16],
+    mac_input: MacInput,
     maximum_ciphertext_len: AtomicUsize,
 }
 
+/// What a ticket's MAC is calculated over.
+#[derive(Clone, Copy)]
+enum MacInput {
+    /// The key name, IV, then the length-prefixed encrypted state, as in RFC 5077.
+    Rfc5077,
+    /// The key name, IV and encrypted state, as in OpenSSL.
+    OpenSsl,
+}
+
 impl Rfc5077Ticketer {
     fn new() -> Result<Self, Error> {
         let rand = SystemRandom::new();
@@ -68,37 +142,91 @@ impl Rfc5077Ticketer {
         rand.fill(&mut aes_key)
             .map_err(|_| GetRandomFailed)?;
 
+        // Generate a random HMAC SHA256 key to use for HMAC authentication.
+        let hmac_key = hmac::Key::generate(hmac::HMAC_SHA256, &rand).map_err(unspecified_err)?;
+
+        // Generate a random key name.
+        let mut key_name = [0u8; 16];
+        rand.fill(&mut key_name)
+            .map_err(|_| GetRandomFailed)?;
+
+        Self::from_keys(key_name, &AES_256, &aes_key, hmac_key, MacInput::Rfc5077, 0)
+    }
+
+    /// Make a ticketer from `key`, which may be shared with other servers.
+    ///
+    /// The key's secret is an HMAC key followed by an AES key of the same
+    /// length, which selects AES 128 or AES 256.
+    #[cfg(feature = "std")]
+    fn with_key(key: &TicketKey, mac_input: MacInput) -> Result<Self, Error> {
+        let secret = key.secret();
+        let aes_alg = match secret.len() {
+            32 => &AES_128,
+            64 => &AES_256,
+            _ => return Err(Error::General("ticket key must be 32 or 64 bytes".into())),
+        };
+        let (hmac_key, aes_key) = secret.split_at(secret.len() / 2);
+
+        // Tickets encrypted with this key may come from other servers,
+        // so we cannot learn the maximum length from our own encryptions.
+        // Instead, limit it to the largest possible ticket.
+        Self::from_keys(
+            *key.name(),
+            aes_alg,
+            aes_key,
+            hmac::Key::new(hmac::HMAC_SHA256, hmac_key),
+            mac_input,
+            usize::from(u16::MAX),
+        )
+    }
+
+    fn from_keys(
+        key_name: [u8; 16],
+        aes_alg: &'static cipher::Algorithm,
+        aes_key: &[u8],
+        hmac_key: hmac::Key,
+        mac_input: MacInput,
+        maximum_ciphertext_len: usize,
+    ) -> Result<Self, Error> {
         // Convert the raw AES 256 key bytes into encrypting and decrypting keys using CBC mode and
         // PKCS#7 padding. We don't want to store just the raw key bytes as constructing the
         // cipher keys has some setup overhead. We can't store just the `UnboundCipherKey` since
         // constructing the padded encrypt/decrypt specific types consume the `UnboundCipherKey`.
-        let aes_encrypt_key =
-            UnboundCipherKey::new(&AES_256, &aes_key[..]).map_err(unspecified_err)?;
+        let aes_encrypt_key = UnboundCipherKey::new(aes_alg, aes_key).map_err(unspecified_err)?;
         let aes_encrypt_key =
             PaddedBlockEncryptingKey::cbc_pkcs7(aes_encrypt_key).map_err(unspecified_err)?;
 
         // Convert the raw AES 256 key bytes into a decrypting key using CBC PKCS#7 padding.
-        let aes_decrypt_key =
-            UnboundCipherKey::new(&AES_256, &aes_key[..]).map_err(unspecified_err)?;
+        let aes_decrypt_key = UnboundCipherKey::new(aes_alg, aes_key).map_err(unspecified_err)?;
         let aes_decrypt_key =
             PaddedBlockDecryptingKey::cbc_pkcs7(aes_decrypt_key).map_err(unspecified_err)?;
 
-        // Generate a random HMAC SHA256 key to use for HMAC authentication.
-        let hmac_key = hmac::Key::generate(hmac::HMAC_SHA256, &rand).map_err(unspecified_err)?;
-
-        // Generate a random key name.
-        let mut key_name = [0u8; 16];
-        rand.fill(&mut key_name)
-            .map_err(|_| GetRandomFailed)?;
-
         Ok(Self {
             aes_encrypt_key,
             aes_decrypt_key,
             hmac_key,
             key_name,
-            maximum_ciphertext_len: AtomicUsize::new(0),
+            mac_input,
+            maximum_ciphertext_len: AtomicUsize::new(maximum_ciphertext_len),
         })
     }
+
+    /// The data a ticket's MAC is calculated over.
+    fn mac_data(&self, key_name: &[u8], iv: &[u8], encrypted_state: &[u8]) -> Option<Vec<u8>> {
+        let mut hmac_data =
+            Vec::with_capacity(key_name.len() + iv.len() + 2 + encrypted_state.len());
+        hmac_data.extend(key_name);
+        hmac_data.extend(iv);
+        if let MacInput::Rfc5077 = self.mac_input {
+            hmac_data.extend(
+                u16::try_from(encrypted_state.len())
+                    .ok()?
+                    .to_be_bytes(),
+            );
+        }
+        hmac_data.extend(encrypted_state);
+        Some(hmac_data)
+    }
 }
 
 impl ProducesTickets for Rfc5077Ticketer {
@@ -129,16 +257,8 @@ impl ProducesTickets for Rfc5077Ticketer {
         //    key_name (16 octets) and IV (16 octets), followed by the length of
         //    the encrypted_state field (2 octets) and its contents (variable
         //    length)."
-        let mut hmac_data =
-            Vec::with_capacity(self.key_name.len() + iv.len() + 2 + encrypted_state.len());
-        hmac_data.extend(&self.key_name);
-        hmac_data.extend(iv);
-        hmac_data.extend(
-            u16::try_from(encrypted_state.len())
-                .ok()?
-                .to_be_bytes(),
-        );
-        hmac_data.extend(&encrypted_state);
+        // OpenSSL omits the length.
+        let hmac_data = self.mac_data(&self.key_name, iv, &encrypted_state)?;
         let tag = hmac::sign(&self.hmac_key, &hmac_data);
         let tag = tag.as_ref();
 
@@ -184,19 +304,10 @@ impl ProducesTickets for Rfc5077Ticketer {
             .algorithm()
             .digest_algorithm()
             .output_len();
-        let (enc_state, mac) = try_split_at(ciphertext, ciphertext.len() - tag_len)?;
+        let (enc_state, mac) = try_split_at(ciphertext, ciphertext.len().checked_sub(tag_len)?)?;
 
         // Reconstitute the HMAC data to verify the tag.
-        let mut hmac_data =
-            Vec::with_capacity(alleged_key_name.len() + iv.len() + 2 + enc_state.len());
-        hmac_data.extend(alleged_key_name);
-        hmac_data.extend(iv);
-        hmac_data.extend(
-            u16::try_from(enc_state.len())
-                .ok()?
-                .to_be_bytes(),
-        );
-        hmac_data.extend(enc_state);
+        let hmac_data = self.mac_data(alleged_key_name, iv, enc_state)?;
         hmac::verify(&self.hmac_key, &hmac_data, mac).ok()?;
 
         // Convert the raw IV back into an appropriate decryption context.
@@ -331,6 +442,159 @@ mod tests {
         assert_eq!(t.lifetime(), 0);
     }
 
+    #[test]
+    fn keyed_ticketers_share_tickets() {
+        let keys = key_set(1, None, &[]);
+        let a = Ticketer::new_with_keys(3600, keys.clone()).unwrap();
+        let b = Ticketer::new_with_keys(3600, keys).unwrap();
+        assert_eq!(a.lifetime(), 3600);
+
+        let cipher = a.encrypt(b"hello world").unwrap();
+        assert_eq!(&cipher[..16], &[1u8; 16]);
+        assert_eq!(b.decrypt(&cipher).unwrap(), b"hello world");
+    }
+
+    #[test]
+    fn keyed_ticketer_rotation() {
+        let a = Ticketer::new_with_keys(3600, key_set(1, Some(2), &[])).unwrap();
+        let b = Ticketer::new_with_keys(3600, key_set(2, None, &[1])).unwrap();
+
+        // `a` accepts tickets from `b`, which has already rotated, and vice versa
+        let cipher1 = a.encrypt(b"ticket 1").unwrap();
+        let cipher2 = b.encrypt(b"ticket 2").unwrap();
+        assert_eq!(a.decrypt(&cipher2).unwrap(), b"ticket 2");
+        assert_eq!(b.decrypt(&cipher1).unwrap(), b"ticket 1");
+
+        a.update(key_set(2, Some(3), &[1]))
+            .unwrap();
+        assert_eq!(&a.encrypt(b"ticket 3").unwrap()[..16], &[2u8; 16]);
+        assert_eq!(a.decrypt(&cipher1).unwrap(), b"ticket 1");
+
+        a.update(key_set(3, None, &[2]))
+            .unwrap();
+        assert!(a.decrypt(&cipher1).is_none());
+        assert_eq!(a.decrypt(&cipher2).unwrap(), b"ticket 2");
+    }
+
+    #[test]
+    fn keyed_ticketer_rejects_bad_keys() {
+        let mut keys = key_set(1, None, &[]);
+        keys.previous
+            .push(TicketKey::new([2u8; 16], &[0u8; 16]));
+        assert!(Ticketer::new_with_keys(3600, keys.clone()).is_err());
+
+        // 32 byte secrets are only for key files, which use AES 128
+        let mut short = key_set(1, None, &[]);
+        short
+            .previous
+            .push(TicketKey::new([2u8; 16], &[0u8; 32]));
+        assert_eq!(
+            Ticketer::new_with_keys(3600, short).err(),
+            Some(Error::General("ticket key must be 64 bytes".into()))
+        );
+
+        // a failed update leaves the existing keys in place
+        let t = Ticketer::new_with_keys(3600, key_set(1, None, &[])).unwrap();
+        let cipher = t.encrypt(b"hello world").unwrap();
+        assert!(t.update(keys).is_err());
+        assert_eq!(t.decrypt(&cipher).unwrap(), b"hello world");
+    }
+
+    #[test]
+    fn keyed_ticketer_rejects_duplicate_key_names() {
+        for keys in [
+            key_set(1, Some(1), &[]),
+            key_set(1, None, &[1]),
+            key_set(1, Some(2), &[3, 2]),
+            key_set(1, None, &[2, 2]),
+        ] {
+            assert!(Ticketer::new_with_keys(3600, keys.clone()).is_err());
+
+            let t = Ticketer::new_with_keys(3600, key_set(4, None, &[])).unwrap();
+            assert!(t.update(keys).is_err());
+        }
+    }
+
+    #[test]
+    fn key_file_ticketers() {
+        for len in [48, 80] {
+            let file = (0..len as u8).collect::<Vec<u8>>();
+            let key = TicketKey::from_key_file(&file).unwrap();
+            assert_eq!(key.name(), &file[..16]);
+
+            let t = Ticketer::new_with_key_files(
+                3600,
+                TicketKeySet {
+                    current: key,
+                    next: None,
+                    previous: Vec::new(),
+                },
+            )
+            .unwrap();
+            let cipher = t.encrypt(b"hello world").unwrap();
+            assert_eq!(&cipher[..16], &file[..16]);
+            assert_eq!(t.decrypt(&cipher).unwrap(), b"hello world");
+
+            // as in OpenSSL, the MAC covers the key name, IV and encrypted state,
+            // using the HMAC key from the file's layout.
+            let hmac_key = match len {
+                48 => &file[32..],
+                _ => &file[16..48],
+            };
+            let (data, mac) = cipher.split_at(cipher.len() - 32);
+            hmac::verify(&hmac::Key::new(hmac::HMAC_SHA256, hmac_key), data, mac).unwrap();
+        }
+
+        assert!(TicketKey::from_key_file(&[0u8; 64]).is_err());
+    }
+
+    #[test]
+    fn key_file_ticketers_decrypt_openssl_tickets() {
+        // Each ticket was issued by OpenSSL using the key file, via a callback
+        // doing what nginx's `ssl_session_ticket_key` support does.
+        for (file, ticket, state) in [
+            (
+                &include_bytes!("../../testdata/ticket-key-file.48.bin")[..],
+                &include_bytes!("../../testdata/openssl-ticket.48.bin")[..],
+                &include_bytes!("../../testdata/ticket-state.48.bin")[..],
+            ),
+            (
+                &include_bytes!("../../testdata/ticket-key-file.80.bin")[..],
+                &include_bytes!("../../testdata/openssl-ticket.80.bin")[..],
+                &include_bytes!("../../testdata/ticket-state.80.bin")[..],
+            ),
+        ] {
+            let t = Ticketer::new_with_key_files(
+                3600,
+                TicketKeySet {
+                    current: TicketKey::from_key_file(file).unwrap(),
+                    next: None,
+                    previous: Vec::new(),
+                },
+            )
+            .unwrap();
+            assert_eq!(t.decrypt(ticket).unwrap(), state);
+
+            let mut tampered = ticket.to_vec();
+            tampered[40] ^= 1;
+            assert_eq!(t.decrypt(&tampered), None);
+        }
+    }
+
+    fn key_set(current: u8, next: Option<u8>, previous: &[u8]) -> TicketKeySet {
+        let key = |n: u8| TicketKey::new([n; 16], &[n; KEY_LEN]);
+        TicketKeySet {
+            current: key(current),
+            next: next.map(key),
+            previous: previous
+                .iter()
+                .map(|n| key(*n))
+                .collect(),
+        }
+    }
+
+    const KEY_LEN: usize = 64;
+
     fn fail_generator() -> Result<Box<dyn ProducesTickets>, Error> {
         Err(Error::FailedToGetRandomBytes)
     }
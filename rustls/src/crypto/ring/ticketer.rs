@@ -14,6 +14,8 @@ use crate::log::debug;
 use crate::polyfill::try_split_at;
 use crate::server::ProducesTickets;
 use crate::sync::Arc;
+#[cfg(feature = "std")]
+use crate::ticketer::{TicketKey, TicketKeyRing, TicketKeySet};
 
 /// A concrete, safe ticket creation mechanism.
 #[non_exhaustive]
@@ -36,12 +38,81 @@ impl Ticketer {
             make_ticket_generator,
         )?))
     }
+
+    /// Make a `Ticketer` using externally supplied keys.
+    ///
+    /// This is suitable for sharing tickets across servers: see [`TicketKeyRing`].
+    /// Each key's secret must be 32 bytes, unlike the 64 bytes needed by the aws-lc-rs
+    /// provider's `Ticketer`: keys of any other length are rejected.  Tickets are
+    /// advertised to clients as lasting for `lifetime` seconds.
+    ///
+    /// The encryption mechanism used is Chacha20Poly1305.
+    #[cfg(feature = "std")]
+    pub fn new_with_keys(lifetime: u32, keys: TicketKeySet) -> Result<Arc<TicketKeyRing>, Error> {
+        Ok(Arc::new(TicketKeyRing::new(
+            lifetime,
+            keys,
+            make_keyed_ticketer,
+        )?))
+    }
+
+    /// Make a `Ticketer` using keys loaded from OpenSSL-style session ticket key
+    /// files, such as those used by nginx and HAProxy.
+    ///
+    /// Each key must come from [`TicketKey::from_key_file()`].  As those servers
+    /// do, the ticket layout starts with the key name, so keys can be shared and
+    /// rotated in step with them.  Tickets are advertised to clients as
+    /// lasting for `lifetime` seconds.
+    ///
+    /// The encryption mechanism used is AES-GCM, with the key file's AES key.
+    /// Note that tickets cannot be resumed across different server implementations,
+    /// as each encrypts its own, incompatible, session state.
+    #[cfg(feature = "std")]
+    pub fn new_with_key_files(
+        lifetime: u32,
+        keys: TicketKeySet,
+    ) -> Result<Arc<TicketKeyRing>, Error> {
+        Ok(Arc::new(TicketKeyRing::new(
+            lifetime,
+            keys,
+            make_key_file_ticketer,
+        )?))
+    }
 }
 
 fn make_ticket_generator() -> Result<Box<dyn ProducesTickets>, Error> {
     Ok(Box::new(AeadTicketer::new()?))
 }
 
+#[cfg(feature = "std")]
+fn make_keyed_ticketer(key: &TicketKey) -> Result<Box<dyn ProducesTickets>, Error> {
+    if key.secret().len() != TICKETER_AEAD.key_len() {
+        return Err(Error::General("ticket key must be 32 bytes".into()));
+    }
+    Ok(Box::new(AeadTicketer::with_key(
+        TICKETER_AEAD,
+        key,
+        key.secret(),
+    )?))
+}
+
+#[cfg(feature = "std")]
+fn make_key_file_ticketer(key: &TicketKey) -> Result<Box<dyn ProducesTickets>, Error> {
+    // The secret is an HMAC key followed by an AES key of the same length.
+    // AES-GCM needs only the latter.
+    let secret = key.secret();
+    let alg = match secret.len() {
+        32 => &aead::AES_128_GCM,
+        64 => &aead::AES_256_GCM,
+        _ => return Err(Error::General("ticket key must be 32 or 64 bytes".into())),
+    };
+    Ok(Box::new(AeadTicketer::with_key(
+        alg,
+        key,
+        &secret[secret.len() / 2..],
+    )?))
+}
+
 /// This is a `ProducesTickets` implementation which uses
 /// any *ring* `aead::Algorithm` to encrypt and authentication
 /// the ticket payload.  It does not enforce any lifetime
@@ -83,6 +154,27 @@ impl AeadTicketer {
             maximum_ciphertext_len: AtomicUsize::new(0),
         })
     }
+
+    /// Make a ticketer using `alg` with key material `secret`, which may be shared with other servers.
+    #[cfg(feature = "std")]
+    fn with_key(
+        alg: &'static aead::Algorithm,
+        key: &TicketKey,
+        secret: &[u8],
+    ) -> Result<Self, Error> {
+        let unbound = aead::UnboundKey::new(alg, secret)
+            .map_err(|_| Error::General("ticket key has wrong length".into()))?;
+
+        Ok(Self {
+            alg,
+            key: aead::LessSafeKey::new(unbound),
+            key_name: *key.name(),
+            // Tickets encrypted with this key may come from other servers,
+            // so we cannot learn the maximum length from our own encryptions.
+            // Instead, limit it to the largest possible ticket.
+            maximum_ciphertext_len: AtomicUsize::new(usize::from(u16::MAX)),
+        })
+    }
 }
 
 impl ProducesTickets for AeadTicketer {
@@ -306,6 +398,153 @@ mod tests {
         assert_eq!(t.lifetime(), 0);
     }
 
+    #[test]
+    fn keyed_ticketers_share_tickets() {
+        let keys = key_set(1, None, &[]);
+        let a = Ticketer::new_with_keys(3600, keys.clone()).unwrap();
+        let b = Ticketer::new_with_keys(3600, keys).unwrap();
+        assert_eq!(a.lifetime(), 3600);
+
+        let cipher = a.encrypt(b"hello world").unwrap();
+        assert_eq!(&cipher[..16], &[1u8; 16]);
+        assert_eq!(b.decrypt(&cipher).unwrap(), b"hello world");
+    }
+
+    #[test]
+    fn keyed_ticketer_rotation() {
+        let a = Ticketer::new_with_keys(3600, key_set(1, Some(2), &[])).unwrap();
+        let b = Ticketer::new_with_keys(3600, key_set(2, None, &[1])).unwrap();
+
+        // `a` accepts tickets from `b`, which has already rotated, and vice versa
+        let cipher1 = a.encrypt(b"ticket 1").unwrap();
+        let cipher2 = b.encrypt(b"ticket 2").unwrap();
+        assert_eq!(a.decrypt(&cipher2).unwrap(), b"ticket 2");
+        assert_eq!(b.decrypt(&cipher1).unwrap(), b"ticket 1");
+
+        a.update(key_set(2, Some(3), &[1]))
+            .unwrap();
+        assert_eq!(&a.encrypt(b"ticket 3").unwrap()[..16], &[2u8; 16]);
+        assert_eq!(a.decrypt(&cipher1).unwrap(), b"ticket 1");
+
+        a.update(key_set(3, None, &[2]))
+            .unwrap();
+        assert!(a.decrypt(&cipher1).is_none());
+        assert_eq!(a.decrypt(&cipher2).unwrap(), b"ticket 2");
+    }
+
+    #[test]
+    fn keyed_ticketer_rejects_bad_keys() {
+        let mut keys = key_set(1, None, &[]);
+        keys.previous
+            .push(TicketKey::new([2u8; 16], &[0u8; 16]));
+        assert!(Ticketer::new_with_keys(3600, keys.clone()).is_err());
+
+        // 64 byte secrets are for the aws-lc-rs ticketer
+        let mut long = key_set(1, None, &[]);
+        long.previous
+            .push(TicketKey::new([2u8; 16], &[0u8; 64]));
+        assert_eq!(
+            Ticketer::new_with_keys(3600, long).err(),
+            Some(Error::General("ticket key must be 32 bytes".into()))
+        );
+
+        // a failed update leaves the existing keys in place
+        let t = Ticketer::new_with_keys(3600, key_set(1, None, &[])).unwrap();
+        let cipher = t.encrypt(b"hello world").unwrap();
+        assert!(t.update(keys).is_err());
+        assert_eq!(t.decrypt(&cipher).unwrap(), b"hello world");
+    }
+
+    #[test]
+    fn keyed_ticketer_rejects_duplicate_key_names() {
+        for keys in [
+            key_set(1, Some(1), &[]),
+            key_set(1, None, &[1]),
+            key_set(1, Some(2), &[3, 2]),
+            key_set(1, None, &[2, 2]),
+        ] {
+            assert!(Ticketer::new_with_keys(3600, keys.clone()).is_err());
+
+            let t = Ticketer::new_with_keys(3600, key_set(4, None, &[])).unwrap();
+            assert!(t.update(keys).is_err());
+        }
+    }
+
+    #[test]
+    fn key_file_ticketers() {
+        for len in [48, 80] {
+            let file = (0..len as u8).collect::<Vec<u8>>();
+            let key = TicketKey::from_key_file(&file).unwrap();
+            assert_eq!(key.name(), &file[..16]);
+
+            // the secret is the HMAC key then the AES key, whichever the file's layout.
+            let secret = match len {
+                48 => [&file[32..], &file[16..32]].concat(),
+                _ => file[16..].to_vec(),
+            };
+            assert_eq!(key.secret(), secret);
+
+            let t = Ticketer::new_with_key_files(
+                3600,
+                TicketKeySet {
+                    current: key,
+                    next: None,
+                    previous: Vec::new(),
+                },
+            )
+            .unwrap();
+            let cipher = t.encrypt(b"hello world").unwrap();
+            assert_eq!(&cipher[..16], &file[..16]);
+            assert_eq!(t.decrypt(&cipher).unwrap(), b"hello world");
+        }
+
+        assert!(TicketKey::from_key_file(&[0u8; 64]).is_err());
+    }
+
+    #[test]
+    fn key_file_ticketers_use_aes_key_from_file() {
+        // *ring* has no AES-CBC, so cannot decrypt OpenSSL's tickets.  Instead,
+        // these tickets were made independently with AES-GCM, using the AES key
+        // from each key file's layout.
+        for (file, ticket, state) in [
+            (
+                &include_bytes!("../../testdata/ticket-key-file.48.bin")[..],
+                &include_bytes!("../../testdata/aes-gcm-ticket.48.bin")[..],
+                &include_bytes!("../../testdata/ticket-state.48.bin")[..],
+            ),
+            (
+                &include_bytes!("../../testdata/ticket-key-file.80.bin")[..],
+                &include_bytes!("../../testdata/aes-gcm-ticket.80.bin")[..],
+                &include_bytes!("../../testdata/ticket-state.80.bin")[..],
+            ),
+        ] {
+            let t = Ticketer::new_with_key_files(
+                3600,
+                TicketKeySet {
+                    current: TicketKey::from_key_file(file).unwrap(),
+                    next: None,
+                    previous: Vec::new(),
+                },
+            )
+            .unwrap();
+            assert_eq!(t.decrypt(ticket).unwrap(), state);
+        }
+    }
+
+    fn key_set(current: u8, next: Option<u8>, previous: &[u8]) -> TicketKeySet {
+        let key = |n: u8| TicketKey::new([n; 16], &[n; KEY_LEN]);
+        TicketKeySet {
+            current: key(current),
+            next: next.map(key),
+            previous: previous
+                .iter()
+                .map(|n| key(*n))
+                .collect(),
+        }
+    }
+
+    const KEY_LEN: usize = 32;
+
     fn fail_generator() -> Result<Box<dyn ProducesTickets>, Error> {
         Err(Error::FailedToGetRandomBytes)
     }
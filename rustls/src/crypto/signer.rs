@@ -6,6 +6,7 @@ use pki_types::{AlgorithmIdentifier, CertificateDer, PrivateKeyDer, SubjectPubli
 
 use super::CryptoProvider;
 use crate::client::ResolvesClientCert;
+use crate::delegated_credential::DelegatedKey;
 use crate::enums::{SignatureAlgorithm, SignatureScheme};
 use crate::error::{Error, InconsistentKeys};
 use crate::server::{ClientHello, ParsedCertificate, ResolvesServerCert};
@@ -85,6 +86,24 @@ pub trait Signer: Debug + Send + Sync {
 
     /// Reveals which scheme will be used when you call [`Self::sign()`].
     fn scheme(&self) -> SignatureScheme;
+
+    /// Whether handshake signatures are made by the application, asynchronously.
+    ///
+    /// This allows keys held elsewhere, for instance by a remote key management service,
+    /// to be used without blocking.  If this returns `true`, unbuffered connections do not
+    /// call [`Self::sign()`] to sign the handshake: instead they yield
+    /// [`ConnectionState::SignatureRequired`], and the handshake continues once the
+    /// application provides the signature.
+    ///
+    /// Buffered connections always call [`Self::sign()`], as do post-handshake client
+    /// authentication and exported authenticators.
+    ///
+    /// The default implementation returns `false`.
+    ///
+    /// [`ConnectionState::SignatureRequired`]: crate::unbuffered::ConnectionState::SignatureRequired
+    fn is_async(&self) -> bool {
+        false
+    }
 }
 
 /// Server certificate resolver which always resolves to the same certificate and key.
@@ -147,6 +166,21 @@ pub struct CertifiedKey {
     /// An optional OCSP response from the certificate issuer,
     /// attesting to its continued validity.
     pub ocsp: Option<Vec<u8>>,
+
+    /// An optional list of signed certificate timestamps (SCTs) for the end-entity
+    /// certificate, in the TLS encoding of a `SignedCertificateTimestampList`
+    /// described in [RFC 6962 section 3.3].
+    ///
+    /// This is sent to clients that ask for SCTs.
+    ///
+    /// [RFC 6962 section 3.3]: https://datatracker.ietf.org/doc/html/rfc6962#section-3.3
+    pub sct_list: Option<Vec<u8>>,
+
+    /// An optional delegated credential for the end-entity certificate,
+    /// with its private key.
+    ///
+    /// This is used in preference to `key` with TLS1.3 clients that support it.
+    pub delegated_credential: Option<DelegatedKey>,
 }
 
 impl CertifiedKey {
@@ -199,6 +233,8 @@ impl CertifiedKey {
                 cert_chain,
                 key,
                 ocsp: None,
+                sct_list: None,
+                delegated_credential: None,
             }),
         }
     }
@@ -218,6 +254,8 @@ impl CertifiedKey {
             cert_chain,
             key,
             ocsp: None,
+            sct_list: None,
+            delegated_credential: None,
         }
     }
 
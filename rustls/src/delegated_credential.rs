@@ -0,0 +1,245 @@
+use alloc::vec::Vec;
+
+use pki_types::{CertificateDer, SubjectPublicKeyInfoDer};
+
+use crate::crypto::signer::{Signer, SigningKey};
+use crate::enums::SignatureScheme;
+use crate::error::{Error, InconsistentKeys, InvalidMessage};
+use crate::msgs::base::{Payload, PayloadU16, PayloadU24};
+use crate::msgs::codec::{Codec, Reader};
+use crate::sync::Arc;
+
+/// A delegated credential, as described in [RFC 9345].
+///
+/// A delegated credential is a short-lived public key, signed by the key of an
+/// end-entity certificate.  A server holding a delegated credential and its private
+/// key can authenticate with them to clients that support delegated credentials,
+/// without access to the certificate's private key.
+///
+/// See [`DelegatedKey`] for how a server uses a delegated credential, and
+/// [`ServerCertVerifier::verify_delegated_credential()`] for how a client
+/// verifies one.
+///
+/// [RFC 9345]: https://datatracker.ietf.org/doc/html/rfc9345
+/// [`ServerCertVerifier::verify_delegated_credential()`]: crate::client::danger::ServerCertVerifier::verify_delegated_credential
+#[derive(Clone, Debug, PartialEq)]
+pub struct DelegatedCredential {
+    valid_time: u32,
+    dc_cert_verify_algorithm: SignatureScheme,
+    public_key: PayloadU24<'static>,
+    algorithm: SignatureScheme,
+    signature: PayloadU16,
+}
+
+impl DelegatedCredential {
+    /// Make and sign a new delegated credential.
+    ///
+    /// `end_entity` is the certificate delegating authority, and `signer` must
+    /// sign with its private key.
+    ///
+    /// `valid_time` is the time, in seconds, after the `notBefore` time of
+    /// `end_entity` at which the credential expires.  Clients reject credentials
+    /// that are valid for more than seven days in their future.
+    ///
+    /// `public_key` is the delegated public key, and `dc_cert_verify_algorithm`
+    /// the signature scheme its private key signs `CertificateVerify` messages with.
+    pub fn new(
+        end_entity: &CertificateDer<'_>,
+        signer: &dyn Signer,
+        valid_time: u32,
+        dc_cert_verify_algorithm: SignatureScheme,
+        public_key: SubjectPublicKeyInfoDer<'_>,
+    ) -> Result<Self, Error> {
+        let mut credential = Self {
+            valid_time,
+            dc_cert_verify_algorithm,
+            public_key: PayloadU24(Payload::new(public_key.as_ref())),
+            algorithm: signer.scheme(),
+            signature: PayloadU16::new(Vec::new()),
+        };
+
+        credential.signature =
+            PayloadU16::new(signer.sign(&credential.signed_message(end_entity))?);
+        Ok(credential)
+    }
+
+    /// The message signed by the end-entity certificate's key.
+    ///
+    /// This is used by [`ServerCertVerifier`] implementations to verify
+    /// [`DelegatedCredential::signature()`].
+    ///
+    /// [`ServerCertVerifier`]: crate::client::danger::ServerCertVerifier
+    pub fn signed_message(&self, end_entity: &CertificateDer<'_>) -> Vec<u8> {
+        let mut message = Vec::with_capacity(200 + end_entity.len());
+        message.resize(64, 0x20u8);
+        message.extend_from_slice(b"TLS, server delegated credentials\x00");
+        message.extend_from_slice(end_entity.as_ref());
+        self.encode_credential(&mut message);
+        self.algorithm.encode(&mut message);
+        message
+    }
+
+    /// The time, in seconds after the `notBefore` time of the end-entity certificate,
+    /// at which this credential expires.
+    pub fn valid_time(&self) -> u32 {
+        self.valid_time
+    }
+
+    /// The signature scheme used by the delegated key to sign `CertificateVerify` messages.
+    pub fn dc_cert_verify_algorithm(&self) -> SignatureScheme {
+        self.dc_cert_verify_algorithm
+    }
+
+    /// The delegated public key.
+    pub fn public_key(&self) -> SubjectPublicKeyInfoDer<'_> {
+        SubjectPublicKeyInfoDer::from(self.public_key.0.bytes())
+    }
+
+    /// The signature scheme used by the end-entity certificate's key to sign this credential.
+    pub fn algorithm(&self) -> SignatureScheme {
+        self.algorithm
+    }
+
+    /// The end-entity certificate's signature over this credential.
+    pub fn signature(&self) -> &[u8] {
+        &self.signature.0
+    }
+
+    /// The TLS encoding of this credential.
+    ///
+    /// This can be parsed again with [`DelegatedCredential::try_from()`].
+    pub fn to_bytes(&self) -> Vec<u8> {
+        self.get_encoding()
+    }
+
+    fn encode_credential(&self, bytes: &mut Vec<u8>) {
+        self.valid_time.encode(bytes);
+        self.dc_cert_verify_algorithm
+            .encode(bytes);
+        self.public_key.encode(bytes);
+    }
+}
+
+impl Codec<'_> for DelegatedCredential {
+    fn encode(&self, bytes: &mut Vec<u8>) {
+        self.encode_credential(bytes);
+        self.algorithm.encode(bytes);
+        self.signature.encode(bytes);
+    }
+
+    fn read(r: &mut Reader<'_>) -> Result<Self, InvalidMessage> {
+        let valid_time = u32::read(r)?;
+        let dc_cert_verify_algorithm = SignatureScheme::read(r)?;
+        let public_key = PayloadU24::read(r)?.into_owned();
+        let algorithm = SignatureScheme::read(r)?;
+        let signature = PayloadU16::read(r)?;
+
+        if public_key.0.bytes().is_empty() || signature.0.is_empty() {
+            return Err(InvalidMessage::InvalidEmptyPayload);
+        }
+
+        Ok(Self {
+            valid_time,
+            dc_cert_verify_algorithm,
+            public_key,
+            algorithm,
+            signature,
+        })
+    }
+}
+
+impl TryFrom<&[u8]> for DelegatedCredential {
+    type Error = Error;
+
+    /// Parse a delegated credential from its TLS encoding.
+    fn try_from(value: &[u8]) -> Result<Self, Self::Error> {
+        let mut r = Reader::init(value);
+        let credential = Self::read(&mut r)?;
+        r.expect_empty("DelegatedCredential")?;
+        Ok(credential)
+    }
+}
+
+/// A [`DelegatedCredential`] and the private key it delegates authority to.
+///
+/// This is attached to a [`CertifiedKey`] with
+/// [`CertifiedKey::delegated_credential`].  When a TLS1.3 client supports the
+/// credential's signature schemes, a server sends the credential with its
+/// certificate and signs the handshake with this key instead of the
+/// certificate's key.
+///
+/// [`CertifiedKey`]: crate::sign::CertifiedKey
+/// [`CertifiedKey::delegated_credential`]: crate::sign::CertifiedKey::delegated_credential
+#[non_exhaustive]
+#[derive(Clone, Debug)]
+pub struct DelegatedKey {
+    /// The delegated credential.
+    pub credential: DelegatedCredential,
+
+    /// The private key matching the credential's public key.
+    pub key: Arc<dyn SigningKey>,
+}
+
+impl DelegatedKey {
+    /// Make a new `DelegatedKey`.
+    ///
+    /// The `key`'s public key must match that of the `credential`.  If the `key`
+    /// does not have a public key, this will return an `InconsistentKeys::Unknown`
+    /// error.
+    pub fn new(credential: DelegatedCredential, key: Arc<dyn SigningKey>) -> Result<Self, Error> {
+        match key.public_key() {
+            None => Err(Error::InconsistentKeys(InconsistentKeys::Unknown)),
+            Some(spki) if spki != credential.public_key() => {
+                Err(Error::InconsistentKeys(InconsistentKeys::KeyMismatch))
+            }
+            Some(_) => Ok(Self { credential, key }),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sample_credential() -> DelegatedCredential {
+        DelegatedCredential {
+            valid_time: 0x1234,
+            dc_cert_verify_algorithm: SignatureScheme::ECDSA_NISTP256_SHA256,
+            public_key: PayloadU24(Payload::new(&b"spki"[..])),
+            algorithm: SignatureScheme::ED25519,
+            signature: PayloadU16::new(b"sig".to_vec()),
+        }
+    }
+
+    #[test]
+    fn delegated_credential_round_trips() {
+        let credential = sample_credential();
+        let bytes = credential.to_bytes();
+        assert_eq!(
+            bytes,
+            b"\x00\x00\x12\x34\x04\x03\x00\x00\x04spki\x08\x07\x00\x03sig"
+        );
+        assert_eq!(
+            DelegatedCredential::try_from(&bytes[..]).unwrap(),
+            credential
+        );
+    }
+
+    #[test]
+    fn delegated_credential_rejects_trailing_data() {
+        let mut bytes = sample_credential().to_bytes();
+        bytes.push(0);
+        assert!(DelegatedCredential::try_from(&bytes[..]).is_err());
+    }
+
+    #[test]
+    fn signed_message_covers_certificate_and_credential() {
+        let credential = sample_credential();
+        let message = credential.signed_message(&CertificateDer::from(&b"cert"[..]));
+        assert_eq!(&message[..64], &[0x20; 64][..]);
+        assert_eq!(
+            &message[64..],
+            &b"TLS, server delegated credentials\x00cert\x00\x00\x12\x34\x04\x03\x00\x00\x04spki\x08\x07"[..]
+        );
+    }
+}
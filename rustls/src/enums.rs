@@ -67,6 +67,7 @@ enum_builder! {
         ServerHelloDone => 0x0e,
         CertificateVerify => 0x0f,
         ClientKeyExchange => 0x10,
+        ClientCertificateRequest => 0x11,
         Finished => 0x14,
         CertificateURL => 0x15,
         CertificateStatus => 0x16,
@@ -574,6 +575,35 @@ enum_builder! {
     }
 }
 
+enum_builder! {
+    /// The `MaxFragmentLength` enum sent in the max_fragment_length extension.
+    /// Values in this enum are taken from [RFC 6066 Section 4].
+    ///
+    /// [RFC 6066 Section 4]: <https://datatracker.ietf.org/doc/html/rfc6066#section-4>
+    #[repr(u8)]
+    pub enum MaxFragmentLength {
+        Length512 => 1,
+        Length1024 => 2,
+        Length2048 => 3,
+        Length4096 => 4,
+    }
+}
+
+impl MaxFragmentLength {
+    /// The largest record plaintext this value allows, in bytes.
+    ///
+    /// Returns `None` for unknown values.
+    pub fn payload_len(&self) -> Option<usize> {
+        match self {
+            Self::Length512 => Some(512),
+            Self::Length1024 => Some(1024),
+            Self::Length2048 => Some(2048),
+            Self::Length4096 => Some(4096),
+            Self::Unknown(_) => None,
+        }
+    }
+}
+
 enum_builder! {
     /// The `CertificateType` enum sent in the cert_type extensions.
     /// Values in this enum are taken from the various RFCs covering TLS, and are listed by IANA.
@@ -619,6 +649,10 @@ mod tests {
             CertificateCompressionAlgorithm::Zstd,
         );
         test_enum8::<CertificateType>(CertificateType::X509, CertificateType::RawPublicKey);
+        test_enum8::<MaxFragmentLength>(
+            MaxFragmentLength::Length512,
+            MaxFragmentLength::Length4096,
+        );
     }
 
     #[test]
@@ -96,8 +96,9 @@ pub enum Error {
     /// An incoming connection did not support any known application protocol.
     NoApplicationProtocol,
 
-    /// The `max_fragment_size` value supplied in configuration was too small,
-    /// or too large.
+    /// The `max_fragment_size` or `record_size_limit` value supplied in
+    /// configuration was too small, or too large, or the `max_fragment_length`
+    /// value was unknown.
     BadMaxFragmentSize,
 
     /// Specific failure cases from [`CertifiedKey::new()`] or a
@@ -253,6 +254,7 @@ pub enum PeerMisbehaved {
     EarlyDataAttemptedInSecondClientHello,
     EarlyDataExtensionWithoutResumption,
     EarlyDataOfferedWithVariedCipherSuite,
+    EncryptedClientHelloVariedAfterRetry,
     HandshakeHashVariedAfterRetry,
     IllegalHelloRetryRequestWithEmptyCookie,
     IllegalHelloRetryRequestWithNoChanges,
@@ -265,8 +267,15 @@ pub enum PeerMisbehaved {
     IllegalMiddleboxChangeCipherSpec,
     IllegalTlsInnerPlaintext,
     IncorrectBinder,
+    IncorrectCertificateRequestContext,
     InvalidCertCompression,
+    InvalidDelegatedCredential,
+    InvalidEncryptedClientHello,
+    InvalidEncryptedClientHelloInner,
+    InvalidHelloRetryRequestCookie,
     InvalidMaxEarlyDataSize,
+    InvalidMaxFragmentLength,
+    InvalidRecordSizeLimit,
     InvalidKeyShare,
     KeyEpochWithPendingFragment,
     KeyUpdateReceivedInQuicConnection,
@@ -290,12 +299,14 @@ pub enum PeerMisbehaved {
     ResumptionOfferedWithIncompatibleCipherSuite,
     SelectedDifferentCipherSuiteAfterRetry,
     SelectedInvalidPsk,
+    SelectedPskWithIncompatibleCipherSuite,
     SelectedTls12UsingTls13VersionExtension,
     SelectedUnofferedApplicationProtocol,
     SelectedUnofferedCertCompression,
     SelectedUnofferedCipherSuite,
     SelectedUnofferedCompression,
     SelectedUnofferedKxGroup,
+    SelectedUnofferedMaxFragmentLength,
     SelectedUnofferedPsk,
     SelectedUnusableCipherSuiteForVersion,
     ServerEchoedCompatibilitySessionId,
@@ -312,6 +323,7 @@ pub enum PeerMisbehaved {
     UnexpectedCleartextExtension,
     UnsolicitedCertExtension,
     UnsolicitedEncryptedExtension,
+    UnsolicitedPostHandshakeCertificateRequest,
     UnsolicitedSctList,
     UnsolicitedServerHelloExtension,
     WrongGroupForKeyShare,
@@ -345,6 +357,7 @@ pub enum PeerIncompatible {
     NoKxGroupsInCommon,
     NoSignatureSchemesInCommon,
     NullCompressionRequired,
+    PostHandshakeAuthNotOffered,
     ServerDoesNotSupportTls12Or13,
     ServerSentHelloRetryRequestWithUnknownExtension,
     ServerTlsVersionIsDisabledByOurConfig,
@@ -504,6 +517,22 @@ pub enum CertificateError {
     /// [`ServerCertVerifier::verify_server_cert()`]: crate::client::danger::ServerCertVerifier::verify_server_cert
     InvalidOcspResponse,
 
+    /// The certificate requires a valid OCSP response to be stapled ("OCSP Must-Staple"),
+    /// but none was provided.
+    ///
+    /// This maps to [`AlertDescription::BadCertificateStatusResponse`].
+    OcspResponseRequired,
+
+    /// The certificate does not have enough valid signed certificate timestamps
+    /// to satisfy a Certificate Transparency policy.
+    ///
+    /// This should be returned from
+    /// [`ServerCertVerifier::verify_signed_certificate_timestamps()`] when a verifier
+    /// enforces such a policy.
+    ///
+    /// [`ServerCertVerifier::verify_signed_certificate_timestamps()`]: crate::client::danger::ServerCertVerifier::verify_signed_certificate_timestamps
+    CertificateTransparencyRequired,
+
     /// The certificate is valid, but the handshake is rejected for other
     /// reasons.
     ApplicationVerificationFailure,
@@ -604,6 +633,8 @@ impl PartialEq<Self> for CertificateError {
                 },
             ) => (left_required, left_presented) == (right_required, right_presented),
             (InvalidOcspResponse, InvalidOcspResponse) => true,
+            (OcspResponseRequired, OcspResponseRequired) => true,
+            (CertificateTransparencyRequired, CertificateTransparencyRequired) => true,
             (ApplicationVerificationFailure, ApplicationVerificationFailure) => true,
             (UnknownRevocationStatus, UnknownRevocationStatus) => true,
             (ExpiredRevocationList, ExpiredRevocationList) => true,
@@ -646,7 +677,7 @@ impl From<CertificateError> for AlertDescription {
             | UnknownRevocationStatus
             | ExpiredRevocationList
             | ExpiredRevocationListContext { .. } => Self::UnknownCA,
-            InvalidOcspResponse => Self::BadCertificateStatusResponse,
+            InvalidOcspResponse | OcspResponseRequired => Self::BadCertificateStatusResponse,
             #[allow(deprecated)]
             BadSignature
             | UnsupportedSignatureAlgorithm
@@ -658,7 +689,7 @@ impl From<CertificateError> for AlertDescription {
             // certificate_unknown
             //  Some other (unspecified) issue arose in processing the
             //  certificate, rendering it unacceptable.
-            Other(..) => Self::CertificateUnknown,
+            CertificateTransparencyRequired | Other(..) => Self::CertificateUnknown,
         }
     }
 }
@@ -1006,7 +1037,10 @@ impl fmt::Display for Error {
             Self::FailedToGetCurrentTime => write!(f, "failed to get current time"),
             Self::FailedToGetRandomBytes => write!(f, "failed to get random bytes"),
             Self::BadMaxFragmentSize => {
-                write!(f, "the supplied max_fragment_size was too small or large")
+                write!(
+                    f,
+                    "the supplied max_fragment_size, record_size_limit or max_fragment_length was invalid"
+                )
             }
             Self::InconsistentKeys(why) => {
                 write!(f, "keys may not be consistent: {why:?}")
@@ -1227,6 +1261,11 @@ mod tests {
             ApplicationVerificationFailure
         );
         assert_eq!(InvalidOcspResponse, InvalidOcspResponse);
+        assert_eq!(OcspResponseRequired, OcspResponseRequired);
+        assert_eq!(
+            CertificateTransparencyRequired,
+            CertificateTransparencyRequired
+        );
         let other = Other(OtherError(
             #[cfg(feature = "std")]
             Arc::from(Box::from("")),
@@ -1352,6 +1391,7 @@ mod tests {
             }
             .into(),
             super::CertificateError::InvalidOcspResponse.into(),
+            super::CertificateError::OcspResponseRequired.into(),
             Error::General("undocumented error".to_string()),
             Error::FailedToGetCurrentTime,
             Error::FailedToGetRandomBytes,
@@ -0,0 +1,481 @@
+use alloc::vec;
+use alloc::vec::Vec;
+
+use pki_types::{CertificateDer, ServerName, UnixTime};
+use subtle::ConstantTimeEq;
+
+use crate::common_state::{CommonState, Side};
+use crate::conn::ConnectionCore;
+use crate::crypto::tls13::OkmBlock;
+use crate::crypto::{hash, hmac};
+use crate::enums::{HandshakeType, ProtocolVersion, SignatureScheme};
+use crate::error::{Error, InvalidMessage, PeerIncompatible, PeerMisbehaved};
+use crate::msgs::base::{Payload, PayloadU8};
+use crate::msgs::codec::{Codec, Reader};
+use crate::msgs::handshake::{
+    CertificatePayloadTls13, CertificateRequestExtensions, CertificateRequestPayloadTls13,
+    DistinguishedName, HandshakeMessagePayload, HandshakePayload,
+};
+use crate::sign::CertifiedKey;
+use crate::tls13::Tls13CipherSuite;
+use crate::verify::{ClientCertVerifier, DigitallySignedStruct, ServerCertVerifier};
+
+/// A request for an exported authenticator, as described in [RFC 9261 section 4].
+///
+/// A server makes these with [`ServerConnection::exported_authenticator_request()`],
+/// and a client with [`ClientConnection::exported_authenticator_request()`].
+/// The encoding from [`AuthenticatorRequest::as_bytes()`] is sent to the peer by
+/// the application (for example, in an HTTP/2 frame), which decodes it with
+/// `AuthenticatorRequest::try_from()` and answers it with an authenticator.
+///
+/// The requesting side must keep its request to validate the authenticator it
+/// receives in reply.
+///
+/// [RFC 9261 section 4]: https://datatracker.ietf.org/doc/html/rfc9261#section-4
+/// [`ServerConnection::exported_authenticator_request()`]: crate::ServerConnection::exported_authenticator_request
+/// [`ClientConnection::exported_authenticator_request()`]: crate::ClientConnection::exported_authenticator_request
+#[derive(Clone, Debug)]
+pub struct AuthenticatorRequest {
+    typ: HandshakeType,
+    context: Vec<u8>,
+    signature_schemes: Vec<SignatureScheme>,
+    certificate_authorities: Vec<DistinguishedName>,
+    encoding: Vec<u8>,
+}
+
+impl AuthenticatorRequest {
+    /// Make a request to send on the connection `common`.
+    ///
+    /// Servers send a `CertificateRequest`, and clients a `ClientCertificateRequest`.
+    pub(crate) fn new(
+        common: &CommonState,
+        context: Vec<u8>,
+        signature_schemes: Vec<SignatureScheme>,
+        certificate_authorities: Vec<DistinguishedName>,
+    ) -> Result<Self, Error> {
+        tls13_suite(common)?;
+
+        if context.len() > usize::from(u8::MAX) {
+            return Err(Error::General(
+                "authenticator request context is too long".into(),
+            ));
+        }
+
+        let signature_schemes = signature_schemes
+            .into_iter()
+            .filter(SignatureScheme::supported_in_tls13)
+            .collect::<Vec<_>>();
+        if signature_schemes.is_empty() {
+            return Err(Error::General(
+                "no signature schemes usable for exported authenticators".into(),
+            ));
+        }
+
+        let payload = CertificateRequestPayloadTls13 {
+            context: PayloadU8::new(context.clone()),
+            extensions: CertificateRequestExtensions {
+                signature_algorithms: Some(signature_schemes.clone()),
+                authority_names: match certificate_authorities.is_empty() {
+                    true => None,
+                    false => Some(certificate_authorities.clone()),
+                },
+                ..Default::default()
+            },
+        };
+
+        let (typ, payload) = match common.side {
+            Side::Server => (
+                HandshakeType::CertificateRequest,
+                HandshakePayload::CertificateRequestTls13(payload),
+            ),
+            Side::Client => (
+                HandshakeType::ClientCertificateRequest,
+                HandshakePayload::ClientCertificateRequest(payload),
+            ),
+        };
+
+        Ok(Self {
+            typ,
+            context,
+            signature_schemes,
+            certificate_authorities,
+            encoding: HandshakeMessagePayload(payload).get_encoding(),
+        })
+    }
+
+    /// The `certificate_request_context` of this request.
+    ///
+    /// This is echoed in the authenticator made in reply.
+    pub fn context(&self) -> &[u8] {
+        &self.context
+    }
+
+    /// The signature schemes the requester can verify.
+    pub fn signature_schemes(&self) -> &[SignatureScheme] {
+        &self.signature_schemes
+    }
+
+    /// The distinguished names of certificate authorities the requester trusts.
+    ///
+    /// This may be empty, in which case the requester gave no hint.
+    pub fn certificate_authorities(&self) -> &[DistinguishedName] {
+        &self.certificate_authorities
+    }
+
+    /// The encoding of this request, to be sent to the peer.
+    pub fn as_bytes(&self) -> &[u8] {
+        &self.encoding
+    }
+
+    /// Which peer answers this request with an authenticator.
+    fn authenticator_sender(&self) -> Side {
+        match self.typ {
+            HandshakeType::ClientCertificateRequest => Side::Server,
+            _ => Side::Client,
+        }
+    }
+}
+
+impl TryFrom<&[u8]> for AuthenticatorRequest {
+    type Error = Error;
+
+    /// Decode a request received from the peer.
+    fn try_from(bytes: &[u8]) -> Result<Self, Self::Error> {
+        let mut r = Reader::init(bytes);
+        let (typ, payload) = match read_message(&mut r)? {
+            HandshakePayload::CertificateRequestTls13(payload) => {
+                (HandshakeType::CertificateRequest, payload)
+            }
+            HandshakePayload::ClientCertificateRequest(payload) => {
+                (HandshakeType::ClientCertificateRequest, payload)
+            }
+            _ => return Err(InvalidMessage::UnexpectedMessage("AuthenticatorRequest").into()),
+        };
+        r.expect_empty("AuthenticatorRequest")?;
+        let CertificateRequestExtensions {
+            signature_algorithms,
+            authority_names,
+            ..
+        } = payload.extensions;
+
+        Ok(Self {
+            typ,
+            context: payload.context.0,
+            signature_schemes: signature_algorithms
+                .ok_or(InvalidMessage::NoSignatureSchemes)?
+                .into_iter()
+                .filter(SignatureScheme::supported_in_tls13)
+                .collect(),
+            certificate_authorities: authority_names.unwrap_or_default(),
+            encoding: bytes.to_vec(),
+        })
+    }
+}
+
+/// Make an authenticator in reply to `request`, to send on the connection `core`.
+///
+/// If `certified_key` is `None` this is an empty authenticator, which declines
+/// the request.
+pub(crate) fn make_authenticator<Data>(
+    core: &ConnectionCore<Data>,
+    request: &AuthenticatorRequest,
+    certified_key: Option<&CertifiedKey>,
+) -> Result<Vec<u8>, Error> {
+    let keys = AuthenticatorKeys::new(core, core.common_state.side, request)?;
+    let mut authenticator = Vec::new();
+
+    if let Some(certified_key) = certified_key {
+        let signer = certified_key
+            .key
+            .choose_scheme(&request.signature_schemes)
+            .ok_or(PeerIncompatible::NoCertificateRequestSignatureSchemesInCommon)?;
+
+        let mut certificate = CertificatePayloadTls13::new(certified_key.cert_chain.iter(), None);
+        certificate.context = PayloadU8::new(request.context.clone());
+        HandshakeMessagePayload(HandshakePayload::CertificateTls13(certificate))
+            .encode(&mut authenticator);
+
+        let message = construct_verify_message(&keys.transcript_hash(request, &authenticator));
+        let dss = DigitallySignedStruct::new(signer.scheme(), signer.sign(&message)?);
+        HandshakeMessagePayload(HandshakePayload::CertificateVerify(dss))
+            .encode(&mut authenticator);
+    }
+
+    let verify_data = keys.finished(request, &authenticator);
+    HandshakeMessagePayload(HandshakePayload::Finished(Payload::new(
+        verify_data.as_ref(),
+    )))
+    .encode(&mut authenticator);
+
+    Ok(authenticator)
+}
+
+/// Check an authenticator sent by the server in reply to `request`.
+///
+/// Returns the server's certificate chain, or `None` for an empty authenticator.
+pub(crate) fn validate_server_authenticator<Data>(
+    core: &ConnectionCore<Data>,
+    request: &AuthenticatorRequest,
+    authenticator: &[u8],
+    server_name: &ServerName<'_>,
+    verifier: &dyn ServerCertVerifier,
+    now: UnixTime,
+) -> Result<Option<Vec<CertificateDer<'static>>>, Error> {
+    let keys = AuthenticatorKeys::new(core, Side::Server, request)?;
+    let Some(received) = keys.read(request, authenticator)? else {
+        return Ok(None);
+    };
+
+    verifier.verify_server_cert(
+        &received.end_entity,
+        &received.intermediates,
+        server_name,
+        &received.ocsp,
+        now,
+    )?;
+    verifier.verify_tls13_signature(
+        &received.verify_message,
+        &received.end_entity,
+        &received.signature,
+    )?;
+    Ok(Some(received.into_chain()))
+}
+
+/// Check an authenticator sent by the client in reply to `request`.
+///
+/// Returns the client's certificate chain, or `None` for an empty authenticator
+/// if `verifier` does not make client authentication mandatory.
+pub(crate) fn validate_client_authenticator<Data>(
+    core: &ConnectionCore<Data>,
+    request: &AuthenticatorRequest,
+    authenticator: &[u8],
+    verifier: &dyn ClientCertVerifier,
+    now: UnixTime,
+) -> Result<Option<Vec<CertificateDer<'static>>>, Error> {
+    let keys = AuthenticatorKeys::new(core, Side::Client, request)?;
+    let Some(received) = keys.read(request, authenticator)? else {
+        return match verifier.client_auth_mandatory() {
+            true => Err(Error::NoCertificatesPresented),
+            false => Ok(None),
+        };
+    };
+
+    verifier.verify_client_cert(&received.end_entity, &received.intermediates, now)?;
+    verifier.verify_tls13_signature(
+        &received.verify_message,
+        &received.end_entity,
+        &received.signature,
+    )?;
+    Ok(Some(received.into_chain()))
+}
+
+fn tls13_suite(common: &CommonState) -> Result<&'static Tls13CipherSuite, Error> {
+    if common.is_handshaking() {
+        return Err(Error::HandshakeNotComplete);
+    }
+
+    common
+        .suite
+        .and_then(|suite| suite.tls13())
+        .ok_or_else(|| Error::General("exported authenticators require TLS1.3".into()))
+}
+
+/// The keys for an authenticator, derived from the connection's exporter.
+///
+/// See [RFC 9261 section 5.1].
+///
+/// [RFC 9261 section 5.1]: https://datatracker.ietf.org/doc/html/rfc9261#section-5.1
+struct AuthenticatorKeys {
+    suite: &'static Tls13CipherSuite,
+    handshake_context: Vec<u8>,
+    finished_key: OkmBlock,
+}
+
+impl AuthenticatorKeys {
+    fn new<Data>(
+        core: &ConnectionCore<Data>,
+        sender: Side,
+        request: &AuthenticatorRequest,
+    ) -> Result<Self, Error> {
+        if request.authenticator_sender() != sender {
+            return Err(Error::InappropriateHandshakeMessage {
+                expect_types: vec![match sender {
+                    Side::Client => HandshakeType::CertificateRequest,
+                    Side::Server => HandshakeType::ClientCertificateRequest,
+                }],
+                got_type: request.typ,
+            });
+        }
+
+        let suite = tls13_suite(&core.common_state)?;
+        let (context_label, finished_label): (&[u8], &[u8]) = match sender {
+            Side::Client => (
+                b"EXPORTER-client authenticator handshake context",
+                b"EXPORTER-client authenticator finished key",
+            ),
+            Side::Server => (
+                b"EXPORTER-server authenticator handshake context",
+                b"EXPORTER-server authenticator finished key",
+            ),
+        };
+
+        let len = suite.common.hash_provider.output_len();
+        let handshake_context = core.export_keying_material(vec![0u8; len], context_label, None)?;
+        let mut finished_key = core.export_keying_material(vec![0u8; len], finished_label, None)?;
+        let okm = OkmBlock::new(&finished_key);
+        finished_key.fill(0);
+
+        Ok(Self {
+            suite,
+            handshake_context,
+            finished_key: okm,
+        })
+    }
+
+    /// Hash the handshake context, the request and the authenticator messages so far.
+    fn transcript_hash(&self, request: &AuthenticatorRequest, messages: &[u8]) -> hash::Output {
+        let mut ctx = self.suite.common.hash_provider.start();
+        ctx.update(&self.handshake_context);
+        ctx.update(&request.encoding);
+        ctx.update(messages);
+        ctx.finish()
+    }
+
+    fn finished(&self, request: &AuthenticatorRequest, messages: &[u8]) -> hmac::Tag {
+        let hash = self.transcript_hash(request, messages);
+        self.suite
+            .hkdf_provider
+            .hmac_sign(&self.finished_key, hash.as_ref())
+    }
+
+    /// Decode `authenticator` and check its `Finished` message.
+    ///
+    /// The certificate chain and signature are left for the caller to verify.
+    fn read<'a>(
+        &self,
+        request: &AuthenticatorRequest,
+        authenticator: &'a [u8],
+    ) -> Result<Option<ReceivedAuthenticator<'a>>, Error> {
+        let mut r = Reader::init(authenticator);
+
+        let certificate = match read_message(&mut r)? {
+            HandshakePayload::Finished(verify_data) => {
+                r.expect_empty("Authenticator")?;
+                self.check_finished(request, &[], &verify_data)?;
+                return Ok(None);
+            }
+            HandshakePayload::CertificateTls13(certificate) => certificate,
+            payload => {
+                return Err(Error::InappropriateHandshakeMessage {
+                    expect_types: vec![HandshakeType::Certificate, HandshakeType::Finished],
+                    got_type: payload.handshake_type(),
+                });
+            }
+        };
+
+        if certificate.context.0 != request.context {
+            return Err(PeerMisbehaved::IncorrectCertificateRequestContext.into());
+        }
+        let verify_message =
+            construct_verify_message(&self.transcript_hash(request, &authenticator[..r.used()]));
+
+        let signature = match read_message(&mut r)? {
+            HandshakePayload::CertificateVerify(signature) => signature,
+            payload => {
+                return Err(Error::InappropriateHandshakeMessage {
+                    expect_types: vec![HandshakeType::CertificateVerify],
+                    got_type: payload.handshake_type(),
+                });
+            }
+        };
+        if !request
+            .signature_schemes
+            .contains(&signature.scheme)
+        {
+            return Err(PeerMisbehaved::SignedHandshakeWithUnadvertisedSigScheme.into());
+        }
+        let signed = &authenticator[..r.used()];
+
+        match read_message(&mut r)? {
+            HandshakePayload::Finished(verify_data) => {
+                r.expect_empty("Authenticator")?;
+                self.check_finished(request, signed, &verify_data)?;
+            }
+            payload => {
+                return Err(Error::InappropriateHandshakeMessage {
+                    expect_types: vec![HandshakeType::Finished],
+                    got_type: payload.handshake_type(),
+                });
+            }
+        }
+
+        let ocsp = certificate.end_entity_ocsp();
+        let mut chain = certificate
+            .into_certificate_chain()
+            .0
+            .into_iter();
+        let Some(end_entity) = chain.next() else {
+            return Err(Error::NoCertificatesPresented);
+        };
+
+        Ok(Some(ReceivedAuthenticator {
+            end_entity,
+            intermediates: chain.collect(),
+            ocsp,
+            verify_message,
+            signature,
+        }))
+    }
+
+    fn check_finished(
+        &self,
+        request: &AuthenticatorRequest,
+        messages: &[u8],
+        verify_data: &Payload<'_>,
+    ) -> Result<(), Error> {
+        let expected = self.finished(request, messages);
+        match bool::from(
+            expected
+                .as_ref()
+                .ct_eq(verify_data.bytes()),
+        ) {
+            true => Ok(()),
+            false => Err(Error::DecryptError),
+        }
+    }
+}
+
+fn read_message<'a>(r: &mut Reader<'a>) -> Result<HandshakePayload<'a>, Error> {
+    Ok(HandshakeMessagePayload::read_version(r, ProtocolVersion::TLSv1_3)?.0)
+}
+
+/// The parts of a non-empty authenticator that remain to be verified.
+struct ReceivedAuthenticator<'a> {
+    end_entity: CertificateDer<'a>,
+    intermediates: Vec<CertificateDer<'a>>,
+    ocsp: Vec<u8>,
+    verify_message: Vec<u8>,
+    signature: DigitallySignedStruct,
+}
+
+impl ReceivedAuthenticator<'_> {
+    fn into_chain(self) -> Vec<CertificateDer<'static>> {
+        let mut chain = vec![self.end_entity.into_owned()];
+        chain.extend(
+            self.intermediates
+                .into_iter()
+                .map(|cert| cert.into_owned()),
+        );
+        chain
+    }
+}
+
+/// Constructs the message signed by an authenticator's `CertificateVerify`.
+///
+/// This is like the handshake's (RFC 8446 section 4.4.3), but with a different context string.
+fn construct_verify_message(hash: &hash::Output) -> Vec<u8> {
+    let mut message = vec![0x20u8; 64];
+    message.extend_from_slice(b"Exported Authenticator\x00");
+    message.extend_from_slice(hash.as_ref());
+    message
+}
@@ -411,12 +411,16 @@ pub mod compress;
 mod conn;
 /// Crypto provider interface.
 pub mod crypto;
+mod delegated_credential;
 mod error;
+#[cfg(feature = "std")]
+mod exported_authenticator;
 mod hash_hs;
 #[cfg(any(feature = "std", feature = "hashbrown"))]
 mod limited_cache;
 mod rand;
 mod record_layer;
+mod sct;
 #[cfg(feature = "std")]
 mod stream;
 mod tls12;
@@ -435,6 +439,7 @@ mod enums;
 mod key_log;
 #[cfg(feature = "std")]
 mod key_log_file;
+mod psk;
 mod suites;
 mod versions;
 mod webpki;
@@ -511,9 +516,9 @@ pub mod internal {
 pub mod unbuffered {
     pub use crate::conn::UnbufferedConnectionCommon;
     pub use crate::conn::unbuffered::{
-        AppDataRecord, ConnectionState, EncodeError, EncodeTlsData, EncryptError,
-        InsufficientSizeError, ReadEarlyData, ReadTraffic, TransmitTlsData, UnbufferedStatus,
-        WriteTraffic,
+        AppDataRecord, ClientCertRequired, ConnectionState, EncodeError, EncodeTlsData,
+        EncryptError, InsufficientSizeError, ReadEarlyData, ReadTraffic, SignatureRequired,
+        TransmitTlsData, UnbufferedStatus, VerificationRequired, WriteTraffic,
     };
 }
 
@@ -525,29 +530,32 @@ pub use crate::conn::{Connection, Reader, Writer};
 pub use crate::conn::{ConnectionCommon, SideData, kernel};
 pub use crate::enums::{
     AlertDescription, CertificateCompressionAlgorithm, CipherSuite, ContentType, HandshakeType,
-    ProtocolVersion, SignatureAlgorithm, SignatureScheme,
+    MaxFragmentLength, ProtocolVersion, SignatureAlgorithm, SignatureScheme,
 };
 pub use crate::error::{
     CertRevocationListError, CertificateError, EncryptedClientHelloError, Error,
     ExtendedKeyPurpose, InconsistentKeys, InvalidMessage, OtherError, PeerIncompatible,
     PeerMisbehaved,
 };
+#[cfg(feature = "std")]
+pub use crate::exported_authenticator::AuthenticatorRequest;
 pub use crate::key_log::{KeyLog, NoKeyLog};
 #[cfg(feature = "std")]
 pub use crate::key_log_file::KeyLogFile;
 pub use crate::msgs::enums::NamedGroup;
 pub use crate::msgs::ffdhe_groups;
 pub use crate::msgs::handshake::DistinguishedName;
+pub use crate::psk::{ExternalPsk, PskKexMode};
 #[cfg(feature = "std")]
 pub use crate::stream::{Stream, StreamOwned};
 pub use crate::suites::{
     CipherSuiteCommon, ConnectionTrafficSecrets, ExtractedSecrets, SupportedCipherSuite,
 };
 #[cfg(feature = "std")]
-pub use crate::ticketer::TicketRotator;
+pub use crate::ticketer::{TicketKey, TicketKeyRing, TicketKeySet, TicketRotator};
 pub use crate::tls12::Tls12CipherSuite;
 pub use crate::tls13::Tls13CipherSuite;
-pub use crate::verify::DigitallySignedStruct;
+pub use crate::verify::{CertificateVerification, DigitallySignedStruct};
 pub use crate::versions::{ALL_VERSIONS, DEFAULT_VERSIONS, SupportedProtocolVersion};
 pub use crate::webpki::RootCertStore;
 
@@ -557,6 +565,8 @@ pub mod client {
     mod client_conn;
     mod common;
     mod ech;
+    #[cfg(feature = "std")]
+    mod file_cache;
     pub(super) mod handy;
     mod hs;
     #[cfg(test)]
@@ -566,12 +576,15 @@ pub mod client {
 
     pub use builder::WantsClientCert;
     pub use client_conn::{
-        ClientConfig, ClientConnectionData, ClientSessionStore, EarlyDataError, ResolvesClientCert,
-        Resumption, Tls12Resumption, UnbufferedClientConnection,
+        ClientCertRequest, ClientConfig, ClientConnectionData, ClientHelloPadding,
+        ClientSessionStore, EarlyDataError, ResolvesClientCert, Resumption, Tls12Resumption,
+        UnbufferedClientConnection,
     };
     #[cfg(feature = "std")]
     pub use client_conn::{ClientConnection, WriteEarlyData};
     pub use ech::{EchConfig, EchGreaseConfig, EchMode, EchStatus};
+    #[cfg(feature = "std")]
+    pub use file_cache::ClientSessionFileCache;
     pub use handy::AlwaysResolvesClientRawPublicKeys;
     #[cfg(any(feature = "std", feature = "hashbrown"))]
     pub use handy::ClientSessionMemoryCache;
@@ -587,8 +600,9 @@ pub mod client {
     pub(crate) use tls13::{TLS13_HANDLER, Tls13Handler};
 
     pub use crate::msgs::persist::{Tls12ClientSessionValue, Tls13ClientSessionValue};
+    pub use crate::sct::{SctSource, SignedCertificateTimestamp};
     pub use crate::webpki::{
-        ServerCertVerifierBuilder, VerifierBuilderError, WebPkiServerVerifier,
+        CtLog, CtPolicy, ServerCertVerifierBuilder, VerifierBuilderError, WebPkiServerVerifier,
         verify_server_cert_signed_by_trust_anchor, verify_server_name,
     };
 }
@@ -599,28 +613,45 @@ pub use client::ClientConnection;
 
 /// Items for use in a server.
 pub mod server {
+    #[cfg(feature = "std")]
+    mod anti_replay;
     pub(crate) mod builder;
     mod common;
+    mod ech;
+    mod fingerprint;
     pub(crate) mod handy;
     mod hs;
     mod server_conn;
+    #[cfg(feature = "std")]
+    mod session_cache;
     #[cfg(test)]
     mod test;
     mod tls12;
     mod tls13;
 
+    #[cfg(feature = "std")]
+    pub use anti_replay::ClientHelloBloomFilter;
     pub use builder::WantsServerCert;
+    #[cfg(feature = "std")]
+    pub use ech::EchForwarder;
+    pub use ech::{EchKeyPair, EchStatus};
     #[cfg(any(feature = "std", feature = "hashbrown"))]
     pub use handy::ResolvesServerCertUsingSni;
     #[cfg(any(feature = "std", feature = "hashbrown"))]
     pub use handy::ServerSessionMemoryCache;
-    pub use handy::{AlwaysResolvesServerRawPublicKeys, NoServerSessionStorage};
+    pub use handy::{AlwaysResolvesServerRawPublicKeys, NoServerPsks, NoServerSessionStorage};
     pub use server_conn::{
-        Accepted, ClientHello, ProducesTickets, ResolvesServerCert, ServerConfig,
-        ServerConnectionData, StoresServerSessions, UnbufferedServerConnection,
+        Accepted, ClientHello, ProducesTickets, RecordsClientHellos, ResolvesServerCert,
+        ResolvesServerPsk, ServerConfig, ServerConnectionData, StoresServerSessions,
+        UnbufferedAcceptStatus, UnbufferedAccepted, UnbufferedAcceptedAlert, UnbufferedAcceptor,
+        UnbufferedServerConnection,
     };
     #[cfg(feature = "std")]
-    pub use server_conn::{AcceptedAlert, Acceptor, ReadEarlyData, ServerConnection};
+    pub use server_conn::{AcceptedAlert, Acceptor, EchSplitMode, ReadEarlyData, ServerConnection};
+    #[cfg(feature = "std")]
+    pub use session_cache::{
+        ServerSessionBackend, ServerSessionCache, ServerSessionCacheStats, ShardedMemoryBackend,
+    };
 
     pub use crate::enums::CertificateType;
     pub use crate::verify::NoClientAuth;
@@ -659,6 +690,7 @@ pub mod pki_types {
 /// Message signing interfaces.
 pub mod sign {
     pub use crate::crypto::signer::{CertifiedKey, Signer, SigningKey, SingleCertAndKey};
+    pub use crate::delegated_credential::{DelegatedCredential, DelegatedKey};
 }
 
 /// APIs for implementing QUIC TLS
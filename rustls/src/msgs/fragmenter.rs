@@ -1,3 +1,5 @@
+use core::cmp::min;
+
 use crate::Error;
 use crate::enums::{ContentType, ProtocolVersion};
 use crate::msgs::message::{OutboundChunks, OutboundPlainMessage, PlainMessage};
@@ -5,6 +7,9 @@ pub(crate) const MAX_FRAGMENT_LEN: usize = 16384;
 pub(crate) const PACKET_OVERHEAD: usize = 1 + 2 + 2;
 pub(crate) const MAX_FRAGMENT_SIZE: usize = MAX_FRAGMENT_LEN + PACKET_OVERHEAD;
 
+/// The smallest `record_size_limit` allowed by RFC 8449.
+pub(crate) const MIN_RECORD_SIZE_LIMIT: u16 = 64;
+
 pub struct MessageFragmenter {
     max_frag: usize,
 }
@@ -68,6 +73,14 @@ impl MessageFragmenter {
         };
         Ok(())
     }
+
+    /// Restrict the fragments produced to at most `max_payload_len` bytes of payload.
+    ///
+    /// This honours a limit advertised by the peer.  It never raises the limit
+    /// set by [`Self::set_max_fragment_size()`].
+    pub(crate) fn limit_payload_len(&mut self, max_payload_len: usize) {
+        self.max_frag = min(self.max_frag, max_payload_len);
+    }
 }
 
 /// An iterator over borrowed fragments of a payload
@@ -229,4 +242,28 @@ mod tests {
         );
         msg_eq(&fragments[2], 13, &typ, &version, b"dddddddd");
     }
+
+    #[test]
+    fn payload_limit_only_lowers_max_fragment_size() {
+        let typ = ContentType::ApplicationData;
+        let version = ProtocolVersion::TLSv1_2;
+        let payload_owner: Vec<&[u8]> = vec![&[b'a'; 100]];
+
+        let mut frag = MessageFragmenter::default();
+        frag.set_max_fragment_size(Some(37))
+            .unwrap();
+        frag.limit_payload_len(64);
+        assert_eq!(
+            frag.fragment_payload(typ, version, OutboundChunks::new(&payload_owner))
+                .len(),
+            4
+        );
+
+        frag.limit_payload_len(20);
+        assert_eq!(
+            frag.fragment_payload(typ, version, OutboundChunks::new(&payload_owner))
+                .len(),
+            5
+        );
+    }
 }
@@ -10,9 +10,10 @@ use core::{fmt, iter};
 use pki_types::{CertificateDer, DnsName};
 
 use crate::crypto::{ActiveKeyExchange, SecureRandom};
+use crate::delegated_credential::DelegatedCredential;
 use crate::enums::{
     CertificateCompressionAlgorithm, CertificateType, CipherSuite, EchClientHelloType,
-    HandshakeType, ProtocolVersion, SignatureScheme,
+    HandshakeType, MaxFragmentLength, ProtocolVersion, SignatureScheme,
 };
 use crate::error::InvalidMessage;
 use crate::ffdhe_groups::FfdheGroup;
@@ -27,6 +28,7 @@ use crate::msgs::enums::{
     PskKeyExchangeMode, ServerNameType,
 };
 use crate::rand;
+use crate::sct::SctList;
 use crate::sync::Arc;
 use crate::verify::DigitallySignedStruct;
 use crate::x509::wrap_in_sequence;
@@ -694,6 +696,10 @@ impl TlsListElement for KeyShareEntry {
 pub(crate) struct SupportedProtocolVersions {
     pub(crate) tls13: bool,
     pub(crate) tls12: bool,
+    /// A GREASE value ([RFC8701]) to send first.  This is never set when decoding.
+    ///
+    /// [RFC8701]: https://datatracker.ietf.org/doc/html/rfc8701
+    pub(crate) grease: Option<ProtocolVersion>,
 }
 
 impl SupportedProtocolVersions {
@@ -716,6 +722,9 @@ impl SupportedProtocolVersions {
 impl Codec<'_> for SupportedProtocolVersions {
     fn encode(&self, bytes: &mut Vec<u8>) {
         let inner = LengthPrefixedBuffer::new(Self::LIST_LENGTH, bytes);
+        if let Some(grease) = self.grease {
+            grease.encode(inner.buf);
+        }
         if self.tls13 {
             ProtocolVersion::TLSv1_3.encode(inner.buf);
         }
@@ -736,7 +745,11 @@ impl Codec<'_> for SupportedProtocolVersions {
             };
         }
 
-        Ok(Self { tls13, tls12 })
+        Ok(Self {
+            tls13,
+            tls12,
+            grease: None,
+        })
     }
 }
 
@@ -836,6 +849,10 @@ extension_struct! {
         ExtensionType::StatusRequest =>
             pub(crate) certificate_status_request: Option<CertificateStatusRequest>,
 
+        /// Requested maximum fragment length (RFC6066)
+        ExtensionType::MaxFragmentLength =>
+            pub(crate) max_fragment_length: Option<MaxFragmentLength>,
+
         /// Supported groups (RFC4492/RFC8446)
         ExtensionType::EllipticCurves =>
             pub(crate) named_groups: Option<Vec<NamedGroup>>,
@@ -852,6 +869,10 @@ extension_struct! {
         ExtensionType::ALProtocolNegotiation =>
             pub(crate) protocols: Option<Vec<ProtocolName>>,
 
+        /// Signed certificate timestamps are requested (RFC6962)
+        ExtensionType::SCT =>
+            pub(crate) signed_certificate_timestamp_request: Option<()>,
+
         /// Available client certificate types (RFC7250)
         ExtensionType::ClientCertificateType =>
             pub(crate) client_certificate_types: Option<Vec<CertificateType>>,
@@ -868,6 +889,14 @@ extension_struct! {
         ExtensionType::CompressCertificate =>
             pub(crate) certificate_compression_algorithms: Option<Vec<CertificateCompressionAlgorithm>>,
 
+        /// Largest record the client will receive (RFC8449)
+        ExtensionType::RecordSizeLimit =>
+            pub(crate) record_size_limit: Option<u16>,
+
+        /// Delegated credential signature schemes (RFC9345)
+        ExtensionType::DelegatedCredential =>
+            pub(crate) delegated_credential_schemes: Option<Vec<SignatureScheme>>,
+
         /// Session ticket offer or request (RFC5077/RFC8446)
         ExtensionType::SessionTicket =>
             pub(crate) session_ticket: Option<ClientSessionTicket>,
@@ -896,6 +925,10 @@ extension_struct! {
         ExtensionType::CertificateAuthorities =>
             pub(crate) certificate_authority_names: Option<Vec<DistinguishedName>>,
 
+        /// Post-handshake client authentication is supported (RFC8446)
+        ExtensionType::PostHandshakeAuth =>
+            pub(crate) post_handshake_auth: Option<()>,
+
         /// Offered key exchange shares (RFC8446)
         ExtensionType::KeyShare =>
             pub(crate) key_shares: Option<Vec<KeyShareEntry>>,
@@ -915,12 +948,29 @@ extension_struct! {
         /// Encrypted client hello outer extensions (draft-ietf-tls-esni)
         ExtensionType::EncryptedClientHelloOuterExtensions =>
             pub(crate) encrypted_client_hello_outer: Option<Vec<ExtensionType>>,
+
+        /// Padding (RFC7685)
+        ExtensionType::Padding =>
+            pub(crate) padding: Option<Payload<'a>>,
     } + {
         /// Order randomization seed.
         pub(crate) order_seed: u16,
 
         /// Extensions that must appear contiguously.
         pub(crate) contiguous_extensions: Vec<ExtensionType>,
+
+        /// Every extension received, in the order received, with its undecoded body.
+        ///
+        /// This includes unknown extensions.  It is empty for locally-constructed values.
+        pub(crate) received: Vec<(ExtensionType, Payload<'a>)>,
+
+        /// GREASE extension types ([RFC8701]) to send.
+        ///
+        /// The first is sent first, with an empty body.  Any others are sent with
+        /// a single zero byte body, after all other extensions except ECH and PSK.
+        ///
+        /// [RFC8701]: https://datatracker.ietf.org/doc/html/rfc8701
+        pub(crate) grease_extensions: Vec<ExtensionType>,
     }
 }
 
@@ -929,14 +979,18 @@ impl ClientExtensions<'_> {
         let Self {
             server_name,
             certificate_status_request,
+            max_fragment_length,
             named_groups,
             ec_point_formats,
             signature_schemes,
             protocols,
+            signed_certificate_timestamp_request,
             client_certificate_types,
             server_certificate_types,
             extended_master_secret_request,
             certificate_compression_algorithms,
+            record_size_limit,
+            delegated_credential_schemes,
             session_ticket,
             preshared_key_offer,
             early_data_request,
@@ -944,25 +998,33 @@ impl ClientExtensions<'_> {
             cookie,
             preshared_key_modes,
             certificate_authority_names,
+            post_handshake_auth,
             key_shares,
             transport_parameters,
             renegotiation_info,
             encrypted_client_hello,
             encrypted_client_hello_outer,
+            padding,
             order_seed,
             contiguous_extensions,
+            received,
+            grease_extensions,
         } = self;
         ClientExtensions {
             server_name: server_name.map(|x| x.into_owned()),
             certificate_status_request,
+            max_fragment_length,
             named_groups,
             ec_point_formats,
             signature_schemes,
             protocols,
+            signed_certificate_timestamp_request,
             client_certificate_types,
             server_certificate_types,
             extended_master_secret_request,
             certificate_compression_algorithms,
+            record_size_limit,
+            delegated_credential_schemes,
             session_ticket,
             preshared_key_offer,
             early_data_request,
@@ -970,19 +1032,33 @@ impl ClientExtensions<'_> {
             cookie,
             preshared_key_modes,
             certificate_authority_names,
+            post_handshake_auth,
             key_shares,
             transport_parameters: transport_parameters.map(|x| x.into_owned()),
             renegotiation_info,
             encrypted_client_hello,
             encrypted_client_hello_outer,
+            padding: padding.map(|x| x.into_owned()),
             order_seed,
             contiguous_extensions,
+            received: received
+                .into_iter()
+                .map(|(typ, body)| (typ, body.into_owned()))
+                .collect(),
+            grease_extensions,
         }
     }
 
     pub(crate) fn used_extensions_in_encoding_order(&self) -> Vec<ExtensionType> {
-        let mut exts = self.order_insensitive_extensions_in_random_order();
+        let mut exts = Vec::new();
+        exts.extend(self.grease_extensions.first());
+        exts.extend(self.order_insensitive_extensions_in_random_order());
         exts.extend(&self.contiguous_extensions);
+        exts.extend(self.grease_extensions.iter().skip(1));
+
+        if self.padding.is_some() {
+            exts.push(ExtensionType::Padding);
+        }
 
         if self
             .encrypted_client_hello_outer
@@ -1012,6 +1088,10 @@ impl ClientExtensions<'_> {
     ///
     /// - Lastly, any ECH and PSK extensions (in that order).  These
     ///   are required to be last by the standard.
+    ///
+    /// Any GREASE extensions are placed around these portions, and any padding
+    /// extension immediately before the last portion, by
+    /// `used_extensions_in_encoding_order()`.
     fn order_insensitive_extensions_in_random_order(&self) -> Vec<ExtensionType> {
         let mut order = self.collect_used();
 
@@ -1022,6 +1102,7 @@ impl ClientExtensions<'_> {
                 ExtensionType::PreSharedKey
                     | ExtensionType::EncryptedClientHello
                     | ExtensionType::EncryptedClientHelloOuterExtensions
+                    | ExtensionType::Padding
             ) || self.contiguous_extensions.contains(ext))
         });
 
@@ -1044,7 +1125,21 @@ impl<'a> Codec<'a> for ClientExtensions<'a> {
 
         let body = LengthPrefixedBuffer::new(ListLength::U16, bytes);
         for item in order {
-            self.encode_one(item, body.buf);
+            match self
+                .grease_extensions
+                .iter()
+                .position(|grease| *grease == item)
+            {
+                Some(index) => {
+                    item.encode(body.buf);
+                    match index {
+                        0 => PayloadU16::<MaybeEmpty>::empty(),
+                        _ => PayloadU16::new(vec![0]),
+                    }
+                    .encode(body.buf);
+                }
+                None => self.encode_one(item, body.buf),
+            }
         }
     }
 
@@ -1060,10 +1155,18 @@ impl<'a> Codec<'a> for ClientExtensions<'a> {
 
         let len = usize::from(u16::read(r)?);
         let mut sub = r.sub(len)?;
+        let raw = sub.rest();
+        let mut sub = Reader::init(raw);
 
         while sub.any_left() {
+            let start = sub.used();
             let typ = out.read_one(&mut sub, |unknown| checker.check(unknown))?;
 
+            // skip the type and length to leave just the body
+            let body = &raw[start + 4..sub.used()];
+            out.received
+                .push((typ, Payload::Borrowed(body)));
+
             // PreSharedKey offer must come last
             if typ == ExtensionType::PreSharedKey && sub.any_left() {
                 return Err(InvalidMessage::PreSharedKeyIsNotFinalExtension);
@@ -1127,6 +1230,10 @@ extension_struct! {
         ExtensionType::ServerName =>
             pub(crate) server_name_ack: Option<()>,
 
+        /// Maximum fragment length acknowledgement (RFC6066)
+        ExtensionType::MaxFragmentLength =>
+            pub(crate) max_fragment_length: Option<MaxFragmentLength>,
+
         /// Session ticket acknowledgement (RFC5077)
         ExtensionType::SessionTicket =>
             pub(crate) session_ticket_ack: Option<()>,
@@ -1162,6 +1269,14 @@ extension_struct! {
         ExtensionType::StatusRequest =>
             pub(crate) certificate_status_request_ack: Option<()>,
 
+        /// Signed certificate timestamps for the server certificate (RFC6962)
+        ExtensionType::SCT =>
+            pub(crate) signed_certificate_timestamps: Option<SctList>,
+
+        /// Largest record the server will receive (RFC8449)
+        ExtensionType::RecordSizeLimit =>
+            pub(crate) record_size_limit: Option<u16>,
+
         /// Selected TLS version (RFC8446)
         ExtensionType::SupportedVersions =>
             pub(crate) selected_version: Option<ProtocolVersion>,
@@ -1187,6 +1302,7 @@ impl ServerExtensions<'_> {
         let Self {
             ec_point_formats,
             server_name_ack,
+            max_fragment_length,
             session_ticket_ack,
             renegotiation_info,
             selected_protocol,
@@ -1196,6 +1312,8 @@ impl ServerExtensions<'_> {
             server_certificate_type,
             extended_master_secret_ack,
             certificate_status_request_ack,
+            signed_certificate_timestamps,
+            record_size_limit,
             selected_version,
             transport_parameters,
             early_data_ack,
@@ -1205,6 +1323,7 @@ impl ServerExtensions<'_> {
         ServerExtensions {
             ec_point_formats,
             server_name_ack,
+            max_fragment_length,
             session_ticket_ack,
             renegotiation_info,
             selected_protocol,
@@ -1214,6 +1333,8 @@ impl ServerExtensions<'_> {
             server_certificate_type,
             extended_master_secret_ack,
             certificate_status_request_ack,
+            signed_certificate_timestamps,
+            record_size_limit,
             selected_version,
             transport_parameters: transport_parameters.map(|x| x.into_owned()),
             early_data_ack,
@@ -1656,6 +1777,14 @@ extension_struct! {
     pub(crate) struct CertificateExtensions<'a> {
         ExtensionType::StatusRequest =>
             pub(crate) status: Option<CertificateStatus<'a>>,
+
+        /// Signed certificate timestamps for an end-entity certificate (RFC6962)
+        ExtensionType::SCT =>
+            pub(crate) signed_certificate_timestamps: Option<SctList>,
+
+        /// Delegated credential for an end-entity certificate (RFC9345)
+        ExtensionType::DelegatedCredential =>
+            pub(crate) delegated_credential: Option<DelegatedCredential>,
     }
 }
 
@@ -1663,6 +1792,8 @@ impl CertificateExtensions<'_> {
     fn into_owned(self) -> CertificateExtensions<'static> {
         CertificateExtensions {
             status: self.status.map(|s| s.into_owned()),
+            signed_certificate_timestamps: self.signed_certificate_timestamps,
+            delegated_credential: self.delegated_credential,
         }
     }
 }
@@ -1811,6 +1942,22 @@ impl<'a> CertificatePayloadTls13<'a> {
             .unwrap_or_default()
     }
 
+    pub(crate) fn end_entity_delegated_credential(&self) -> Option<DelegatedCredential> {
+        self.entries
+            .first()?
+            .extensions
+            .delegated_credential
+            .clone()
+    }
+
+    pub(crate) fn end_entity_scts(&self) -> Option<SctList> {
+        self.entries
+            .first()?
+            .extensions
+            .signed_certificate_timestamps
+            .clone()
+    }
+
     pub(crate) fn into_certificate_chain(self) -> CertificateChain<'a> {
         CertificateChain(
             self.entries
@@ -2485,6 +2632,7 @@ pub(crate) enum HandshakePayload<'a> {
     ServerKeyExchange(ServerKeyExchangePayload),
     CertificateRequest(CertificateRequestPayload),
     CertificateRequestTls13(CertificateRequestPayloadTls13),
+    ClientCertificateRequest(CertificateRequestPayloadTls13),
     CertificateVerify(DigitallySignedStruct),
     ServerHelloDone,
     EndOfEarlyData,
@@ -2514,6 +2662,7 @@ impl HandshakePayload<'_> {
             ClientKeyExchange(x) => x.encode(bytes),
             CertificateRequest(x) => x.encode(bytes),
             CertificateRequestTls13(x) => x.encode(bytes),
+            ClientCertificateRequest(x) => x.encode(bytes),
             CertificateVerify(x) => x.encode(bytes),
             NewSessionTicket(x) => x.encode(bytes),
             NewSessionTicketTls13(x) => x.encode(bytes),
@@ -2537,6 +2686,7 @@ impl HandshakePayload<'_> {
             CompressedCertificate(_) => HandshakeType::CompressedCertificate,
             ServerKeyExchange(_) => HandshakeType::ServerKeyExchange,
             CertificateRequest(_) | CertificateRequestTls13(_) => HandshakeType::CertificateRequest,
+            ClientCertificateRequest(_) => HandshakeType::ClientCertificateRequest,
             CertificateVerify(_) => HandshakeType::CertificateVerify,
             ServerHelloDone => HandshakeType::ServerHelloDone,
             EndOfEarlyData => HandshakeType::EndOfEarlyData,
@@ -2573,6 +2723,7 @@ impl HandshakePayload<'_> {
             ServerKeyExchange(x) => ServerKeyExchange(x),
             CertificateRequest(x) => CertificateRequest(x),
             CertificateRequestTls13(x) => CertificateRequestTls13(x),
+            ClientCertificateRequest(x) => ClientCertificateRequest(x),
             CertificateVerify(x) => CertificateVerify(x),
             ServerHelloDone => ServerHelloDone,
             EndOfEarlyData => EndOfEarlyData,
@@ -2657,6 +2808,10 @@ impl<'a> HandshakeMessagePayload<'a> {
                 let p = CertificateRequestPayload::read(&mut sub)?;
                 HandshakePayload::CertificateRequest(p)
             }
+            HandshakeType::ClientCertificateRequest if vers == ProtocolVersion::TLSv1_3 => {
+                let p = CertificateRequestPayloadTls13::read(&mut sub)?;
+                HandshakePayload::ClientCertificateRequest(p)
+            }
             HandshakeType::CompressedCertificate => HandshakePayload::CompressedCertificate(
                 CompressedCertificatePayload::read(&mut sub)?,
             ),
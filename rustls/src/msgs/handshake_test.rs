@@ -22,9 +22,10 @@ use super::handshake::{
     ServerNamePayload, SessionId, SingleProtocolName, SupportedEcPointFormats,
     SupportedProtocolVersions,
 };
+use crate::delegated_credential::DelegatedCredential;
 use crate::enums::{
-    CertificateCompressionAlgorithm, CertificateType, CipherSuite, HandshakeType, ProtocolVersion,
-    SignatureScheme,
+    CertificateCompressionAlgorithm, CertificateType, CipherSuite, HandshakeType,
+    MaxFragmentLength, ProtocolVersion, SignatureScheme,
 };
 use crate::error::InvalidMessage;
 use crate::sync::Arc;
@@ -780,6 +781,7 @@ fn sample_client_hello_payload() -> ClientHelloPayload {
             ec_point_formats: Some(SupportedEcPointFormats::default()),
             named_groups: Some(vec![NamedGroup::X25519]),
             protocols: Some(vec![ProtocolName::from(vec![0])]),
+            signed_certificate_timestamp_request: Some(()),
             supported_versions: Some(SupportedProtocolVersions {
                 tls13: true,
                 ..Default::default()
@@ -801,11 +803,14 @@ fn sample_client_hello_payload() -> ClientHelloPayload {
             }),
             extended_master_secret_request: Some(()),
             certificate_status_request: Some(CertificateStatusRequest::build_ocsp()),
+            max_fragment_length: Some(MaxFragmentLength::Length2048),
             server_certificate_types: Some(vec![CertificateType::RawPublicKey]),
             client_certificate_types: Some(vec![CertificateType::RawPublicKey]),
             transport_parameters: Some(Payload::new(vec![1, 2, 3])),
             early_data_request: Some(()),
+            post_handshake_auth: Some(()),
             certificate_compression_algorithms: Some(vec![CertificateCompressionAlgorithm::Brotli]),
+            record_size_limit: Some(1024),
             encrypted_client_hello: Some(EncryptedClientHello::Inner),
             encrypted_client_hello_outer: Some(vec![ExtensionType::SCT]),
             ..Default::default()
@@ -823,6 +828,7 @@ fn sample_server_hello_payload() -> ServerHelloPayload {
         extensions: Box::new(ServerExtensions {
             ec_point_formats: Some(SupportedEcPointFormats::default()),
             server_name_ack: Some(()),
+            max_fragment_length: Some(MaxFragmentLength::Length2048),
             session_ticket_ack: Some(()),
             renegotiation_info: Some(PayloadU8::new(vec![0])),
             selected_protocol: Some(SingleProtocolName::new(ProtocolName::from(vec![0]))),
@@ -834,6 +840,8 @@ fn sample_server_hello_payload() -> ServerHelloPayload {
             }),
             extended_master_secret_ack: Some(()),
             certificate_status_request_ack: Some(()),
+            signed_certificate_timestamps: Some(vec![PayloadU16::new(vec![1, 2, 3])]),
+            record_size_limit: Some(1024),
             selected_version: Some(ProtocolVersion::TLSv1_2),
             transport_parameters: Some(Payload::new(vec![1, 2, 3])),
             client_certificate_type: Some(CertificateType::RawPublicKey),
@@ -919,6 +927,9 @@ fn all_tls13_handshake_payloads() -> Vec<HandshakeMessagePayload<'static>> {
         HandshakeMessagePayload(HandshakePayload::CertificateRequestTls13(
             sample_certificate_request_payload_tls13(),
         )),
+        HandshakeMessagePayload(HandshakePayload::ClientCertificateRequest(
+            sample_certificate_request_payload_tls13(),
+        )),
         HandshakeMessagePayload(HandshakePayload::CertificateVerify(
             DigitallySignedStruct::new(SignatureScheme::ECDSA_NISTP256_SHA256, vec![1, 2, 3]),
         )),
@@ -958,6 +969,13 @@ fn sample_certificate_payload_tls13() -> CertificatePayloadTls13<'static> {
                 status: Some(CertificateStatus {
                     ocsp_response: PayloadU24(Payload::new(vec![1, 2, 3])),
                 }),
+                signed_certificate_timestamps: Some(vec![PayloadU16::new(vec![1, 2, 3])]),
+                delegated_credential: Some(
+                    DelegatedCredential::try_from(
+                        &b"\x00\x00\x12\x34\x04\x03\x00\x00\x04spki\x08\x07\x00\x03sig"[..],
+                    )
+                    .unwrap(),
+                ),
             },
         }],
     }
@@ -199,7 +199,6 @@ impl Message<'_> {
         }
     }
 
-    #[cfg(feature = "std")]
     pub(crate) fn into_owned(self) -> Message<'static> {
         let Self { version, payload } = self;
         Message {
@@ -1,15 +1,18 @@
 use alloc::vec::Vec;
 use core::cmp;
 
-use pki_types::{DnsName, UnixTime};
+use pki_types::{DnsName, ServerName, UnixTime};
 use zeroize::Zeroizing;
 
-use crate::client::ResolvesClientCert;
+use crate::client::{ClientConfig, ResolvesClientCert};
+use crate::crypto::CryptoProvider;
 use crate::enums::{CipherSuite, ProtocolVersion};
-use crate::error::InvalidMessage;
+use crate::error::{Error, InvalidMessage};
 use crate::msgs::base::{MaybeEmpty, PayloadU8, PayloadU16};
 use crate::msgs::codec::{Codec, Reader};
+use crate::msgs::enums::NamedGroup;
 use crate::msgs::handshake::{CertificateChain, ProtocolName, SessionId};
+use crate::suites::SupportedCipherSuite;
 use crate::sync::{Arc, Weak};
 use crate::tls12::Tls12CipherSuite;
 use crate::tls13::Tls13CipherSuite;
@@ -50,14 +53,14 @@ impl Retrieved<&Tls13ClientSessionValue> {
 impl<T: core::ops::Deref<Target = ClientSessionCommon>> Retrieved<T> {
     pub(crate) fn has_expired(&self) -> bool {
         let common = &*self.value;
-        common.lifetime_secs != 0
-            && common
-                .epoch
-                .saturating_add(u64::from(common.lifetime_secs))
-                < self.retrieved_at.as_secs()
+        has_expired(common.epoch, common.lifetime_secs, self.retrieved_at)
     }
 }
 
+fn has_expired(epoch: u64, lifetime_secs: u32, now: UnixTime) -> bool {
+    lifetime_secs != 0 && epoch.saturating_add(u64::from(lifetime_secs)) < now.as_secs()
+}
+
 impl<T> core::ops::Deref for Retrieved<T> {
     type Target = T;
 
@@ -137,6 +140,74 @@ impl Tls13ClientSessionValue {
     pub fn quic_params(&self) -> Vec<u8> {
         self.quic_params.0.clone()
     }
+
+    /// Encode this ticket for storage outside this process.
+    ///
+    /// The encoding is versioned, and can be read back by [`Tls13ClientSessionValue::decode()`]
+    /// in this or a later version of rustls.  It contains the resumption secret, so it must be
+    /// protected like any other key material.
+    pub fn encode(&self) -> Vec<u8> {
+        let mut bytes = Vec::new();
+        CLIENT_SESSION_ENCODING_VERSION.encode(&mut bytes);
+        ProtocolVersion::TLSv1_3.encode(&mut bytes);
+        self.suite
+            .common
+            .suite
+            .encode(&mut bytes);
+        self.secret.encode(&mut bytes);
+        self.age_add.encode(&mut bytes);
+        self.max_early_data_size
+            .encode(&mut bytes);
+        self.quic_params.encode(&mut bytes);
+        self.common.encode(&mut bytes);
+        bytes
+    }
+
+    /// Decode a ticket produced by [`Tls13ClientSessionValue::encode()`], for resuming
+    /// connections to `server_name` with `config`.
+    ///
+    /// The ticket's cipher suite must be one of `config`'s, and the server certificate chain
+    /// it was obtained with is verified again by `config`'s server certificate verifier, as of
+    /// `config`'s current time.  The ticket is then only used for resumption by connections
+    /// with the same server certificate verifier and client certificate resolver as `config`.
+    pub fn decode(
+        bytes: &[u8],
+        server_name: &ServerName<'_>,
+        config: &ClientConfig,
+    ) -> Result<Self, Error> {
+        Self::decode_for(
+            bytes,
+            server_name,
+            config.current_time()?,
+            &ClientSessionContext::from(config),
+        )
+    }
+
+    pub(crate) fn decode_for(
+        bytes: &[u8],
+        server_name: &ServerName<'_>,
+        now: UnixTime,
+        cx: &ClientSessionContext<'_>,
+    ) -> Result<Self, Error> {
+        let r = &mut Reader::init(bytes);
+        read_client_session_header(r, ProtocolVersion::TLSv1_3)?;
+        let Some(SupportedCipherSuite::Tls13(suite)) = cx.find_cipher_suite(CipherSuite::read(r)?)
+        else {
+            return Err(unsupported_client_session_suite());
+        };
+
+        let value = Self {
+            suite,
+            secret: Zeroizing::new(PayloadU8::read(r)?),
+            age_add: u32::read(r)?,
+            max_early_data_size: u32::read(r)?,
+            quic_params: PayloadU16::read(r)?,
+            common: ClientSessionCommon::read(r, cx)?,
+        };
+        r.expect_empty("Tls13ClientSessionValue")?;
+        cx.verify_server_cert_chain(&value.common, server_name, now)?;
+        Ok(value)
+    }
 }
 
 impl core::ops::Deref for Tls13ClientSessionValue {
@@ -202,6 +273,75 @@ impl Tls12ClientSessionValue {
         self.suite
     }
 
+    /// Encode this session for storage outside this process.
+    ///
+    /// The encoding is versioned, and can be read back by [`Tls12ClientSessionValue::decode()`]
+    /// in this or a later version of rustls.  It contains the master secret, so it must be
+    /// protected like any other key material.
+    pub fn encode(&self) -> Vec<u8> {
+        let mut bytes = Vec::new();
+        CLIENT_SESSION_ENCODING_VERSION.encode(&mut bytes);
+        ProtocolVersion::TLSv1_2.encode(&mut bytes);
+        self.suite
+            .common
+            .suite
+            .encode(&mut bytes);
+        self.session_id.encode(&mut bytes);
+        bytes.extend_from_slice(self.master_secret.as_ref());
+        u8::from(self.extended_ms).encode(&mut bytes);
+        self.common.encode(&mut bytes);
+        bytes
+    }
+
+    /// Decode a session produced by [`Tls12ClientSessionValue::encode()`], for resuming
+    /// connections to `server_name` with `config`.
+    ///
+    /// The session's cipher suite must be one of `config`'s, and its server certificate chain
+    /// is verified again by `config`'s server certificate verifier, as of `config`'s current
+    /// time.  The session is then only resumed by connections with the same server certificate
+    /// verifier and client certificate resolver as `config`.
+    pub fn decode(
+        bytes: &[u8],
+        server_name: &ServerName<'_>,
+        config: &ClientConfig,
+    ) -> Result<Self, Error> {
+        Self::decode_for(
+            bytes,
+            server_name,
+            config.current_time()?,
+            &ClientSessionContext::from(config),
+        )
+    }
+
+    pub(crate) fn decode_for(
+        bytes: &[u8],
+        server_name: &ServerName<'_>,
+        now: UnixTime,
+        cx: &ClientSessionContext<'_>,
+    ) -> Result<Self, Error> {
+        let r = &mut Reader::init(bytes);
+        read_client_session_header(r, ProtocolVersion::TLSv1_2)?;
+        let Some(SupportedCipherSuite::Tls12(suite)) = cx.find_cipher_suite(CipherSuite::read(r)?)
+        else {
+            return Err(unsupported_client_session_suite());
+        };
+        let session_id = SessionId::read(r)?;
+        let master_secret = r
+            .take(48)
+            .ok_or(InvalidMessage::MissingData("master secret"))?;
+
+        let value = Self {
+            suite,
+            session_id,
+            master_secret: Zeroizing::new(master_secret.try_into().unwrap()),
+            extended_ms: u8::read(r)? != 0,
+            common: ClientSessionCommon::read(r, cx)?,
+        };
+        r.expect_empty("Tls12ClientSessionValue")?;
+        cx.verify_server_cert_chain(&value.common, server_name, now)?;
+        Ok(value)
+    }
+
     /// Test only: rewind epoch by `delta` seconds.
     #[doc(hidden)]
     pub fn rewind_epoch(&mut self, delta: u32) {
@@ -276,6 +416,24 @@ impl ClientSessionCommon {
         &self.server_cert_chain
     }
 
+    fn encode(&self, bytes: &mut Vec<u8>) {
+        self.ticket.encode(bytes);
+        self.epoch.encode(bytes);
+        self.lifetime_secs.encode(bytes);
+        self.server_cert_chain.encode(bytes);
+    }
+
+    fn read(r: &mut Reader<'_>, cx: &ClientSessionContext<'_>) -> Result<Self, InvalidMessage> {
+        Ok(Self {
+            ticket: Arc::new(PayloadU16::read(r)?),
+            epoch: u64::read(r)?,
+            lifetime_secs: cmp::min(u32::read(r)?, MAX_TICKET_LIFETIME),
+            server_cert_chain: Arc::new(CertificateChain::read(r)?.into_owned()),
+            server_cert_verifier: Arc::downgrade(cx.server_cert_verifier),
+            client_creds: Arc::downgrade(cx.client_creds),
+        })
+    }
+
     pub(crate) fn ticket(&self) -> &[u8] {
         self.ticket.0.as_ref()
     }
@@ -283,6 +441,122 @@ impl ClientSessionCommon {
 
 static MAX_TICKET_LIFETIME: u32 = 7 * 24 * 60 * 60;
 
+/// The version of the encoding used by `Tls12ClientSessionValue::encode()` and
+/// `Tls13ClientSessionValue::encode()`.
+///
+/// This must be incremented if that encoding changes incompatibly.
+const CLIENT_SESSION_ENCODING_VERSION: u8 = 1;
+
+fn read_client_session_header(
+    r: &mut Reader<'_>,
+    expected: ProtocolVersion,
+) -> Result<(), InvalidMessage> {
+    match (u8::read(r)?, ProtocolVersion::read(r)?) {
+        (CLIENT_SESSION_ENCODING_VERSION, version) if version == expected => Ok(()),
+        _ => Err(InvalidMessage::UnknownProtocolVersion),
+    }
+}
+
+fn unsupported_client_session_suite() -> Error {
+    Error::General("stored session uses a cipher suite that is not enabled".into())
+}
+
+/// Whether a session encoded by `Tls12ClientSessionValue::encode()` or
+/// `Tls13ClientSessionValue::encode()` has expired at `now`.
+///
+/// Unlike decoding, this does not depend on the configuration: so it can be used
+/// to discard sessions that no configuration can resume.
+#[cfg(feature = "std")]
+pub(crate) fn encoded_client_session_has_expired(
+    bytes: &[u8],
+    now: UnixTime,
+) -> Result<bool, InvalidMessage> {
+    let r = &mut Reader::init(bytes);
+    let version = match u8::read(r)? {
+        CLIENT_SESSION_ENCODING_VERSION => ProtocolVersion::read(r)?,
+        _ => return Err(InvalidMessage::UnknownProtocolVersion),
+    };
+    CipherSuite::read(r)?;
+
+    match version {
+        ProtocolVersion::TLSv1_2 => {
+            SessionId::read(r)?;
+            r.take(48)
+                .ok_or(InvalidMessage::MissingData("master secret"))?;
+            u8::read(r)?;
+        }
+        ProtocolVersion::TLSv1_3 => {
+            PayloadU8::<MaybeEmpty>::read(r)?;
+            u32::read(r)?;
+            u32::read(r)?;
+            PayloadU16::<MaybeEmpty>::read(r)?;
+        }
+        _ => return Err(InvalidMessage::UnknownProtocolVersion),
+    }
+
+    PayloadU16::<MaybeEmpty>::read(r)?;
+    let epoch = u64::read(r)?;
+    let lifetime_secs = cmp::min(u32::read(r)?, MAX_TICKET_LIFETIME);
+    Ok(has_expired(epoch, lifetime_secs, now))
+}
+
+/// What decoded client sessions are bound to.
+///
+/// This supplies the cipher suites, the verifier which checks a decoded
+/// session's server certificate chain, and the objects which resumption is
+/// limited to (see `ClientSessionCommon::compatible_config()`).
+pub(crate) struct ClientSessionContext<'a> {
+    pub(crate) provider: &'a CryptoProvider,
+    pub(crate) server_cert_verifier: &'a Arc<dyn ServerCertVerifier>,
+    pub(crate) client_creds: &'a Arc<dyn ResolvesClientCert>,
+}
+
+impl ClientSessionContext<'_> {
+    /// Verify the server certificate chain of a decoded session.
+    ///
+    /// Decoding binds the session to this context's verifier, so this must
+    /// succeed first: otherwise a session stored by a configuration with a
+    /// less strict verifier would be resumed without its server being checked.
+    fn verify_server_cert_chain(
+        &self,
+        common: &ClientSessionCommon,
+        server_name: &ServerName<'_>,
+        now: UnixTime,
+    ) -> Result<(), Error> {
+        if self.server_cert_verifier.is_async() {
+            return Err(Error::General(
+                "stored sessions cannot be verified asynchronously".into(),
+            ));
+        }
+
+        let (end_entity, intermediates) = common
+            .server_cert_chain
+            .split_first()
+            .ok_or(Error::NoCertificatesPresented)?;
+        self.server_cert_verifier
+            .verify_server_cert(end_entity, intermediates, server_name, &[], now)
+            .map(|_| ())
+    }
+
+    fn find_cipher_suite(&self, suite: CipherSuite) -> Option<SupportedCipherSuite> {
+        self.provider
+            .cipher_suites
+            .iter()
+            .copied()
+            .find(|scs| scs.suite() == suite)
+    }
+}
+
+impl<'a> From<&'a ClientConfig> for ClientSessionContext<'a> {
+    fn from(config: &'a ClientConfig) -> Self {
+        Self {
+            provider: config.crypto_provider(),
+            server_cert_verifier: &config.verifier,
+            client_creds: &config.client_auth_cert_resolver,
+        }
+    }
+}
+
 /// This is the maximum allowed skew between server and client clocks, over
 /// the maximum ticket lifetime period.  This encompasses TCP retransmission
 /// times in case packet loss occurs when the client sends the ClientHello
@@ -380,7 +654,8 @@ pub struct Tls13ServerSessionValue {
     pub(crate) age_obfuscation_offset: u32,
 
     // not encoded vv
-    freshness: Option<bool>,
+    /// The difference between the client's and our idea of the ticket's age.
+    age_difference_ms: Option<u32>,
 }
 
 impl Tls13ServerSessionValue {
@@ -393,7 +668,7 @@ impl Tls13ServerSessionValue {
             common,
             secret: Zeroizing::new(PayloadU8::new(secret.to_vec())),
             age_obfuscation_offset,
-            freshness: None,
+            age_difference_ms: None,
         }
     }
 
@@ -408,14 +683,18 @@ impl Tls13ServerSessionValue {
             .saturating_sub(self.common.creation_time_sec) as u32)
             .saturating_mul(1000);
 
-        let age_difference = server_age_ms.abs_diff(client_age_ms);
-
-        self.freshness = Some(age_difference <= MAX_FRESHNESS_SKEW_MS);
+        self.age_difference_ms = Some(server_age_ms.abs_diff(client_age_ms));
         self
     }
 
     pub(crate) fn is_fresh(&self) -> bool {
-        self.freshness.unwrap_or_default()
+        self.is_fresh_within(MAX_FRESHNESS_SKEW_MS)
+    }
+
+    /// Like `is_fresh()`, but with a tolerance no larger than `max_skew_ms`.
+    pub(crate) fn is_fresh_within(&self, max_skew_ms: u32) -> bool {
+        self.age_difference_ms
+            .is_some_and(|difference| difference <= max_skew_ms.min(MAX_FRESHNESS_SKEW_MS))
     }
 }
 
@@ -432,7 +711,7 @@ impl Codec<'_> for Tls13ServerSessionValue {
             common: CommonServerSessionValue::read(r)?,
             secret: Zeroizing::new(PayloadU8::read(r)?),
             age_obfuscation_offset: u32::read(r)?,
-            freshness: None,
+            age_difference_ms: None,
         })
     }
 }
@@ -531,6 +810,92 @@ impl Codec<'_> for CommonServerSessionValue {
     }
 }
 
+/// Server state sealed into the cookie of a stateless HelloRetryRequest.
+///
+/// This carries everything needed to continue the handshake from the
+/// client's second `ClientHello` on a connection that did not send the
+/// HelloRetryRequest.
+#[derive(Debug)]
+pub(crate) struct HelloRetryCookie {
+    pub(crate) sni: Option<DnsName<'static>>,
+    pub(crate) cipher_suite: CipherSuite,
+    pub(crate) group: NamedGroup,
+    /// The transcript hash of the first `ClientHello`.
+    pub(crate) client_hello_hash: PayloadU8,
+    pub(crate) creation_time_sec: u64,
+}
+
+impl HelloRetryCookie {
+    pub(crate) fn new(
+        sni: Option<&DnsName<'_>>,
+        cipher_suite: CipherSuite,
+        group: NamedGroup,
+        client_hello_hash: &[u8],
+        creation_time: UnixTime,
+    ) -> Self {
+        Self {
+            sni: sni.map(|s| s.to_owned()),
+            cipher_suite,
+            group,
+            client_hello_hash: PayloadU8::new(client_hello_hash.to_vec()),
+            creation_time_sec: creation_time.as_secs(),
+        }
+    }
+}
+
+impl Codec<'_> for HelloRetryCookie {
+    fn encode(&self, bytes: &mut Vec<u8>) {
+        bytes.extend_from_slice(HELLO_RETRY_COOKIE_LABEL);
+        if let Some(sni) = &self.sni {
+            1u8.encode(bytes);
+            let sni_bytes: &str = sni.as_ref();
+            PayloadU8::<MaybeEmpty>::encode_slice(sni_bytes.as_bytes(), bytes);
+        } else {
+            0u8.encode(bytes);
+        }
+        self.cipher_suite.encode(bytes);
+        self.group.encode(bytes);
+        self.client_hello_hash.encode(bytes);
+        self.creation_time_sec.encode(bytes);
+    }
+
+    fn read(r: &mut Reader<'_>) -> Result<Self, InvalidMessage> {
+        if r.take(HELLO_RETRY_COOKIE_LABEL.len()) != Some(HELLO_RETRY_COOKIE_LABEL) {
+            return Err(InvalidMessage::UnexpectedMessage(
+                "hello retry cookie label",
+            ));
+        }
+
+        let sni = match u8::read(r)? {
+            1 => {
+                let dns_name = PayloadU8::<MaybeEmpty>::read(r)?;
+                match DnsName::try_from(dns_name.0.as_slice()) {
+                    Ok(dns_name) => Some(dns_name.to_owned()),
+                    Err(_) => return Err(InvalidMessage::InvalidServerName),
+                }
+            }
+            _ => None,
+        };
+
+        Ok(Self {
+            sni,
+            cipher_suite: CipherSuite::read(r)?,
+            group: NamedGroup::read(r)?,
+            client_hello_hash: PayloadU8::read(r)?,
+            creation_time_sec: u64::read(r)?,
+        })
+    }
+}
+
+/// Begins every encoded [`HelloRetryCookie`].
+///
+/// This separates cookies from tickets sealed by the same [`ProducesTickets`]: a
+/// [`ServerSessionValue`] encoding never begins with these bytes, and one cannot be
+/// read from them.
+///
+/// [`ProducesTickets`]: crate::server::ProducesTickets
+const HELLO_RETRY_COOKIE_LABEL: &[u8] = b"rustls hello retry cookie\0";
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -555,6 +920,36 @@ mod tests {
         println!("{:#04x?}", ssv.get_encoding());
     }
 
+    #[cfg(feature = "std")] // for UnixTime::now
+    #[test]
+    fn hello_retry_cookie_is_distinct_from_server_session_value() {
+        use std::vec;
+        let ssv = ServerSessionValue::Tls13(Tls13ServerSessionValue::new(
+            CommonServerSessionValue::new(
+                None,
+                CipherSuite::TLS13_AES_128_GCM_SHA256,
+                None,
+                None,
+                vec![4, 5, 6],
+                UnixTime::now(),
+            ),
+            &[1, 2, 3],
+            0x12345678,
+        ));
+        assert!(HelloRetryCookie::read_bytes(&ssv.get_encoding()).is_err());
+
+        let cookie = HelloRetryCookie::new(
+            None,
+            CipherSuite::TLS13_AES_128_GCM_SHA256,
+            NamedGroup::X25519,
+            &[7; 32],
+            UnixTime::now(),
+        )
+        .get_encoding();
+        assert!(HelloRetryCookie::read_bytes(&cookie).is_ok());
+        assert!(ServerSessionValue::read_bytes(&cookie).is_err());
+    }
+
     #[test]
     fn serversessionvalue_no_sni() {
         let bytes = [
@@ -0,0 +1,169 @@
+use alloc::vec::Vec;
+use core::fmt;
+
+use zeroize::Zeroizing;
+
+use crate::crypto::hash::HashAlgorithm;
+use crate::enums::ProtocolVersion;
+use crate::error::Error;
+use crate::msgs::base::{MaybeEmpty, NonEmpty, PayloadU16};
+use crate::msgs::codec::Codec;
+use crate::tls13::Tls13CipherSuite;
+use crate::tls13::key_schedule::derive_imported_psk;
+
+/// An external pre-shared key (PSK) for use in TLS1.3 handshakes.
+///
+/// External PSKs are provisioned out-of-band, and allow a client and server to
+/// authenticate each other without certificates.  See [RFC 8446 section 2.2].
+///
+/// Each PSK has an identity, which is sent in the clear, and a secret.  The secret is
+/// associated with the hash function of the given cipher suite, and may only be used
+/// in handshakes that negotiate a cipher suite with that hash function.  To use the
+/// same secret with several hash functions, derive an imported PSK for each
+/// with [`ExternalPsk::import()`].
+///
+/// Clients offer these via [`ClientConfig::external_psks`]; servers find them
+/// via [`ServerConfig::psk_resolver`].
+///
+/// [RFC 8446 section 2.2]: https://datatracker.ietf.org/doc/html/rfc8446#section-2.2
+/// [`ClientConfig::external_psks`]: crate::ClientConfig::external_psks
+/// [`ServerConfig::psk_resolver`]: crate::ServerConfig::psk_resolver
+pub struct ExternalPsk {
+    identity: Vec<u8>,
+    secret: Zeroizing<Vec<u8>>,
+    suite: &'static Tls13CipherSuite,
+    imported: bool,
+}
+
+impl ExternalPsk {
+    /// Make a new external PSK.
+    ///
+    /// `suite` determines the hash function associated with `secret`.
+    ///
+    /// This fails if `identity` is empty, as TLS cannot send an empty identity.
+    pub fn new(
+        identity: impl Into<Vec<u8>>,
+        secret: impl Into<Vec<u8>>,
+        suite: &'static Tls13CipherSuite,
+    ) -> Result<Self, Error> {
+        let identity = identity.into();
+        if identity.is_empty() {
+            return Err(Error::General(
+                "external PSK identity must not be empty".into(),
+            ));
+        }
+
+        Ok(Self {
+            identity,
+            secret: Zeroizing::new(secret.into()),
+            suite,
+            imported: false,
+        })
+    }
+
+    /// Derive an imported PSK from this one, as described in [RFC 9258].
+    ///
+    /// The hash function associated with this PSK is used for the import. If the PSK
+    /// has no natural association with a hash function, it should be made with a
+    /// SHA-256 cipher suite.
+    ///
+    /// The result is a PSK for use with the hash function of `suite`.  Its identity
+    /// is the encoded `ImportedIdentity` structure, which binds this PSK's identity,
+    /// the application-chosen `context`, TLS1.3 and the target hash function.
+    /// Clients and servers must perform the same imports.
+    ///
+    /// This fails if `suite`'s hash function has no registered KDF identifier.
+    ///
+    /// [RFC 9258]: https://datatracker.ietf.org/doc/html/rfc9258
+    pub fn import(&self, context: &[u8], suite: &'static Tls13CipherSuite) -> Result<Self, Error> {
+        // "HKDF_SHA256" and "HKDF_SHA384" from the "TLS KDF Identifiers" registry.
+        let target_kdf: u16 = match suite.common.hash_provider.algorithm() {
+            HashAlgorithm::SHA256 => 0x0001,
+            HashAlgorithm::SHA384 => 0x0002,
+            _ => {
+                return Err(Error::General(
+                    "no KDF identifier for cipher suite hash function".into(),
+                ));
+            }
+        };
+
+        // struct {
+        //    opaque external_identity<1...2^16-1>;
+        //    opaque context<0..2^16-1>;
+        //    uint16 target_protocol;
+        //    uint16 target_kdf;
+        // } ImportedIdentity;
+        let mut identity = Vec::new();
+        PayloadU16::<NonEmpty>::new(self.identity.clone()).encode(&mut identity);
+        PayloadU16::<MaybeEmpty>::new(context.to_vec()).encode(&mut identity);
+        ProtocolVersion::TLSv1_3.encode(&mut identity);
+        target_kdf.encode(&mut identity);
+
+        let secret = derive_imported_psk(
+            self.suite,
+            &self.secret,
+            &identity,
+            suite.common.hash_provider.output_len(),
+        )?;
+
+        Ok(Self {
+            identity,
+            secret,
+            suite,
+            imported: true,
+        })
+    }
+
+    /// The identity of this PSK, as sent in the `ClientHello`.
+    pub fn identity(&self) -> &[u8] {
+        &self.identity
+    }
+
+    /// The cipher suite this PSK was made for.
+    ///
+    /// The PSK can be used with any cipher suite that shares this suite's hash function.
+    pub fn suite(&self) -> &'static Tls13CipherSuite {
+        self.suite
+    }
+
+    /// Whether this PSK was produced by [`ExternalPsk::import()`].
+    ///
+    /// Imported PSKs use a different binder key label.
+    pub(crate) fn is_imported(&self) -> bool {
+        self.imported
+    }
+
+    pub(crate) fn secret(&self) -> &[u8] {
+        &self.secret
+    }
+}
+
+impl fmt::Debug for ExternalPsk {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ExternalPsk")
+            .field("identity", &self.identity)
+            .field("suite", &self.suite.common.suite)
+            .field("imported", &self.imported)
+            .finish_non_exhaustive()
+    }
+}
+
+/// The ways a pre-shared key can be combined with a key exchange.
+///
+/// See [RFC 8446 section 4.2.9].
+///
+/// [RFC 8446 section 4.2.9]: https://datatracker.ietf.org/doc/html/rfc8446#section-4.2.9
+#[non_exhaustive]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum PskKexMode {
+    /// The PSK is combined with an (EC)DHE key exchange (`psk_dhe_ke`).
+    ///
+    /// This provides forward secrecy.
+    PskWithDhe,
+
+    /// The PSK is used alone (`psk_ke`).
+    ///
+    /// Such connections do not have forward secrecy: anyone who later learns the
+    /// PSK can decrypt them.  This suits peers that cannot afford a key exchange.
+    PskOnly,
+}
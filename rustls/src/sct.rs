@@ -0,0 +1,236 @@
+use alloc::vec::Vec;
+
+use pki_types::CertificateDer;
+
+use crate::error::{CertificateError, Error, InvalidMessage};
+use crate::log::debug;
+use crate::msgs::base::{NonEmpty, Payload, PayloadU16, PayloadU24};
+use crate::msgs::codec::{Codec, ListLength, Reader, TlsListElement};
+use crate::verify::DigitallySignedStruct;
+use crate::x509;
+
+/// Where a [`SignedCertificateTimestamp`] was received from.
+#[non_exhaustive]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SctSource {
+    /// The server's `signed_certificate_timestamp` TLS extension.
+    TlsExtension,
+    /// The server's stapled OCSP response.
+    OcspResponse,
+    /// An extension of the end-entity certificate.
+    Certificate,
+}
+
+/// A signed certificate timestamp (SCT), as described in [RFC 6962 section 3.2].
+///
+/// An SCT is a Certificate Transparency log's promise to publish a certificate.
+/// Only version 1 SCTs are represented: SCTs of other versions are ignored.
+///
+/// [RFC 6962 section 3.2]: https://datatracker.ietf.org/doc/html/rfc6962#section-3.2
+#[derive(Clone, Debug)]
+pub struct SignedCertificateTimestamp {
+    log_id: [u8; 32],
+    timestamp: u64,
+    extensions: PayloadU16,
+    signature: DigitallySignedStruct,
+    source: SctSource,
+}
+
+impl SignedCertificateTimestamp {
+    /// The ID of the log that issued this SCT: the SHA-256 hash of its public key.
+    pub fn log_id(&self) -> &[u8; 32] {
+        &self.log_id
+    }
+
+    /// The time this SCT was issued, in milliseconds since the UNIX epoch.
+    pub fn timestamp(&self) -> u64 {
+        self.timestamp
+    }
+
+    /// The encoded `CtExtensions` of this SCT.
+    pub fn extensions(&self) -> &[u8] {
+        &self.extensions.0
+    }
+
+    /// The log's signature over [`SignedCertificateTimestamp::signed_message()`].
+    pub fn signature(&self) -> &DigitallySignedStruct {
+        &self.signature
+    }
+
+    /// Where this SCT was received from.
+    pub fn source(&self) -> SctSource {
+        self.source
+    }
+
+    /// The message signed by the log, for an SCT issued for `end_entity`.
+    ///
+    /// `issuer_key_hash` is the SHA-256 hash of the `SubjectPublicKeyInfo` of the
+    /// issuer of `end_entity`.  It is only used for SCTs from
+    /// [`SctSource::Certificate`], which are issued for a precertificate.
+    pub fn signed_message(
+        &self,
+        end_entity: &CertificateDer<'_>,
+        issuer_key_hash: &[u8; 32],
+    ) -> Result<Vec<u8>, Error> {
+        let mut message = Vec::with_capacity(64 + end_entity.len());
+        // sct_version: v1, signature_type: certificate_timestamp
+        message.extend_from_slice(&[0, 0]);
+        self.timestamp.encode(&mut message);
+
+        match self.source {
+            SctSource::TlsExtension | SctSource::OcspResponse => {
+                // entry_type: x509_entry
+                0u16.encode(&mut message);
+                PayloadU24(Payload::Borrowed(end_entity.as_ref())).encode(&mut message);
+            }
+            SctSource::Certificate => {
+                let tbs = x509::TbsCertificate::from_cert(end_entity)
+                    .ok_or(CertificateError::BadEncoding)?;
+                // entry_type: precert_entry
+                1u16.encode(&mut message);
+                message.extend_from_slice(issuer_key_hash);
+                PayloadU24(Payload::new(tbs.precertificate_tbs())).encode(&mut message);
+            }
+        }
+
+        self.extensions.encode(&mut message);
+        Ok(message)
+    }
+
+    /// Parse a version 1 `SerializedSCT`, returning `Ok(None)` for other versions.
+    fn read(r: &mut Reader<'_>, source: SctSource) -> Result<Option<Self>, InvalidMessage> {
+        // "Clients MUST ignore SCTs with versions they do not understand" - RFC 6962 section 3.2
+        if u8::read(r)? != 0 {
+            return Ok(None);
+        }
+
+        let log_id = r
+            .take(32)
+            .ok_or(InvalidMessage::MissingData("LogID"))?
+            .try_into()
+            .unwrap();
+        let sct = Self {
+            log_id,
+            timestamp: u64::read(r)?,
+            extensions: PayloadU16::read(r)?,
+            signature: DigitallySignedStruct::read(r)?,
+            source,
+        };
+        r.expect_empty("SignedCertificateTimestamp")?;
+        Ok(Some(sct))
+    }
+}
+
+/// RFC 6962: `SerializedSCT sct_list <1..2^16-1>` of `opaque SerializedSCT<1..2^16-1>`
+pub(crate) type SctList = Vec<PayloadU16<NonEmpty>>;
+
+impl TlsListElement for PayloadU16<NonEmpty> {
+    const SIZE_LEN: ListLength = ListLength::NonZeroU16 {
+        empty_error: InvalidMessage::IllegalEmptyList("SignedCertificateTimestamps"),
+    };
+}
+
+/// Collect the SCTs received for `end_entity`.
+///
+/// `sct_list` is the content of the `signed_certificate_timestamp` TLS extension, if
+/// received.  SCTs are also taken from `ocsp_response` and `end_entity` itself.
+///
+/// SCTs that cannot be parsed are ignored: they could not count towards a CT policy.
+pub(crate) fn received_scts(
+    end_entity: &CertificateDer<'_>,
+    sct_list: Option<&SctList>,
+    ocsp_response: &[u8],
+) -> Vec<SignedCertificateTimestamp> {
+    let mut scts = Vec::new();
+    let mut add = |list: &SctList, source| {
+        for sct in list {
+            match SignedCertificateTimestamp::read(&mut Reader::init(&sct.0), source) {
+                Ok(Some(sct)) => scts.push(sct),
+                Ok(None) => {}
+                #[cfg_attr(not(feature = "log"), allow(unused_variables))]
+                Err(err) => debug!("ignoring malformed SCT from {source:?}: {err:?}"),
+            }
+        }
+    };
+
+    if let Some(list) = sct_list {
+        add(list, SctSource::TlsExtension);
+    }
+
+    let embedded = x509::TbsCertificate::from_cert(end_entity)
+        .and_then(|tbs| tbs.sct_list())
+        .map(|list| (list, SctSource::Certificate));
+    let stapled = x509::ocsp_sct_list(ocsp_response).map(|list| (list, SctSource::OcspResponse));
+    for (list, source) in embedded.into_iter().chain(stapled) {
+        match SctList::read_bytes(list) {
+            Ok(list) => add(&list, source),
+            #[cfg_attr(not(feature = "log"), allow(unused_variables))]
+            Err(err) => debug!("ignoring malformed SCT list from {source:?}: {err:?}"),
+        }
+    }
+
+    scts
+}
+
+#[cfg(test)]
+mod tests {
+    use std::vec;
+
+    use super::*;
+
+    // A v1 SCT, with log ID 0x01.., timestamp 0x1234 and an ECDSA-P256-SHA256 signature.
+    const SCT: &[u8] = b"\x00\
+        \x01\x01\x01\x01\x01\x01\x01\x01\x01\x01\x01\x01\x01\x01\x01\x01\
+        \x01\x01\x01\x01\x01\x01\x01\x01\x01\x01\x01\x01\x01\x01\x01\x01\
+        \x00\x00\x00\x00\x00\x00\x12\x34\
+        \x00\x00\
+        \x04\x03\x00\x03sig";
+
+    #[test]
+    fn reads_sct() {
+        let sct = SignedCertificateTimestamp::read(&mut Reader::init(SCT), SctSource::Certificate)
+            .unwrap()
+            .unwrap();
+        assert_eq!(sct.log_id(), &[1; 32]);
+        assert_eq!(sct.timestamp(), 0x1234);
+        assert_eq!(sct.extensions(), b"");
+        assert_eq!(sct.signature().signature(), b"sig");
+        assert_eq!(sct.source(), SctSource::Certificate);
+    }
+
+    #[test]
+    fn ignores_unknown_versions() {
+        let mut sct = SCT.to_vec();
+        sct[0] = 1;
+        assert!(
+            SignedCertificateTimestamp::read(&mut Reader::init(&sct), SctSource::TlsExtension)
+                .unwrap()
+                .is_none()
+        );
+    }
+
+    #[test]
+    fn signed_message_for_x509_entry() {
+        let sct = SignedCertificateTimestamp::read(&mut Reader::init(SCT), SctSource::OcspResponse)
+            .unwrap()
+            .unwrap();
+        let message = sct
+            .signed_message(&CertificateDer::from(&b"cert"[..]), &[0; 32])
+            .unwrap();
+        assert_eq!(
+            message,
+            b"\x00\x00\x00\x00\x00\x00\x00\x00\x12\x34\x00\x00\x00\x00\x04cert\x00\x00"
+        );
+    }
+
+    #[test]
+    fn received_scts_ignores_malformed_scts() {
+        let list = vec![
+            PayloadU16::new(SCT.to_vec()),
+            PayloadU16::new(b"junk".to_vec()),
+        ];
+        let scts = received_scts(&CertificateDer::from(&b"cert"[..]), Some(&list), &[]);
+        assert_eq!(scts.len(), 1);
+        assert_eq!(scts[0].source(), SctSource::TlsExtension);
+    }
+}
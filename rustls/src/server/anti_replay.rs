@@ -0,0 +1,198 @@
+use alloc::vec;
+use alloc::vec::Vec;
+use core::fmt::{Debug, Formatter};
+use core::hash::BuildHasher;
+use core::time::Duration;
+use std::hash::RandomState;
+use std::sync::Mutex;
+
+use pki_types::UnixTime;
+
+use crate::server::RecordsClientHellos;
+
+/// An implementer of `RecordsClientHellos` that stores ClientHellos in memory,
+/// using a pair of Bloom filters.
+///
+/// ClientHellos are recorded in the current filter.  Once more than `window`
+/// has passed, the current filter becomes the previous one, and the previous
+/// one is discarded, so each ClientHello is remembered for between one and
+/// two `window`s.  Memory use is fixed, but once more than `capacity`
+/// ClientHellos are recorded per `window` the rate at which they are
+/// wrongly considered replays increases.
+///
+/// This only detects replays to a single server: servers sharing session
+/// ticket keys need to share a `RecordsClientHellos` implementation.
+pub struct ClientHelloBloomFilter {
+    window: Duration,
+    hashers: [RandomState; 2],
+    state: Mutex<FilterState>,
+}
+
+impl ClientHelloBloomFilter {
+    /// Make a new `ClientHelloBloomFilter`.
+    ///
+    /// `window` is how long ClientHellos are remembered for, and so twice how
+    /// far a client's ticket age may differ from the server's; something like
+    /// ten seconds is appropriate.  `capacity` is the expected number of
+    /// ClientHellos offering early data per `window`: at this number, fewer
+    /// than one in a hundred are wrongly considered replays.
+    pub fn new(window: Duration, capacity: usize) -> Self {
+        let words = capacity
+            .saturating_mul(BITS_PER_ITEM)
+            .div_ceil(64)
+            .max(1);
+        Self {
+            window,
+            hashers: [RandomState::new(), RandomState::new()],
+            state: Mutex::new(FilterState {
+                current: vec![0; words],
+                previous: vec![0; words],
+                current_started: None,
+            }),
+        }
+    }
+
+    /// The bit positions for `client_random`, by double hashing.
+    fn positions(&self, client_random: &[u8; 32], bits: u64) -> impl Iterator<Item = usize> {
+        let h1 = self.hashers[0].hash_one(client_random);
+        let h2 = self.hashers[1].hash_one(client_random) | 1;
+        (0..HASHES).map(move |i| (h1.wrapping_add(i.wrapping_mul(h2)) % bits) as usize)
+    }
+}
+
+impl RecordsClientHellos for ClientHelloBloomFilter {
+    fn enabled(&self) -> bool {
+        true
+    }
+
+    fn window(&self) -> Duration {
+        self.window
+    }
+
+    fn record(&self, client_random: &[u8; 32], now: UnixTime) -> bool {
+        let Ok(mut state) = self.state.lock() else {
+            return false;
+        };
+        state.maybe_rotate(Duration::from_secs(now.as_secs()), self.window);
+
+        let bits = state.current.len() as u64 * 64;
+        let positions = self
+            .positions(client_random, bits)
+            .collect::<Vec<_>>();
+        let is_set = |filter: &[u64]| {
+            positions
+                .iter()
+                .all(|&bit| filter[bit / 64] & (1 << (bit % 64)) != 0)
+        };
+
+        if is_set(&state.current) || is_set(&state.previous) {
+            return false;
+        }
+
+        for bit in positions {
+            state.current[bit / 64] |= 1 << (bit % 64);
+        }
+        true
+    }
+}
+
+impl Debug for ClientHelloBloomFilter {
+    fn fmt(&self, f: &mut Formatter<'_>) -> core::fmt::Result {
+        f.debug_struct("ClientHelloBloomFilter")
+            .field("window", &self.window)
+            .finish_non_exhaustive()
+    }
+}
+
+struct FilterState {
+    current: Vec<u64>,
+    previous: Vec<u64>,
+    current_started: Option<Duration>,
+}
+
+impl FilterState {
+    fn maybe_rotate(&mut self, now: Duration, window: Duration) {
+        let Some(started) = self.current_started else {
+            self.current_started = Some(now);
+            return;
+        };
+
+        // `now` is truncated to whole seconds, so a rotation needs strictly
+        // more than `window` to have elapsed for the filters to cover it.
+        let elapsed = now.saturating_sub(started);
+        if elapsed <= window {
+            return;
+        }
+
+        if elapsed <= window * 2 {
+            // Everything in `current` was recorded at most one window ago,
+            // so must be kept for another.
+            core::mem::swap(&mut self.current, &mut self.previous);
+        } else {
+            self.previous.fill(0);
+        }
+        self.current.fill(0);
+        self.current_started = Some(now);
+    }
+}
+
+/// Filter size and number of hashes, for a false positive rate of about 1%.
+const BITS_PER_ITEM: usize = 10;
+const HASHES: u64 = 7;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_clienthellobloomfilter_rejects_repeats() {
+        let f = ClientHelloBloomFilter::new(Duration::from_secs(10), 100);
+        assert!(f.enabled());
+        assert!(f.record(&[1; 32], at(1000)));
+        assert!(!f.record(&[1; 32], at(1000)));
+        assert!(f.record(&[2; 32], at(1000)));
+        assert!(!f.record(&[2; 32], at(1001)));
+    }
+
+    #[test]
+    fn test_clienthellobloomfilter_remembers_for_at_least_window() {
+        let f = ClientHelloBloomFilter::new(Duration::from_secs(10), 100);
+        assert!(f.record(&[1; 32], at(1000)));
+        assert!(f.record(&[2; 32], at(1009)));
+
+        assert!(!f.record(&[1; 32], at(1010)));
+
+        // rotation: both are now in the previous filter
+        assert!(!f.record(&[1; 32], at(1011)));
+        assert!(!f.record(&[2; 32], at(1019)));
+
+        // rotation: the previous filter is discarded
+        assert!(f.record(&[1; 32], at(1022)));
+        assert!(f.record(&[2; 32], at(1022)));
+    }
+
+    #[test]
+    fn test_clienthellobloomfilter_forgets_after_two_windows() {
+        let f = ClientHelloBloomFilter::new(Duration::from_secs(10), 100);
+        assert!(f.record(&[1; 32], at(1000)));
+        assert!(f.record(&[1; 32], at(1025)));
+    }
+
+    #[test]
+    fn test_clienthellobloomfilter_false_positive_rate() {
+        let f = ClientHelloBloomFilter::new(Duration::from_secs(10), 1000);
+        let mut false_positives = 0;
+        for i in 0..1000u32 {
+            let mut random = [0u8; 32];
+            random[..4].copy_from_slice(&i.to_be_bytes());
+            if !f.record(&random, at(1000)) {
+                false_positives += 1;
+            }
+        }
+        assert!(false_positives < 50, "{false_positives} false positives");
+    }
+
+    fn at(secs: u64) -> UnixTime {
+        UnixTime::since_unix_epoch(Duration::from_secs(secs))
+    }
+}
@@ -1,15 +1,22 @@
+use alloc::vec;
 use alloc::vec::Vec;
 use core::marker::PhantomData;
+#[cfg(feature = "std")]
+use core::time::Duration;
 
 use pki_types::{CertificateDer, PrivateKeyDer};
 
+#[cfg(feature = "std")]
+use super::ServerSessionCache;
+#[cfg(feature = "std")]
+use super::server_conn::MAX_STATEFUL_TICKET_LIFETIME;
 use super::{ResolvesServerCert, ServerConfig, handy};
 use crate::builder::{ConfigBuilder, WantsVerifier};
 use crate::error::Error;
 use crate::sign::{CertifiedKey, SingleCertAndKey};
 use crate::sync::Arc;
 use crate::verify::{ClientCertVerifier, NoClientAuth};
-use crate::{NoKeyLog, compress, versions};
+use crate::{NoKeyLog, PskKexMode, compress, versions};
 
 impl ConfigBuilder<ServerConfig, WantsVerifier> {
     /// Choose how to verify client certificates.
@@ -104,16 +111,23 @@ impl ConfigBuilder<ServerConfig, WantsServerCert> {
             cert_resolver,
             ignore_client_order: false,
             max_fragment_size: None,
+            record_size_limit: None,
             #[cfg(feature = "std")]
-            session_storage: handy::ServerSessionMemoryCache::new(256),
+            session_storage: ServerSessionCache::new(
+                256,
+                Duration::from_secs(MAX_STATEFUL_TICKET_LIFETIME.into()),
+                self.time_provider.clone(),
+            ),
             #[cfg(not(feature = "std"))]
             session_storage: Arc::new(handy::NoServerSessionStorage {}),
             ticketer: Arc::new(handy::NeverProducesTickets {}),
+            hello_retry_cookies: Arc::new(handy::NeverProducesTickets {}),
             alpn_protocols: Vec::new(),
             versions: self.state.versions,
             key_log: Arc::new(NoKeyLog {}),
             enable_secret_extraction: false,
             max_early_data_size: 0,
+            anti_replay: Arc::new(handy::NeverRecordsClientHellos {}),
             send_half_rtt_data: false,
             send_tls13_tickets: 2,
             require_ems: cfg!(feature = "fips"),
@@ -121,6 +135,10 @@ impl ConfigBuilder<ServerConfig, WantsServerCert> {
             cert_compressors: compress::default_cert_compressors().to_vec(),
             cert_compression_cache: Arc::new(compress::CompressionCache::default()),
             cert_decompressors: compress::default_cert_decompressors().to_vec(),
+            ech_keys: Vec::new(),
+            ech_backend: false,
+            psk_resolver: Arc::new(handy::NoServerPsks {}),
+            psk_kex_modes: vec![PskKexMode::PskWithDhe],
         }
     }
 }
@@ -1,5 +1,9 @@
 use pki_types::CertificateDer;
 
+use crate::enums::SignatureScheme;
+use crate::log::warn;
+use crate::msgs::codec::Codec;
+use crate::sct::SctList;
 use crate::sign;
 
 /// ActiveCertifiedKey wraps [`sign::CertifiedKey`] and tracks OSCP state in a single handshake.
@@ -32,4 +36,38 @@ impl ActiveCertifiedKey<'_> {
     pub(super) fn get_ocsp(&self) -> Option<&[u8]> {
         self.ocsp
     }
+
+    /// Get the SCT list, if there is a well-formed one.
+    pub(super) fn get_sct_list(&self) -> Option<SctList> {
+        match SctList::read_bytes(self.key.sct_list.as_deref()?) {
+            Ok(sct_list) => Some(sct_list),
+            #[cfg_attr(not(feature = "log"), allow(unused_variables))]
+            Err(err) => {
+                warn!("ignoring invalid SCT list: {err:?}");
+                None
+            }
+        }
+    }
+
+    /// Get the delegated credential and key, if the client can use them.
+    ///
+    /// `dc_schemes` are the schemes offered in the client's `delegated_credential`
+    /// extension, and `sigschemes` those in its `signature_algorithms` extension.
+    pub(super) fn get_delegated_key(
+        &self,
+        dc_schemes: Option<&[SignatureScheme]>,
+        sigschemes: &[SignatureScheme],
+    ) -> Option<&sign::DelegatedKey> {
+        let delegated = self.key.delegated_credential.as_ref()?;
+        let credential = &delegated.credential;
+        match dc_schemes? {
+            schemes
+                if schemes.contains(&credential.dc_cert_verify_algorithm())
+                    && sigschemes.contains(&credential.algorithm()) =>
+            {
+                Some(delegated)
+            }
+            _ => None,
+        }
+    }
 }
@@ -0,0 +1,768 @@
+use alloc::boxed::Box;
+use alloc::vec;
+use alloc::vec::Vec;
+use core::fmt;
+use core::ops::Range;
+#[cfg(feature = "std")]
+use std::io;
+
+use pki_types::{DnsName, EchConfigListBytes};
+
+use crate::CommonState;
+use crate::crypto::hpke::{
+    EncapsulatedSecret, Hpke, HpkeKeyPair, HpkeOpener, HpkePrivateKey, HpkeSuite,
+};
+use crate::enums::{AlertDescription, ContentType, HandshakeType, ProtocolVersion};
+use crate::error::{EncryptedClientHelloError, Error, InvalidMessage, PeerMisbehaved};
+use crate::log::{debug, trace};
+use crate::msgs::base::PayloadU16;
+use crate::msgs::codec::{Codec, LengthPrefixedBuffer, ListLength, Reader, u24};
+use crate::msgs::enums::ExtensionType;
+#[cfg(feature = "std")]
+use crate::msgs::fragmenter::MessageFragmenter;
+#[cfg(feature = "std")]
+use crate::msgs::handshake::ServerNamePayload;
+use crate::msgs::handshake::{
+    ClientHelloPayload, EchConfigContents, EchConfigPayload, EncryptedClientHello,
+    EncryptedClientHelloOuter, HandshakePayload, HpkeKeyConfig, HpkeSymmetricCipherSuite, Random,
+    SessionId,
+};
+#[cfg(feature = "std")]
+use crate::msgs::message::{HEADER_SIZE, MAX_WIRE_SIZE};
+use crate::msgs::message::{Message, MessagePayload};
+use crate::sync::Arc;
+#[cfg(feature = "std")]
+use crate::vecbuf::ChunkVecBuffer;
+
+/// An Encrypted Client Hello (ECH) configuration, together with the HPKE private key
+/// needed to decrypt ClientHellos encrypted to it.
+///
+/// Add these to [`ServerConfig::ech_keys`] to enable ECH on a server.  The
+/// [`EchKeyPair::config_list()`] should be published to clients, typically in the
+/// `ech` parameter of the server's DNS `HTTPS` resource record.
+///
+/// [`ServerConfig::ech_keys`]: crate::server::ServerConfig::ech_keys
+#[derive(Clone)]
+pub struct EchKeyPair {
+    /// The ECH configuration clients are using to encrypt their inner hello.
+    pub(crate) config: EchConfigPayload,
+
+    /// The HPKE instance matching the key encapsulation mechanism and symmetric cipher
+    /// suite advertised in `config`.
+    pub(crate) suite: &'static dyn Hpke,
+
+    private_key: Arc<HpkePrivateKey>,
+}
+
+impl EchKeyPair {
+    /// Construct an `EchKeyPair` from an existing HPKE key pair.
+    ///
+    /// `config_id` identifies this configuration to clients, and must be distinct from
+    /// the `config_id` of other keys in use at the same time.  `public_name` is the
+    /// name clients will send in the (unencrypted) outer ClientHello, and is the name
+    /// the server must be able to authenticate as if ECH is rejected.
+    ///
+    /// Returns an error if `suite` uses an HPKE AEAD that cannot be used for ECH.
+    pub fn new(
+        config_id: u8,
+        public_name: DnsName<'static>,
+        key_pair: HpkeKeyPair,
+        suite: &'static dyn Hpke,
+    ) -> Result<Self, Error> {
+        let HpkeSuite { kem, sym } = suite.suite();
+        if sym.aead_id.tag_len().is_none() {
+            return Err(EncryptedClientHelloError::NoCompatibleConfig.into());
+        }
+
+        let HpkeKeyPair {
+            public_key,
+            private_key,
+        } = key_pair;
+
+        Ok(Self {
+            config: EchConfigPayload::V18(EchConfigContents {
+                key_config: HpkeKeyConfig {
+                    config_id,
+                    kem_id: kem,
+                    public_key: PayloadU16::new(public_key.0),
+                    symmetric_cipher_suites: vec![sym],
+                },
+                maximum_name_length: 0,
+                public_name,
+                extensions: Vec::new(),
+            }),
+            suite,
+            private_key: Arc::new(private_key),
+        })
+    }
+
+    /// Generate a fresh HPKE key pair for `suite`, and construct an `EchKeyPair` from it.
+    ///
+    /// See [`EchKeyPair::new()`] for the meaning of the other arguments.
+    pub fn generate(
+        config_id: u8,
+        public_name: DnsName<'static>,
+        suite: &'static dyn Hpke,
+    ) -> Result<Self, Error> {
+        let (public_key, private_key) = suite.generate_key_pair()?;
+        Self::new(
+            config_id,
+            public_name,
+            HpkeKeyPair {
+                public_key,
+                private_key,
+            },
+            suite,
+        )
+    }
+
+    /// Return an encoded `ECHConfigList` containing only this configuration.
+    ///
+    /// This is the form in which clients expect to receive ECH configurations, for
+    /// example from [`crate::client::EchConfig::new()`].
+    pub fn config_list(&self) -> EchConfigListBytes<'static> {
+        let mut bytes = Vec::new();
+        vec![self.config.clone()].encode(&mut bytes);
+        EchConfigListBytes::from(bytes)
+    }
+
+    /// Return the `config_id` clients use to refer to this configuration.
+    pub fn config_id(&self) -> u8 {
+        self.contents().key_config.config_id
+    }
+
+    /// Return true if this key pair uses a FIPS approved HPKE suite.
+    pub fn fips(&self) -> bool {
+        self.suite.fips()
+    }
+
+    fn contents(&self) -> &EchConfigContents {
+        match &self.config {
+            EchConfigPayload::V18(contents) => contents,
+            // `EchKeyPair::new()` only constructs V18 configurations.
+            EchConfigPayload::Unknown { .. } => unreachable!(),
+        }
+    }
+
+    fn matches(&self, ext: &EncryptedClientHelloOuter) -> bool {
+        self.config_id() == ext.config_id && self.suite.suite().sym == ext.cipher_suite
+    }
+
+    /// Compute the HPKE `SetupBaseR` `info` parameter for this ECH configuration.
+    ///
+    /// See <https://datatracker.ietf.org/doc/html/draft-ietf-tls-esni-18#section-7.1>.
+    fn hpke_info(&self) -> Vec<u8> {
+        let mut info = Vec::with_capacity(128);
+        // "tls ech" || 0x00 || ECHConfig
+        info.extend_from_slice(b"tls ech\0");
+        self.config.encode(&mut info);
+        info
+    }
+}
+
+impl fmt::Debug for EchKeyPair {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("EchKeyPair")
+            .field("config", &self.config)
+            .field("suite", &self.suite)
+            .finish_non_exhaustive()
+    }
+}
+
+/// An enum representing the outcome of a client's Encrypted Client Hello (ECH) offer.
+#[non_exhaustive]
+#[derive(Debug, Clone, Copy, Default, Eq, PartialEq)]
+pub enum EchStatus {
+    /// The client did not offer ECH, or the server has no ECH keys configured.
+    #[default]
+    NotOffered,
+    /// The client offered ECH and the server decrypted the inner ClientHello.
+    ///
+    /// The handshake proceeded using the inner ClientHello.
+    Accepted,
+    /// The client offered ECH but the server could not decrypt it.
+    ///
+    /// The handshake proceeded using the outer ClientHello, and the server's ECH
+    /// configurations were sent to the client as retry configurations.  This is
+    /// also the outcome for clients sending GREASE ECH extensions.
+    Rejected,
+    /// The server is the backend in a split-mode ECH deployment, and received an
+    /// inner ClientHello forwarded by a client-facing server.
+    ///
+    /// The server did not decrypt the ECH offer itself.  That the inner ClientHello
+    /// was protected relies on it only being reachable via the client-facing server:
+    /// see [`ServerConfig::ech_backend`].
+    ///
+    /// [`ServerConfig::ech_backend`]: crate::server::ServerConfig::ech_backend
+    Forwarded,
+}
+
+/// Server-side ECH state, carried across a `HelloRetryRequest`.
+pub(super) enum EchState {
+    /// No ECH offer has been processed yet.
+    NotOffered,
+    /// The client's offer could not be decrypted; we continue with the outer hello.
+    Rejected,
+    /// We decrypted the client's offer.
+    Accepted(Box<EchAccepted>),
+    /// We are the backend server in a split-mode deployment: a client-facing server
+    /// decrypted the client's offer and forwarded the inner hello to us.
+    Forwarded,
+}
+
+impl EchState {
+    /// Decrypt the inner hello from an ECH offer in the `ClientHello` message `m`.
+    ///
+    /// Returns the reconstructed `ClientHelloInner` message if ECH was accepted, or
+    /// `None` if the handshake should continue with `m` itself.
+    ///
+    /// If `backend` is true and there are no `ech_keys`, an inner hello forwarded
+    /// by a client-facing server is accepted.
+    pub(super) fn process_client_hello(
+        &mut self,
+        ech_keys: &[EchKeyPair],
+        backend: bool,
+        m: &Message<'_>,
+        common: &mut CommonState,
+    ) -> Result<Option<Message<'static>>, Error> {
+        let Some((client_hello, encoded)) = client_hello_parts(m) else {
+            return Ok(None);
+        };
+
+        let outer_ext = match (&client_hello.encrypted_client_hello, &*self) {
+            // "Upon receipt of an "encrypted_client_hello" extension of type inner in a
+            //  ClientHello, if the backend server negotiates TLS 1.3 or higher, then it
+            //  MUST confirm ECH acceptance to the client" -- draft-ietf-tls-esni-18 7.2
+            (Some(EncryptedClientHello::Inner), Self::NotOffered | Self::Forwarded)
+                if backend && ech_keys.is_empty() =>
+            {
+                *self = Self::Forwarded;
+                return Ok(None);
+            }
+            // Otherwise, an inner hello is only valid inside an outer one.
+            (Some(EncryptedClientHello::Inner), Self::NotOffered) if !ech_keys.is_empty() => {
+                return Err(common.send_fatal_alert(
+                    AlertDescription::IllegalParameter,
+                    PeerMisbehaved::InvalidEncryptedClientHello,
+                ));
+            }
+            (Some(EncryptedClientHello::Outer(outer_ext)), Self::NotOffered)
+                if !ech_keys.is_empty() =>
+            {
+                outer_ext
+            }
+            (Some(EncryptedClientHello::Outer(outer_ext)), Self::Accepted(_)) => outer_ext,
+            // A retried hello must offer ECH in the same way as the first, if we accepted that.
+            (Some(EncryptedClientHello::Inner), Self::Accepted(_))
+            | (Some(EncryptedClientHello::Outer(_)), Self::Forwarded)
+            | (None, Self::Accepted(_) | Self::Forwarded) => {
+                return Err(common.send_fatal_alert(
+                    AlertDescription::IllegalParameter,
+                    PeerMisbehaved::EncryptedClientHelloVariedAfterRetry,
+                ));
+            }
+            _ => return Ok(None),
+        };
+
+        // Skip the handshake message header.
+        let outer = RawClientHello::read(&encoded[HANDSHAKE_HEADER_LEN..], false)
+            .map_err(|err| common.send_fatal_alert(AlertDescription::DecodeError, err))?;
+
+        let inner = match self {
+            Self::NotOffered => match Self::open_first(ech_keys, outer_ext, &outer) {
+                Some((accepted, encoded_inner)) => {
+                    *self = Self::Accepted(Box::new(accepted));
+                    decode_inner_hello(&encoded_inner, &outer, m.version)
+                }
+                None => {
+                    debug!("Rejecting ECH offer for config ID {}", outer_ext.config_id);
+                    *self = Self::Rejected;
+                    return Ok(None);
+                }
+            },
+            Self::Accepted(accepted) => accepted.open_retry(outer_ext, &outer, m.version),
+            Self::Rejected | Self::Forwarded => return Ok(None),
+        };
+
+        let inner = inner.map_err(|err| {
+            let alert = match err {
+                PeerMisbehaved::InvalidEncryptedClientHello => AlertDescription::DecryptError,
+                _ => AlertDescription::IllegalParameter,
+            };
+            common.send_fatal_alert(alert, err)
+        })?;
+        trace!("ECH accepted, inner hello: {inner:?}");
+        Ok(Some(inner))
+    }
+
+    pub(super) fn status(&self) -> EchStatus {
+        match self {
+            Self::NotOffered => EchStatus::NotOffered,
+            Self::Rejected => EchStatus::Rejected,
+            Self::Accepted(_) => EchStatus::Accepted,
+            Self::Forwarded => EchStatus::Forwarded,
+        }
+    }
+
+    /// Whether our `ServerHello` or `HelloRetryRequest` must confirm ECH acceptance.
+    pub(super) fn confirms_acceptance(&self) -> bool {
+        matches!(self, Self::Accepted(_) | Self::Forwarded)
+    }
+
+    fn open_first(
+        ech_keys: &[EchKeyPair],
+        outer_ext: &EncryptedClientHelloOuter,
+        outer: &RawClientHello<'_>,
+    ) -> Option<(EchAccepted, Vec<u8>)> {
+        let aad = outer.aad(outer_ext.payload.0.len());
+        let enc = EncapsulatedSecret(outer_ext.enc.0.clone());
+
+        for key in ech_keys
+            .iter()
+            .filter(|key| key.matches(outer_ext))
+        {
+            let Ok(mut opener) = key
+                .suite
+                .setup_opener(&enc, &key.hpke_info(), &key.private_key)
+            else {
+                continue;
+            };
+
+            if let Ok(encoded_inner) = opener.open(&aad, &outer_ext.payload.0) {
+                debug!("Accepted ECH offer for config ID {}", outer_ext.config_id);
+                let accepted = EchAccepted {
+                    opener,
+                    config_id: outer_ext.config_id,
+                    cipher_suite: outer_ext.cipher_suite,
+                };
+                return Some((accepted, encoded_inner));
+            }
+        }
+
+        None
+    }
+}
+
+/// The state kept once we have accepted an ECH offer.
+pub(super) struct EchAccepted {
+    // The HPKE context established by the first ClientHelloOuter.  This is reused to
+    // decrypt the ClientHelloOuter sent in response to a HelloRetryRequest.
+    opener: Box<dyn HpkeOpener>,
+    config_id: u8,
+    cipher_suite: HpkeSymmetricCipherSuite,
+}
+
+impl EchAccepted {
+    /// Decrypt the inner hello from the ClientHelloOuter sent after a HelloRetryRequest.
+    fn open_retry(
+        &mut self,
+        outer_ext: &EncryptedClientHelloOuter,
+        outer: &RawClientHello<'_>,
+        version: ProtocolVersion,
+    ) -> Result<Message<'static>, PeerMisbehaved> {
+        // "If the server sent a HelloRetryRequest, ... [the client] MUST use the same
+        //  config_id and cipher_suite ... and [enc] MUST be empty."
+        if outer_ext.config_id != self.config_id
+            || outer_ext.cipher_suite != self.cipher_suite
+            || !outer_ext.enc.0.is_empty()
+        {
+            return Err(PeerMisbehaved::EncryptedClientHelloVariedAfterRetry);
+        }
+
+        let encoded_inner = self
+            .opener
+            .open(&outer.aad(outer_ext.payload.0.len()), &outer_ext.payload.0)
+            .map_err(|_| PeerMisbehaved::InvalidEncryptedClientHello)?;
+        decode_inner_hello(&encoded_inner, outer, version)
+    }
+}
+
+/// The client-facing server's side of a split-mode Encrypted Client Hello (ECH) deployment.
+///
+/// This is obtained from [`Accepted::decrypt_ech()`] once the client's ECH offer has been
+/// decrypted.  It holds the reconstructed `ClientHelloInner`, and the HPKE context needed
+/// to decrypt a second `ClientHelloOuter` if the backend sends a `HelloRetryRequest`.
+///
+/// The application is responsible for connecting to the backend server named by
+/// [`EchForwarder::server_name()`], and then:
+///
+/// - passing all data received from the client to [`EchForwarder::forward()`],
+/// - writing the data produced by [`EchForwarder::write_tls()`] to the backend, and
+/// - passing all data received from the backend to the client unmodified.
+///
+/// The backend runs a normal [`ServerConnection`], which needs no ECH keys: it recognises
+/// the forwarded inner hello, and confirms ECH acceptance to the client.
+///
+/// See <https://datatracker.ietf.org/doc/html/draft-ietf-tls-esni-18#section-3.1>.
+///
+/// [`Accepted::decrypt_ech()`]: crate::server::Accepted::decrypt_ech
+/// [`ServerConnection`]: crate::server::ServerConnection
+#[cfg(feature = "std")]
+pub struct EchForwarder {
+    server_name: Option<DnsName<'static>>,
+    accepted: Box<EchAccepted>,
+    record_version: ProtocolVersion,
+    /// Data received from the client that does not yet form a complete record.
+    received: Vec<u8>,
+    /// The fragments received so far of a retried `ClientHelloOuter`.
+    retry_hello: Vec<u8>,
+    retried: bool,
+    to_backend: ChunkVecBuffer,
+}
+
+#[cfg(feature = "std")]
+impl EchForwarder {
+    pub(super) fn new(accepted: Box<EchAccepted>, inner: &Message<'_>) -> Self {
+        let server_name =
+            match client_hello_parts(inner).and_then(|(ch, _)| ch.server_name.as_ref()) {
+                Some(ServerNamePayload::SingleDnsName(name)) => Some(name.to_lowercase_owned()),
+                _ => None,
+            };
+
+        let mut forwarder = Self {
+            server_name,
+            accepted,
+            record_version: inner.version,
+            received: Vec::new(),
+            retry_hello: Vec::new(),
+            retried: false,
+            to_backend: ChunkVecBuffer::new(None),
+        };
+        forwarder.queue_hello(inner);
+        forwarder
+    }
+
+    /// Return the server name from the client's inner hello.
+    ///
+    /// This names the backend server to which the connection should be forwarded.
+    pub fn server_name(&self) -> Option<&DnsName<'static>> {
+        self.server_name.as_ref()
+    }
+
+    /// Process `data` received from the client, queuing it to be sent to the backend.
+    ///
+    /// Data is forwarded unmodified, with the exception of a `ClientHelloOuter`
+    /// sent in response to a `HelloRetryRequest` from the backend: this is decrypted,
+    /// and the corresponding `ClientHelloInner` forwarded instead.
+    ///
+    /// An error means the client misbehaved, and the connection should be closed.
+    pub fn forward(&mut self, data: &[u8]) -> Result<(), Error> {
+        if self.retried {
+            // No further plaintext handshake messages are expected from the client.
+            if !data.is_empty() {
+                self.to_backend.append(data.to_vec());
+            }
+            return Ok(());
+        }
+
+        self.received.extend_from_slice(data);
+        let mut used = 0;
+        while let Some(record) = self
+            .received
+            .get(used..used + HEADER_SIZE)
+        {
+            let len = usize::from(u16::from_be_bytes([record[3], record[4]]));
+            if HEADER_SIZE + len > MAX_WIRE_SIZE {
+                return Err(InvalidMessage::MessageTooLarge.into());
+            }
+
+            let Some(record) = self
+                .received
+                .get(used..used + HEADER_SIZE + len)
+            else {
+                break;
+            };
+            used += record.len();
+
+            if ContentType::from(record[0]) != ContentType::Handshake {
+                self.to_backend.append(record.to_vec());
+                continue;
+            }
+
+            // The only plaintext handshake message the client can send now is
+            // a retried `ClientHello`.
+            self.retry_hello
+                .extend_from_slice(&record[HEADER_SIZE..]);
+            let Some(header) = self
+                .retry_hello
+                .get(..HANDSHAKE_HEADER_LEN)
+            else {
+                continue;
+            };
+            let hello_len =
+                HANDSHAKE_HEADER_LEN + u24::read_bytes(&header[1..]).map(usize::from)?;
+            if self.retry_hello.len() < hello_len {
+                continue;
+            } else if self.retry_hello.len() > hello_len {
+                return Err(InvalidMessage::TrailingData("ClientHello").into());
+            }
+
+            self.process_retry()?;
+            self.retried = true;
+            let rest = self.received.split_off(used);
+            self.received = Vec::new();
+            return self.forward(&rest);
+        }
+
+        self.received.drain(..used);
+        Ok(())
+    }
+
+    /// Return true if there is data to be sent to the backend.
+    pub fn wants_write(&self) -> bool {
+        !self.to_backend.is_empty()
+    }
+
+    /// Write data to be sent to the backend to `wr`.
+    ///
+    /// To account for short writes this function should be called repeatedly until it
+    /// returns `Ok(0)` or an error.
+    pub fn write_tls(&mut self, wr: &mut dyn io::Write) -> io::Result<usize> {
+        self.to_backend.write_to(wr)
+    }
+
+    /// The alert to send the client when [`EchForwarder::forward()`] fails with `err`.
+    pub(super) fn alert_for(err: &Error) -> AlertDescription {
+        match err {
+            Error::InvalidMessage(_) => AlertDescription::DecodeError,
+            Error::InappropriateHandshakeMessage { .. } => AlertDescription::UnexpectedMessage,
+            Error::PeerMisbehaved(PeerMisbehaved::InvalidEncryptedClientHello) => {
+                AlertDescription::DecryptError
+            }
+            _ => AlertDescription::IllegalParameter,
+        }
+    }
+
+    fn process_retry(&mut self) -> Result<(), Error> {
+        let payload = MessagePayload::new(
+            ContentType::Handshake,
+            self.record_version,
+            &self.retry_hello,
+        )?;
+        let m = Message {
+            version: self.record_version,
+            payload,
+        };
+        let client_hello =
+            require_handshake_msg!(m, HandshakeType::ClientHello, HandshakePayload::ClientHello)?;
+        let Some(EncryptedClientHello::Outer(outer_ext)) = &client_hello.encrypted_client_hello
+        else {
+            return Err(PeerMisbehaved::EncryptedClientHelloVariedAfterRetry.into());
+        };
+
+        let outer = RawClientHello::read(&self.retry_hello[HANDSHAKE_HEADER_LEN..], false)?;
+        let inner = self
+            .accepted
+            .open_retry(outer_ext, &outer, self.record_version)?;
+        self.queue_hello(&inner);
+        Ok(())
+    }
+
+    fn queue_hello(&mut self, hello: &Message<'_>) {
+        let MessagePayload::Handshake { encoded, .. } = &hello.payload else {
+            unreachable!("constructed from a handshake message");
+        };
+
+        for fragment in MessageFragmenter::default().fragment_payload(
+            ContentType::Handshake,
+            self.record_version,
+            encoded.bytes().into(),
+        ) {
+            self.to_backend.append(
+                fragment
+                    .to_unencrypted_opaque()
+                    .encode(),
+            );
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl fmt::Debug for EchForwarder {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("EchForwarder")
+            .field("server_name", &self.server_name)
+            .finish_non_exhaustive()
+    }
+}
+
+/// Split a `ClientHello` message into its parsed payload and its encoding.
+fn client_hello_parts<'a>(m: &'a Message<'_>) -> Option<(&'a ClientHelloPayload, &'a [u8])> {
+    match &m.payload {
+        MessagePayload::Handshake { parsed, encoded } => match &parsed.0 {
+            HandshakePayload::ClientHello(ch) => Some((ch, encoded.bytes())),
+            _ => None,
+        },
+        _ => None,
+    }
+}
+
+/// Reconstruct the `ClientHelloInner` message from the decrypted `EncodedClientHelloInner`.
+///
+/// See <https://datatracker.ietf.org/doc/html/draft-ietf-tls-esni-18#section-5.1>.
+fn decode_inner_hello(
+    encoded_inner: &[u8],
+    outer: &RawClientHello<'_>,
+    version: ProtocolVersion,
+) -> Result<Message<'static>, PeerMisbehaved> {
+    let invalid = |_| PeerMisbehaved::InvalidEncryptedClientHelloInner;
+    let inner = RawClientHello::read(encoded_inner, true).map_err(invalid)?;
+
+    // The legacy_session_id is elided from the encoding, and must be empty.
+    if inner.session_id().len() != 1 {
+        return Err(PeerMisbehaved::InvalidEncryptedClientHelloInner);
+    }
+
+    let mut body = Vec::with_capacity(encoded_inner.len() + outer.bytes.len());
+    body.extend_from_slice(&inner.bytes[..SESSION_ID_OFFSET]);
+    body.extend_from_slice(outer.session_id());
+    body.extend_from_slice(&inner.bytes[SESSION_ID_OFFSET + 1..inner.extensions_offset]);
+
+    let extensions = LengthPrefixedBuffer::new(ListLength::U16, &mut body);
+    let mut outer_exts = outer.extensions.iter();
+    for (typ, range) in &inner.extensions {
+        if *typ != ExtensionType::EncryptedClientHelloOuterExtensions {
+            put_extension(*typ, &inner.bytes[range.clone()], extensions.buf);
+            continue;
+        }
+
+        // Substitute the referenced extensions from the outer hello, which must
+        // appear there in the same order.
+        let referenced =
+            Vec::<ExtensionType>::read_bytes(&inner.bytes[range.clone()]).map_err(invalid)?;
+        for wanted in referenced {
+            if wanted == ExtensionType::EncryptedClientHello {
+                return Err(PeerMisbehaved::InvalidEncryptedClientHelloInner);
+            }
+
+            let (typ, range) = outer_exts
+                .find(|(typ, _)| *typ == wanted)
+                .ok_or(PeerMisbehaved::InvalidEncryptedClientHelloInner)?;
+            put_extension(*typ, &outer.bytes[range.clone()], extensions.buf);
+        }
+    }
+    drop(extensions);
+
+    let mut encoded = Vec::with_capacity(HANDSHAKE_HEADER_LEN + body.len());
+    HandshakeType::ClientHello.encode(&mut encoded);
+    u24(body.len() as u32).encode(&mut encoded);
+    encoded.extend_from_slice(&body);
+
+    let payload = MessagePayload::new(ContentType::Handshake, version, &encoded)
+        .map_err(invalid)?
+        .into_owned();
+
+    let MessagePayload::Handshake { parsed, .. } = &payload else {
+        unreachable!("parsed from a handshake message");
+    };
+    let HandshakePayload::ClientHello(inner_hello) = &parsed.0 else {
+        unreachable!("encoded as a ClientHello");
+    };
+    check_inner_hello(inner_hello)?;
+
+    Ok(Message { version, payload })
+}
+
+/// Validate a `ClientHelloInner` per draft-ietf-tls-esni-18 section 7.1.
+fn check_inner_hello(inner_hello: &ClientHelloPayload) -> Result<(), PeerMisbehaved> {
+    // It must carry an inner ECH extension...
+    if !matches!(
+        inner_hello.encrypted_client_hello,
+        Some(EncryptedClientHello::Inner)
+    ) {
+        return Err(PeerMisbehaved::InvalidEncryptedClientHelloInner);
+    }
+
+    // ...and must not offer TLS1.2 or below.
+    match &inner_hello.supported_versions {
+        Some(versions) if versions.tls13 && !versions.tls12 => Ok(()),
+        _ => Err(PeerMisbehaved::InvalidEncryptedClientHelloInner),
+    }
+}
+
+fn put_extension(typ: ExtensionType, body: &[u8], out: &mut Vec<u8>) {
+    typ.encode(out);
+    (body.len() as u16).encode(out);
+    out.extend_from_slice(body);
+}
+
+/// A `ClientHello` body, with the location of each extension recorded.
+///
+/// We need to work on the encoding directly, because both the ECH AAD and
+/// the reconstructed inner hello depend on the exact bytes and extension order sent
+/// by the client.
+struct RawClientHello<'a> {
+    bytes: &'a [u8],
+    extensions_offset: usize,
+    extensions: Vec<(ExtensionType, Range<usize>)>,
+}
+
+impl<'a> RawClientHello<'a> {
+    /// Index the fields of a `ClientHello` body.
+    ///
+    /// If `padded`, this is an `EncodedClientHelloInner` and may be followed
+    /// by zero bytes of padding (which are excluded from `bytes`).
+    fn read(bytes: &'a [u8], padded: bool) -> Result<Self, InvalidMessage> {
+        let mut r = Reader::init(bytes);
+        ProtocolVersion::read(&mut r)?;
+        Random::read(&mut r)?;
+        SessionId::read(&mut r)?;
+        let cipher_suites_len = usize::from(u16::read(&mut r)?);
+        r.take(cipher_suites_len)
+            .ok_or(InvalidMessage::MissingData("CipherSuites"))?;
+        let compression_len = usize::from(u8::read(&mut r)?);
+        r.take(compression_len)
+            .ok_or(InvalidMessage::MissingData("Compressions"))?;
+
+        let extensions_offset = r.used();
+        let len = usize::from(u16::read(&mut r)?);
+        let mut sub = r.sub(len)?;
+        let mut extensions = Vec::new();
+        while sub.any_left() {
+            let typ = ExtensionType::read(&mut sub)?;
+            let len = usize::from(u16::read(&mut sub)?);
+            let start = extensions_offset + 2 + sub.used();
+            sub.take(len)
+                .ok_or(InvalidMessage::MissingData("ClientExtension"))?;
+            extensions.push((typ, start..start + len));
+        }
+
+        let end = r.used();
+        let rest = r.rest();
+        if !(rest.is_empty() || padded && rest.iter().all(|b| *b == 0)) {
+            return Err(InvalidMessage::TrailingData("ClientHelloPayload"));
+        }
+
+        Ok(Self {
+            bytes: &bytes[..end],
+            extensions_offset,
+            extensions,
+        })
+    }
+
+    /// The encoded `legacy_session_id`, including its length prefix.
+    fn session_id(&self) -> &'a [u8] {
+        let len = usize::from(self.bytes[SESSION_ID_OFFSET]);
+        &self.bytes[SESSION_ID_OFFSET..SESSION_ID_OFFSET + 1 + len]
+    }
+
+    /// Compute the `ClientHelloOuterAAD`: this hello with the ECH payload zeroed.
+    fn aad(&self, payload_len: usize) -> Vec<u8> {
+        let mut aad = self.bytes.to_vec();
+        if let Some((_, range)) = self
+            .extensions
+            .iter()
+            .find(|(typ, _)| *typ == ExtensionType::EncryptedClientHello)
+        {
+            // The payload is the final field of the extension.
+            aad[range.end - payload_len..range.end].fill(0);
+        }
+        aad
+    }
+}
+
+/// Handshake type (1 byte) and length (3 bytes).
+const HANDSHAKE_HEADER_LEN: usize = 1 + 3;
+
+/// The `legacy_session_id` follows the `legacy_version` (2 bytes) and `random` (32 bytes).
+const SESSION_ID_OFFSET: usize = 2 + 32;
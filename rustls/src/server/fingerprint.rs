@@ -0,0 +1,261 @@
+//! ClientHello fingerprinting: [JA3] and [JA4].
+//!
+//! [JA3]: https://github.com/salesforce/ja3
+//! [JA4]: https://github.com/FoxIO-LLC/ja4/blob/main/technical_details/JA4.md
+
+use alloc::string::String;
+use alloc::vec::Vec;
+use core::fmt::Write;
+
+use crate::crypto::hash::{Hash, HashAlgorithm};
+use crate::msgs::codec::{Codec, Reader};
+use crate::msgs::enums::ExtensionType;
+use crate::server::ClientHello;
+
+/// Compute the JA3 string (before hashing) for `hello`.
+pub(super) fn ja3(hello: &ClientHello<'_>) -> String {
+    let extensions = hello
+        .extensions()
+        .map(|(typ, _)| typ)
+        .filter(|typ| !is_grease(*typ))
+        .collect::<Vec<_>>();
+    let groups = extension_body(hello, ExtensionType::EllipticCurves)
+        .map(|body| u16_list(body, false))
+        .unwrap_or_default();
+    let formats = extension_body(hello, ExtensionType::ECPointFormats)
+        .map(u8_list)
+        .unwrap_or_default();
+
+    let mut out = String::new();
+    let _ = write!(out, "{}", u16::from(hello.client_version));
+    for list in [
+        &non_grease_suites(hello),
+        &extensions,
+        &groups
+            .into_iter()
+            .filter(|g| !is_grease(*g))
+            .collect(),
+        &formats
+            .into_iter()
+            .map(u16::from)
+            .collect::<Vec<_>>(),
+    ] {
+        out.push(',');
+        for (i, item) in list.iter().enumerate() {
+            if i > 0 {
+                out.push('-');
+            }
+            let _ = write!(out, "{item}");
+        }
+    }
+    out
+}
+
+/// Compute the JA4 fingerprint for `hello`, using `sha256` for the truncated hashes.
+pub(super) fn ja4(hello: &ClientHello<'_>, sha256: &dyn Hash) -> String {
+    debug_assert_eq!(sha256.algorithm(), HashAlgorithm::SHA256);
+
+    let protocol = match extension_body(hello, ExtensionType::TransportParameters) {
+        Some(_) => 'q',
+        None => 't',
+    };
+
+    let version = extension_body(hello, ExtensionType::SupportedVersions)
+        .and_then(|body| {
+            u16_list(body, true)
+                .into_iter()
+                .filter(|v| !is_grease(*v))
+                .max()
+        })
+        .unwrap_or(u16::from(hello.client_version));
+    let version = match version {
+        0x0304 => "13",
+        0x0303 => "12",
+        0x0302 => "11",
+        0x0301 => "10",
+        0x0300 => "s3",
+        0x0002 => "s2",
+        0xfeff => "d1",
+        0xfefd => "d2",
+        0xfefc => "d3",
+        _ => "00",
+    };
+
+    let sni = match extension_body(hello, ExtensionType::ServerName) {
+        Some(_) => 'd',
+        None => 'i',
+    };
+
+    let suites = non_grease_suites(hello);
+    let extensions = hello
+        .extensions()
+        .map(|(typ, _)| typ)
+        .filter(|typ| !is_grease(*typ))
+        .collect::<Vec<_>>();
+
+    let mut out = String::new();
+    let _ = write!(
+        out,
+        "{protocol}{version}{sni}{:02}{:02}",
+        suites.len().min(99),
+        extensions.len().min(99)
+    );
+    push_alpn(
+        &mut out,
+        extension_body(hello, ExtensionType::ALProtocolNegotiation),
+    );
+
+    out.push('_');
+    let mut sorted_suites = suites;
+    sorted_suites.sort_unstable();
+    push_truncated_hash(&mut out, sha256, &hex_list(&sorted_suites));
+
+    out.push('_');
+    let mut sorted_extensions = extensions
+        .into_iter()
+        .filter(|typ| {
+            *typ != u16::from(ExtensionType::ServerName)
+                && *typ != u16::from(ExtensionType::ALProtocolNegotiation)
+        })
+        .collect::<Vec<_>>();
+    sorted_extensions.sort_unstable();
+    let mut input = hex_list(&sorted_extensions);
+    let sigalgs = extension_body(hello, ExtensionType::SignatureAlgorithms)
+        .map(|body| u16_list(body, false))
+        .unwrap_or_default()
+        .into_iter()
+        .filter(|s| !is_grease(*s))
+        .collect::<Vec<_>>();
+    if !sigalgs.is_empty() {
+        input.push('_');
+        input.push_str(&hex_list(&sigalgs));
+    }
+    push_truncated_hash(&mut out, sha256, &input);
+
+    out
+}
+
+/// Return true if `value` is a GREASE value ([RFC8701]).
+///
+/// [RFC8701]: https://datatracker.ietf.org/doc/html/rfc8701
+fn is_grease(value: u16) -> bool {
+    value & 0x0f0f == 0x0a0a && value >> 8 == value & 0xff
+}
+
+fn non_grease_suites(hello: &ClientHello<'_>) -> Vec<u16> {
+    hello
+        .cipher_suites
+        .iter()
+        .map(|cs| u16::from(*cs))
+        .filter(|cs| !is_grease(*cs))
+        .collect()
+}
+
+/// Return the body of the first extension of type `typ`, if the client sent it.
+fn extension_body<'a>(hello: &ClientHello<'a>, typ: ExtensionType) -> Option<&'a [u8]> {
+    hello
+        .extensions()
+        .find(|(t, _)| *t == u16::from(typ))
+        .map(|(_, body)| body)
+}
+
+/// Decode a vector of `u16` with a `u16` (or, if `short_len`, a `u8`) length prefix.
+///
+/// Extensions we know are validated during parsing, so anything malformed here
+/// just truncates the result.
+fn u16_list(body: &[u8], short_len: bool) -> Vec<u16> {
+    let mut r = Reader::init(body);
+    let len = match short_len {
+        true => u8::read(&mut r).map(usize::from),
+        false => u16::read(&mut r).map(usize::from),
+    };
+    let Ok(mut sub) = len.and_then(|len| r.sub(len)) else {
+        return Vec::new();
+    };
+
+    let mut out = Vec::new();
+    while let Ok(item) = u16::read(&mut sub) {
+        out.push(item);
+    }
+    out
+}
+
+/// Decode a vector of `u8` with a `u8` length prefix.
+fn u8_list(body: &[u8]) -> Vec<u8> {
+    let mut r = Reader::init(body);
+    u8::read(&mut r)
+        .ok()
+        .and_then(|len| r.take(usize::from(len)))
+        .map(|items| items.to_vec())
+        .unwrap_or_default()
+}
+
+/// Append the first and last characters of the first ALPN protocol, or `00` if none.
+fn push_alpn(out: &mut String, body: Option<&[u8]>) {
+    let first = body.and_then(|body| {
+        let mut r = Reader::init(body);
+        u16::read(&mut r).ok()?;
+        let len = u8::read(&mut r).ok()?;
+        r.take(usize::from(len))
+    });
+
+    match first {
+        Some([first, .., last]) | Some([first @ last]) => {
+            if first.is_ascii_alphanumeric() && last.is_ascii_alphanumeric() {
+                out.push(char::from(*first));
+                out.push(char::from(*last));
+            } else {
+                let _ = write!(out, "{:x}{:x}", first >> 4, last & 0x0f);
+            }
+        }
+        _ => out.push_str("00"),
+    }
+}
+
+/// Format `items` as comma-separated, four digit lowercase hex.
+fn hex_list(items: &[u16]) -> String {
+    let mut out = String::new();
+    for (i, item) in items.iter().enumerate() {
+        if i > 0 {
+            out.push(',');
+        }
+        let _ = write!(out, "{item:04x}");
+    }
+    out
+}
+
+/// Append the first 12 hex characters of the hash of `input`, or zeroes if `input` is empty.
+fn push_truncated_hash(out: &mut String, sha256: &dyn Hash, input: &str) {
+    if input.is_empty() {
+        out.push_str("000000000000");
+        return;
+    }
+
+    for b in &sha256.hash(input.as_bytes()).as_ref()[..6] {
+        let _ = write!(out, "{b:02x}");
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn grease_values() {
+        assert!(is_grease(0x0a0a));
+        assert!(is_grease(0xfafa));
+        assert!(!is_grease(0x0a1a));
+        assert!(!is_grease(0x1301));
+    }
+
+    #[test]
+    fn alpn_encoding() {
+        let mut out = String::new();
+        push_alpn(&mut out, Some(b"\x00\x03\x02h2"));
+        push_alpn(&mut out, Some(b"\x00\x09\x08http/1.1"));
+        push_alpn(&mut out, Some(b"\x00\x02\x01x"));
+        push_alpn(&mut out, Some(b"\x00\x03\x02\xab\xcd"));
+        push_alpn(&mut out, None);
+        assert_eq!(out, "h2h1xxad00");
+    }
+}
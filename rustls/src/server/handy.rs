@@ -1,9 +1,12 @@
 use alloc::vec::Vec;
 use core::fmt::Debug;
+use core::time::Duration;
+
+use pki_types::UnixTime;
 
 use crate::server::ClientHello;
 use crate::sync::Arc;
-use crate::{server, sign};
+use crate::{ExternalPsk, server, sign};
 
 /// Something which never stores sessions.
 #[allow(clippy::exhaustive_structs)]
@@ -25,6 +28,17 @@ impl server::StoresServerSessions for NoServerSessionStorage {
     }
 }
 
+/// Something which never resolves an external PSK.
+#[allow(clippy::exhaustive_structs)]
+#[derive(Debug)]
+pub struct NoServerPsks {}
+
+impl server::ResolvesServerPsk for NoServerPsks {
+    fn resolve(&self, _identity: &[u8]) -> Option<Arc<ExternalPsk>> {
+        None
+    }
+}
+
 #[cfg(any(feature = "std", feature = "hashbrown"))]
 mod cache {
     use alloc::vec::Vec;
@@ -167,6 +181,22 @@ impl server::ProducesTickets for NeverProducesTickets {
     }
 }
 
+/// Something which never records ClientHellos.
+#[derive(Debug)]
+pub(super) struct NeverRecordsClientHellos {}
+
+impl server::RecordsClientHellos for NeverRecordsClientHellos {
+    fn enabled(&self) -> bool {
+        false
+    }
+    fn window(&self) -> Duration {
+        Duration::ZERO
+    }
+    fn record(&self, _client_random: &[u8; 32], _now: UnixTime) -> bool {
+        false
+    }
+}
+
 /// An exemplar `ResolvesServerCert` implementation that always resolves to a single
 /// [RFC 7250] raw public key.
 ///
@@ -261,6 +291,7 @@ mod sni_resolver {
     #[cfg(test)]
     mod tests {
         use super::*;
+        use crate::enums::ProtocolVersion;
         use crate::server::ResolvesServerCert;
 
         #[test]
@@ -277,6 +308,8 @@ mod sni_resolver {
                         cipher_suites: &[],
                         certificate_authorities: None,
                         named_groups: None,
+                        client_version: ProtocolVersion::TLSv1_2,
+                        extensions: &[],
                     })
                     .is_none()
             );
@@ -299,6 +332,8 @@ mod sni_resolver {
                         cipher_suites: &[],
                         certificate_authorities: None,
                         named_groups: None,
+                        client_version: ProtocolVersion::TLSv1_2,
+                        extensions: &[],
                     })
                     .is_none()
             );
@@ -4,6 +4,7 @@ use alloc::vec::Vec;
 
 use pki_types::DnsName;
 
+use super::ech::EchState;
 use super::server_conn::ServerConnectionData;
 use super::tls12;
 use crate::common_state::{KxState, Protocol, State};
@@ -17,6 +18,7 @@ use crate::error::{Error, PeerIncompatible, PeerMisbehaved};
 use crate::hash_hs::{HandshakeHash, HandshakeHashBuffer};
 use crate::log::{debug, trace};
 use crate::msgs::enums::{Compression, ExtensionType, NamedGroup};
+use crate::msgs::fragmenter::MAX_FRAGMENT_LEN;
 use crate::msgs::handshake::{
     ClientHelloPayload, HandshakePayload, KeyExchangeAlgorithm, ProtocolName, Random,
     ServerExtensions, ServerExtensionsInput, ServerNamePayload, SessionId, SingleProtocolName,
@@ -134,6 +136,43 @@ impl ExtensionProcessing {
             }
         }
 
+        // Record size limit.  QUIC packets are not TLS records, so this does not apply.
+        if let (false, Some(limit)) = (cx.common.is_quic(), hello.record_size_limit) {
+            cx.common
+                .set_peer_record_size_limit(limit)?;
+
+            // "Endpoints MUST NOT send a value higher than the protocol-defined maximum
+            // record size" - RFC8449 section 4.
+            let protocol_max = match cx.common.is_tls13() {
+                true => MAX_FRAGMENT_LEN as u16 + 1,
+                false => MAX_FRAGMENT_LEN as u16,
+            };
+            self.extensions.record_size_limit = Some(
+                cx.common
+                    .record_size_limit
+                    .map_or(protocol_max, |ours| ours.min(protocol_max)),
+            );
+        }
+
+        // Max fragment length.  "A server that supports the record_size_limit extension
+        // MUST ignore a max_fragment_length that appears in a ClientHello if both
+        // extensions appear." - RFC8449 section 5.
+        if let (false, None, Some(mfl)) = (
+            cx.common.is_quic(),
+            hello.record_size_limit,
+            hello.max_fragment_length,
+        ) {
+            if mfl.payload_len().is_none() {
+                return Err(cx.common.send_fatal_alert(
+                    AlertDescription::IllegalParameter,
+                    PeerMisbehaved::InvalidMaxFragmentLength,
+                ));
+            }
+
+            cx.common.set_max_fragment_length(mfl);
+            self.extensions.max_fragment_length = Some(mfl);
+        }
+
         let for_resume = resumedata.is_some();
         // SNI
         if let (false, Some(ServerNamePayload::SingleDnsName(_))) = (for_resume, &hello.server_name)
@@ -294,6 +333,7 @@ pub(super) struct ExpectClientHello {
     pub(super) using_ems: bool,
     pub(super) done_retry: bool,
     pub(super) send_tickets: usize,
+    pub(super) ech_state: EchState,
 }
 
 impl ExpectClientHello {
@@ -315,9 +355,29 @@ impl ExpectClientHello {
             using_ems: false,
             done_retry: false,
             send_tickets: 0,
+            ech_state: EchState::NotOffered,
         }
     }
 
+    /// Process any Encrypted Client Hello offer in the `ClientHello` message `m`.
+    ///
+    /// Returns the inner hello if the offer was accepted, in which case the handshake
+    /// must continue with that instead of `m`.
+    pub(super) fn process_ech(
+        &mut self,
+        m: &Message<'_>,
+        cx: &mut ServerContext<'_>,
+    ) -> Result<Option<Message<'static>>, Error> {
+        let inner = self.ech_state.process_client_hello(
+            &self.config.ech_keys,
+            self.config.ech_backend,
+            m,
+            cx.common,
+        )?;
+        cx.data.ech_status = self.ech_state.status();
+        Ok(inner)
+    }
+
     /// Continues handling of a `ClientHello` message once config and certificate are available.
     pub(super) fn with_certified_key(
         self,
@@ -399,6 +459,13 @@ impl ExpectClientHello {
                 .certificate_authority_names
                 .as_deref(),
         };
+        // A handshake using an external PSK needs no certificate, and one using
+        // PSK_KE needs no key exchange: so decide on that first.
+        let external_psk = match version {
+            ProtocolVersion::TLSv1_3 => tls13::offered_external_psk(&self.config, client_hello),
+            _ => None,
+        };
+
         // Choose a certificate.
         let certkey = {
             let client_hello = ClientHello {
@@ -414,6 +481,8 @@ impl ExpectClientHello {
                 cipher_suites: &client_hello.cipher_suites,
                 certificate_authorities,
                 named_groups: client_hello.named_groups.as_deref(),
+                client_version: client_hello.client_version,
+                extensions: &client_hello.received,
             };
             trace!("Resolving server certificate: {client_hello:#?}");
 
@@ -422,19 +491,28 @@ impl ExpectClientHello {
                 .cert_resolver
                 .resolve(&client_hello);
 
-            certkey.ok_or_else(|| {
-                cx.common.send_fatal_alert(
-                    AlertDescription::AccessDenied,
-                    Error::General("no server certificate chain resolved".to_owned()),
-                )
-            })?
+            match (certkey, &external_psk) {
+                (Some(certkey), _) => Some(certkey),
+                (None, Some(_)) => None,
+                (None, None) => {
+                    return Err(cx.common.send_fatal_alert(
+                        AlertDescription::AccessDenied,
+                        Error::General("no server certificate chain resolved".to_owned()),
+                    ));
+                }
+            }
         };
-        let certkey = ActiveCertifiedKey::from_certified_key(&certkey);
+        let certkey = certkey
+            .as_deref()
+            .map(ActiveCertifiedKey::from_certified_key);
 
         let (suite, skxg) = self
             .choose_suite_and_kx_group(
                 version,
-                certkey.get_key().algorithm(),
+                certkey
+                    .as_ref()
+                    .map(|certkey| certkey.get_key().algorithm()),
+                external_psk.as_ref(),
                 cx.common.protocol,
                 client_hello
                     .named_groups
@@ -449,7 +527,10 @@ impl ExpectClientHello {
 
         debug!("decided upon suite {suite:?}");
         cx.common.suite = Some(suite);
-        cx.common.kx_state = KxState::Start(skxg);
+        cx.common.kx_state = match skxg {
+            Some(skxg) => KxState::Start(skxg),
+            None => KxState::None,
+        };
 
         // Start handshake hash.
         let starting_hash = suite.hash_provider();
@@ -486,6 +567,8 @@ impl ExpectClientHello {
                         done_retry: self.done_retry,
                         send_tickets: self.send_tickets,
                         extra_exts: self.extra_exts,
+                        ech_state: self.ech_state,
+                        external_psk,
                     },
                     cx,
                     certkey,
@@ -494,39 +577,53 @@ impl ExpectClientHello {
                     skxg,
                     sig_schemes,
                 ),
-            SupportedCipherSuite::Tls12(suite) => suite
-                .protocol_version
-                .server
-                .handle_client_hello(
-                    tls12::CompleteClientHelloHandling {
-                        config: self.config,
-                        transcript,
-                        session_id: self.session_id,
-                        suite,
-                        using_ems: self.using_ems,
-                        randoms,
-                        send_ticket: self.send_tickets > 0,
-                        extra_exts: self.extra_exts,
-                    },
-                    cx,
-                    certkey,
-                    m,
-                    client_hello,
-                    skxg,
-                    sig_schemes,
-                    tls13_enabled,
-                ),
+            SupportedCipherSuite::Tls12(suite) => {
+                // Only TLS1.3 external PSKs can do without these.
+                let (Some(certkey), Some(skxg)) = (certkey, skxg) else {
+                    return Err(cx.common.send_fatal_alert(
+                        AlertDescription::HandshakeFailure,
+                        PeerIncompatible::NoKxGroupsInCommon,
+                    ));
+                };
+                suite
+                    .protocol_version
+                    .server
+                    .handle_client_hello(
+                        tls12::CompleteClientHelloHandling {
+                            config: self.config,
+                            transcript,
+                            session_id: self.session_id,
+                            suite,
+                            using_ems: self.using_ems,
+                            randoms,
+                            send_ticket: self.send_tickets > 0,
+                            extra_exts: self.extra_exts,
+                        },
+                        cx,
+                        certkey,
+                        m,
+                        client_hello,
+                        skxg,
+                        sig_schemes,
+                        tls13_enabled,
+                    )
+            }
         }
     }
 
     fn choose_suite_and_kx_group(
         &self,
         selected_version: ProtocolVersion,
-        sig_key_algorithm: SignatureAlgorithm,
+        sig_key_algorithm: Option<SignatureAlgorithm>,
+        external_psk: Option<&tls13::OfferedExternalPsk>,
         protocol: Protocol,
         client_groups: &[NamedGroup],
         client_suites: &[CipherSuite],
-    ) -> Result<(SupportedCipherSuite, &'static dyn SupportedKxGroup), PeerIncompatible> {
+    ) -> Result<(SupportedCipherSuite, Option<&'static dyn SupportedKxGroup>), PeerIncompatible>
+    {
+        // A PSK_KE handshake does not need a key exchange group.
+        let kx_required = !matches!(external_psk, Some(psk) if psk.is_psk_only());
+
         // Determine which `KeyExchangeAlgorithm`s are theoretically possible, based
         // on the offered and supported groups.
         let mut ecdhe_possible = false;
@@ -574,7 +671,7 @@ impl ExpectClientHello {
             None
         };
 
-        if !ecdhe_possible && !ffdhe_possible {
+        if kx_required && !ecdhe_possible && !ffdhe_possible {
             return Err(PeerIncompatible::NoKxGroupsInCommon);
         }
 
@@ -584,14 +681,24 @@ impl ExpectClientHello {
             .cipher_suites
             .iter()
             .filter(|suite| {
-                // Reduce our supported ciphersuites by the certified key's algorithm.
-                suite.usable_for_signature_algorithm(sig_key_algorithm)
+                // Reduce our supported ciphersuites by the certified key's algorithm,
+                // or without one, to those the external PSK can be used with.
+                let authenticated = match (sig_key_algorithm, external_psk) {
+                    (Some(sig_key_algorithm), _) => {
+                        suite.usable_for_signature_algorithm(sig_key_algorithm)
+                    }
+                    (None, Some(psk)) => psk.usable_with(suite),
+                    (None, None) => false,
+                };
+
+                authenticated
                 // And version
                 && suite.version().version() == selected_version
                 // And protocol
                 && suite.usable_for_protocol(protocol)
                 // And support one of key exchange groups
-                && (ecdhe_possible && suite.usable_for_kx_algorithm(KeyExchangeAlgorithm::ECDHE)
+                && (!kx_required
+                || ecdhe_possible && suite.usable_for_kx_algorithm(KeyExchangeAlgorithm::ECDHE)
                 || ffdhe_possible && suite.usable_for_kx_algorithm(KeyExchangeAlgorithm::DHE))
             });
 
@@ -628,19 +735,20 @@ impl ExpectClientHello {
             });
 
         if selected_version == ProtocolVersion::TLSv1_3 {
-            // This unwrap is structurally guaranteed by the early return for `!ffdhe_possible && !ecdhe_possible`
-            return Ok((*suite, *maybe_skxg.unwrap()));
+            // This is structurally guaranteed to be `Some` when `kx_required`, by the early
+            // return for `!ffdhe_possible && !ecdhe_possible`
+            return Ok((*suite, maybe_skxg.copied()));
         }
 
         // For TLS1.2, the server can unilaterally choose a DHE group if it has one and
         // there was no better option.
         match maybe_skxg {
-            Some(skxg) => Ok((*suite, *skxg)),
+            Some(skxg) => Ok((*suite, Some(*skxg))),
             None if suite.usable_for_kx_algorithm(KeyExchangeAlgorithm::DHE) => {
                 // If kx for the selected cipher suite is DHE and no DHE groups are specified in the extension,
                 // the server is free to choose DHE params, we choose the first DHE kx group of the provider.
                 if let Some(server_selected_ffdhe_skxg) = first_supported_dhe_kxg {
-                    Ok((*suite, *server_selected_ffdhe_skxg))
+                    Ok((*suite, Some(*server_selected_ffdhe_skxg)))
                 } else {
                     Err(PeerIncompatible::NoKxGroupsInCommon)
                 }
@@ -659,8 +767,27 @@ impl State<ServerConnectionData> for ExpectClientHello {
     where
         Self: 'm,
     {
-        let (client_hello, sig_schemes) = process_client_hello(&m, self.done_retry, cx)?;
-        self.with_certified_key(sig_schemes, client_hello, &m, cx)
+        let mut state = self;
+
+        // A client continuing after a stateless HelloRetryRequest may send its
+        // middlebox compatibility CCS before we have seen any ClientHello.
+        if let (MessagePayload::ChangeCipherSpec(_), false) = (&m.payload, state.done_retry) {
+            if state
+                .config
+                .hello_retry_cookies
+                .enabled()
+            {
+                cx.common
+                    .received_tls13_change_cipher_spec()?;
+                return Ok(state);
+            }
+        }
+
+        let inner = state.process_ech(&m, cx)?;
+        let m = inner.as_ref().unwrap_or(&m);
+
+        let (client_hello, sig_schemes) = process_client_hello(m, state.done_retry, cx)?;
+        state.with_certified_key(sig_schemes, client_hello, m, cx)
     }
 
     fn into_owned(self: Box<Self>) -> NextState<'static> {
@@ -1,40 +1,53 @@
 use alloc::boxed::Box;
+use alloc::string::String;
 use alloc::vec::Vec;
 use core::fmt;
 use core::fmt::{Debug, Formatter};
 use core::marker::PhantomData;
 use core::ops::{Deref, DerefMut};
+use core::time::Duration;
 #[cfg(feature = "std")]
 use std::io;
 
 use pki_types::{DnsName, UnixTime};
 
-use super::hs;
+#[cfg(feature = "std")]
+use super::ech::{EchForwarder, EchState};
+use super::ech::{EchKeyPair, EchStatus};
+use super::{fingerprint, hs};
 #[cfg(feature = "std")]
 use crate::WantsVerifier;
 use crate::builder::ConfigBuilder;
-use crate::common_state::{CommonState, Side};
+use crate::check::inappropriate_message;
 #[cfg(feature = "std")]
-use crate::common_state::{Protocol, State};
+use crate::common_state::Protocol;
+use crate::common_state::{CommonState, Side, State};
 use crate::conn::{ConnectionCommon, ConnectionCore, UnbufferedConnectionCommon};
 #[cfg(doc)]
 use crate::crypto;
 use crate::crypto::CryptoProvider;
-use crate::enums::{CertificateType, CipherSuite, ProtocolVersion, SignatureScheme};
+use crate::crypto::hash::HashAlgorithm;
+use crate::enums::{
+    AlertDescription, CertificateType, CipherSuite, ContentType, ProtocolVersion, SignatureScheme,
+};
 use crate::error::Error;
 use crate::kernel::KernelConnection;
 use crate::log::trace;
 use crate::msgs::base::Payload;
+use crate::msgs::ccs::ChangeCipherSpecPayload;
+use crate::msgs::enums::ExtensionType;
 use crate::msgs::handshake::{ClientHelloPayload, ProtocolName, ServerExtensionsInput};
-use crate::msgs::message::Message;
+use crate::msgs::message::{Message, MessagePayload};
 use crate::suites::ExtractedSecrets;
 use crate::sync::Arc;
 #[cfg(feature = "std")]
 use crate::time_provider::DefaultTimeProvider;
 use crate::time_provider::TimeProvider;
+use crate::unbuffered::{EncodeError, InsufficientSizeError};
 use crate::vecbuf::ChunkVecBuffer;
 use crate::{
-    DistinguishedName, KeyLog, NamedGroup, WantsVersions, compress, sign, verify, versions,
+    DistinguishedName, ExternalPsk, KeyLog, NamedGroup, PskKexMode, WantsVersions, compress, sign,
+    verify, versions,
 };
 
 /// A trait for the ability to store server session data.
@@ -77,8 +90,20 @@ pub trait StoresServerSessions: Debug + Send + Sync {
     /// whether their session can be resumed; the implementation is not required to remember
     /// a session even if it returns `true` here.
     fn can_cache(&self) -> bool;
+
+    /// How long, in seconds, a stored session remains usable.
+    ///
+    /// This is advertised to clients as the lifetime of TLS1.3 stateful
+    /// tickets, and is capped at 24 hours.  The default implementation
+    /// returns 24 hours.
+    fn lifetime(&self) -> u32 {
+        MAX_STATEFUL_TICKET_LIFETIME
+    }
 }
 
+/// The longest lifetime advertised for TLS1.3 stateful tickets, in seconds.
+pub(crate) const MAX_STATEFUL_TICKET_LIFETIME: u32 = 24 * 60 * 60;
+
 /// A trait for the ability to encrypt and decrypt tickets.
 pub trait ProducesTickets: Debug + Send + Sync {
     /// Returns true if this implementation will encrypt/decrypt
@@ -112,6 +137,42 @@ pub trait ProducesTickets: Debug + Send + Sync {
     fn decrypt(&self, cipher: &[u8]) -> Option<Vec<u8>>;
 }
 
+/// A trait for recording ClientHellos that offer early data, so that
+/// replays of them can be detected.
+///
+/// This is the "ClientHello recording" mechanism of [RFC 8446 §8.2]: each
+/// ClientHello is identified by its random value, which cannot be changed
+/// without invalidating the PSK binder.  Combined with the ticket age
+/// checks of [RFC 8446 §8.3], a replayed ClientHello is either recorded or
+/// too old, and its early data is rejected.  The handshake then continues
+/// as normal, without early data.
+///
+/// [RFC 8446 §8.2]: https://datatracker.ietf.org/doc/html/rfc8446#section-8.2
+/// [RFC 8446 §8.3]: https://datatracker.ietf.org/doc/html/rfc8446#section-8.3
+pub trait RecordsClientHellos: Debug + Send + Sync {
+    /// Returns true if this implementation records ClientHellos.
+    /// Should return false if this is a dummy implementation: the
+    /// server will not call the other functions.
+    fn enabled(&self) -> bool;
+
+    /// Returns how long a ClientHello is at least remembered for after it is
+    /// recorded.
+    ///
+    /// Early data is only accepted if the client's view of its ticket's age
+    /// is within half this duration of the server's.  A ClientHello can then
+    /// only be replayed with its early data accepted within `window` of its
+    /// first arrival, while it is still remembered.
+    fn window(&self) -> Duration;
+
+    /// Record a ClientHello with the given `client_random`, received at `now`.
+    ///
+    /// Returns `true` if it was not previously recorded, in which case its
+    /// early data may be accepted.  This may spuriously return `false`,
+    /// which costs a round trip but not correctness; it must never
+    /// spuriously return `true`.
+    fn record(&self, client_random: &[u8; 32], now: UnixTime) -> bool;
+}
+
 /// How to choose a certificate chain and signing key for use
 /// in server authentication.
 ///
@@ -134,6 +195,17 @@ pub trait ResolvesServerCert: Debug + Send + Sync {
     }
 }
 
+/// How to find the external pre-shared key (PSK) for an identity
+/// offered by a client.
+///
+/// See [`ExternalPsk`] for more information.
+pub trait ResolvesServerPsk: Debug + Send + Sync {
+    /// Return the PSK with the given `identity`, or `None` if it is unknown.
+    ///
+    /// `identity` is untrusted data supplied by the client.
+    fn resolve(&self, identity: &[u8]) -> Option<Arc<ExternalPsk>>;
+}
+
 /// A struct representing the received Client Hello
 #[derive(Debug)]
 pub struct ClientHello<'a> {
@@ -148,6 +220,8 @@ pub struct ClientHello<'a> {
     /// [certificate_authorities]: https://datatracker.ietf.org/doc/html/rfc8446#section-4.2.4
     pub(super) certificate_authorities: Option<&'a [DistinguishedName]>,
     pub(super) named_groups: Option<&'a [NamedGroup]>,
+    pub(super) client_version: ProtocolVersion,
+    pub(super) extensions: &'a [(ExtensionType, Payload<'static>)],
 }
 
 impl<'a> ClientHello<'a> {
@@ -238,6 +312,45 @@ impl<'a> ClientHello<'a> {
     pub fn named_groups(&self) -> Option<&'a [NamedGroup]> {
         self.named_groups
     }
+
+    /// Get every extension sent by the client, in the order it was sent.
+    ///
+    /// Each item is the extension type and its undecoded body.  Unlike the other
+    /// accessors, this includes extensions unknown to rustls and [GREASE] values.
+    ///
+    /// [GREASE]: https://datatracker.ietf.org/doc/html/rfc8701
+    pub fn extensions(&self) -> impl Iterator<Item = (u16, &'a [u8])> {
+        self.extensions
+            .iter()
+            .map(|(typ, body)| (u16::from(*typ), body.bytes()))
+    }
+
+    /// Compute the [JA3] fingerprint string of this `ClientHello`.
+    ///
+    /// This is the comma-separated string before hashing: the canonical JA3
+    /// fingerprint is its MD5 hash, which is left to the caller.  GREASE values
+    /// are ignored.
+    ///
+    /// [JA3]: https://github.com/salesforce/ja3
+    pub fn ja3(&self) -> String {
+        fingerprint::ja3(self)
+    }
+
+    /// Compute the [JA4] fingerprint of this `ClientHello`.
+    ///
+    /// The truncated hashes are computed with the SHA-256 implementation of `provider`.
+    /// Returns `None` if `provider` has no cipher suite using SHA-256.  GREASE values
+    /// are ignored.
+    ///
+    /// [JA4]: https://github.com/FoxIO-LLC/ja4/blob/main/technical_details/JA4.md
+    pub fn ja4(&self, provider: &CryptoProvider) -> Option<String> {
+        let sha256 = provider
+            .cipher_suites
+            .iter()
+            .map(|suite| suite.hash_provider())
+            .find(|hash| hash.algorithm() == HashAlgorithm::SHA256)?;
+        Some(fingerprint::ja4(self, sha256))
+    }
 }
 
 /// Common configuration for a set of server sessions.
@@ -252,17 +365,26 @@ impl<'a> ClientHello<'a> {
 /// # Defaults
 ///
 /// * [`ServerConfig::max_fragment_size`]: the default is `None` (meaning 16kB).
-/// * [`ServerConfig::session_storage`]: if the `std` feature is enabled, the default stores 256
-///   sessions in memory. If the `std` feature is not enabled, the default is to not store any
+/// * [`ServerConfig::record_size_limit`]: the default is `None` (meaning the protocol maximum).
+/// * [`ServerConfig::session_storage`]: if the `std` feature is enabled, the default is a
+///   [`ServerSessionCache`] storing 256 sessions in memory for up to 24 hours, timed by the
+///   time provider the config was built with. If the `std` feature is not enabled, the default is to not store any
 ///   sessions. In a no-std context, by enabling the `hashbrown` feature you may provide your
 ///   own `session_storage` using [`ServerSessionMemoryCache`] and a `crate::lock::MakeMutex`
 ///   implementation.
 /// * [`ServerConfig::alpn_protocols`]: the default is empty -- no ALPN protocol is negotiated.
+/// * [`ServerConfig::hello_retry_cookies`]: the default is disabled -- HelloRetryRequests are stateful.
 /// * [`ServerConfig::key_log`]: key material is not logged.
+/// * [`ServerConfig::anti_replay`]: the default is disabled -- early data is only accepted for
+///   stateful resumption.
 /// * [`ServerConfig::send_tls13_tickets`]: 2 tickets are sent.
 /// * [`ServerConfig::cert_compressors`]: depends on the crate features, see [`compress::default_cert_compressors()`].
 /// * [`ServerConfig::cert_compression_cache`]: caches the most recently used 4 compressions
 /// * [`ServerConfig::cert_decompressors`]: depends on the crate features, see [`compress::default_cert_decompressors()`].
+/// * [`ServerConfig::ech_keys`]: the default is empty -- Encrypted Client Hello is not supported.
+/// * [`ServerConfig::ech_backend`]: the default is false -- split-mode ECH backends are not supported.
+/// * [`ServerConfig::psk_resolver`]: the default resolves no external PSKs.
+/// * [`ServerConfig::psk_kex_modes`]: the default is [`PskKexMode::PskWithDhe`] only.
 ///
 /// # Sharing resumption storage between `ServerConfig`s
 ///
@@ -291,6 +413,7 @@ impl<'a> ClientHello<'a> {
 ///
 /// [`RootCertStore`]: crate::RootCertStore
 /// [`ServerSessionMemoryCache`]: crate::server::handy::ServerSessionMemoryCache
+/// [`ServerSessionCache`]: crate::server::ServerSessionCache
 #[derive(Clone, Debug)]
 pub struct ServerConfig {
     /// Source of randomness and other crypto.
@@ -310,10 +433,36 @@ pub struct ServerConfig {
     /// Setting this value to a little less than the TCP MSS may improve latency
     /// for stream-y workloads.
     ///
+    /// Smaller limits requested by the client with the `max_fragment_length`
+    /// extension of [RFC 6066] are always honoured, unless the client also sent
+    /// `record_size_limit`; see [`CommonState::max_fragment_length()`].
+    ///
     /// [TLS maximum]: https://datatracker.ietf.org/doc/html/rfc8446#section-5.1
     /// [ServerConnection::new]: crate::server::ServerConnection::new
+    /// [RFC 6066]: https://datatracker.ietf.org/doc/html/rfc6066#section-4
+    /// [`CommonState::max_fragment_length()`]: crate::CommonState::max_fragment_length
     pub max_fragment_size: Option<usize>,
 
+    /// The largest record this server is willing to receive, advertised to
+    /// clients with the `record_size_limit` extension of [RFC 8449].
+    ///
+    /// This is a limit on the size of a record's plaintext.  In TLS1.3 it includes
+    /// the content type, so the largest payload is one byte smaller.
+    ///
+    /// Clients that send the extension are always answered with it, and their
+    /// limit is honoured for records sent to them.  This value is advertised in
+    /// return; a client that supports the extension should then send no larger
+    /// records, and larger records are rejected.  A value of None advertises
+    /// the protocol maximum, and applies no additional limit.
+    ///
+    /// The value must be at least 64.  Out of range values are reported as
+    /// errors from [ServerConnection::new].  The extension is not used for QUIC
+    /// connections.
+    ///
+    /// [RFC 8449]: https://datatracker.ietf.org/doc/html/rfc8449
+    /// [ServerConnection::new]: crate::server::ServerConnection::new
+    pub record_size_limit: Option<u16>,
+
     /// How to store client sessions.
     ///
     /// See [ServerConfig#sharing-resumption-storage-between-serverconfigs]
@@ -326,6 +475,25 @@ pub struct ServerConfig {
     /// for a warning related to this field.
     pub ticketer: Arc<dyn ProducesTickets>,
 
+    /// How to seal the cookie sent in a TLS1.3 HelloRetryRequest.
+    ///
+    /// If this is enabled, each HelloRetryRequest carries a cookie sealing the
+    /// transcript hash of the client's first `ClientHello`, along with the cipher
+    /// suite and key exchange group chosen for it.  The client's second `ClientHello`
+    /// can then be processed by a fresh [`ServerConnection`] or [`Acceptor`], so a
+    /// server need not keep any state between the two.  Such a connection also
+    /// accepts the middlebox compatibility `change_cipher_spec` record that the
+    /// client may send before its second `ClientHello`.
+    ///
+    /// Cookies are accepted for [`ProducesTickets::lifetime()`] seconds after they
+    /// are issued.  Their sealed contents are labelled, so this may share a key with
+    /// [`ServerConfig::ticketer`] without a ticket being accepted as a cookie, or
+    /// the reverse.  No cookie is sent if the client offered Encrypted Client Hello,
+    /// because the state for that cannot be recovered from the transcript.
+    ///
+    /// [`Acceptor`]: crate::server::Acceptor
+    pub hello_retry_cookies: Arc<dyn ProducesTickets>,
+
     /// How to choose a server cert and key. This is usually set by
     /// [ConfigBuilder::with_single_cert] or [ConfigBuilder::with_cert_resolver].
     /// For async applications, see also [Acceptor].
@@ -356,6 +524,10 @@ pub struct ServerConfig {
     ///
     /// Read the early data via [`ServerConnection::early_data`].
     ///
+    /// Unless [`ServerConfig::anti_replay`] is enabled, early data is only
+    /// accepted for stateful resumption, where each session can only be
+    /// used once.
+    ///
     /// The units for this are _both_ plaintext bytes, _and_ ciphertext
     /// bytes, depending on whether the server accepts a client's early_data
     /// or not.  It is therefore recommended to include some slop in
@@ -363,6 +535,14 @@ pub struct ServerConfig {
     /// expansion in the latter case.
     pub max_early_data_size: u32,
 
+    /// How to detect replayed early data.
+    ///
+    /// If enabled, early data is also accepted for resumption via
+    /// [`ServerConfig::ticketer`], and all ClientHellos offering early data
+    /// are recorded: see [`RecordsClientHellos`].  The default
+    /// does nothing.
+    pub anti_replay: Arc<dyn RecordsClientHellos>,
+
     /// Whether the server should send "0.5RTT" data.  This means the server
     /// sends data after its first flight of handshake messages, without
     /// waiting for the client to complete the handshake.
@@ -443,6 +623,48 @@ pub struct ServerConfig {
     ///
     /// [RFC8779]: https://datatracker.ietf.org/doc/rfc8879/
     pub cert_decompressors: Vec<&'static dyn compress::CertDecompressor>,
+
+    /// Keys for decrypting Encrypted Client Hello (ECH) offers.
+    ///
+    /// If this is non-empty, a client's ECH offer made using one of these
+    /// configurations is decrypted, and the handshake proceeds with the
+    /// protected inner `ClientHello`.  Offers that cannot be decrypted are
+    /// rejected: the handshake continues with the outer `ClientHello`, and
+    /// all of these configurations are sent to the client as retry configurations.
+    ///
+    /// This only applies to TLS1.3 connections.
+    pub ech_keys: Vec<EchKeyPair>,
+
+    /// Whether to act as the backend server in a split-mode ECH deployment.
+    ///
+    /// If true, and [`ServerConfig::ech_keys`] is empty, a `ClientHello` carrying an
+    /// inner `encrypted_client_hello` extension is taken to have been decrypted and
+    /// forwarded by a client-facing server (see [`Accepted::decrypt_ech()`]).  The
+    /// handshake confirms ECH acceptance to the client, and the connection's
+    /// [`EchStatus`] is [`EchStatus::Forwarded`].
+    ///
+    /// Only enable this if the server is reachable solely via the client-facing
+    /// server: anyone connecting directly can send such a `ClientHello`.  The default
+    /// is false, in which case these `ClientHello`s are rejected if `ech_keys`
+    /// is non-empty, and their ECH extension is otherwise ignored.
+    pub ech_backend: bool,
+
+    /// How to find external pre-shared keys (PSKs) offered by clients.
+    ///
+    /// A handshake using one of these PSKs authenticates both peers with it:
+    /// the server sends no certificate, and does not request one from the client.
+    ///
+    /// The cipher suite is chosen before this is consulted: a PSK whose hash
+    /// function does not match that suite is ignored.
+    ///
+    /// This only applies to TLS1.3 connections.
+    pub psk_resolver: Arc<dyn ResolvesServerPsk>,
+
+    /// The key exchange modes allowed for external PSKs, in order of preference.
+    ///
+    /// The first of these that the client also offers is used.  Resumption
+    /// always uses [`PskKexMode::PskWithDhe`], regardless of this setting.
+    pub psk_kex_modes: Vec<PskKexMode>,
 }
 
 impl ServerConfig {
@@ -535,7 +757,12 @@ impl ServerConfig {
     /// is concerned only with cryptography, whereas this _also_ covers TLS-level
     /// configuration that NIST recommends.
     pub fn fips(&self) -> bool {
-        self.provider.fips() && self.require_ems
+        self.provider.fips()
+            && self.require_ems
+            && self
+                .ech_keys
+                .iter()
+                .all(|key| key.fips())
     }
 
     /// Return the crypto provider used to construct this client configuration.
@@ -573,24 +800,28 @@ impl ServerConfig {
 #[cfg(feature = "std")]
 mod connection {
     use alloc::boxed::Box;
-    use core::fmt;
+    use alloc::vec::Vec;
     use core::fmt::{Debug, Formatter};
     use core::ops::{Deref, DerefMut};
+    use core::{fmt, mem};
     use std::io;
 
-    use pki_types::DnsName;
+    use pki_types::{CertificateDer, DnsName, UnixTime};
 
     use super::{
-        Accepted, Accepting, EarlyDataState, ServerConfig, ServerConnectionData,
-        ServerExtensionsInput,
+        Accepted, Accepting, EarlyDataState, EchStatus, ServerConfig, ServerConnectionData,
+        ServerExtensionsInput, is_middlebox_ccs,
     };
     use crate::common_state::{CommonState, Context, Side};
     use crate::conn::{ConnectionCommon, ConnectionCore};
     use crate::error::Error;
+    use crate::exported_authenticator::{self, AuthenticatorRequest};
     use crate::server::hs;
+    use crate::sign::CertifiedKey;
     use crate::suites::ExtractedSecrets;
     use crate::sync::Arc;
     use crate::vecbuf::ChunkVecBuffer;
+    use crate::verify::ClientCertVerifier;
 
     /// Allows reading of early data in resumed TLS1.3 connections.
     ///
@@ -666,6 +897,112 @@ mod connection {
                 .map(|x| &x[..])
         }
 
+        /// The identity of the external PSK used to authenticate the client, if any.
+        ///
+        /// This is `Some` if and only if the handshake used a PSK found by
+        /// [`ServerConfig::psk_resolver`].
+        pub fn external_psk_identity(&self) -> Option<&[u8]> {
+            self.inner
+                .core
+                .data
+                .external_psk_identity
+                .as_deref()
+        }
+
+        /// Ask the client to authenticate itself with a certificate, after the
+        /// handshake has completed.
+        ///
+        /// This sends a TLS1.3 `CertificateRequest` message, and is only possible
+        /// if the client offered post-handshake authentication in its `ClientHello`.
+        /// The client's response is checked with `verifier`, and once it is verified
+        /// the client's certificates are available from
+        /// [`CommonState::peer_certificates()`].  A client that declines sends no
+        /// certificates; that is an error if `verifier` makes client auth mandatory.
+        ///
+        /// Only one request may be outstanding at a time.  Meanwhile, application
+        /// data continues to flow in both directions.
+        ///
+        /// This is not available for TLS1.2 or QUIC connections.
+        pub fn request_client_certificate(
+            &mut self,
+            verifier: Arc<dyn ClientCertVerifier>,
+        ) -> Result<(), Error> {
+            self.inner
+                .core
+                .request_client_certificate(verifier)
+        }
+
+        /// Make a request for the client to prove possession of an identity.
+        ///
+        /// This is an exported authenticator request, described in [RFC 9261].  Unlike
+        /// [`ServerConnection::request_client_certificate()`], the request and the reply
+        /// are carried by the application.  The application sends
+        /// [`AuthenticatorRequest::as_bytes()`] to the client, which answers with
+        /// [`ClientConnection::exported_authenticator()`].  The reply is then checked with
+        /// [`ServerConnection::validate_exported_authenticator()`].
+        ///
+        /// `context` must be unique for each request on this connection, and should be
+        /// unpredictable to the client (for example, randomly generated).  It may be
+        /// at most 255 bytes.  The request asks for the signature schemes and root hint
+        /// subjects of `verifier`.
+        ///
+        /// This is only possible once the handshake is complete, and only for TLS1.3.
+        ///
+        /// [RFC 9261]: https://datatracker.ietf.org/doc/html/rfc9261
+        /// [`ClientConnection::exported_authenticator()`]: crate::ClientConnection::exported_authenticator
+        pub fn exported_authenticator_request(
+            &self,
+            context: Vec<u8>,
+            verifier: &dyn ClientCertVerifier,
+        ) -> Result<AuthenticatorRequest, Error> {
+            AuthenticatorRequest::new(
+                &self.inner.core.common_state,
+                context,
+                verifier.supported_verify_schemes(),
+                verifier.root_hint_subjects().to_vec(),
+            )
+        }
+
+        /// Make an exported authenticator in reply to a `request` from the client.
+        ///
+        /// The authenticator proves possession of `certified_key`, and is sent to the
+        /// client by the application.  If `certified_key` is `None`, the authenticator
+        /// is empty, declining the request.
+        ///
+        /// See [`ClientConnection::exported_authenticator_request()`].
+        ///
+        /// [`ClientConnection::exported_authenticator_request()`]: crate::ClientConnection::exported_authenticator_request
+        pub fn exported_authenticator(
+            &self,
+            request: &AuthenticatorRequest,
+            certified_key: Option<&CertifiedKey>,
+        ) -> Result<Vec<u8>, Error> {
+            exported_authenticator::make_authenticator(&self.inner.core, request, certified_key)
+        }
+
+        /// Check an exported authenticator the client sent in reply to `request`.
+        ///
+        /// `request` must have come from [`ServerConnection::exported_authenticator_request()`].
+        /// The client's certificate chain is checked with `verifier` at time `now`.
+        ///
+        /// Returns the client's certificate chain, or `None` if the client declined the
+        /// request.  Declining is an error if `verifier` makes client authentication mandatory.
+        pub fn validate_exported_authenticator(
+            &self,
+            request: &AuthenticatorRequest,
+            authenticator: &[u8],
+            verifier: &dyn ClientCertVerifier,
+            now: UnixTime,
+        ) -> Result<Option<Vec<CertificateDer<'static>>>, Error> {
+            exported_authenticator::validate_client_authenticator(
+                &self.inner.core,
+                request,
+                authenticator,
+                verifier,
+                now,
+            )
+        }
+
         /// Set the resumption data to embed in future resumption tickets supplied to the client.
         ///
         /// Defaults to the empty byte string. Must be less than 2^15 bytes to allow room for other
@@ -698,6 +1035,7 @@ mod connection {
         /// - The client just doesn't support early data.
         /// - The connection doesn't resume an existing session.
         /// - The client hasn't sent a full ClientHello yet.
+        /// - [`ServerConfig::anti_replay`] identified the ClientHello as a possible replay.
         pub fn early_data(&mut self) -> Option<ReadEarlyData<'_>> {
             let data = &mut self.inner.core.data;
             if data.early_data.was_accepted() {
@@ -707,6 +1045,11 @@ mod connection {
             }
         }
 
+        /// Return the connection's Encrypted Client Hello (ECH) status.
+        pub fn ech_status(&self) -> EchStatus {
+            self.inner.core.data.ech_status
+        }
+
         /// Return true if the connection was made with a `ServerConfig` that is FIPS compatible.
         ///
         /// This is different from [`crate::crypto::CryptoProvider::fips()`]:
@@ -798,6 +1141,7 @@ mod connection {
     /// ```
     pub struct Acceptor {
         inner: Option<ConnectionCommon<ServerConnectionData>>,
+        received_tls: Vec<u8>,
     }
 
     impl Default for Acceptor {
@@ -812,6 +1156,7 @@ mod connection {
                     )
                     .into(),
                 ),
+                received_tls: Vec::new(),
             }
         }
     }
@@ -825,18 +1170,36 @@ mod connection {
         /// [`Connection::read_tls()`]: crate::Connection::read_tls
         pub fn read_tls(&mut self, rd: &mut dyn io::Read) -> Result<usize, io::Error> {
             match &mut self.inner {
-                Some(conn) => conn.read_tls(rd),
+                Some(conn) => conn.read_tls(&mut CopyingReader {
+                    rd,
+                    copy: &mut self.received_tls,
+                }),
                 None => Err(io::Error::other(
                     "acceptor cannot read after successful acceptance",
                 )),
             }
         }
 
+        /// The TLS records read from the client so far, exactly as read by
+        /// [`Acceptor::read_tls()`].
+        ///
+        /// This allows the connection to be passed through to another server verbatim,
+        /// rather than being completed here.  Once [`Acceptor::accept()`] yields an
+        /// [`Accepted`], they are available from [`Accepted::received_tls()`] instead.
+        pub fn received_tls(&self) -> &[u8] {
+            &self.received_tls
+        }
+
         /// Check if a `ClientHello` message has been received.
         ///
         /// Returns `Ok(None)` if the complete `ClientHello` has not yet been received.
         /// Do more I/O and then call this function again.
         ///
+        /// A `change_cipher_spec` record preceding the `ClientHello` is skipped: a client
+        /// sends this after a stateless HelloRetryRequest.  [`Accepted::into_connection()`]
+        /// then rejects the connection unless the chosen [`ServerConfig`] enables
+        /// [`ServerConfig::hello_retry_cookies`].
+        ///
         /// Returns `Ok(Some(accepted))` if the connection has been accepted. Call
         /// `accepted.into_connection()` to continue. Do not call this function again.
         ///
@@ -851,13 +1214,26 @@ mod connection {
                 ));
             };
 
-            let message = match connection.first_handshake_message() {
-                Ok(Some(msg)) => msg,
-                Ok(None) => {
-                    self.inner = Some(connection);
-                    return Ok(None);
+            let mut skipped_ccs = false;
+            let message = loop {
+                match connection.first_handshake_message() {
+                    Ok(Some(msg)) if is_middlebox_ccs(&msg) => {
+                        skipped_ccs = true;
+                        if let Err(err) = connection
+                            .core
+                            .common_state
+                            .received_tls13_change_cipher_spec()
+                        {
+                            return Err((err, AcceptedAlert::from(connection)));
+                        }
+                    }
+                    Ok(Some(msg)) => break msg,
+                    Ok(None) => {
+                        self.inner = Some(connection);
+                        return Ok(None);
+                    }
+                    Err(err) => return Err((err, AcceptedAlert::from(connection))),
                 }
-                Err(err) => return Err((err, AcceptedAlert::from(connection))),
             };
 
             let mut cx = Context::from(&mut connection);
@@ -872,10 +1248,27 @@ mod connection {
                 connection,
                 message,
                 sig_schemes,
+                skipped_ccs,
+                received_tls: mem::take(&mut self.received_tls),
             }))
         }
     }
 
+    /// An [`io::Read`] that keeps a copy of everything read through it.
+    struct CopyingReader<'a> {
+        rd: &'a mut dyn io::Read,
+        copy: &'a mut Vec<u8>,
+    }
+
+    impl io::Read for CopyingReader<'_> {
+        fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+            let read = self.rd.read(buf)?;
+            self.copy
+                .extend_from_slice(&buf[..read]);
+            Ok(read)
+        }
+    }
+
     /// Represents a TLS alert resulting from handling the client's `ClientHello` message.
     ///
     /// When [`Acceptor::accept()`] returns an error, it yields an `AcceptedAlert` such that the
@@ -978,6 +1371,264 @@ impl DerefMut for UnbufferedServerConnection {
     }
 }
 
+/// Handle a server-side connection before configuration is available.
+///
+/// This is the unbuffered version of [`Acceptor`]: like the rest of the [`crate::unbuffered`]
+/// API, it works on buffers provided by the caller.  It allows the caller to choose a
+/// [`ServerConfig`] after reading the [`ClientHello`] of an incoming connection, by
+/// inspecting [`UnbufferedAccepted::client_hello()`].
+///
+/// Bytes received from the client are passed to [`UnbufferedAcceptor::accept()`] until it
+/// returns an [`UnbufferedAccepted`].  The same buffer is later passed to the resulting
+/// [`UnbufferedServerConnection`], which continues with anything the client sent after its
+/// `ClientHello`.
+///
+/// Note that `accept()` may rewrite the buffer in place, to join up a `ClientHello`
+/// that the client fragmented over several records.  To pass the connection through to
+/// another server instead, keep a copy of the received bytes before passing them to
+/// `accept()`, and forward that copy.  [`Acceptor`] does this for you: see
+/// [`Accepted::received_tls()`].
+///
+/// [`Acceptor`]: crate::server::Acceptor
+/// [`Accepted::received_tls()`]: crate::server::Accepted::received_tls
+pub struct UnbufferedAcceptor {
+    inner: Option<UnbufferedConnectionCommon<ServerConnectionData>>,
+}
+
+impl Default for UnbufferedAcceptor {
+    /// Return an empty `UnbufferedAcceptor`, ready to receive bytes from a new client connection.
+    fn default() -> Self {
+        Self {
+            inner: Some(UnbufferedConnectionCommon::from(ConnectionCore::new(
+                Box::new(Accepting),
+                ServerConnectionData::default(),
+                CommonState::new(Side::Server),
+            ))),
+        }
+    }
+}
+
+/// Whether `msg` is a `change_cipher_spec` record, which the [`Acceptor`]s skip.
+fn is_middlebox_ccs(msg: &Message<'_>) -> bool {
+    matches!(msg.payload, MessagePayload::ChangeCipherSpec(_))
+}
+
+impl UnbufferedAcceptor {
+    /// Check if a complete `ClientHello` message is in `incoming_tls`.
+    ///
+    /// Returns `Ok(None)` in [`UnbufferedAcceptStatus::accepted`] if the complete `ClientHello`
+    /// has not yet been received.  Receive more data into `incoming_tls` and then call this
+    /// function again.
+    ///
+    /// A `change_cipher_spec` record preceding the `ClientHello` is skipped: a client
+    /// sends this after a stateless HelloRetryRequest.  [`UnbufferedAccepted::into_connection()`]
+    /// then rejects the connection unless the chosen [`ServerConfig`] enables
+    /// [`ServerConfig::hello_retry_cookies`].
+    ///
+    /// Returns `Ok(Some(accepted))` if the connection has been accepted.  Call
+    /// `accepted.into_connection()` to continue.  Do not call this function again.
+    ///
+    /// Returns `Err((err, alert))` if an error occurred.  The application should then send
+    /// the alert to the client, using [`UnbufferedAcceptedAlert::encode()`].  It should not
+    /// call `accept()` again.
+    ///
+    /// In all cases, [`UnbufferedAcceptStatus::discard`] bytes must then be removed from the
+    /// front of `incoming_tls`.
+    pub fn accept(&mut self, incoming_tls: &mut [u8]) -> UnbufferedAcceptStatus {
+        let Some(mut connection) = self.inner.take() else {
+            return UnbufferedAcceptStatus {
+                discard: 0,
+                accepted: Err((
+                    Error::General("Acceptor polled after completion".into()),
+                    UnbufferedAcceptedAlert::empty(),
+                )),
+            };
+        };
+
+        let mut discard = 0;
+        let mut skipped_ccs = false;
+        let message = loop {
+            let (taken, message) = connection.first_handshake_message(&mut incoming_tls[discard..]);
+            discard += taken;
+            match message {
+                Ok(Some(msg)) if is_middlebox_ccs(&msg) => {
+                    skipped_ccs = true;
+                    if let Err(err) = connection
+                        .core
+                        .common_state
+                        .received_tls13_change_cipher_spec()
+                    {
+                        return UnbufferedAcceptStatus {
+                            discard,
+                            accepted: Err((err, UnbufferedAcceptedAlert::from(connection))),
+                        };
+                    }
+                }
+                Ok(Some(msg)) => break msg,
+                Ok(None) => {
+                    self.inner = Some(connection);
+                    return UnbufferedAcceptStatus {
+                        discard,
+                        accepted: Ok(None),
+                    };
+                }
+                Err(err) => {
+                    return UnbufferedAcceptStatus {
+                        discard,
+                        accepted: Err((err, UnbufferedAcceptedAlert::from(connection))),
+                    };
+                }
+            }
+        };
+
+        let mut cx = hs::ServerContext {
+            common: &mut connection.core.common_state,
+            data: &mut connection.core.data,
+            sendable_plaintext: None,
+        };
+        let accepted = match hs::process_client_hello(&message, false, &mut cx) {
+            Ok((_, sig_schemes)) => Ok(Some(UnbufferedAccepted {
+                connection,
+                message,
+                sig_schemes,
+                skipped_ccs,
+            })),
+            Err(err) => Err((err, UnbufferedAcceptedAlert::from(connection))),
+        };
+
+        UnbufferedAcceptStatus { discard, accepted }
+    }
+}
+
+/// The outcome of [`UnbufferedAcceptor::accept()`].
+#[non_exhaustive]
+#[must_use]
+#[derive(Debug)]
+pub struct UnbufferedAcceptStatus {
+    /// Number of bytes to discard
+    ///
+    /// `discard` bytes must be removed from the *front* of the `incoming_tls` buffer that was
+    /// passed to the [`UnbufferedAcceptor::accept`] call that returned this object, before
+    /// that buffer is used again.
+    pub discard: usize,
+
+    /// Whether the client's `ClientHello` has been received
+    pub accepted: Result<Option<UnbufferedAccepted>, (Error, UnbufferedAcceptedAlert)>,
+}
+
+/// Represents a `ClientHello` message received through the [`UnbufferedAcceptor`].
+///
+/// Contains the state required to resume the connection through
+/// [`UnbufferedAccepted::into_connection()`].
+pub struct UnbufferedAccepted {
+    connection: UnbufferedConnectionCommon<ServerConnectionData>,
+    message: Message<'static>,
+    sig_schemes: Vec<SignatureScheme>,
+    skipped_ccs: bool,
+}
+
+impl UnbufferedAccepted {
+    /// Get the [`ClientHello`] for this connection.
+    pub fn client_hello(&self) -> ClientHello<'_> {
+        Accepted::make_client_hello(&self.connection.core.data, &self.message, &self.sig_schemes)
+    }
+
+    /// Convert the [`UnbufferedAccepted`] into an [`UnbufferedServerConnection`].
+    ///
+    /// Takes the state returned from [`UnbufferedAcceptor::accept()`] as well as the
+    /// [`ServerConfig`] that should be used for the session. Returns an error if
+    /// configuration-dependent validation of the received `ClientHello` message fails.
+    pub fn into_connection(
+        mut self,
+        config: Arc<ServerConfig>,
+    ) -> Result<UnbufferedServerConnection, (Error, UnbufferedAcceptedAlert)> {
+        let common = &mut self.connection.core.common_state;
+        if let Err(err) = Accepted::check_skipped_ccs(self.skipped_ccs, common, &config) {
+            return Err((err, UnbufferedAcceptedAlert::from(self.connection)));
+        }
+
+        if let Err(err) = Accepted::configure(&mut self.connection.core.common_state, &config) {
+            // As for `Accepted::into_connection()`, there is no alert for a configuration error.
+            return Err((err, UnbufferedAcceptedAlert::empty()));
+        }
+
+        let state = hs::ExpectClientHello::new(config, ServerExtensionsInput::default());
+        let mut cx = hs::ServerContext {
+            common: &mut self.connection.core.common_state,
+            data: &mut self.connection.core.data,
+            sendable_plaintext: None,
+        };
+
+        match Accepted::with_config(state, self.sig_schemes, &self.message, &mut cx) {
+            Ok(new) => {
+                self.connection.core.state = Ok(new);
+                Ok(UnbufferedServerConnection {
+                    inner: self.connection,
+                })
+            }
+            Err(err) => Err((err, UnbufferedAcceptedAlert::from(self.connection))),
+        }
+    }
+}
+
+impl Debug for UnbufferedAccepted {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        f.debug_struct("UnbufferedAccepted")
+            .finish()
+    }
+}
+
+/// Represents a TLS alert resulting from handling the client's `ClientHello` message.
+///
+/// When [`UnbufferedAcceptor::accept()`] or [`UnbufferedAccepted::into_connection()`] return
+/// an error, they yield an `UnbufferedAcceptedAlert` such that the application can
+/// communicate failure to the client via [`UnbufferedAcceptedAlert::encode()`].
+pub struct UnbufferedAcceptedAlert(Vec<u8>);
+
+impl UnbufferedAcceptedAlert {
+    fn empty() -> Self {
+        Self(Vec::new())
+    }
+
+    /// Encodes the alert into the `outgoing_tls` buffer, to be sent to the client.
+    ///
+    /// Returns the number of bytes that were written into `outgoing_tls`, which is zero
+    /// if there is no alert to send, or an error if the provided buffer is too small.
+    /// In the error case, `outgoing_tls` is not modified.
+    pub fn encode(&mut self, outgoing_tls: &mut [u8]) -> Result<usize, EncodeError> {
+        let required_size = self.0.len();
+        if required_size > outgoing_tls.len() {
+            return Err(InsufficientSizeError { required_size }.into());
+        }
+
+        outgoing_tls[..required_size].copy_from_slice(&self.0);
+        self.0.clear();
+        Ok(required_size)
+    }
+}
+
+impl From<UnbufferedConnectionCommon<ServerConnectionData>> for UnbufferedAcceptedAlert {
+    fn from(mut conn: UnbufferedConnectionCommon<ServerConnectionData>) -> Self {
+        let mut alert = Vec::new();
+        while let Some(chunk) = conn
+            .core
+            .common_state
+            .sendable_tls
+            .pop()
+        {
+            alert.extend_from_slice(&chunk);
+        }
+        Self(alert)
+    }
+}
+
+impl Debug for UnbufferedAcceptedAlert {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        f.debug_struct("UnbufferedAcceptedAlert")
+            .finish()
+    }
+}
+
 impl UnbufferedConnectionCommon<ServerConnectionData> {
     pub(crate) fn pop_early_data(&mut self) -> Option<Vec<u8>> {
         self.core.data.early_data.pop()
@@ -995,15 +1646,38 @@ pub struct Accepted {
     connection: ConnectionCommon<ServerConnectionData>,
     message: Message<'static>,
     sig_schemes: Vec<SignatureScheme>,
+    /// Whether a `change_cipher_spec` record preceding the `ClientHello` was skipped.
+    #[cfg(feature = "std")]
+    skipped_ccs: bool,
+    received_tls: Vec<u8>,
 }
 
 impl Accepted {
     /// Get the [`ClientHello`] for this connection.
     pub fn client_hello(&self) -> ClientHello<'_> {
-        let payload = Self::client_hello_payload(&self.message);
+        Self::make_client_hello(&self.connection.core.data, &self.message, &self.sig_schemes)
+    }
+
+    /// The TLS records received from the client, exactly as read by [`Acceptor::read_tls()`].
+    ///
+    /// These include the `ClientHello`, and anything the client sent after it that had
+    /// been read.  This allows a connection to be passed through to another server
+    /// verbatim, rather than continued with [`Accepted::into_connection()`].
+    ///
+    /// [`Acceptor::read_tls()`]: crate::server::Acceptor::read_tls
+    pub fn received_tls(&self) -> &[u8] {
+        &self.received_tls
+    }
+
+    fn make_client_hello<'a>(
+        data: &'a ServerConnectionData,
+        message: &'a Message<'_>,
+        sig_schemes: &'a [SignatureScheme],
+    ) -> ClientHello<'a> {
+        let payload = Self::client_hello_payload(message);
         let ch = ClientHello {
-            server_name: &self.connection.core.data.sni,
-            signature_schemes: &self.sig_schemes,
+            server_name: &data.sni,
+            signature_schemes: sig_schemes,
             alpn: payload.protocols.as_ref(),
             server_cert_types: payload
                 .server_certificate_types
@@ -1016,12 +1690,41 @@ impl Accepted {
                 .certificate_authority_names
                 .as_deref(),
             named_groups: payload.named_groups.as_deref(),
+            client_version: payload.client_version,
+            extensions: &payload.received,
         };
 
         trace!("Accepted::client_hello(): {ch:#?}");
         ch
     }
 
+    /// Apply the connection-level settings of `config` to `common`.
+    /// A client only sends a `change_cipher_spec` record before its `ClientHello` after
+    /// a stateless HelloRetryRequest, so one is unexpected unless `config` sends those.
+    fn check_skipped_ccs(
+        skipped_ccs: bool,
+        common: &mut CommonState,
+        config: &ServerConfig,
+    ) -> Result<(), Error> {
+        match skipped_ccs && !config.hello_retry_cookies.enabled() {
+            true => Err(common.send_fatal_alert(
+                AlertDescription::UnexpectedMessage,
+                inappropriate_message(
+                    &MessagePayload::ChangeCipherSpec(ChangeCipherSpecPayload {}),
+                    &[ContentType::Handshake],
+                ),
+            )),
+            false => Ok(()),
+        }
+    }
+
+    fn configure(common: &mut CommonState, config: &ServerConfig) -> Result<(), Error> {
+        common.set_max_fragment_size(config.max_fragment_size)?;
+        common.set_record_size_limit(config.record_size_limit)?;
+        common.enable_secret_extraction = config.enable_secret_extraction;
+        Ok(())
+    }
+
     /// Convert the [`Accepted`] into a [`ServerConnection`].
     ///
     /// Takes the state returned from [`Acceptor::accept()`] as well as the [`ServerConfig`] and
@@ -1032,22 +1735,21 @@ impl Accepted {
         mut self,
         config: Arc<ServerConfig>,
     ) -> Result<ServerConnection, (Error, AcceptedAlert)> {
-        if let Err(err) = self
-            .connection
-            .set_max_fragment_size(config.max_fragment_size)
-        {
+        let common = &mut self.connection.core.common_state;
+        if let Err(err) = Self::check_skipped_ccs(self.skipped_ccs, common, &config) {
+            return Err((err, AcceptedAlert::from(self.connection)));
+        }
+
+        if let Err(err) = Self::configure(&mut self.connection.core.common_state, &config) {
             // We have a connection here, but it won't contain an alert since the error
             // is with the fragment size configured in the `ServerConfig`.
             return Err((err, AcceptedAlert::empty()));
         }
 
-        self.connection.enable_secret_extraction = config.enable_secret_extraction;
-
         let state = hs::ExpectClientHello::new(config, ServerExtensionsInput::default());
         let mut cx = hs::ServerContext::from(&mut self.connection);
 
-        let ch = Self::client_hello_payload(&self.message);
-        let new = match state.with_certified_key(self.sig_schemes, ch, &self.message, &mut cx) {
+        let new = match Self::with_config(state, self.sig_schemes, &self.message, &mut cx) {
             Ok(new) => new,
             Err(err) => return Err((err, AcceptedAlert::from(self.connection))),
         };
@@ -1058,9 +1760,78 @@ impl Accepted {
         })
     }
 
+    /// Decrypt the client's Encrypted Client Hello (ECH) offer, as the client-facing
+    /// server in a split-mode ECH deployment.
+    ///
+    /// If the offer is decrypted using one of `ech_keys`, returns an [`EchForwarder`]
+    /// which holds the client's inner hello, and names the backend server the connection
+    /// should be forwarded to.
+    ///
+    /// Otherwise the `Accepted` is returned unchanged in [`EchSplitMode::Terminate`], and the
+    /// handshake should be completed here using [`Accepted::into_connection()`] -- with a
+    /// [`ServerConfig`] that includes `ech_keys`, so that the client is sent retry
+    /// configurations.
+    #[cfg(feature = "std")]
+    pub fn decrypt_ech(
+        mut self,
+        ech_keys: &[EchKeyPair],
+    ) -> Result<EchSplitMode, (Error, AcceptedAlert)> {
+        let mut state = EchState::NotOffered;
+        let inner = match state.process_client_hello(
+            ech_keys,
+            false,
+            &self.message,
+            &mut self.connection.core.common_state,
+        ) {
+            Ok(Some(inner)) => inner,
+            Ok(None) => return Ok(EchSplitMode::Terminate(Box::new(self))),
+            Err(err) => return Err((err, AcceptedAlert::from(self.connection))),
+        };
+
+        let EchState::Accepted(accepted) = state else {
+            unreachable!("inner hello is only returned once ECH is accepted");
+        };
+
+        // Anything else the client sent after its hello must follow it to the backend.
+        let received = self.connection.take_received_tls();
+        let mut forwarder = EchForwarder::new(accepted, &inner);
+        match forwarder.forward(&received) {
+            Ok(()) => Ok(EchSplitMode::Forward(forwarder)),
+            Err(err) => {
+                let err = self
+                    .connection
+                    .core
+                    .common_state
+                    .send_fatal_alert(EchForwarder::alert_for(&err), err);
+                Err((err, AcceptedAlert::from(self.connection)))
+            }
+        }
+    }
+
+    fn with_config(
+        mut state: hs::ExpectClientHello,
+        sig_schemes: Vec<SignatureScheme>,
+        message: &Message<'_>,
+        cx: &mut hs::ServerContext<'_>,
+    ) -> hs::NextStateOrError<'static> {
+        // The `ClientHello` we examined before the config was available is the outer
+        // hello. If we now accept ECH, the handshake continues with the inner one.
+        match state.process_ech(message, cx)? {
+            Some(inner) => {
+                cx.data.sni = None;
+                let (ch, sig_schemes) = hs::process_client_hello(&inner, false, cx)?;
+                state.with_certified_key(sig_schemes, ch, &inner, cx)
+            }
+            None => {
+                let ch = Self::client_hello_payload(message);
+                state.with_certified_key(sig_schemes, ch, message, cx)
+            }
+        }
+    }
+
     fn client_hello_payload<'a>(message: &'a Message<'_>) -> &'a ClientHelloPayload {
         match &message.payload {
-            crate::msgs::message::MessagePayload::Handshake { parsed, .. } => match &parsed.0 {
+            MessagePayload::Handshake { parsed, .. } => match &parsed.0 {
                 crate::msgs::handshake::HandshakePayload::ClientHello(ch) => ch,
                 _ => unreachable!(),
             },
@@ -1075,10 +1846,22 @@ impl Debug for Accepted {
     }
 }
 
+/// The outcome of [`Accepted::decrypt_ech()`].
 #[cfg(feature = "std")]
+#[non_exhaustive]
+#[derive(Debug)]
+pub enum EchSplitMode {
+    /// The client's ECH offer was decrypted, and the connection should be forwarded
+    /// to a backend server.
+    Forward(EchForwarder),
+    /// The client did not offer ECH, or the offer could not be decrypted.
+    ///
+    /// The handshake should be completed by the client-facing server.
+    Terminate(Box<Accepted>),
+}
+
 struct Accepting;
 
-#[cfg(feature = "std")]
 impl State<ServerConnectionData> for Accepting {
     fn handle<'m>(
         self: Box<Self>,
@@ -1192,6 +1975,7 @@ impl ConnectionCore<ServerConnectionData> {
     ) -> Result<Self, Error> {
         let mut common = CommonState::new(Side::Server);
         common.set_max_fragment_size(config.max_fragment_size)?;
+        common.set_record_size_limit(config.record_size_limit)?;
         common.enable_secret_extraction = config.enable_secret_extraction;
         common.fips = config.fips();
         Ok(Self::new(
@@ -1201,6 +1985,17 @@ impl ConnectionCore<ServerConnectionData> {
         ))
     }
 
+    #[cfg(feature = "std")]
+    pub(crate) fn request_client_certificate(
+        &mut self,
+        verifier: Arc<dyn verify::ClientCertVerifier>,
+    ) -> Result<(), Error> {
+        match &mut self.state {
+            Ok(st) => st.request_client_certificate(&mut self.common_state, verifier),
+            Err(e) => Err(e.clone()),
+        }
+    }
+
     #[cfg(feature = "std")]
     pub(crate) fn reject_early_data(&mut self) {
         assert!(
@@ -1216,8 +2011,11 @@ impl ConnectionCore<ServerConnectionData> {
 pub struct ServerConnectionData {
     pub(crate) sni: Option<DnsName<'static>>,
     pub(super) received_resumption_data: Option<Vec<u8>>,
+    pub(super) external_psk_identity: Option<Vec<u8>>,
+    pub(super) post_handshake_auth_offered: bool,
     pub(super) resumption_data: Vec<u8>,
     pub(super) early_data: EarlyDataState,
+    pub(super) ech_status: EchStatus,
 }
 
 impl crate::conn::SideData for ServerConnectionData {}
@@ -0,0 +1,500 @@
+use alloc::collections::VecDeque;
+use alloc::vec::Vec;
+use core::fmt::{Debug, Formatter};
+use core::hash::BuildHasher;
+use core::sync::atomic::{AtomicUsize, Ordering};
+use core::time::Duration;
+use std::hash::RandomState;
+use std::sync::Mutex;
+
+use pki_types::UnixTime;
+
+use crate::hash_map::HashMap;
+use crate::server::StoresServerSessions;
+use crate::server::server_conn::MAX_STATEFUL_TICKET_LIFETIME;
+use crate::sync::Arc;
+use crate::time_provider::TimeProvider;
+
+/// Storage for server sessions, with per-entry expiry.
+///
+/// This has operations similar to Redis's `SET` (with `EX`), `GET` and
+/// `GETDEL`, so that a shared store can be placed behind a [`ServerSessionCache`].
+/// [`ShardedMemoryBackend`] is an in-memory implementation.
+///
+/// Each operation is given the current time, for backends that track expiry
+/// themselves.
+pub trait ServerSessionBackend: Debug + Send + Sync {
+    /// Store `value` against `key` for `lifetime` from `now`, overwriting any
+    /// existing value.  Returns `true` if the value was stored.
+    fn set(&self, key: Vec<u8>, value: Vec<u8>, lifetime: Duration, now: UnixTime) -> bool;
+
+    /// Return the value stored against `key`, or `None` if there is none or it
+    /// has expired as of `now`.
+    fn get(&self, key: &[u8], now: UnixTime) -> Option<Vec<u8>>;
+
+    /// As [`ServerSessionBackend::get()`], but also delete any value stored
+    /// against `key`.
+    fn get_del(&self, key: &[u8], now: UnixTime) -> Option<Vec<u8>>;
+
+    /// The number of unexpired values deleted so far to make space for others.
+    ///
+    /// The default implementation returns zero, for backends that do not track this.
+    fn evictions(&self) -> usize {
+        0
+    }
+}
+
+/// An implementer of `StoresServerSessions` that expires sessions after a
+/// fixed lifetime, and keeps statistics about its use.
+///
+/// Sessions are stored in a [`ServerSessionBackend`].
+pub struct ServerSessionCache {
+    backend: Arc<dyn ServerSessionBackend>,
+    lifetime: Duration,
+    time_provider: Arc<dyn TimeProvider>,
+    hits: AtomicUsize,
+    misses: AtomicUsize,
+}
+
+impl ServerSessionCache {
+    /// Make a new `ServerSessionCache` that stores sessions in memory.
+    ///
+    /// `size` is the maximum number of stored sessions, and may be rounded-up
+    /// for efficiency.  Sessions expire once `lifetime` has passed after they
+    /// are stored, according to `time_provider`: this should normally be the
+    /// [`ServerConfig::time_provider`] of the configs using this cache.
+    ///
+    /// `lifetime` is also advertised to clients as the lifetime of TLS1.3
+    /// stateful tickets, and is capped at 24 hours.
+    ///
+    /// [`ServerConfig::time_provider`]: crate::server::ServerConfig::time_provider
+    pub fn new(size: usize, lifetime: Duration, time_provider: Arc<dyn TimeProvider>) -> Arc<Self> {
+        Self::with_backend(
+            Arc::new(ShardedMemoryBackend::new(size)),
+            lifetime,
+            time_provider,
+        )
+    }
+
+    /// Make a new `ServerSessionCache` that stores sessions in `backend`.
+    ///
+    /// Sessions expire once `lifetime` has passed after they are stored,
+    /// according to `time_provider`.  `lifetime` is capped as for
+    /// [`ServerSessionCache::new()`].
+    pub fn with_backend(
+        backend: Arc<dyn ServerSessionBackend>,
+        lifetime: Duration,
+        time_provider: Arc<dyn TimeProvider>,
+    ) -> Arc<Self> {
+        Arc::new(Self {
+            backend,
+            lifetime: lifetime.min(Duration::from_secs(MAX_STATEFUL_TICKET_LIFETIME.into())),
+            time_provider,
+            hits: AtomicUsize::new(0),
+            misses: AtomicUsize::new(0),
+        })
+    }
+
+    /// Return statistics about this cache's use so far.
+    pub fn stats(&self) -> ServerSessionCacheStats {
+        ServerSessionCacheStats {
+            hits: self.hits.load(Ordering::Relaxed),
+            misses: self.misses.load(Ordering::Relaxed),
+            evictions: self.backend.evictions(),
+        }
+    }
+
+    fn count(&self, value: Option<Vec<u8>>) -> Option<Vec<u8>> {
+        match value {
+            Some(_) => &self.hits,
+            None => &self.misses,
+        }
+        .fetch_add(1, Ordering::Relaxed);
+        value
+    }
+}
+
+impl StoresServerSessions for ServerSessionCache {
+    fn put(&self, key: Vec<u8>, value: Vec<u8>) -> bool {
+        let Some(now) = self.time_provider.current_time() else {
+            return false;
+        };
+        self.backend
+            .set(key, value, self.lifetime, now)
+    }
+
+    fn get(&self, key: &[u8]) -> Option<Vec<u8>> {
+        let now = self.time_provider.current_time()?;
+        self.count(self.backend.get(key, now))
+    }
+
+    fn take(&self, key: &[u8]) -> Option<Vec<u8>> {
+        let now = self.time_provider.current_time()?;
+        self.count(self.backend.get_del(key, now))
+    }
+
+    fn can_cache(&self) -> bool {
+        true
+    }
+
+    fn lifetime(&self) -> u32 {
+        // `with_backend` caps this well within `u32`
+        self.lifetime.as_secs() as u32
+    }
+}
+
+impl Debug for ServerSessionCache {
+    fn fmt(&self, f: &mut Formatter<'_>) -> core::fmt::Result {
+        f.debug_struct("ServerSessionCache")
+            .field("backend", &self.backend)
+            .field("lifetime", &self.lifetime)
+            .finish_non_exhaustive()
+    }
+}
+
+/// Statistics about the use of a [`ServerSessionCache`].
+#[non_exhaustive]
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct ServerSessionCacheStats {
+    /// The number of lookups that found a session.
+    pub hits: usize,
+
+    /// The number of lookups that found no session, including those whose
+    /// session had expired.
+    pub misses: usize,
+
+    /// The number of unexpired sessions deleted to make space for others.
+    pub evictions: usize,
+}
+
+/// A [`ServerSessionBackend`] that stores everything in memory.
+///
+/// Sessions are spread over several independently locked shards, to reduce
+/// contention between threads.  Each shard enforces a limit on the number of
+/// stored sessions, evicting the oldest when full, and deletes expired
+/// sessions as they are found.
+pub struct ShardedMemoryBackend {
+    shards: Vec<Mutex<Shard>>,
+    hasher: RandomState,
+    evictions: AtomicUsize,
+}
+
+impl ShardedMemoryBackend {
+    /// Make a new `ShardedMemoryBackend`.  `size` is the maximum number of
+    /// stored sessions, and may be rounded-up for efficiency.
+    pub fn new(size: usize) -> Self {
+        // Small shards would evict early when keys are unevenly spread
+        // between them, so only use as many as keeps them reasonably sized.
+        let shards = (size / MIN_SHARD_SIZE).clamp(1, MAX_SHARDS);
+        let shard_size = size.div_ceil(shards).max(1);
+        Self {
+            shards: (0..shards)
+                .map(|_| Mutex::new(Shard::new(shard_size)))
+                .collect(),
+            hasher: RandomState::new(),
+            evictions: AtomicUsize::new(0),
+        }
+    }
+
+    fn shard(&self, key: &[u8]) -> &Mutex<Shard> {
+        let index = self.hasher.hash_one(key) as usize % self.shards.len();
+        &self.shards[index]
+    }
+}
+
+impl ServerSessionBackend for ShardedMemoryBackend {
+    fn set(&self, key: Vec<u8>, value: Vec<u8>, lifetime: Duration, now: UnixTime) -> bool {
+        let Ok(mut shard) = self.shard(&key).lock() else {
+            return false;
+        };
+
+        let now = now.as_secs();
+        let expires_at = now.saturating_add(lifetime.as_secs());
+        let evicted = shard.insert(key, value, expires_at, now);
+        if evicted > 0 {
+            self.evictions
+                .fetch_add(evicted, Ordering::Relaxed);
+        }
+        true
+    }
+
+    fn get(&self, key: &[u8], now: UnixTime) -> Option<Vec<u8>> {
+        self.shard(key)
+            .lock()
+            .ok()?
+            .get(key, now.as_secs())
+    }
+
+    fn get_del(&self, key: &[u8], now: UnixTime) -> Option<Vec<u8>> {
+        self.shard(key)
+            .lock()
+            .ok()?
+            .remove(key, now.as_secs())
+    }
+
+    fn evictions(&self) -> usize {
+        self.evictions.load(Ordering::Relaxed)
+    }
+}
+
+impl Debug for ShardedMemoryBackend {
+    fn fmt(&self, f: &mut Formatter<'_>) -> core::fmt::Result {
+        f.debug_struct("ShardedMemoryBackend")
+            .field("shards", &self.shards.len())
+            .finish_non_exhaustive()
+    }
+}
+
+/// One independently-locked part of a [`ShardedMemoryBackend`].
+struct Shard {
+    entries: HashMap<Vec<u8>, Entry>,
+
+    /// Keys in insertion order, oldest first.
+    ///
+    /// Each is tagged with the `Entry::seq` it was inserted with: items that
+    /// do not match their entry were since overwritten or removed, and are
+    /// skipped.  When sessions have the same lifetime, as they do in a
+    /// [`ServerSessionCache`], this is also the order in which they expire.
+    order: VecDeque<(Vec<u8>, u64)>,
+    next_seq: u64,
+    capacity: usize,
+}
+
+struct Entry {
+    value: Vec<u8>,
+    expires_at: u64,
+    seq: u64,
+}
+
+impl Shard {
+    fn new(capacity: usize) -> Self {
+        Self {
+            entries: HashMap::with_capacity(capacity),
+            order: VecDeque::with_capacity(capacity),
+            next_seq: 0,
+            capacity,
+        }
+    }
+
+    /// Insert `value` against `key`, returning the number of unexpired entries evicted.
+    fn insert(&mut self, key: Vec<u8>, value: Vec<u8>, expires_at: u64, now: u64) -> usize {
+        let seq = self.next_seq;
+        self.next_seq += 1;
+        self.order.push_back((key.clone(), seq));
+        self.entries.insert(
+            key,
+            Entry {
+                value,
+                expires_at,
+                seq,
+            },
+        );
+
+        // Delete expired entries from the front, and then the oldest entries
+        // until we are within capacity.
+        let mut evicted = 0;
+        while let Some((oldest, oldest_seq)) = self.order.front() {
+            let Some(entry) = self
+                .entries
+                .get(oldest)
+                .filter(|entry| entry.seq == *oldest_seq)
+            else {
+                self.order.pop_front();
+                continue;
+            };
+
+            if entry.expires_at > now {
+                if self.entries.len() <= self.capacity {
+                    break;
+                }
+                evicted += 1;
+            }
+            self.entries.remove(oldest);
+            self.order.pop_front();
+        }
+
+        // Overwrites and removals leave stale items in `order`; drop them
+        // if they come to dominate.
+        if self.order.len() > 2 * self.capacity {
+            let entries = &self.entries;
+            self.order.retain(|(key, seq)| {
+                entries
+                    .get(key)
+                    .is_some_and(|entry| entry.seq == *seq)
+            });
+        }
+
+        evicted
+    }
+
+    fn get(&mut self, key: &[u8], now: u64) -> Option<Vec<u8>> {
+        match self.entries.get(key) {
+            Some(entry) if entry.expires_at > now => Some(entry.value.clone()),
+            Some(_) => {
+                self.entries.remove(key);
+                None
+            }
+            None => None,
+        }
+    }
+
+    fn remove(&mut self, key: &[u8], now: u64) -> Option<Vec<u8>> {
+        self.entries
+            .remove(key)
+            .filter(|entry| entry.expires_at > now)
+            .map(|entry| entry.value)
+    }
+}
+
+const MAX_SHARDS: usize = 16;
+const MIN_SHARD_SIZE: usize = 32;
+
+#[cfg(test)]
+mod tests {
+    use std::vec;
+
+    use super::*;
+    use crate::time_provider::DefaultTimeProvider;
+
+    #[test]
+    fn test_serversessioncache_counts_hits_and_misses() {
+        let (c, _) = make_cache(4);
+        assert!(c.put(vec![0x01], vec![0x02]));
+        assert_eq!(c.get(&[0x01]), Some(vec![0x02]));
+        assert_eq!(c.get(&[0x03]), None);
+        assert_eq!(c.take(&[0x01]), Some(vec![0x02]));
+        assert_eq!(c.take(&[0x01]), None);
+        assert_eq!(
+            c.stats(),
+            ServerSessionCacheStats {
+                hits: 2,
+                misses: 2,
+                evictions: 0
+            }
+        );
+    }
+
+    #[test]
+    fn test_serversessioncache_expires_entries() {
+        let (c, time) = make_cache(4);
+        assert!(c.put(vec![0x01], vec![0x02]));
+        assert!(c.put(vec![0x03], vec![0x04]));
+
+        time.advance(LIFETIME - 1);
+        assert_eq!(c.get(&[0x01]), Some(vec![0x02]));
+
+        time.advance(1);
+        assert_eq!(c.get(&[0x01]), None);
+        assert_eq!(c.take(&[0x03]), None);
+        assert_eq!(c.stats().misses, 2);
+    }
+
+    #[test]
+    fn test_serversessioncache_evicts_oldest_to_maintain_size() {
+        let (c, _) = make_cache(1);
+        assert!(c.put(vec![0x01], vec![0x02]));
+        assert!(c.put(vec![0x03], vec![0x04]));
+        assert_eq!(c.get(&[0x01]), None);
+        assert_eq!(c.get(&[0x03]), Some(vec![0x04]));
+        assert_eq!(c.stats().evictions, 1);
+
+        // overwriting does not evict
+        assert!(c.put(vec![0x03], vec![0x05]));
+        assert_eq!(c.get(&[0x03]), Some(vec![0x05]));
+        assert_eq!(c.stats().evictions, 1);
+    }
+
+    #[test]
+    fn test_serversessioncache_does_not_count_expired_entries_as_evictions() {
+        let (c, time) = make_cache(1);
+        assert!(c.put(vec![0x01], vec![0x02]));
+        time.advance(LIFETIME);
+        assert!(c.put(vec![0x03], vec![0x04]));
+        assert_eq!(c.stats().evictions, 0);
+    }
+
+    #[test]
+    fn test_shardedmemorybackend_bounds_stale_order_entries() {
+        let b = ShardedMemoryBackend::new(1);
+        let now = UnixTime::since_unix_epoch(Duration::from_secs(1000));
+        let lifetime = Duration::from_secs(LIFETIME);
+        for i in 0..100u8 {
+            assert!(b.set(vec![i], vec![i], lifetime, now));
+            assert_eq!(b.get_del(&[i], now), Some(vec![i]));
+        }
+
+        let shard = b.shards[0].lock().unwrap();
+        assert!(shard.entries.is_empty());
+        assert!(shard.order.len() <= 2);
+    }
+
+    #[test]
+    fn test_serversessioncache_is_usable_from_many_threads() {
+        let c = ServerSessionCache::with_backend(
+            Arc::new(ShardedMemoryBackend::new(64)),
+            Duration::from_secs(LIFETIME),
+            Arc::new(DefaultTimeProvider),
+        );
+        let threads = (0..8u8)
+            .map(|t| {
+                let c = c.clone();
+                std::thread::spawn(move || {
+                    for i in 0..100u8 {
+                        assert!(c.put(vec![t, i], vec![i]));
+                        c.take(&[t, i]);
+                    }
+                })
+            })
+            .collect::<Vec<_>>();
+        for t in threads {
+            t.join().unwrap();
+        }
+        assert_eq!(c.stats().hits + c.stats().misses, 800);
+    }
+
+    #[test]
+    fn test_serversessioncache_lifetime_is_capped_at_advertised_lifetime() {
+        let (c, _) = make_cache(1);
+        assert_eq!(c.lifetime(), LIFETIME as u32);
+
+        let c = ServerSessionCache::with_backend(
+            Arc::new(ShardedMemoryBackend::new(1)),
+            Duration::from_secs(7 * 24 * 60 * 60),
+            Arc::new(DefaultTimeProvider),
+        );
+        assert_eq!(c.lifetime(), MAX_STATEFUL_TICKET_LIFETIME);
+        assert_eq!(
+            c.lifetime,
+            Duration::from_secs(MAX_STATEFUL_TICKET_LIFETIME.into())
+        );
+    }
+
+    fn make_cache(size: usize) -> (Arc<ServerSessionCache>, Arc<FakeTime>) {
+        let time = Arc::new(FakeTime(Mutex::new(1000)));
+        let cache = ServerSessionCache::with_backend(
+            Arc::new(ShardedMemoryBackend::new(size)),
+            Duration::from_secs(LIFETIME),
+            time.clone(),
+        );
+        (cache, time)
+    }
+
+    #[derive(Debug)]
+    struct FakeTime(Mutex<u64>);
+
+    impl FakeTime {
+        fn advance(&self, secs: u64) {
+            *self.0.lock().unwrap() += secs;
+        }
+    }
+
+    impl TimeProvider for FakeTime {
+        fn current_time(&self) -> Option<UnixTime> {
+            Some(UnixTime::since_unix_epoch(Duration::from_secs(
+                *self.0.lock().unwrap(),
+            )))
+        }
+    }
+
+    const LIFETIME: u64 = 60;
+}
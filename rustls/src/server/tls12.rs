@@ -12,7 +12,10 @@ use super::common::ActiveCertifiedKey;
 use super::hs::{self, ServerContext};
 use super::server_conn::{ProducesTickets, ServerConfig, ServerConnectionData};
 use crate::check::inappropriate_message;
-use crate::common_state::{CommonState, HandshakeFlightTls12, HandshakeKind, Side, State};
+use crate::common_state::{
+    CommonState, ExpectVerification, HandshakeFlightTls12, HandshakeKind, ResumeWithVerification,
+    Side, State,
+};
 use crate::conn::ConnectionRandoms;
 use crate::conn::kernel::{Direction, KernelContext, KernelState};
 use crate::crypto::ActiveKeyExchange;
@@ -32,6 +35,7 @@ use crate::msgs::persist;
 use crate::suites::PartiallyExtractedSecrets;
 use crate::sync::Arc;
 use crate::tls12::{self, ConnectionSecrets, Tls12CipherSuite};
+use crate::verify::{CertificateVerification, ClientCertVerifier};
 use crate::{ConnectionTrafficSecrets, verify};
 
 mod client_hello {
@@ -40,7 +44,7 @@ mod client_hello {
     use pki_types::CertificateDer;
 
     use super::*;
-    use crate::common_state::KxState;
+    use crate::common_state::{ExpectSignature, KxState, ResumeWithSignature};
     use crate::crypto::SupportedKxGroup;
     use crate::enums::SignatureScheme;
     use crate::msgs::enums::{ClientCertificateType, Compression};
@@ -49,8 +53,8 @@ mod client_hello {
         Random, ServerExtensionsInput, ServerHelloPayload, ServerKeyExchange,
         ServerKeyExchangeParams, ServerKeyExchangePayload,
     };
+    use crate::sct::SctList;
     use crate::sealed::Sealed;
-    use crate::sign;
     use crate::verify::DigitallySignedStruct;
 
     pub(crate) static TLS12_HANDLER: &dyn Tls12Handler = &Handler;
@@ -178,6 +182,10 @@ mod client_hello {
             }
 
             let mut ocsp_response = server_key.get_ocsp();
+            let sct_list = match client_hello.signed_certificate_timestamp_request {
+                Some(()) => server_key.get_sct_list(),
+                None => None,
+            };
 
             // If we're not offered a ticket or a potential session ID, allocate a session ID.
             if !cch.config.session_storage.can_cache() {
@@ -199,6 +207,7 @@ mod client_hello {
                 cch.suite,
                 cch.using_ems,
                 &mut ocsp_response,
+                sct_list,
                 client_hello,
                 None,
                 &cch.randoms,
@@ -208,40 +217,77 @@ mod client_hello {
             if let Some(ocsp_response) = ocsp_response {
                 emit_cert_status(&mut flight, ocsp_response);
             }
-            let server_kx = emit_server_kx(
-                &mut flight,
-                sigschemes,
-                selected_kxg,
-                server_key.get_key(),
-                &cch.randoms,
-            )?;
-            let doing_client_auth = emit_certificate_req(&mut flight, &cch.config)?;
+
+            let server_kx = selected_kxg.start()?;
+            let signer = server_key
+                .get_key()
+                .choose_scheme(&sigschemes)
+                .ok_or_else(|| Error::General("incompatible signing key".to_string()))?;
+            let message = server_kx_message(&*server_kx, &cch.randoms);
+
+            let next = CompleteServerFlight {
+                flight: flight.suspend(),
+                config: cch.config,
+                transcript: cch.transcript,
+                randoms: cch.randoms,
+                session_id: cch.session_id,
+                suite: cch.suite,
+                using_ems: cch.using_ems,
+                server_kx,
+                send_ticket: cch.send_ticket,
+            };
+            ExpectSignature::sign(&*signer, message, next, cx)
+        }
+    }
+
+    /// Our first flight, from the signed ServerKeyExchange onwards.
+    struct CompleteServerFlight {
+        flight: Vec<u8>,
+        config: Arc<ServerConfig>,
+        transcript: HandshakeHash,
+        randoms: ConnectionRandoms,
+        session_id: SessionId,
+        suite: &'static Tls12CipherSuite,
+        using_ems: bool,
+        server_kx: Box<dyn ActiveKeyExchange>,
+        send_ticket: bool,
+    }
+
+    impl ResumeWithSignature<ServerConnectionData> for CompleteServerFlight {
+        fn resume(
+            mut self,
+            cx: &mut ServerContext<'_>,
+            signature: DigitallySignedStruct,
+        ) -> hs::NextStateOrError<'static> {
+            let mut flight = HandshakeFlightTls12::resume(&mut self.transcript, self.flight);
+            emit_server_kx(&mut flight, &*self.server_kx, signature);
+            let doing_client_auth = emit_certificate_req(&mut flight, &self.config)?;
             emit_server_hello_done(&mut flight);
 
             flight.finish(cx.common);
 
             if doing_client_auth {
                 Ok(Box::new(ExpectCertificate {
-                    config: cch.config,
-                    transcript: cch.transcript,
-                    randoms: cch.randoms,
-                    session_id: cch.session_id,
-                    suite: cch.suite,
-                    using_ems: cch.using_ems,
-                    server_kx,
-                    send_ticket: cch.send_ticket,
+                    config: self.config,
+                    transcript: self.transcript,
+                    randoms: self.randoms,
+                    session_id: self.session_id,
+                    suite: self.suite,
+                    using_ems: self.using_ems,
+                    server_kx: self.server_kx,
+                    send_ticket: self.send_ticket,
                 }))
             } else {
                 Ok(Box::new(ExpectClientKx {
-                    config: cch.config,
-                    transcript: cch.transcript,
-                    randoms: cch.randoms,
-                    session_id: cch.session_id,
-                    suite: cch.suite,
-                    using_ems: cch.using_ems,
-                    server_kx,
+                    config: self.config,
+                    transcript: self.transcript,
+                    randoms: self.randoms,
+                    session_id: self.session_id,
+                    suite: self.suite,
+                    using_ems: self.using_ems,
+                    server_kx: self.server_kx,
                     client_cert: None,
-                    send_ticket: cch.send_ticket,
+                    send_ticket: self.send_ticket,
                 }))
             }
         }
@@ -301,6 +347,7 @@ mod client_hello {
                 self.suite,
                 self.using_ems,
                 &mut None,
+                None,
                 client_hello,
                 Some(&resumedata),
                 &self.randoms,
@@ -358,6 +405,7 @@ mod client_hello {
         suite: &'static Tls12CipherSuite,
         using_ems: bool,
         ocsp_response: &mut Option<&[u8]>,
+        sct_list: Option<SctList>,
         hello: &ClientHelloPayload,
         resumedata: Option<&persist::Tls12ServerSessionValue>,
         randoms: &ConnectionRandoms,
@@ -372,6 +420,8 @@ mod client_hello {
             resumedata.map(|r| &r.common),
         )?;
         ep.process_tls12(config, hello, using_ems);
+        ep.extensions
+            .signed_certificate_timestamps = sct_list;
 
         let sh = HandshakeMessagePayload(HandshakePayload::ServerHello(ServerHelloPayload {
             legacy_version: ProtocolVersion::TLSv1_2,
@@ -402,36 +452,28 @@ mod client_hello {
         ));
     }
 
-    fn emit_server_kx(
-        flight: &mut HandshakeFlightTls12<'_>,
-        sigschemes: Vec<SignatureScheme>,
-        selected_group: &'static dyn SupportedKxGroup,
-        signing_key: &dyn sign::SigningKey,
-        randoms: &ConnectionRandoms,
-    ) -> Result<Box<dyn ActiveKeyExchange>, Error> {
-        let kx = selected_group.start()?;
-        let kx_params = ServerKeyExchangeParams::new(&*kx);
-
+    /// The message signed in our ServerKeyExchange.
+    fn server_kx_message(kx: &dyn ActiveKeyExchange, randoms: &ConnectionRandoms) -> Vec<u8> {
         let mut msg = Vec::new();
         msg.extend(randoms.client);
         msg.extend(randoms.server);
-        kx_params.encode(&mut msg);
-
-        let signer = signing_key
-            .choose_scheme(&sigschemes)
-            .ok_or_else(|| Error::General("incompatible signing key".to_string()))?;
-        let sigscheme = signer.scheme();
-        let sig = signer.sign(&msg)?;
+        ServerKeyExchangeParams::new(kx).encode(&mut msg);
+        msg
+    }
 
+    fn emit_server_kx(
+        flight: &mut HandshakeFlightTls12<'_>,
+        kx: &dyn ActiveKeyExchange,
+        dss: DigitallySignedStruct,
+    ) {
         let skx = ServerKeyExchangePayload::from(ServerKeyExchange {
-            params: kx_params,
-            dss: DigitallySignedStruct::new(sigscheme, sig),
+            params: ServerKeyExchangeParams::new(kx),
+            dss,
         });
 
         flight.add(HandshakeMessagePayload(
             HandshakePayload::ServerKeyExchange(skx),
         ));
-        Ok(kx)
     }
 
     fn emit_certificate_req(
@@ -523,6 +565,18 @@ impl State<ServerConnectionData> for ExpectCertificate {
             Some((end_entity, intermediates)) => {
                 let now = self.config.current_time()?;
 
+                if self.config.verifier.is_async() {
+                    let pending = CertificateVerification::new(
+                        end_entity,
+                        intermediates,
+                        None,
+                        Vec::new(),
+                        now,
+                    );
+                    let next = self.into_client_kx(Some(cert_chain.into_owned()));
+                    return Ok(ExpectVerification::new(pending, next, cx.common));
+                }
+
                 self.config
                     .verifier
                     .verify_client_cert(end_entity, intermediates, now)
@@ -535,7 +589,17 @@ impl State<ServerConnectionData> for ExpectCertificate {
             }
         };
 
-        Ok(Box::new(ExpectClientKx {
+        Ok(Box::new(self.into_client_kx(client_cert)))
+    }
+
+    fn into_owned(self: Box<Self>) -> hs::NextState<'static> {
+        self
+    }
+}
+
+impl ExpectCertificate {
+    fn into_client_kx(self, client_cert: Option<CertificateChain<'_>>) -> ExpectClientKx<'_> {
+        ExpectClientKx {
             config: self.config,
             transcript: self.transcript,
             randoms: self.randoms,
@@ -545,11 +609,7 @@ impl State<ServerConnectionData> for ExpectCertificate {
             server_kx: self.server_kx,
             client_cert,
             send_ticket: self.send_ticket,
-        }))
-    }
-
-    fn into_owned(self: Box<Self>) -> hs::NextState<'static> {
-        self
+        }
     }
 }
 
@@ -652,6 +712,12 @@ impl State<ServerConnectionData> for ExpectClientKx<'_> {
     }
 }
 
+impl ResumeWithVerification<ServerConnectionData> for ExpectClientKx<'static> {
+    fn resume(self, _cx: &mut ServerContext<'_>) -> hs::NextStateOrError<'static> {
+        Ok(Box::new(self))
+    }
+}
+
 // --- Process client's certificate proof ---
 struct ExpectCertificateVerify<'a> {
     config: Arc<ServerConfig>,
@@ -1006,6 +1072,14 @@ impl State<ServerConnectionData> for ExpectTraffic {
             .extract_secrets(Side::Server)
     }
 
+    fn request_client_certificate(
+        &mut self,
+        _common: &mut CommonState,
+        _verifier: Arc<dyn ClientCertVerifier>,
+    ) -> Result<(), Error> {
+        Err(PeerIncompatible::PostHandshakeAuthNotOffered.into())
+    }
+
     fn into_external_state(self: Box<Self>) -> Result<Box<dyn KernelState + 'static>, Error> {
         Ok(self)
     }
@@ -2,16 +2,19 @@ use alloc::boxed::Box;
 use alloc::vec;
 use alloc::vec::Vec;
 
-pub(super) use client_hello::CompleteClientHelloHandling;
+pub(super) use client_hello::{
+    CompleteClientHelloHandling, OfferedExternalPsk, offered_external_psk,
+};
 pub(crate) use client_hello::{TLS13_HANDLER, Tls13Handler};
 use pki_types::{CertificateDer, UnixTime};
 use subtle::ConstantTimeEq;
 
 use super::hs::{self, HandshakeHashOrBuffer, ServerContext};
-use super::server_conn::ServerConnectionData;
+use super::server_conn::{MAX_STATEFUL_TICKET_LIFETIME, ServerConnectionData};
 use crate::check::{inappropriate_handshake_message, inappropriate_message};
 use crate::common_state::{
-    CommonState, HandshakeFlightTls13, HandshakeKind, Protocol, Side, State,
+    CommonState, ExpectVerification, HandshakeFlightTls13, HandshakeKind, Protocol,
+    ResumeWithVerification, Side, State,
 };
 use crate::conn::ConnectionRandoms;
 use crate::conn::kernel::{Direction, KernelContext, KernelState};
@@ -19,10 +22,12 @@ use crate::enums::{AlertDescription, ContentType, HandshakeType, ProtocolVersion
 use crate::error::{Error, InvalidMessage, PeerIncompatible, PeerMisbehaved};
 use crate::hash_hs::HandshakeHash;
 use crate::log::{debug, trace, warn};
+use crate::msgs::base::PayloadU8;
 use crate::msgs::codec::{Codec, Reader};
 use crate::msgs::enums::KeyUpdateRequest;
 use crate::msgs::handshake::{
-    CERTIFICATE_MAX_SIZE_LIMIT, CertificateChain, CertificatePayloadTls13, HandshakeMessagePayload,
+    CERTIFICATE_MAX_SIZE_LIMIT, CertificateChain, CertificatePayloadTls13,
+    CertificateRequestExtensions, CertificateRequestPayloadTls13, HandshakeMessagePayload,
     HandshakePayload, NewSessionTicketPayloadTls13,
 };
 use crate::msgs::message::{Message, MessagePayload};
@@ -36,30 +41,41 @@ use crate::tls13::key_schedule::{
 use crate::tls13::{
     Tls13CipherSuite, construct_client_verify_message, construct_server_verify_message,
 };
+use crate::verify::{CertificateVerification, ClientCertVerifier};
 use crate::{ConnectionTrafficSecrets, compress, rand, verify};
 
 mod client_hello {
+    use alloc::borrow::ToOwned;
     use core::fmt;
 
+    use pki_types::DnsName;
+
     use super::*;
+    use crate::common_state::{ExpectSignature, KxState, ResumeWithSignature};
     use crate::compress::CertCompressor;
     use crate::crypto::SupportedKxGroup;
+    use crate::crypto::hash;
+    use crate::delegated_credential::DelegatedCredential;
     use crate::enums::SignatureScheme;
-    use crate::msgs::base::{Payload, PayloadU8};
+    use crate::msgs::base::{NonEmpty, Payload, PayloadU16};
     use crate::msgs::ccs::ChangeCipherSpecPayload;
     use crate::msgs::enums::{Compression, NamedGroup};
     use crate::msgs::handshake::{
-        CertificatePayloadTls13, CertificateRequestExtensions, CertificateRequestPayloadTls13,
         ClientHelloPayload, HelloRetryRequest, HelloRetryRequestExtensions, KeyShareEntry, Random,
-        ServerExtensions, ServerExtensionsInput, ServerHelloPayload, SessionId,
+        ServerEncryptedClientHello, ServerExtensions, ServerExtensionsInput, ServerHelloPayload,
+        SessionId,
     };
+    use crate::sct::SctList;
     use crate::sealed::Sealed;
     use crate::server::common::ActiveCertifiedKey;
+    use crate::server::ech::{EchState, EchStatus};
     use crate::sign;
     use crate::tls13::key_schedule::{
         KeyScheduleEarly, KeyScheduleHandshake, KeySchedulePreHandshake,
+        server_ech_confirmation_secret, server_ech_hrr_confirmation_secret,
     };
     use crate::verify::DigitallySignedStruct;
+    use crate::{ExternalPsk, PskKexMode, SupportedCipherSuite};
 
     pub(crate) static TLS13_HANDLER: &'static dyn Tls13Handler = &Handler;
 
@@ -71,10 +87,10 @@ mod client_hello {
             &self,
             mut cch: CompleteClientHelloHandling,
             cx: &mut ServerContext<'_>,
-            server_key: ActiveCertifiedKey<'_>,
+            server_key: Option<ActiveCertifiedKey<'_>>,
             chm: &Message<'_>,
             client_hello: &ClientHelloPayload,
-            selected_kxg: &'static dyn SupportedKxGroup,
+            selected_kxg: Option<&'static dyn SupportedKxGroup>,
             mut sigschemes_ext: Vec<SignatureScheme>,
         ) -> hs::NextStateOrError<'static> {
             if client_hello.compression_methods.len() != 1 {
@@ -86,15 +102,22 @@ mod client_hello {
 
             sigschemes_ext.retain(SignatureScheme::supported_in_tls13);
 
-            let shares_ext = client_hello
-                .key_shares
+            // A PSK_KE handshake using a known external PSK can do without a key share.
+            let psk_only = cch
+                .external_psk
                 .as_ref()
-                .ok_or_else(|| {
-                    cx.common.send_fatal_alert(
+                .is_some_and(OfferedExternalPsk::is_psk_only);
+
+            let shares_ext = match &client_hello.key_shares {
+                Some(shares) => shares.as_slice(),
+                None if psk_only => &[],
+                None => {
+                    return Err(cx.common.send_fatal_alert(
                         AlertDescription::HandshakeFailure,
                         PeerIncompatible::KeyShareExtensionRequired,
-                    )
-                })?;
+                    ));
+                }
+            };
 
             if client_hello.has_keyshare_extension_with_duplicates() {
                 return Err(cx.common.send_fatal_alert(
@@ -122,6 +145,13 @@ mod client_hello {
                         .find(|compressor| offered.contains(&compressor.algorithm()))
                         .cloned());
 
+            let retry_group = match (&client_hello.cookie, cch.done_retry) {
+                (Some(cookie), false) => {
+                    cch.continue_stateless_retry(&cookie.0, client_hello.session_id, cx)?
+                }
+                _ => None,
+            };
+
             let early_data_requested = client_hello
                 .early_data_request
                 .is_some();
@@ -137,11 +167,28 @@ mod client_hello {
             }
 
             // See if there is a KeyShare for the selected kx group.
-            let chosen_share_and_kxg = shares_ext.iter().find_map(|share| {
-                (share.group == selected_kxg.name()).then_some((share, selected_kxg))
+            let chosen_share_and_kxg = selected_kxg.and_then(|selected_kxg| {
+                shares_ext.iter().find_map(|share| {
+                    (share.group == selected_kxg.name()).then_some((share, selected_kxg))
+                })
             });
 
-            let Some(chosen_share_and_kxg) = chosen_share_and_kxg else {
+            // "the client MUST send the same ClientHello without modification, except as
+            //  follows: If a "key_share" extension was supplied in the HelloRetryRequest,
+            //  replacing the list of shares with a list containing a single KeyShareEntry
+            //  from the indicated group." - RFC8446 4.1.2
+            if let (Some(retry_group), Some((share, _))) = (retry_group, chosen_share_and_kxg) {
+                if share.group != retry_group {
+                    return Err(cx.common.send_fatal_alert(
+                        AlertDescription::IllegalParameter,
+                        PeerMisbehaved::WrongGroupForKeyShare,
+                    ));
+                }
+            }
+
+            if let (None, Some(selected_kxg), false) =
+                (chosen_share_and_kxg, selected_kxg, psk_only)
+            {
                 // We don't have a suitable key share.  Send a HelloRetryRequest
                 // for the mutually_preferred_group.
                 cch.transcript.add_message(chm);
@@ -153,12 +200,27 @@ mod client_hello {
                     ));
                 }
 
+                // The state of an ECH offer cannot be recovered from a cookie.
+                let cookie = match cch.ech_state {
+                    EchState::NotOffered => seal_hello_retry_cookie(
+                        &cch.config,
+                        &cch.transcript,
+                        cch.suite,
+                        selected_kxg.name(),
+                        cx.data.sni.as_ref(),
+                    )?,
+                    _ => None,
+                };
+
                 emit_hello_retry_request(
                     &mut cch.transcript,
+                    &cch.randoms,
                     cch.suite,
                     client_hello.session_id,
                     cx.common,
                     selected_kxg.name(),
+                    cookie,
+                    cch.ech_state.confirms_acceptance(),
                 );
                 emit_fake_ccs(cx.common);
 
@@ -172,6 +234,7 @@ mod client_hello {
                     done_retry: true,
                     send_tickets: cch.send_tickets,
                     extra_exts: cch.extra_exts,
+                    ech_state: cch.ech_state,
                 });
 
                 return if early_data_requested {
@@ -182,10 +245,19 @@ mod client_hello {
                 } else {
                     Ok(next)
                 };
-            };
+            }
 
             let mut chosen_psk_index = None;
             let mut resumedata = None;
+            let mut external_psk = None;
+
+            let psk_modes = client_hello
+                .preshared_key_modes
+                .unwrap_or_default();
+            let external_psk_kex_mode = cch
+                .external_psk
+                .as_ref()
+                .map(|psk| psk.kex_mode);
 
             if let Some(psk_offer) = &client_hello.preshared_key_offer {
                 // "A client MUST provide a "psk_key_exchange_modes" extension if it
@@ -219,23 +291,57 @@ mod client_hello {
                 let now = cch.config.current_time()?;
 
                 for (i, psk_id) in psk_offer.identities.iter().enumerate() {
-                    let maybe_resume_data = cch
-                        .attempt_tls13_ticket_decryption(&psk_id.identity.0)
-                        .map(|resumedata| {
-                            resumedata.set_freshness(psk_id.obfuscated_ticket_age, now)
+                    // We only resume sessions with PSK_DHE_KE.
+                    let maybe_resume_data = match psk_modes.psk_dhe {
+                        true => cch
+                            .attempt_tls13_ticket_decryption(&psk_id.identity.0)
+                            .map(|resumedata| {
+                                resumedata.set_freshness(psk_id.obfuscated_ticket_age, now)
+                            })
+                            .filter(|resumedata| {
+                                hs::can_resume(cch.suite.into(), &cx.data.sni, &resumedata.common)
+                            }),
+                        false => None,
+                    };
+
+                    if let Some(resume) = maybe_resume_data {
+                        if !cch.check_binder(
+                            cch.suite,
+                            chm,
+                            &resume.secret.0,
+                            psk_offer.binders[i].as_ref(),
+                        ) {
+                            return Err(cx.common.send_fatal_alert(
+                                AlertDescription::DecryptError,
+                                PeerMisbehaved::IncorrectBinder,
+                            ));
+                        }
+
+                        chosen_psk_index = Some(i);
+                        resumedata = Some(resume);
+                        break;
+                    }
+
+                    let maybe_external_psk = external_psk_kex_mode
+                        .and_then(|_| {
+                            cch.config
+                                .psk_resolver
+                                .resolve(&psk_id.identity.0)
                         })
-                        .filter(|resumedata| {
-                            hs::can_resume(cch.suite.into(), &cx.data.sni, &resumedata.common)
+                        .filter(|psk| {
+                            cch.suite
+                                .can_resume_from(psk.suite())
+                                .is_some()
                         });
 
-                    let Some(resume) = maybe_resume_data else {
+                    let Some(psk) = maybe_external_psk else {
                         continue;
                     };
 
-                    if !cch.check_binder(
+                    if !cch.check_external_binder(
                         cch.suite,
                         chm,
-                        &resume.secret.0,
+                        &psk,
                         psk_offer.binders[i].as_ref(),
                     ) {
                         return Err(cx.common.send_fatal_alert(
@@ -245,21 +351,14 @@ mod client_hello {
                     }
 
                     chosen_psk_index = Some(i);
-                    resumedata = Some(resume);
+                    external_psk = Some(psk);
                     break;
                 }
             }
 
-            if !client_hello
-                .preshared_key_modes
-                .as_ref()
-                .map(|offer| offer.psk_dhe)
-                .unwrap_or_default()
-            {
+            if !psk_modes.psk_dhe {
                 debug!("Client unwilling to resume, PSK_DHE_KE not offered");
                 cch.send_tickets = 0;
-                chosen_psk_index = None;
-                resumedata = None;
             } else {
                 cch.send_tickets = cch.config.send_tls13_tickets;
             }
@@ -271,34 +370,71 @@ mod client_hello {
                     .clone_from(&resume.common.client_cert_chain);
             }
 
-            let full_handshake = resumedata.is_none();
+            cx.data.post_handshake_auth_offered = client_hello
+                .post_handshake_auth
+                .is_some();
+
+            // A PSK_KE handshake ignores the client's key share.
+            let mut share_and_kxgroup = chosen_share_and_kxg;
+            if let Some(psk) = &external_psk {
+                debug!("Using external PSK with {external_psk_kex_mode:?}");
+                cx.data.external_psk_identity = Some(psk.identity().to_vec());
+                if external_psk_kex_mode == Some(PskKexMode::PskOnly) {
+                    share_and_kxgroup = None;
+                }
+            } else if share_and_kxgroup.is_none() {
+                // We went without a key share expecting a PSK_KE handshake, but the
+                // client's PSK was not usable after all.
+                return Err(cx.common.send_fatal_alert(
+                    AlertDescription::HandshakeFailure,
+                    PeerIncompatible::KeyShareExtensionRequired,
+                ));
+            }
+
+            let full_handshake = resumedata.is_none() && external_psk.is_none();
+            if full_handshake && server_key.is_none() {
+                return Err(cx.common.send_fatal_alert(
+                    AlertDescription::AccessDenied,
+                    Error::General("no server certificate chain resolved".to_owned()),
+                ));
+            }
             cch.transcript.add_message(chm);
             let key_schedule = emit_server_hello(
                 &mut cch.transcript,
-                &cch.randoms,
+                &mut cch.randoms,
                 cch.suite,
                 cx,
                 &client_hello.session_id,
-                chosen_share_and_kxg,
+                share_and_kxgroup,
                 chosen_psk_index,
                 resumedata
                     .as_ref()
-                    .map(|x| &x.secret.0[..]),
+                    .map(|x| &x.secret.0[..])
+                    .or_else(|| {
+                        external_psk
+                            .as_ref()
+                            .map(|psk| psk.secret())
+                    }),
                 &cch.config,
+                cch.ech_state.confirms_acceptance(),
             )?;
             if !cch.done_retry {
                 emit_fake_ccs(cx.common);
             }
 
-            if full_handshake {
+            if resumedata.is_some() {
+                cx.common.handshake_kind = Some(HandshakeKind::Resumed);
+            } else if external_psk.is_some() {
+                cx.common.handshake_kind = Some(HandshakeKind::ExternalPsk);
+            } else {
                 cx.common
                     .handshake_kind
                     .get_or_insert(HandshakeKind::Full);
-            } else {
-                cx.common.handshake_kind = Some(HandshakeKind::Resumed);
             }
 
-            let mut ocsp_response = server_key.get_ocsp();
+            let mut ocsp_response = server_key
+                .as_ref()
+                .and_then(ActiveCertifiedKey::get_ocsp);
             let mut flight = HandshakeFlightTls13::new(&mut cch.transcript);
             let doing_early_data = emit_encrypted_extensions(
                 &mut flight,
@@ -311,60 +447,133 @@ mod client_hello {
                 &cch.config,
             )?;
 
-            let doing_client_auth = if full_handshake {
+            let mut certificate_verify = None;
+            let doing_client_auth = if let (true, Some(server_key)) = (full_handshake, &server_key)
+            {
                 let client_auth = emit_certificate_req_tls13(&mut flight, &cch.config)?;
 
+                let delegated_key = server_key.get_delegated_key(
+                    client_hello
+                        .delegated_credential_schemes
+                        .as_deref(),
+                    &sigschemes_ext,
+                );
+                let delegated_credential = delegated_key.map(|dk| &dk.credential);
+                let sct_list = match client_hello.signed_certificate_timestamp_request {
+                    Some(()) => server_key.get_sct_list(),
+                    None => None,
+                };
+
                 if let Some(compressor) = cert_compressor {
                     emit_compressed_certificate_tls13(
                         &mut flight,
                         &cch.config,
                         server_key.get_cert(),
                         ocsp_response,
+                        sct_list,
+                        delegated_credential,
                         compressor,
                     );
                 } else {
-                    emit_certificate_tls13(&mut flight, server_key.get_cert(), ocsp_response);
+                    emit_certificate_tls13(
+                        &mut flight,
+                        server_key.get_cert(),
+                        ocsp_response,
+                        sct_list,
+                        delegated_credential,
+                    );
                 }
-                emit_certificate_verify_tls13(
-                    &mut flight,
-                    cx.common,
-                    server_key.get_key(),
-                    &sigschemes_ext,
-                )?;
+
+                // "the server [signs] the CertificateVerify message using the delegated
+                // credential's private key and dc_cert_verify_algorithm" - RFC9345 section 4.1
+                let signer = match delegated_key {
+                    Some(dk) => choose_signer(
+                        cx.common,
+                        &*dk.key,
+                        &[dk.credential.dc_cert_verify_algorithm()],
+                    )?,
+                    None => choose_signer(cx.common, server_key.get_key(), &sigschemes_ext)?,
+                };
+                let message = construct_server_verify_message(&flight.transcript.current_hash());
+                certificate_verify = Some((signer, message.as_ref().to_vec()));
                 client_auth
             } else {
                 false
             };
 
+            let next = CompleteServerFlight {
+                flight: flight.suspend(),
+                config: cch.config,
+                transcript: cch.transcript,
+                suite: cch.suite,
+                randoms: cch.randoms,
+                key_schedule,
+                send_tickets: cch.send_tickets,
+                doing_client_auth,
+                doing_early_data,
+            };
+            match certificate_verify {
+                Some((signer, message)) => ExpectSignature::sign(&*signer, message, next, cx),
+                None => next.emit(cx, None),
+            }
+        }
+    }
+
+    /// Our first flight, from the CertificateVerify (if any) onwards.
+    struct CompleteServerFlight {
+        flight: Vec<u8>,
+        config: Arc<ServerConfig>,
+        transcript: HandshakeHash,
+        suite: &'static Tls13CipherSuite,
+        randoms: ConnectionRandoms,
+        key_schedule: KeyScheduleHandshake,
+        send_tickets: usize,
+        doing_client_auth: bool,
+        doing_early_data: EarlyDataDecision,
+    }
+
+    impl CompleteServerFlight {
+        fn emit(
+            mut self,
+            cx: &mut ServerContext<'_>,
+            certificate_verify: Option<DigitallySignedStruct>,
+        ) -> hs::NextStateOrError<'static> {
+            let mut flight = HandshakeFlightTls13::resume(&mut self.transcript, self.flight);
+            if let Some(cv) = certificate_verify {
+                emit_certificate_verify_tls13(&mut flight, cv);
+            }
+
             // If we're not doing early data, then the next messages we receive
             // are encrypted with the handshake keys.
-            match doing_early_data {
+            match self.doing_early_data {
                 EarlyDataDecision::Disabled => {
-                    key_schedule.set_handshake_decrypter(None, cx.common);
+                    self.key_schedule
+                        .set_handshake_decrypter(None, cx.common);
                     cx.data.early_data.reject();
                 }
                 EarlyDataDecision::RequestedButRejected => {
                     debug!(
                         "Client requested early_data, but not accepted: switching to handshake keys with trial decryption"
                     );
-                    key_schedule.set_handshake_decrypter(
-                        Some(max_early_data_size(cch.config.max_early_data_size)),
-                        cx.common,
-                    );
+                    self.key_schedule
+                        .set_handshake_decrypter(
+                            Some(max_early_data_size(self.config.max_early_data_size)),
+                            cx.common,
+                        );
                     cx.data.early_data.reject();
                 }
                 EarlyDataDecision::Accepted => {
                     cx.data
                         .early_data
-                        .accept(cch.config.max_early_data_size as usize);
+                        .accept(self.config.max_early_data_size as usize);
                 }
             }
 
             cx.common.check_aligned_handshake()?;
             let key_schedule_traffic =
-                emit_finished_tls13(flight, &cch.randoms, cx, key_schedule, &cch.config);
+                emit_finished_tls13(flight, &self.randoms, cx, self.key_schedule, &self.config);
 
-            if !doing_client_auth && cch.config.send_half_rtt_data {
+            if !self.doing_client_auth && self.config.send_half_rtt_data {
                 // Application data can be sent immediately after Finished, in one
                 // flight.  However, if client auth is enabled, we don't want to send
                 // application data to an unauthenticated peer.
@@ -372,48 +581,62 @@ mod client_hello {
                     .start_outgoing_traffic(&mut cx.sendable_plaintext);
             }
 
-            if doing_client_auth {
-                if cch.config.cert_decompressors.is_empty() {
+            if self.doing_client_auth {
+                if self
+                    .config
+                    .cert_decompressors
+                    .is_empty()
+                {
                     Ok(Box::new(ExpectCertificate {
-                        config: cch.config,
-                        transcript: cch.transcript,
-                        suite: cch.suite,
+                        config: self.config,
+                        transcript: self.transcript,
+                        suite: self.suite,
                         key_schedule: key_schedule_traffic,
-                        send_tickets: cch.send_tickets,
+                        send_tickets: self.send_tickets,
                         message_already_in_transcript: false,
                     }))
                 } else {
                     Ok(Box::new(ExpectCertificateOrCompressedCertificate {
-                        config: cch.config,
-                        transcript: cch.transcript,
-                        suite: cch.suite,
+                        config: self.config,
+                        transcript: self.transcript,
+                        suite: self.suite,
                         key_schedule: key_schedule_traffic,
-                        send_tickets: cch.send_tickets,
+                        send_tickets: self.send_tickets,
                     }))
                 }
-            } else if doing_early_data == EarlyDataDecision::Accepted && !cx.common.is_quic() {
+            } else if self.doing_early_data == EarlyDataDecision::Accepted && !cx.common.is_quic() {
                 // Not used for QUIC: RFC 9001 §8.3: Clients MUST NOT send the EndOfEarlyData
                 // message. A server MUST treat receipt of a CRYPTO frame in a 0-RTT packet as a
                 // connection error of type PROTOCOL_VIOLATION.
                 Ok(Box::new(ExpectEarlyData {
-                    config: cch.config,
-                    transcript: cch.transcript,
-                    suite: cch.suite,
+                    config: self.config,
+                    transcript: self.transcript,
+                    suite: self.suite,
                     key_schedule: key_schedule_traffic,
-                    send_tickets: cch.send_tickets,
+                    send_tickets: self.send_tickets,
                 }))
             } else {
                 Ok(Box::new(ExpectFinished {
-                    config: cch.config,
-                    transcript: cch.transcript,
-                    suite: cch.suite,
+                    config: self.config,
+                    transcript: self.transcript,
+                    suite: self.suite,
                     key_schedule: key_schedule_traffic,
-                    send_tickets: cch.send_tickets,
+                    send_tickets: self.send_tickets,
                 }))
             }
         }
     }
 
+    impl ResumeWithSignature<ServerConnectionData> for CompleteServerFlight {
+        fn resume(
+            self,
+            cx: &mut ServerContext<'_>,
+            signature: DigitallySignedStruct,
+        ) -> hs::NextStateOrError<'static> {
+            self.emit(cx, Some(signature))
+        }
+    }
+
     impl Sealed for Handler {}
 
     pub(crate) trait Tls13Handler: fmt::Debug + Sealed + Send + Sync {
@@ -421,10 +644,10 @@ mod client_hello {
             &self,
             cch: CompleteClientHelloHandling,
             cx: &mut ServerContext<'_>,
-            server_key: ActiveCertifiedKey<'_>,
+            server_key: Option<ActiveCertifiedKey<'_>>,
             chm: &Message<'_>,
             client_hello: &ClientHelloPayload,
-            selected_kxg: &'static dyn SupportedKxGroup,
+            selected_kxg: Option<&'static dyn SupportedKxGroup>,
             sigschemes_ext: Vec<SignatureScheme>,
         ) -> hs::NextStateOrError<'static>;
     }
@@ -444,6 +667,64 @@ mod client_hello {
         pub(in crate::server) done_retry: bool,
         pub(in crate::server) send_tickets: usize,
         pub(in crate::server) extra_exts: ServerExtensionsInput<'static>,
+        pub(in crate::server) ech_state: EchState,
+        pub(in crate::server) external_psk: Option<OfferedExternalPsk>,
+    }
+
+    /// An external PSK offered by the client that we know, found before choosing a
+    /// certificate and key exchange group.
+    ///
+    /// Using it needs no certificate, and a PSK_KE handshake needs no key exchange.
+    /// The client's binder is only checked once a cipher suite is chosen.
+    pub(crate) struct OfferedExternalPsk {
+        kex_mode: PskKexMode,
+        suite: &'static Tls13CipherSuite,
+    }
+
+    impl OfferedExternalPsk {
+        pub(crate) fn is_psk_only(&self) -> bool {
+            self.kex_mode == PskKexMode::PskOnly
+        }
+
+        /// Whether the PSK can be used with `suite`.
+        pub(crate) fn usable_with(&self, suite: &SupportedCipherSuite) -> bool {
+            suite
+                .tls13()
+                .and_then(|suite| suite.can_resume_from(self.suite))
+                .is_some()
+        }
+    }
+
+    /// Find the first external PSK offered by the client that `config` knows, if the
+    /// client and `config` share a PSK key exchange mode.
+    pub(crate) fn offered_external_psk(
+        config: &ServerConfig,
+        client_hello: &ClientHelloPayload,
+    ) -> Option<OfferedExternalPsk> {
+        let psk_modes = client_hello.preshared_key_modes?;
+        let kex_mode = config
+            .psk_kex_modes
+            .iter()
+            .copied()
+            .find(|mode| match mode {
+                PskKexMode::PskWithDhe => psk_modes.psk_dhe,
+                PskKexMode::PskOnly => psk_modes.psk,
+            })?;
+
+        client_hello
+            .preshared_key_offer
+            .as_ref()?
+            .identities
+            .iter()
+            .find_map(|psk_id| {
+                config
+                    .psk_resolver
+                    .resolve(&psk_id.identity.0)
+            })
+            .map(|psk| OfferedExternalPsk {
+                kex_mode,
+                suite: psk.suite(),
+            })
     }
 
     fn max_early_data_size(configured: u32) -> usize {
@@ -469,6 +750,30 @@ mod client_hello {
             psk: &[u8],
             binder: &[u8],
         ) -> bool {
+            let handshake_hash = self.binder_handshake_hash(client_hello);
+            let key_schedule = KeyScheduleEarly::new(suite, psk);
+            let real_binder =
+                key_schedule.resumption_psk_binder_key_and_sign_verify_data(&handshake_hash);
+
+            ConstantTimeEq::ct_eq(real_binder.as_ref(), binder).into()
+        }
+
+        fn check_external_binder(
+            &self,
+            suite: &'static Tls13CipherSuite,
+            client_hello: &Message<'_>,
+            psk: &ExternalPsk,
+            binder: &[u8],
+        ) -> bool {
+            let handshake_hash = self.binder_handshake_hash(client_hello);
+            let key_schedule = KeyScheduleEarly::new(suite, psk.secret());
+            let real_binder = key_schedule
+                .external_psk_binder_key_and_sign_verify_data(psk.is_imported(), &handshake_hash);
+
+            ConstantTimeEq::ct_eq(real_binder.as_ref(), binder).into()
+        }
+
+        fn binder_handshake_hash(&self, client_hello: &Message<'_>) -> hash::Output {
             let binder_plaintext = match &client_hello.payload {
                 MessagePayload::Handshake { parsed, encoded } => {
                     &encoded.bytes()[..encoded.bytes().len() - parsed.total_binder_length()]
@@ -476,15 +781,73 @@ mod client_hello {
                 _ => unreachable!(),
             };
 
-            let handshake_hash = self
-                .transcript
-                .hash_given(binder_plaintext);
+            self.transcript
+                .hash_given(binder_plaintext)
+        }
 
-            let key_schedule = KeyScheduleEarly::new(suite, psk);
-            let real_binder =
-                key_schedule.resumption_psk_binder_key_and_sign_verify_data(&handshake_hash);
+        /// Continue the handshake from a HelloRetryRequest sent with `cookie`.
+        ///
+        /// This restores the transcript (and the fact that a retry happened) from the
+        /// cookie, so that the second `ClientHello` can be handled by a connection which
+        /// did not see the first.  Cookies are ignored if we are not configured to send them.
+        ///
+        /// Returns the group the HelloRetryRequest asked for a key share in.
+        fn continue_stateless_retry(
+            &mut self,
+            cookie: &[u8],
+            session_id: SessionId,
+            cx: &mut ServerContext<'_>,
+        ) -> Result<Option<NamedGroup>, Error> {
+            let sealer = &self.config.hello_retry_cookies;
+            if !sealer.enabled() {
+                return Ok(None);
+            }
 
-            ConstantTimeEq::ct_eq(real_binder.as_ref(), binder).into()
+            let now = self.config.current_time()?.as_secs();
+            let retry = sealer
+                .decrypt(cookie)
+                .and_then(|plain| persist::HelloRetryCookie::read_bytes(&plain).ok())
+                .filter(|retry| {
+                    now.saturating_sub(retry.creation_time_sec) <= u64::from(sealer.lifetime())
+                        && retry.cipher_suite == self.suite.common.suite
+                        && retry.client_hello_hash.0.len()
+                            == self
+                                .suite
+                                .common
+                                .hash_provider
+                                .output_len()
+                });
+
+            let Some(retry) = retry else {
+                return Err(cx.common.send_fatal_alert(
+                    AlertDescription::IllegalParameter,
+                    PeerMisbehaved::InvalidHelloRetryRequestCookie,
+                ));
+            };
+
+            if retry.sni != cx.data.sni {
+                return Err(cx.common.send_fatal_alert(
+                    AlertDescription::IllegalParameter,
+                    PeerMisbehaved::ServerNameDifferedOnRetry,
+                ));
+            }
+
+            trace!("Continuing from stateless retry {retry:?}");
+            self.transcript.add(
+                &HandshakeMessagePayload::build_handshake_hash(&retry.client_hello_hash.0)
+                    .get_encoding(),
+            );
+            self.transcript
+                .add_message(&hello_retry_request(
+                    session_id,
+                    self.suite,
+                    retry.group,
+                    Some(PayloadU16::new(cookie.to_vec())),
+                    None,
+                ));
+            self.done_retry = true;
+            cx.common.handshake_kind = Some(HandshakeKind::FullWithHelloRetryRequest);
+            Ok(Some(retry.group))
         }
 
         fn attempt_tls13_ticket_decryption(
@@ -512,39 +875,52 @@ mod client_hello {
 
     fn emit_server_hello(
         transcript: &mut HandshakeHash,
-        randoms: &ConnectionRandoms,
+        randoms: &mut ConnectionRandoms,
         suite: &'static Tls13CipherSuite,
         cx: &mut ServerContext<'_>,
         session_id: &SessionId,
-        share_and_kxgroup: (&KeyShareEntry, &'static dyn SupportedKxGroup),
+        share_and_kxgroup: Option<(&KeyShareEntry, &'static dyn SupportedKxGroup)>,
         chosen_psk_idx: Option<usize>,
-        resuming_psk: Option<&[u8]>,
+        psk: Option<&[u8]>,
         config: &ServerConfig,
+        ech_accepted: bool,
     ) -> Result<KeyScheduleHandshake, Error> {
-        // Prepare key exchange; the caller already found the matching SupportedKxGroup
-        let (share, kxgroup) = share_and_kxgroup;
-        debug_assert_eq!(kxgroup.name(), share.group);
-        let ckx = kxgroup
-            .start_and_complete(&share.payload.0)
-            .map_err(|err| {
-                cx.common
-                    .send_fatal_alert(AlertDescription::IllegalParameter, err)
-            })?;
-        cx.common.kx_state.complete();
+        // Prepare key exchange; the caller already found the matching SupportedKxGroup.
+        // There is no key exchange for a PSK_KE handshake.
+        let (key_share, kx_secret) = match share_and_kxgroup {
+            Some((share, kxgroup)) => {
+                debug_assert_eq!(kxgroup.name(), share.group);
+                let ckx = kxgroup
+                    .start_and_complete(&share.payload.0)
+                    .map_err(|err| {
+                        cx.common
+                            .send_fatal_alert(AlertDescription::IllegalParameter, err)
+                    })?;
+                cx.common.kx_state.complete();
+                (
+                    Some(KeyShareEntry::new(ckx.group, ckx.pub_key)),
+                    Some(ckx.secret),
+                )
+            }
+            None => {
+                cx.common.kx_state = KxState::None;
+                (None, None)
+            }
+        };
 
         let extensions = Box::new(ServerExtensions {
-            key_share: Some(KeyShareEntry::new(ckx.group, ckx.pub_key)),
+            key_share,
             selected_version: Some(ProtocolVersion::TLSv1_3),
             preshared_key: chosen_psk_idx.map(|idx| idx as u16),
             ..Default::default()
         });
 
-        let sh = Message {
+        let server_hello = |random: [u8; 32], extensions| Message {
             version: ProtocolVersion::TLSv1_2,
             payload: MessagePayload::handshake(HandshakeMessagePayload(
                 HandshakePayload::ServerHello(ServerHelloPayload {
                     legacy_version: ProtocolVersion::TLSv1_2,
-                    random: Random::from(randoms.server),
+                    random: Random::from(random),
                     session_id: *session_id,
                     cipher_suite: suite.common.suite,
                     compression_method: Compression::Null,
@@ -553,6 +929,24 @@ mod client_hello {
             )),
         };
 
+        if ech_accepted {
+            // Signal acceptance of ECH by replacing the final 8 bytes of our random
+            // with a confirmation computed over a ServerHello where those bytes are zero.
+            //
+            // See draft-ietf-tls-esni-18 7.2.
+            randoms.server[24..].fill(0);
+            let mut confirmation_transcript = transcript.clone();
+            confirmation_transcript.add_message(&server_hello(randoms.server, extensions.clone()));
+            let confirmation = server_ech_confirmation_secret(
+                suite.hkdf_provider,
+                &randoms.client,
+                confirmation_transcript.current_hash(),
+            );
+            randoms.server[24..].copy_from_slice(&confirmation);
+        }
+
+        let sh = server_hello(randoms.server, extensions);
+
         cx.common.check_aligned_handshake()?;
 
         let client_hello_hash = transcript.hash_given(&[]);
@@ -562,7 +956,7 @@ mod client_hello {
         cx.common.send_msg(sh, false);
 
         // Start key schedule
-        let key_schedule_pre_handshake = if let Some(psk) = resuming_psk {
+        let key_schedule_pre_handshake = if let Some(psk) = psk {
             let early_key_schedule = KeyScheduleEarly::new(suite, psk);
             early_key_schedule.client_early_traffic_secret(
                 &client_hello_hash,
@@ -577,7 +971,10 @@ mod client_hello {
         };
 
         // Do key exchange
-        let key_schedule = key_schedule_pre_handshake.into_handshake(ckx.secret);
+        let key_schedule = match kx_secret {
+            Some(secret) => key_schedule_pre_handshake.into_handshake(secret),
+            None => key_schedule_pre_handshake.into_handshake_without_kx(),
+        };
 
         let handshake_hash = transcript.current_hash();
         let key_schedule = key_schedule.derive_server_handshake_secrets(
@@ -601,33 +998,104 @@ mod client_hello {
         common.send_msg(m, false);
     }
 
+    /// Seal the state needed to continue after a HelloRetryRequest, if configured to.
+    ///
+    /// `transcript` must contain exactly the first `ClientHello`.
+    fn seal_hello_retry_cookie(
+        config: &ServerConfig,
+        transcript: &HandshakeHash,
+        suite: &'static Tls13CipherSuite,
+        group: NamedGroup,
+        sni: Option<&DnsName<'_>>,
+    ) -> Result<Option<PayloadU16<NonEmpty>>, Error> {
+        if !config.hello_retry_cookies.enabled() {
+            return Ok(None);
+        }
+
+        let plain = persist::HelloRetryCookie::new(
+            sni,
+            suite.common.suite,
+            group,
+            transcript.current_hash().as_ref(),
+            config.current_time()?,
+        )
+        .get_encoding();
+
+        Ok(config
+            .hello_retry_cookies
+            .encrypt(&plain)
+            .filter(|cookie| !cookie.is_empty())
+            .map(PayloadU16::new))
+    }
+
+    fn hello_retry_request(
+        session_id: SessionId,
+        suite: &'static Tls13CipherSuite,
+        group: NamedGroup,
+        cookie: Option<PayloadU16<NonEmpty>>,
+        encrypted_client_hello: Option<Payload<'static>>,
+    ) -> Message<'static> {
+        Message {
+            version: ProtocolVersion::TLSv1_2,
+            payload: MessagePayload::handshake(HandshakeMessagePayload(
+                HandshakePayload::HelloRetryRequest(HelloRetryRequest {
+                    legacy_version: ProtocolVersion::TLSv1_2,
+                    session_id,
+                    cipher_suite: suite.common.suite,
+                    extensions: HelloRetryRequestExtensions {
+                        key_share: Some(group),
+                        cookie,
+                        supported_versions: Some(ProtocolVersion::TLSv1_3),
+                        encrypted_client_hello,
+                        ..Default::default()
+                    },
+                }),
+            )),
+        }
+    }
+
     fn emit_hello_retry_request(
         transcript: &mut HandshakeHash,
+        randoms: &ConnectionRandoms,
         suite: &'static Tls13CipherSuite,
         session_id: SessionId,
         common: &mut CommonState,
         group: NamedGroup,
+        cookie: Option<PayloadU16<NonEmpty>>,
+        ech_accepted: bool,
     ) {
-        let req = HelloRetryRequest {
-            legacy_version: ProtocolVersion::TLSv1_2,
-            session_id,
-            cipher_suite: suite.common.suite,
-            extensions: HelloRetryRequestExtensions {
-                key_share: Some(group),
-                supported_versions: Some(ProtocolVersion::TLSv1_3),
-                ..Default::default()
-            },
+        let hello_retry_request = |encrypted_client_hello| {
+            hello_retry_request(
+                session_id,
+                suite,
+                group,
+                cookie.clone(),
+                encrypted_client_hello,
+            )
         };
 
-        let m = Message {
-            version: ProtocolVersion::TLSv1_2,
-            payload: MessagePayload::handshake(HandshakeMessagePayload(
-                HandshakePayload::HelloRetryRequest(req),
-            )),
+        transcript.rollup_for_hrr();
+
+        let m = match ech_accepted {
+            // Signal acceptance of ECH with a confirmation computed over a
+            // HelloRetryRequest where the confirmation is zero.
+            //
+            // See draft-ietf-tls-esni-18 7.2.1.
+            true => {
+                let mut confirmation_transcript = transcript.clone();
+                confirmation_transcript
+                    .add_message(&hello_retry_request(Some(Payload::new(vec![0u8; 8]))));
+                let confirmation = server_ech_hrr_confirmation_secret(
+                    suite.hkdf_provider,
+                    &randoms.client,
+                    confirmation_transcript.current_hash(),
+                );
+                hello_retry_request(Some(Payload::new(confirmation.to_vec())))
+            }
+            false => hello_retry_request(None),
         };
 
         trace!("Requesting retry {m:?}");
-        transcript.rollup_for_hrr();
         transcript.add_message(&m);
         common.send_msg(m, false);
         common.handshake_kind = Some(HandshakeKind::FullWithHelloRetryRequest);
@@ -654,8 +1122,10 @@ mod client_hello {
         };
 
         /* Non-zero max_early_data_size controls whether early_data is allowed at all.
-         * We also require stateful resumption. */
-        let early_data_configured = config.max_early_data_size > 0 && !config.ticketer.enabled();
+         * We also require stateful resumption, unless we can detect replays. */
+        let anti_replay = config.anti_replay.enabled();
+        let early_data_configured =
+            config.max_early_data_size > 0 && (anti_replay || !config.ticketer.enabled());
 
         /* "For PSKs provisioned via NewSessionTicket, a server MUST validate
          *  that the ticket age for the selected PSK identity (computed by
@@ -679,7 +1149,40 @@ mod client_hello {
             && resume.common.cipher_suite == suite.common.suite
             && resume.common.alpn == cx.common.alpn_protocol;
 
-        if early_data_configured && early_data_possible && !cx.data.early_data.was_rejected() {
+        /* "[...] servers MUST NOT accept more than one [ClientHello with early
+         *  data] for any given value of ClientHello.random." -- this must come
+         *  last, as it records the ClientHello.
+         *
+         * (RFC8446, 8.2)
+         *
+         * A fresh ClientHello may arrive anywhere within twice the tolerance of
+         * the ticket age check, but is only certain to be remembered for
+         * `window()`: so the tolerance is half that, and a replay arriving after
+         * a ClientHello is forgotten is never fresh. */
+        let not_replayed = || {
+            if !anti_replay {
+                return true;
+            }
+
+            let window_ms =
+                u32::try_from(config.anti_replay.window().as_millis()).unwrap_or(u32::MAX);
+            let recorded = resume.is_fresh_within(window_ms / 2)
+                && config.current_time().is_ok_and(|now| {
+                    config
+                        .anti_replay
+                        .record(&client_hello.random.0, now)
+                });
+            if !recorded {
+                debug!("Rejecting early data from stale or replayed ClientHello");
+            }
+            recorded
+        };
+
+        if early_data_configured
+            && early_data_possible
+            && !cx.data.early_data.was_rejected()
+            && not_replayed()
+        {
             EarlyDataDecision::Accepted
         } else {
             if cx.common.is_quic() {
@@ -715,6 +1218,18 @@ mod client_hello {
             ep.extensions.early_data_ack = Some(());
         }
 
+        // "If the server rejects ECH ... it MAY include [retry configurations]
+        //  in its EncryptedExtensions message." -- draft-ietf-tls-esni-18 7.1
+        if cx.data.ech_status == EchStatus::Rejected {
+            ep.extensions.encrypted_client_hello_ack = Some(ServerEncryptedClientHello {
+                retry_configs: config
+                    .ech_keys
+                    .iter()
+                    .map(|key| key.config.clone())
+                    .collect(),
+            });
+        }
+
         let ee = HandshakeMessagePayload(HandshakePayload::EncryptedExtensions(ep.extensions));
 
         trace!("sending encrypted extensions {ee:?}");
@@ -769,9 +1284,11 @@ mod client_hello {
         flight: &mut HandshakeFlightTls13<'_>,
         cert_chain: &[CertificateDer<'static>],
         ocsp_response: Option<&[u8]>,
+        sct_list: Option<SctList>,
+        delegated_credential: Option<&DelegatedCredential>,
     ) {
         let cert = HandshakeMessagePayload(HandshakePayload::CertificateTls13(
-            CertificatePayloadTls13::new(cert_chain.iter(), ocsp_response),
+            certificate_payload(cert_chain, ocsp_response, sct_list, delegated_credential),
         ));
 
         trace!("sending certificate {cert:?}");
@@ -783,15 +1300,28 @@ mod client_hello {
         config: &ServerConfig,
         cert_chain: &[CertificateDer<'static>],
         ocsp_response: Option<&[u8]>,
+        sct_list: Option<SctList>,
+        delegated_credential: Option<&DelegatedCredential>,
         cert_compressor: &'static dyn CertCompressor,
     ) {
-        let payload = CertificatePayloadTls13::new(cert_chain.iter(), ocsp_response);
+        let payload = certificate_payload(
+            cert_chain,
+            ocsp_response,
+            sct_list.clone(),
+            delegated_credential,
+        );
 
         let Ok(entry) = config
             .cert_compression_cache
             .compression_for(cert_compressor, &payload)
         else {
-            return emit_certificate_tls13(flight, cert_chain, ocsp_response);
+            return emit_certificate_tls13(
+                flight,
+                cert_chain,
+                ocsp_response,
+                sct_list,
+                delegated_credential,
+            );
         };
 
         let c = HandshakeMessagePayload(HandshakePayload::CompressedCertificate(
@@ -802,33 +1332,45 @@ mod client_hello {
         flight.add(c);
     }
 
-    fn emit_certificate_verify_tls13(
-        flight: &mut HandshakeFlightTls13<'_>,
+    fn certificate_payload<'a>(
+        cert_chain: &'a [CertificateDer<'static>],
+        ocsp_response: Option<&'a [u8]>,
+        sct_list: Option<SctList>,
+        delegated_credential: Option<&DelegatedCredential>,
+    ) -> CertificatePayloadTls13<'a> {
+        let mut payload = CertificatePayloadTls13::new(cert_chain.iter(), ocsp_response);
+        if let Some(entry) = payload.entries.first_mut() {
+            entry
+                .extensions
+                .signed_certificate_timestamps = sct_list;
+            entry.extensions.delegated_credential = delegated_credential.cloned();
+        }
+        payload
+    }
+
+    fn choose_signer(
         common: &mut CommonState,
         signing_key: &dyn sign::SigningKey,
         schemes: &[SignatureScheme],
-    ) -> Result<(), Error> {
-        let message = construct_server_verify_message(&flight.transcript.current_hash());
-
-        let signer = signing_key
+    ) -> Result<Box<dyn sign::Signer>, Error> {
+        signing_key
             .choose_scheme(schemes)
             .ok_or_else(|| {
                 common.send_fatal_alert(
                     AlertDescription::HandshakeFailure,
                     PeerIncompatible::NoSignatureSchemesInCommon,
                 )
-            })?;
-
-        let scheme = signer.scheme();
-        let sig = signer.sign(message.as_ref())?;
-
-        let cv = DigitallySignedStruct::new(scheme, sig);
+            })
+    }
 
+    fn emit_certificate_verify_tls13(
+        flight: &mut HandshakeFlightTls13<'_>,
+        cv: DigitallySignedStruct,
+    ) {
         let cv = HandshakeMessagePayload(HandshakePayload::CertificateVerify(cv));
 
         trace!("sending certificate-verify {cv:?}");
         flight.add(cv);
-        Ok(())
     }
 
     fn emit_finished_tls13(
@@ -1114,22 +1656,37 @@ impl State<ServerConnectionData> for ExpectCertificate {
 
         let now = self.config.current_time()?;
 
-        self.config
-            .verifier
-            .verify_client_cert(end_entity, intermediates, now)
-            .map_err(|err| {
-                cx.common
-                    .send_cert_verify_error_alert(err)
-            })?;
+        let pending = if self.config.verifier.is_async() {
+            Some(CertificateVerification::new(
+                end_entity,
+                intermediates,
+                None,
+                Vec::new(),
+                now,
+            ))
+        } else {
+            self.config
+                .verifier
+                .verify_client_cert(end_entity, intermediates, now)
+                .map_err(|err| {
+                    cx.common
+                        .send_cert_verify_error_alert(err)
+                })?;
+            None
+        };
 
-        Ok(Box::new(ExpectCertificateVerify {
+        let next = ExpectCertificateVerify {
             config: self.config,
             suite: self.suite,
             transcript: self.transcript,
             key_schedule: self.key_schedule,
             client_cert: client_cert.into_owned(),
             send_tickets: self.send_tickets,
-        }))
+        };
+        match pending {
+            Some(pending) => Ok(ExpectVerification::new(pending, next, cx.common)),
+            None => Ok(Box::new(next)),
+        }
     }
 
     fn into_owned(self: Box<Self>) -> hs::NextState<'static> {
@@ -1195,6 +1752,12 @@ impl State<ServerConnectionData> for ExpectCertificateVerify {
     }
 }
 
+impl ResumeWithVerification<ServerConnectionData> for ExpectCertificateVerify {
+    fn resume(self, _cx: &mut ServerContext<'_>) -> hs::NextStateOrError<'static> {
+        Ok(Box::new(self))
+    }
+}
+
 // --- Process (any number of) early ApplicationData messages,
 //     followed by a terminating handshake EndOfEarlyData message ---
 
@@ -1323,19 +1886,23 @@ impl ExpectFinished {
                 trace!("resumption not available; not issuing ticket");
                 return Ok(());
             }
-            let stateful_lifetime = 24 * 60 * 60; // this is a bit of a punt
+            let stateful_lifetime = config
+                .session_storage
+                .lifetime()
+                .min(MAX_STATEFUL_TICKET_LIFETIME);
             (id, stateful_lifetime)
         };
 
         let mut payload = NewSessionTicketPayloadTls13::new(lifetime, age_add, nonce, ticket);
 
         if config.max_early_data_size > 0 {
-            if !stateless {
+            if !stateless || config.anti_replay.enabled() {
                 payload.extensions.max_early_data_size = Some(config.max_early_data_size);
             } else {
                 // We implement RFC8446 section 8.1: by enforcing that 0-RTT is
-                // only possible if using stateful resumption
-                warn!("early_data with stateless resumption is not allowed");
+                // only possible if using stateful resumption, or section 8.2
+                // if configured to record ClientHellos.
+                warn!("early_data with stateless resumption is not allowed without anti_replay");
             }
         }
 
@@ -1380,6 +1947,17 @@ impl State<ServerConnectionData> for ExpectFinished {
 
         cx.common.check_aligned_handshake()?;
 
+        // Post-handshake authentication is performed over the transcript so far.
+        let post_handshake_auth = match cx.data.post_handshake_auth_offered {
+            true => Some(PostHandshakeAuth {
+                config: self.config.clone(),
+                transcript: self.transcript.clone(),
+                next_context: 0,
+                pending: None,
+            }),
+            false => None,
+        };
+
         let (key_schedule_traffic, resumption) =
             key_schedule_before_finished.into_traffic(self.transcript.current_hash());
 
@@ -1400,6 +1978,7 @@ impl State<ServerConnectionData> for ExpectFinished {
             }),
             false => Box::new(ExpectTraffic {
                 key_schedule: key_schedule_traffic,
+                post_handshake_auth,
                 _fin_verified: fin,
             }),
         })
@@ -1413,9 +1992,200 @@ impl State<ServerConnectionData> for ExpectFinished {
 // --- Process traffic ---
 struct ExpectTraffic {
     key_schedule: KeyScheduleTraffic,
+    /// Present if the client offered post-handshake authentication.
+    post_handshake_auth: Option<PostHandshakeAuth>,
     _fin_verified: verify::FinishedMessageVerified,
 }
 
+struct PostHandshakeAuth {
+    config: Arc<ServerConfig>,
+    /// The handshake transcript, up to and including the client's Finished.
+    transcript: HandshakeHash,
+    next_context: u64,
+    pending: Option<PendingClientAuth>,
+}
+
+/// An outstanding post-handshake `CertificateRequest`.
+struct PendingClientAuth {
+    context: Vec<u8>,
+    verifier: Arc<dyn ClientCertVerifier>,
+    transcript: HandshakeHash,
+    expect: PendingClientAuthMessage,
+}
+
+enum PendingClientAuthMessage {
+    Certificate,
+    CertificateVerify(CertificateChain<'static>),
+    Finished(Option<CertificateChain<'static>>),
+}
+
+impl PostHandshakeAuth {
+    fn request(
+        &mut self,
+        common: &mut CommonState,
+        verifier: Arc<dyn ClientCertVerifier>,
+    ) -> Result<(), Error> {
+        if self.pending.is_some() {
+            return Err(Error::General(
+                "a client certificate request is already outstanding".into(),
+            ));
+        }
+
+        // "The certificate_request_context ... MUST be unique within the scope
+        //  of this connection" - RFC8446 4.3.2
+        let context = self.next_context.to_be_bytes().to_vec();
+        self.next_context += 1;
+
+        let cr = CertificateRequestPayloadTls13 {
+            context: PayloadU8::new(context.clone()),
+            extensions: CertificateRequestExtensions {
+                signature_algorithms: Some(verifier.supported_verify_schemes()),
+                certificate_compression_algorithms: None,
+                authority_names: match verifier.root_hint_subjects().as_ref() {
+                    [] => None,
+                    authorities => Some(authorities.to_vec()),
+                },
+            },
+        };
+
+        let m = Message {
+            version: ProtocolVersion::TLSv1_3,
+            payload: MessagePayload::handshake(HandshakeMessagePayload(
+                HandshakePayload::CertificateRequestTls13(cr),
+            )),
+        };
+        trace!("Sending post-handshake CertificateRequest {m:?}");
+
+        let mut transcript = self.transcript.clone();
+        transcript.add_message(&m);
+        common.send_msg(m, true);
+
+        self.pending = Some(PendingClientAuth {
+            context,
+            verifier,
+            transcript,
+            expect: PendingClientAuthMessage::Certificate,
+        });
+        Ok(())
+    }
+
+    fn handle(
+        &mut self,
+        cx: &mut ServerContext<'_>,
+        key_schedule: &KeyScheduleTraffic,
+        m: Message<'_>,
+    ) -> Result<(), Error> {
+        let Some(mut pending) = self.pending.take() else {
+            return Err(inappropriate_handshake_message(
+                &m.payload,
+                &[ContentType::ApplicationData, ContentType::Handshake],
+                &[HandshakeType::KeyUpdate],
+            ));
+        };
+
+        match pending.expect {
+            PendingClientAuthMessage::Certificate => {
+                pending.transcript.add_message(&m);
+                let certp = require_handshake_msg_move!(
+                    m,
+                    HandshakeType::Certificate,
+                    HandshakePayload::CertificateTls13
+                )?;
+
+                if certp.context.0 != pending.context {
+                    return Err(cx.common.send_fatal_alert(
+                        AlertDescription::IllegalParameter,
+                        PeerMisbehaved::IncorrectCertificateRequestContext,
+                    ));
+                }
+
+                // Our CertificateRequest does not ask for any certificate entry
+                // extensions (such as status_request or signed_certificate_timestamp),
+                // so any extensions on the entries here are illegal.
+                if certp
+                    .entries
+                    .iter()
+                    .any(|e| !e.extensions.only_contains(&[]))
+                {
+                    return Err(PeerMisbehaved::UnsolicitedCertExtension.into());
+                }
+
+                let client_cert = certp.into_certificate_chain();
+                let Some((end_entity, intermediates)) = client_cert.split_first() else {
+                    if pending.verifier.client_auth_mandatory() {
+                        return Err(cx.common.send_fatal_alert(
+                            AlertDescription::CertificateRequired,
+                            Error::NoCertificatesPresented,
+                        ));
+                    }
+
+                    debug!("client certificate requested but none supplied");
+                    pending.expect = PendingClientAuthMessage::Finished(None);
+                    self.pending = Some(pending);
+                    return Ok(());
+                };
+
+                let now = self.config.current_time()?;
+                pending
+                    .verifier
+                    .verify_client_cert(end_entity, intermediates, now)
+                    .map_err(|err| {
+                        cx.common
+                            .send_cert_verify_error_alert(err)
+                    })?;
+
+                pending.expect =
+                    PendingClientAuthMessage::CertificateVerify(client_cert.into_owned());
+            }
+
+            PendingClientAuthMessage::CertificateVerify(client_cert) => {
+                let sig = require_handshake_msg!(
+                    m,
+                    HandshakeType::CertificateVerify,
+                    HandshakePayload::CertificateVerify
+                )?;
+                let msg = construct_client_verify_message(&pending.transcript.current_hash());
+                pending
+                    .verifier
+                    .verify_tls13_signature(msg.as_ref(), &client_cert[0], sig)
+                    .map_err(|err| {
+                        cx.common
+                            .send_cert_verify_error_alert(err)
+                    })?;
+
+                trace!("client post-handshake CertificateVerify OK");
+                pending.transcript.add_message(&m);
+                pending.expect = PendingClientAuthMessage::Finished(Some(client_cert));
+            }
+
+            PendingClientAuthMessage::Finished(client_cert) => {
+                let finished =
+                    require_handshake_msg!(m, HandshakeType::Finished, HandshakePayload::Finished)?;
+                let expect_verify_data = key_schedule
+                    .sign_post_handshake_client_finish(&pending.transcript.current_hash());
+
+                if !bool::from(ConstantTimeEq::ct_eq(
+                    expect_verify_data.as_ref(),
+                    finished.bytes(),
+                )) {
+                    return Err(cx
+                        .common
+                        .send_fatal_alert(AlertDescription::DecryptError, Error::DecryptError));
+                }
+
+                debug!("post-handshake client authentication complete");
+                if let Some(client_cert) = client_cert {
+                    cx.common.peer_certificates = Some(client_cert);
+                }
+                return Ok(());
+            }
+        }
+
+        self.pending = Some(pending);
+        Ok(())
+    }
+}
+
 impl ExpectTraffic {
     fn handle_key_update(
         &mut self,
@@ -1460,6 +2230,24 @@ impl State<ServerConnectionData> for ExpectTraffic {
                 parsed: HandshakeMessagePayload(HandshakePayload::KeyUpdate(key_update)),
                 ..
             } => self.handle_key_update(cx.common, &key_update)?,
+            MessagePayload::Handshake {
+                parsed:
+                    HandshakeMessagePayload(
+                        HandshakePayload::CertificateTls13(_)
+                        | HandshakePayload::CertificateVerify(_)
+                        | HandshakePayload::Finished(_),
+                    ),
+                ..
+            } => {
+                let Some(post_handshake_auth) = &mut self.post_handshake_auth else {
+                    return Err(inappropriate_handshake_message(
+                        &m.payload,
+                        &[ContentType::ApplicationData, ContentType::Handshake],
+                        &[HandshakeType::KeyUpdate],
+                    ));
+                };
+                post_handshake_auth.handle(cx, &self.key_schedule, m)?;
+            }
             payload => {
                 return Err(inappropriate_handshake_message(
                     &payload,
@@ -1492,6 +2280,17 @@ impl State<ServerConnectionData> for ExpectTraffic {
             .request_key_update_and_update_encrypter(common)
     }
 
+    fn request_client_certificate(
+        &mut self,
+        common: &mut CommonState,
+        verifier: Arc<dyn ClientCertVerifier>,
+    ) -> Result<(), Error> {
+        match &mut self.post_handshake_auth {
+            Some(post_handshake_auth) => post_handshake_auth.request(common, verifier),
+            None => Err(PeerIncompatible::PostHandshakeAuthNotOffered.into()),
+        }
+    }
+
     fn into_external_state(self: Box<Self>) -> Result<Box<dyn KernelState + 'static>, Error> {
         Ok(self)
     }
@@ -1539,6 +2338,14 @@ impl State<ServerConnectionData> for ExpectQuicTraffic {
         Err(inappropriate_message(&m.payload, &[]))
     }
 
+    fn request_client_certificate(
+        &mut self,
+        _common: &mut CommonState,
+        _verifier: Arc<dyn ClientCertVerifier>,
+    ) -> Result<(), Error> {
+        Err(PeerIncompatible::PostHandshakeAuthNotOffered.into())
+    }
+
     fn export_keying_material(
         &self,
         output: &mut [u8],
@@ -5,6 +5,8 @@ use core::mem;
 use std::sync::{RwLock, RwLockReadGuard};
 
 use pki_types::UnixTime;
+#[cfg(feature = "std")]
+use zeroize::Zeroizing;
 
 use crate::Error;
 use crate::server::ProducesTickets;
@@ -143,6 +145,247 @@ impl ProducesTickets for TicketRotator {
     }
 }
 
+/// Session ticket key material that is supplied from outside this process.
+///
+/// Distributing the same keys to every server behind a load balancer means
+/// that tickets issued by one server can be redeemed at any of them.
+#[cfg(feature = "std")]
+#[derive(Clone)]
+pub struct TicketKey {
+    name: [u8; 16],
+    secret: Zeroizing<Vec<u8>>,
+}
+
+#[cfg(feature = "std")]
+impl TicketKey {
+    /// Make a new `TicketKey`.
+    ///
+    /// `name` identifies the key: it is embedded in the clear at the start of
+    /// every ticket encrypted with it, and used to find the key again when
+    /// the ticket is decrypted.  It should be unique across a fleet's key
+    /// schedule, but need not be secret.
+    ///
+    /// `secret` is the key material.  The required length depends on the
+    /// crypto provider's `Ticketer::new_with_keys()` using it: 32 bytes for
+    /// *ring*, and 64 bytes for aws-lc-rs.  Tickets and keys cannot be shared
+    /// between the two.  Key files, from [`TicketKey::from_key_file()`], can be
+    /// used with either provider's `Ticketer::new_with_key_files()`.
+    pub fn new(name: [u8; 16], secret: &[u8]) -> Self {
+        Self {
+            name,
+            secret: Zeroizing::new(secret.to_vec()),
+        }
+    }
+
+    /// Parse a session ticket key file, as used by nginx (`ssl_session_ticket_key`)
+    /// and other OpenSSL-based servers.
+    ///
+    /// `contents` is either:
+    ///
+    /// - 48 bytes: a 16 byte key name, a 16 byte AES 128 key, then a 16 byte
+    ///   HMAC-SHA256 key; or
+    /// - 80 bytes: a 16 byte key name, a 32 byte HMAC-SHA256 key, then a 32 byte
+    ///   AES 256 key.
+    ///
+    /// HAProxy's `tls-ticket-keys` file contains the same keys, base64-encoded
+    /// one per line; decode each line before calling this.
+    ///
+    /// The resulting key's secret is the HMAC key followed by the AES key, whichever
+    /// the file's layout.  It is for use with the `Ticketer::new_with_key_files()`
+    /// function of each crypto provider.
+    pub fn from_key_file(contents: &[u8]) -> Result<Self, Error> {
+        let (name, hmac_key, aes_key) = match contents.len() {
+            48 => (&contents[..16], &contents[32..], &contents[16..32]),
+            80 => (&contents[..16], &contents[16..48], &contents[48..]),
+            _ => {
+                return Err(Error::General(
+                    "ticket key file must be 48 or 80 bytes".into(),
+                ));
+            }
+        };
+
+        let mut key_name = [0u8; 16];
+        key_name.copy_from_slice(name);
+        let mut secret = Zeroizing::new(Vec::with_capacity(hmac_key.len() + aes_key.len()));
+        secret.extend_from_slice(hmac_key);
+        secret.extend_from_slice(aes_key);
+        Ok(Self {
+            name: key_name,
+            secret,
+        })
+    }
+
+    /// The key's name.
+    pub fn name(&self) -> &[u8; 16] {
+        &self.name
+    }
+
+    /// The secret key material.
+    pub fn secret(&self) -> &[u8] {
+        &self.secret
+    }
+}
+
+#[cfg(feature = "std")]
+impl core::fmt::Debug for TicketKey {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        // Note: we deliberately omit the secret from the debug output.
+        f.debug_struct("TicketKey")
+            .field("name", &self.name)
+            .finish_non_exhaustive()
+    }
+}
+
+/// The ticket keys in use at a given moment, as distributed to a fleet of servers.
+///
+/// Every key's secret must have the length required by the ticketer it is given
+/// to: see [`TicketKey::new()`].  A key set with a key of the wrong length is
+/// rejected as a whole, when it is given to the ticketer or to [`TicketKeyRing::update()`].
+#[cfg(feature = "std")]
+#[allow(clippy::exhaustive_structs)]
+#[derive(Clone, Debug)]
+pub struct TicketKeySet {
+    /// The key used to encrypt new tickets.
+    pub current: TicketKey,
+
+    /// The key that will become `current` at the next rotation.
+    ///
+    /// It is only used for decryption, so that tickets issued by servers
+    /// that have already rotated are accepted by servers that have not.
+    pub next: Option<TicketKey>,
+
+    /// Keys that were previously `current`.
+    ///
+    /// These are only used for decryption.  How many to keep determines how
+    /// long a ticket remains usable after its key stops being `current`.
+    pub previous: Vec<TicketKey>,
+}
+
+/// Sub-ticketers by key name.  The first is for the current key.
+#[cfg(feature = "std")]
+type KeyedTicketers = Vec<([u8; 16], Box<dyn ProducesTickets>)>;
+
+/// A ticketer using externally supplied keys, which can be replaced at runtime.
+///
+/// Each ticket starts with the name of the key that encrypted it, and
+/// decryption uses the key of that name from the current [`TicketKeySet`].
+/// This allows a rotation schedule distributed from a central secret store
+/// to work across a fleet of servers: each server is given the same
+/// [`TicketKeySet`] and calls [`TicketKeyRing::update()`] when it changes.
+#[cfg(feature = "std")]
+pub struct TicketKeyRing {
+    factory: fn(&TicketKey) -> Result<Box<dyn ProducesTickets>, Error>,
+    lifetime: u32,
+    state: RwLock<KeyedTicketers>,
+}
+
+#[cfg(feature = "std")]
+impl TicketKeyRing {
+    /// Creates a new `TicketKeyRing` using `keys`.
+    ///
+    /// `lifetime` is in seconds, and is the ticket lifetime advertised to
+    /// clients.  It should not exceed the time a key remains in `previous`
+    /// after it stops being `current`.
+    ///
+    /// `factory` makes a `ProducesTickets` implementation for a single key.
+    /// The tickets it produces must start with the key's name.
+    ///
+    /// An error is returned if any key is unusable, or if two keys in `keys`
+    /// have the same name.
+    pub fn new(
+        lifetime: u32,
+        keys: TicketKeySet,
+        factory: fn(&TicketKey) -> Result<Box<dyn ProducesTickets>, Error>,
+    ) -> Result<Self, Error> {
+        Ok(Self {
+            factory,
+            lifetime,
+            state: RwLock::new(Self::make_ticketers(factory, &keys)?),
+        })
+    }
+
+    /// Replace the keys in use with `keys`.
+    ///
+    /// Tickets encrypted with keys that do not appear in `keys` are no
+    /// longer accepted.  If any key is unusable, or two keys have the same name,
+    /// an error is returned and the existing keys remain in use.
+    pub fn update(&self, keys: TicketKeySet) -> Result<(), Error> {
+        // Make the new ticketers outside the lock.
+        let ticketers = Self::make_ticketers(self.factory, &keys)?;
+        match self.state.write() {
+            Ok(mut state) => {
+                *state = ticketers;
+                Ok(())
+            }
+            Err(_) => Err(Error::General("ticket key state lock poisoned".into())),
+        }
+    }
+
+    fn make_ticketers(
+        factory: fn(&TicketKey) -> Result<Box<dyn ProducesTickets>, Error>,
+        keys: &TicketKeySet,
+    ) -> Result<KeyedTicketers, Error> {
+        let mut ticketers = Vec::with_capacity(2 + keys.previous.len());
+        for key in [&keys.current]
+            .into_iter()
+            .chain(keys.next.iter())
+            .chain(keys.previous.iter())
+        {
+            // Otherwise, which key decrypts a ticket would be ambiguous.
+            if ticketers
+                .iter()
+                .any(|(name, _)| *name == key.name)
+            {
+                return Err(Error::General(
+                    "duplicate ticket key name in TicketKeySet".into(),
+                ));
+            }
+            ticketers.push((key.name, factory(key)?));
+        }
+        Ok(ticketers)
+    }
+}
+
+#[cfg(feature = "std")]
+impl ProducesTickets for TicketKeyRing {
+    fn lifetime(&self) -> u32 {
+        self.lifetime
+    }
+
+    fn enabled(&self) -> bool {
+        true
+    }
+
+    fn encrypt(&self, message: &[u8]) -> Option<Vec<u8>> {
+        self.state
+            .read()
+            .ok()?
+            .first()?
+            .1
+            .encrypt(message)
+    }
+
+    fn decrypt(&self, ciphertext: &[u8]) -> Option<Vec<u8>> {
+        let name = ciphertext.get(..16)?;
+        let state = self.state.read().ok()?;
+
+        // Key names are not secret, so a simple search is fine here.
+        state
+            .iter()
+            .find(|(key_name, _)| key_name[..] == *name)
+            .and_then(|(_, ticketer)| ticketer.decrypt(ciphertext))
+    }
+}
+
+#[cfg(feature = "std")]
+impl core::fmt::Debug for TicketKeyRing {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        f.debug_struct("TicketKeyRing")
+            .field("lifetime", &self.lifetime)
+            .finish_non_exhaustive()
+    }
+}
+
 #[cfg(feature = "std")]
 impl core::fmt::Debug for TicketRotator {
     fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
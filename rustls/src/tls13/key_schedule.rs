@@ -2,8 +2,12 @@
 
 use alloc::boxed::Box;
 use alloc::string::ToString;
+use alloc::vec;
+use alloc::vec::Vec;
 use core::ops::Deref;
 
+use zeroize::Zeroizing;
+
 use crate::common_state::{CommonState, Side};
 use crate::crypto::cipher::{AeadKey, Iv, MessageDecrypter, Tls13AeadAlgorithm};
 use crate::crypto::tls13::{Hkdf, HkdfExpander, OkmBlock, OutputLengthError, expand};
@@ -80,11 +84,32 @@ impl KeyScheduleEarly {
         &self,
         hs_hash: &hash::Output,
     ) -> hmac::Tag {
-        let resumption_psk_binder_key = self
-            .ks
-            .derive_for_empty_hash(SecretKind::ResumptionPskBinderKey);
+        self.psk_binder_key_and_sign_verify_data(SecretKind::ResumptionPskBinderKey, hs_hash)
+    }
+
+    /// As for `resumption_psk_binder_key_and_sign_verify_data`, but for an external PSK.
+    ///
+    /// `imported` selects the "imp binder" label from RFC 9258 instead of "ext binder".
+    pub(crate) fn external_psk_binder_key_and_sign_verify_data(
+        &self,
+        imported: bool,
+        hs_hash: &hash::Output,
+    ) -> hmac::Tag {
+        let kind = match imported {
+            true => SecretKind::ImportedPskBinderKey,
+            false => SecretKind::ExternalPskBinderKey,
+        };
+        self.psk_binder_key_and_sign_verify_data(kind, hs_hash)
+    }
+
+    fn psk_binder_key_and_sign_verify_data(
+        &self,
+        kind: SecretKind,
+        hs_hash: &hash::Output,
+    ) -> hmac::Tag {
+        let binder_key = self.ks.derive_for_empty_hash(kind);
         self.ks
-            .sign_verify_data(&resumption_psk_binder_key, hs_hash)
+            .sign_verify_data(&binder_key, hs_hash)
     }
 }
 
@@ -137,6 +162,13 @@ impl KeySchedulePreHandshake {
             .input_secret(shared_secret.secret_bytes());
         KeyScheduleHandshakeStart { ks: self.ks }
     }
+
+    /// As for `into_handshake`, but for a `psk_ke` handshake where there
+    /// is no "(EC)DHE" secret, so the 0-value is used instead.
+    pub(crate) fn into_handshake_without_kx(mut self) -> KeyScheduleHandshakeStart {
+        self.ks.input_empty();
+        KeyScheduleHandshakeStart { ks: self.ks }
+    }
 }
 
 /// Creates a key schedule with a PSK.
@@ -204,22 +236,10 @@ impl KeyScheduleHandshakeStart {
         client_hello_inner_random: &[u8],
         hs_hash: hash::Output,
     ) -> [u8; 8] {
-        /*
-        Per ietf-tls-esni-17 section 7.2:
-        <https://datatracker.ietf.org/doc/html/draft-ietf-tls-esni-17#section-7.2>
-        accept_confirmation = HKDF-Expand-Label(
-          HKDF-Extract(0, ClientHelloInner.random),
-          "ech accept confirmation",
-          transcript_ech_conf,8)
-         */
-        hkdf_expand_label(
-            self.ks
-                .suite
-                .hkdf_provider
-                .extract_from_secret(None, client_hello_inner_random)
-                .as_ref(),
-            SecretKind::ServerEchConfirmationSecret.to_bytes(),
-            hs_hash.as_ref(),
+        server_ech_confirmation_secret(
+            self.ks.suite.hkdf_provider,
+            client_hello_inner_random,
+            hs_hash,
         )
     }
 
@@ -562,6 +582,15 @@ impl KeyScheduleTraffic {
             .export_keying_material(&self.current_exporter_secret, out, label, context)
     }
 
+    /// Sign the client's Finished message at the end of post-handshake authentication.
+    ///
+    /// The base key is the current client application traffic secret.  See RFC 8446
+    /// section 4.4.
+    pub(crate) fn sign_post_handshake_client_finish(&self, hs_hash: &hash::Output) -> hmac::Tag {
+        self.ks
+            .sign_finish(&self.current_client_traffic_secret, hs_hash)
+    }
+
     pub(crate) fn refresh_traffic_secret(
         &mut self,
         side: Side,
@@ -932,6 +961,28 @@ fn hkdf_expand_label_slice(
     })
 }
 
+pub(crate) fn server_ech_confirmation_secret(
+    hkdf_provider: &'static dyn Hkdf,
+    client_hello_inner_random: &[u8],
+    hs_hash: hash::Output,
+) -> [u8; 8] {
+    /*
+    Per ietf-tls-esni-17 section 7.2:
+    <https://datatracker.ietf.org/doc/html/draft-ietf-tls-esni-17#section-7.2>
+    accept_confirmation = HKDF-Expand-Label(
+      HKDF-Extract(0, ClientHelloInner.random),
+      "ech accept confirmation",
+      transcript_ech_conf,8)
+     */
+    hkdf_expand_label(
+        hkdf_provider
+            .extract_from_secret(None, client_hello_inner_random)
+            .as_ref(),
+        SecretKind::ServerEchConfirmationSecret.to_bytes(),
+        hs_hash.as_ref(),
+    )
+}
+
 pub(crate) fn server_ech_hrr_confirmation_secret(
     hkdf_provider: &'static dyn Hkdf,
     client_hello_inner_random: &[u8],
@@ -955,6 +1006,42 @@ pub(crate) fn server_ech_hrr_confirmation_secret(
     )
 }
 
+/// Derive the secret of an imported PSK.
+///
+/// `epsk_suite` supplies the hash function associated with the external PSK `epsk`, and
+/// `imported_identity` is the encoded `ImportedIdentity`.
+pub(crate) fn derive_imported_psk(
+    epsk_suite: &'static Tls13CipherSuite,
+    epsk: &[u8],
+    imported_identity: &[u8],
+    output_len: usize,
+) -> Result<Zeroizing<Vec<u8>>, Error> {
+    /*
+    Per RFC 9258 section 5.1:
+    <https://datatracker.ietf.org/doc/html/rfc9258#section-5.1>
+    epskx = HKDF-Extract(0, epsk)
+    ipskx = HKDF-Expand-Label(epskx, "derived psk",
+                              Hash(ImportedIdentity), L)
+     */
+    let epskx = epsk_suite
+        .hkdf_provider
+        .extract_from_secret(None, epsk);
+    let context = epsk_suite
+        .common
+        .hash_provider
+        .hash(imported_identity);
+
+    let mut ipskx = Zeroizing::new(vec![0u8; output_len]);
+    hkdf_expand_label_slice(
+        epskx.as_ref(),
+        SecretKind::DerivedPsk.to_bytes(),
+        context.as_ref(),
+        &mut ipskx,
+    )
+    .map_err(|_| Error::General("imported PSK too long".into()))?;
+    Ok(ipskx)
+}
+
 fn hkdf_expand_label_inner<F, T>(
     expander: &dyn HkdfExpander,
     label: &[u8],
@@ -987,6 +1074,9 @@ where
 #[derive(Debug, Clone, Copy, PartialEq)]
 enum SecretKind {
     ResumptionPskBinderKey,
+    ExternalPskBinderKey,
+    ImportedPskBinderKey,
+    DerivedPsk,
     ClientEarlyTrafficSecret,
     ClientHandshakeTrafficSecret,
     ServerHandshakeTrafficSecret,
@@ -1004,6 +1094,11 @@ impl SecretKind {
         use self::SecretKind::*;
         match self {
             ResumptionPskBinderKey => b"res binder",
+            ExternalPskBinderKey => b"ext binder",
+            // https://datatracker.ietf.org/doc/html/rfc9258#section-4.2
+            ImportedPskBinderKey => b"imp binder",
+            // https://datatracker.ietf.org/doc/html/rfc9258#section-5.1
+            DerivedPsk => b"derived psk",
             ClientEarlyTrafficSecret => b"c e traffic",
             ClientHandshakeTrafficSecret => b"c hs traffic",
             ServerHandshakeTrafficSecret => b"s hs traffic",
@@ -3,11 +3,13 @@ use core::fmt::Debug;
 
 use pki_types::{CertificateDer, ServerName, UnixTime};
 
+use crate::delegated_credential::DelegatedCredential;
 use crate::enums::SignatureScheme;
 use crate::error::{Error, InvalidMessage};
 use crate::msgs::base::PayloadU16;
 use crate::msgs::codec::{Codec, Reader};
 use crate::msgs::handshake::DistinguishedName;
+use crate::sct::SignedCertificateTimestamp;
 use crate::sync::Arc;
 
 // Marker types.  These are used to bind the fact some verification
@@ -92,6 +94,26 @@ pub trait ServerCertVerifier: Debug + Send + Sync {
         now: UnixTime,
     ) -> Result<ServerCertVerified, Error>;
 
+    /// Whether certificate chains are verified by the application, asynchronously.
+    ///
+    /// This allows verification to consult a remote policy service, or fetch CRLs or
+    /// intermediate certificates, without blocking.  If this returns `true`, handshakes do
+    /// not call [`Self::verify_server_cert()`]: instead they pause with a
+    /// [`CertificateVerification`] for the application to make, and continue once the
+    /// application provides its result.  Buffered connections report this via
+    /// [`IoState::awaiting_verification()`], and unbuffered connections by yielding
+    /// [`ConnectionState::VerificationRequired`].
+    ///
+    /// Exported authenticators always call [`Self::verify_server_cert()`].
+    ///
+    /// The default implementation returns `false`.
+    ///
+    /// [`IoState::awaiting_verification()`]: crate::IoState::awaiting_verification
+    /// [`ConnectionState::VerificationRequired`]: crate::unbuffered::ConnectionState::VerificationRequired
+    fn is_async(&self) -> bool {
+        false
+    }
+
     /// Verify a signature allegedly by the given server certificate.
     ///
     /// `message` is not hashed, and needs hashing during the verification.
@@ -141,12 +163,90 @@ pub trait ServerCertVerifier: Debug + Send + Sync {
     /// This should be in priority order, with the most preferred first.
     fn supported_verify_schemes(&self) -> Vec<SignatureScheme>;
 
+    /// Verify a delegated credential sent by the server with `end_entity`, as
+    /// described in [RFC 9345 section 4.2].
+    ///
+    /// This must check that `end_entity` permits delegation, that the credential is
+    /// valid at `now` and expires no more than seven days later, and verify the
+    /// signature of `end_entity`'s key over [`DelegatedCredential::signed_message()`].
+    ///
+    /// `end_entity` has already been validated by [`ServerCertVerifier::verify_server_cert`].
+    /// rustls checks that the credential's `dc_cert_verify_algorithm` was offered.
+    ///
+    /// This method is only called for TLS1.3 handshakes, when
+    /// [`ClientConfig::enable_delegated_credentials`] is set.  The default
+    /// implementation rejects all delegated credentials.
+    ///
+    /// [RFC 9345 section 4.2]: https://datatracker.ietf.org/doc/html/rfc9345#section-4.2
+    /// [`ClientConfig::enable_delegated_credentials`]: crate::ClientConfig::enable_delegated_credentials
+    fn verify_delegated_credential(
+        &self,
+        _end_entity: &CertificateDer<'_>,
+        _credential: &DelegatedCredential,
+        _now: UnixTime,
+    ) -> Result<HandshakeSignatureValid, Error> {
+        Err(Error::General(
+            "delegated credentials are not supported by this verifier".into(),
+        ))
+    }
+
+    /// Verify a TLS1.3 signature allegedly made with the key of a delegated credential.
+    ///
+    /// This is like [`ServerCertVerifier::verify_tls13_signature`], but the public key
+    /// to use is [`DelegatedCredential::public_key()`].
+    ///
+    /// `credential` has already been validated by
+    /// [`ServerCertVerifier::verify_delegated_credential`], and rustls checks that
+    /// `dss` uses its `dc_cert_verify_algorithm`.
+    fn verify_tls13_signature_with_delegated_credential(
+        &self,
+        _message: &[u8],
+        _credential: &DelegatedCredential,
+        _dss: &DigitallySignedStruct,
+    ) -> Result<HandshakeSignatureValid, Error> {
+        Err(Error::General(
+            "delegated credentials are not supported by this verifier".into(),
+        ))
+    }
+
     /// Return true if this verifier will process stapled OCSP responses.
     ///
     /// This controls whether a client will ask the server for a stapled OCSP response.
     /// There is no guarantee the server will provide one.
     fn request_ocsp_response(&self) -> bool;
 
+    /// Return true if this verifier will process signed certificate timestamps (SCTs).
+    ///
+    /// This controls whether a client will ask the server for SCTs, and whether
+    /// [`ServerCertVerifier::verify_signed_certificate_timestamps`] is called.
+    /// There is no guarantee the server will provide any.
+    fn request_scts(&self) -> bool {
+        false
+    }
+
+    /// Verify the signed certificate timestamps (SCTs) received for `end_entity`,
+    /// as described in [RFC 6962].
+    ///
+    /// `scts` contains the version 1 SCTs from the server's `signed_certificate_timestamp`
+    /// extension, its stapled OCSP response and `end_entity` itself; see
+    /// [`SignedCertificateTimestamp::source()`].  It may be empty.
+    ///
+    /// `end_entity` and `intermediates` have already been validated by
+    /// [`ServerCertVerifier::verify_server_cert`].  This is only called if
+    /// [`ServerCertVerifier::request_scts`] returns true.  The default implementation
+    /// accepts any SCTs.
+    ///
+    /// [RFC 6962]: https://datatracker.ietf.org/doc/html/rfc6962
+    fn verify_signed_certificate_timestamps(
+        &self,
+        _end_entity: &CertificateDer<'_>,
+        _intermediates: &[CertificateDer<'_>],
+        _scts: &[SignedCertificateTimestamp],
+        _now: UnixTime,
+    ) -> Result<(), Error> {
+        Ok(())
+    }
+
     /// Returns whether this verifier requires raw public keys as defined
     /// in [RFC 7250](https://tools.ietf.org/html/rfc7250).
     fn requires_raw_public_keys(&self) -> bool {
@@ -232,6 +332,20 @@ pub trait ClientCertVerifier: Debug + Send + Sync {
         now: UnixTime,
     ) -> Result<ClientCertVerified, Error>;
 
+    /// Whether certificate chains are verified by the application, asynchronously.
+    ///
+    /// If this returns `true`, handshakes do not call [`Self::verify_client_cert()`]:
+    /// instead they pause with a [`CertificateVerification`] for the application to make.
+    /// See [`ServerCertVerifier::is_async()`] for details.
+    ///
+    /// Post-handshake client authentication and exported authenticators always call
+    /// [`Self::verify_client_cert()`].
+    ///
+    /// The default implementation returns `false`.
+    fn is_async(&self) -> bool {
+        false
+    }
+
     /// Verify a signature allegedly by the given client certificate.
     ///
     /// `message` is not hashed, and needs hashing during the verification.
@@ -283,6 +397,70 @@ pub trait ClientCertVerifier: Debug + Send + Sync {
     }
 }
 
+/// A certificate chain verification for the application to make.
+///
+/// The application should verify the chain as the connection's verifier would have in
+/// [`ServerCertVerifier::verify_server_cert()`] or [`ClientCertVerifier::verify_client_cert()`],
+/// then provide the result to the connection.
+///
+/// See [`ServerCertVerifier::is_async()`] and [`ClientCertVerifier::is_async()`].
+#[derive(Debug)]
+pub struct CertificateVerification {
+    end_entity: CertificateDer<'static>,
+    intermediates: Vec<CertificateDer<'static>>,
+    server_name: Option<ServerName<'static>>,
+    ocsp_response: Vec<u8>,
+    now: UnixTime,
+}
+
+impl CertificateVerification {
+    pub(crate) fn new(
+        end_entity: &CertificateDer<'_>,
+        intermediates: &[CertificateDer<'_>],
+        server_name: Option<ServerName<'static>>,
+        ocsp_response: Vec<u8>,
+        now: UnixTime,
+    ) -> Self {
+        Self {
+            end_entity: end_entity.clone().into_owned(),
+            intermediates: intermediates
+                .iter()
+                .map(|cert| cert.clone().into_owned())
+                .collect(),
+            server_name,
+            ocsp_response,
+            now,
+        }
+    }
+
+    /// The end-entity certificate presented by the peer.
+    pub fn end_entity(&self) -> &CertificateDer<'static> {
+        &self.end_entity
+    }
+
+    /// The other certificates presented by the peer, in the order they were sent.
+    pub fn intermediates(&self) -> &[CertificateDer<'static>] {
+        &self.intermediates
+    }
+
+    /// The name of the server, when verifying a server's certificate chain.
+    ///
+    /// This is `None` when verifying a client's certificate chain.
+    pub fn server_name(&self) -> Option<&ServerName<'static>> {
+        self.server_name.as_ref()
+    }
+
+    /// The stapled OCSP response, or empty if none was received.
+    pub fn ocsp_response(&self) -> &[u8] {
+        &self.ocsp_response
+    }
+
+    /// The time at which to verify the certificate chain.
+    pub fn now(&self) -> UnixTime {
+        self.now
+    }
+}
+
 /// Turns off client authentication.
 ///
 /// In contrast to using
@@ -655,6 +655,7 @@ mod tests {
         let all = vec![
             VerifierBuilderError::NoRootAnchors,
             VerifierBuilderError::InvalidCrl(crate::CertRevocationListError::ParseError),
+            VerifierBuilderError::InvalidCtLogId,
         ];
 
         for err in all {
@@ -0,0 +1,242 @@
+use alloc::string::String;
+use alloc::vec::Vec;
+use core::fmt;
+
+use pki_types::{CertificateDer, SubjectPublicKeyInfoDer, UnixTime};
+
+use crate::crypto::WebPkiSupportedAlgorithms;
+use crate::crypto::hash::Hash;
+use crate::error::{CertificateError, Error};
+use crate::sct::{SctSource, SignedCertificateTimestamp};
+use crate::webpki::RootCertStore;
+use crate::x509;
+
+/// A Certificate Transparency log, trusted by a [`CtPolicy`].
+#[derive(Clone, Debug)]
+pub struct CtLog {
+    id: [u8; 32],
+    key: SubjectPublicKeyInfoDer<'static>,
+    operator: String,
+}
+
+impl CtLog {
+    /// Make a new `CtLog`.
+    ///
+    /// `id` is the log's ID, the SHA-256 hash of its public `key`:
+    /// [`ServerCertVerifierBuilder::build()`] fails if it is not.  `operator`
+    /// names the organization running the log: a [`CtPolicy`] can require SCTs
+    /// from logs run by several operators.
+    ///
+    /// [`ServerCertVerifierBuilder::build()`]: crate::client::ServerCertVerifierBuilder::build
+    pub fn new(
+        id: [u8; 32],
+        key: SubjectPublicKeyInfoDer<'static>,
+        operator: impl Into<String>,
+    ) -> Self {
+        Self {
+            id,
+            key,
+            operator: operator.into(),
+        }
+    }
+}
+
+/// A Certificate Transparency policy, enforced with
+/// [`ServerCertVerifierBuilder::with_ct_policy()`].
+///
+/// A server certificate complies with the policy if valid signed certificate
+/// timestamps (SCTs) for it were received from enough distinct logs in the policy,
+/// run by enough distinct operators.  SCTs from unknown logs, and SCTs with a
+/// timestamp in the future, are ignored.
+///
+/// By default, SCTs from two logs run by two operators are required.
+///
+/// [`ServerCertVerifierBuilder::with_ct_policy()`]: crate::client::ServerCertVerifierBuilder::with_ct_policy
+#[derive(Clone, Debug)]
+pub struct CtPolicy {
+    logs: Vec<CtLog>,
+    min_scts: usize,
+    min_operators: usize,
+}
+
+impl CtPolicy {
+    /// Make a new `CtPolicy`, trusting the given `logs`.
+    pub fn new(logs: impl IntoIterator<Item = CtLog>) -> Self {
+        Self {
+            logs: logs.into_iter().collect(),
+            min_scts: 2,
+            min_operators: 2,
+        }
+    }
+
+    /// Require valid SCTs from at least `min_scts` distinct logs.
+    pub fn with_min_scts(mut self, min_scts: usize) -> Self {
+        self.min_scts = min_scts;
+        self
+    }
+
+    /// Require valid SCTs from logs run by at least `min_operators` distinct operators.
+    pub fn with_min_operators(mut self, min_operators: usize) -> Self {
+        self.min_operators = min_operators;
+        self
+    }
+}
+
+/// An optional [`CtPolicy`], and the SHA-256 implementation used to enforce it.
+#[derive(Clone, Default)]
+pub(crate) struct CtVerifier {
+    pub(crate) policy: Option<CtPolicy>,
+    sha256: Option<&'static dyn Hash>,
+}
+
+impl CtVerifier {
+    pub(crate) fn new(sha256: Option<&'static dyn Hash>) -> Self {
+        Self {
+            policy: None,
+            sha256,
+        }
+    }
+
+    /// Whether each log in the policy, if there is one, has the SHA-256 hash of its
+    /// key as its ID.
+    pub(crate) fn log_ids_match_keys(&self) -> bool {
+        let Some(policy) = &self.policy else {
+            return true;
+        };
+        let Some(sha256) = self.sha256 else {
+            return false;
+        };
+
+        policy
+            .logs
+            .iter()
+            .all(|log| sha256.hash(log.key.as_ref()).as_ref() == log.id)
+    }
+
+    /// Check `scts` comply with the policy, if there is one.
+    ///
+    /// `end_entity` and `intermediates` must already have been validated against `roots`.
+    pub(crate) fn verify(
+        &self,
+        end_entity: &CertificateDer<'_>,
+        intermediates: &[CertificateDer<'_>],
+        roots: &RootCertStore,
+        scts: &[SignedCertificateTimestamp],
+        now: UnixTime,
+        supported: &WebPkiSupportedAlgorithms,
+    ) -> Result<(), Error> {
+        let Some(policy) = &self.policy else {
+            return Ok(());
+        };
+
+        let now_ms = now.as_secs().saturating_mul(1000);
+        let issuer_key_hashes = self.issuer_key_hashes(end_entity, intermediates, roots);
+        let mut logs = Vec::<&CtLog>::new();
+        for sct in scts {
+            let Some(log) = policy
+                .logs
+                .iter()
+                .find(|log| &log.id == sct.log_id())
+            else {
+                continue;
+            };
+
+            if sct.timestamp() > now_ms
+                || logs
+                    .iter()
+                    .any(|seen| seen.id == log.id)
+            {
+                continue;
+            }
+
+            let valid = match sct.source() {
+                SctSource::Certificate => issuer_key_hashes
+                    .iter()
+                    .any(|hash| verify_sct(sct, log, end_entity, hash, supported)),
+                _ => verify_sct(sct, log, end_entity, &[0; 32], supported),
+            };
+            if valid {
+                logs.push(log);
+            }
+        }
+
+        let mut operators = Vec::<&str>::new();
+        for log in &logs {
+            if !operators.contains(&log.operator.as_str()) {
+                operators.push(&log.operator);
+            }
+        }
+
+        match logs.len() >= policy.min_scts && operators.len() >= policy.min_operators {
+            true => Ok(()),
+            false => Err(CertificateError::CertificateTransparencyRequired.into()),
+        }
+    }
+
+    /// The SHA-256 hashes of the public keys that may have issued `end_entity`.
+    ///
+    /// These are needed to verify SCTs embedded in `end_entity`, which were issued
+    /// for its precertificate.
+    fn issuer_key_hashes(
+        &self,
+        end_entity: &CertificateDer<'_>,
+        intermediates: &[CertificateDer<'_>],
+        roots: &RootCertStore,
+    ) -> Vec<[u8; 32]> {
+        let (Some(sha256), Some(tbs)) = (self.sha256, x509::TbsCertificate::from_cert(end_entity))
+        else {
+            return Vec::new();
+        };
+
+        let intermediate_keys = intermediates
+            .iter()
+            .filter_map(|cert| x509::TbsCertificate::from_cert(cert))
+            .filter(|cert| cert.subject == tbs.issuer)
+            .map(|cert| cert.subject_public_key_info.to_vec());
+        let root_keys = roots
+            .roots
+            .iter()
+            .filter(|anchor| anchor.subject.as_ref() == tbs.issuer)
+            .map(|anchor| x509::wrap_in_sequence(anchor.subject_public_key_info.as_ref()));
+
+        intermediate_keys
+            .chain(root_keys)
+            .filter_map(|spki| {
+                sha256
+                    .hash(&spki)
+                    .as_ref()
+                    .try_into()
+                    .ok()
+            })
+            .collect()
+    }
+}
+
+impl fmt::Debug for CtVerifier {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("CtVerifier")
+            .field("policy", &self.policy)
+            .finish_non_exhaustive()
+    }
+}
+
+fn verify_sct(
+    sct: &SignedCertificateTimestamp,
+    log: &CtLog,
+    end_entity: &CertificateDer<'_>,
+    issuer_key_hash: &[u8; 32],
+    supported: &WebPkiSupportedAlgorithms,
+) -> bool {
+    let (Ok(message), Ok(key), Ok(algs)) = (
+        sct.signed_message(end_entity, issuer_key_hash),
+        webpki::RawPublicKeyEntity::try_from(&log.key),
+        supported.convert_scheme(sct.signature().scheme),
+    ) else {
+        return false;
+    };
+
+    algs.iter().any(|alg| {
+        key.verify_signature(*alg, &message, sct.signature().signature())
+            .is_ok()
+    })
+}
@@ -12,11 +12,14 @@ use crate::sync::Arc;
 
 mod anchors;
 mod client_verifier;
+mod ct;
+mod ocsp;
 mod server_verifier;
 mod verify;
 
 pub use anchors::RootCertStore;
 pub use client_verifier::{ClientCertVerifierBuilder, WebPkiClientVerifier};
+pub use ct::{CtLog, CtPolicy};
 pub use server_verifier::{ServerCertVerifierBuilder, WebPkiServerVerifier};
 // Conditionally exported from crate.
 #[allow(unreachable_pub)]
@@ -24,8 +27,8 @@ pub use verify::{
     ParsedCertificate, verify_server_cert_signed_by_trust_anchor, verify_server_name,
 };
 pub use verify::{
-    WebPkiSupportedAlgorithms, verify_tls12_signature, verify_tls13_signature,
-    verify_tls13_signature_with_raw_key,
+    WebPkiSupportedAlgorithms, verify_delegated_credential, verify_tls12_signature,
+    verify_tls13_signature, verify_tls13_signature_with_raw_key,
 };
 
 /// An error that can occur when building a certificate verifier.
@@ -36,6 +39,9 @@ pub enum VerifierBuilderError {
     NoRootAnchors,
     /// A provided CRL could not be parsed.
     InvalidCrl(CertRevocationListError),
+    /// A Certificate Transparency log's ID is not the SHA-256 hash of its key, or
+    /// SHA-256 is not available to check it.
+    InvalidCtLogId,
 }
 
 impl From<CertRevocationListError> for VerifierBuilderError {
@@ -49,6 +55,7 @@ impl fmt::Display for VerifierBuilderError {
         match self {
             Self::NoRootAnchors => write!(f, "no root trust anchors were provided"),
             Self::InvalidCrl(e) => write!(f, "provided CRL could not be parsed: {e:?}"),
+            Self::InvalidCtLogId => write!(f, "a CT log ID does not match the log's key"),
         }
     }
 }
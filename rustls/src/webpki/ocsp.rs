@@ -0,0 +1,249 @@
+use alloc::vec::Vec;
+use core::time::Duration;
+use core::{fmt, slice};
+
+use pki_types::{CertificateDer, SubjectPublicKeyInfoDer, UnixTime};
+use webpki::UnknownStatusPolicy;
+
+use super::pki_error;
+use crate::crypto::WebPkiSupportedAlgorithms;
+use crate::crypto::hash::{Hash, HashAlgorithm};
+use crate::error::{CertificateError, Error};
+use crate::x509::{self, OcspCertStatus, OcspResponse};
+
+/// How a `WebPkiServerVerifier` treats stapled OCSP responses.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) enum StapledOcspPolicy {
+    /// Stapled OCSP responses are not requested, and ignored.
+    Ignore,
+    /// Stapled OCSP responses are requested, and checked if provided.
+    Check,
+    /// As for `Check`, but a response must be provided for certificates
+    /// with the OCSP Must-Staple TLS feature.
+    RequireForMustStaple,
+}
+
+impl StapledOcspPolicy {
+    /// Check the stapled `ocsp_response` for the end-entity certificate of `path`.
+    ///
+    /// `ocsp_response` is empty if none was provided.
+    pub(crate) fn verify(
+        self,
+        path: &webpki::VerifiedPath<'_>,
+        ocsp_response: &[u8],
+        now: UnixTime,
+        max_age: Duration,
+        unknown_status_policy: UnknownStatusPolicy,
+        supported: &WebPkiSupportedAlgorithms,
+        cert_id_hashes: &CertIdHashes,
+    ) -> Result<(), Error> {
+        match self {
+            Self::Ignore => Ok(()),
+            _ if !ocsp_response.is_empty() => verify_ocsp_response(
+                path,
+                ocsp_response,
+                now,
+                max_age,
+                unknown_status_policy,
+                supported,
+                cert_id_hashes,
+            ),
+            Self::RequireForMustStaple
+                if x509::TbsCertificate::from_cert(&path.end_entity().der())
+                    .is_some_and(|tbs| tbs.must_staple()) =>
+            {
+                Err(CertificateError::OcspResponseRequired.into())
+            }
+            _ => Ok(()),
+        }
+    }
+}
+
+/// Verify the OCSP `response` for the end-entity certificate of `path`, as described
+/// in [RFC 6960].
+///
+/// The response must be signed by the issuer of the end-entity certificate, or by a
+/// responder certificate included in the response that the issuer authorized for OCSP
+/// signing.  The end-entity certificate's status is found by its `CertID`: its serial
+/// number, and the hashes of its issuer's name and public key.  The status must be
+/// current at `now`: responses without a nextUpdate time are current for `max_age`
+/// after their thisUpdate time.
+///
+/// [RFC 6960]: https://datatracker.ietf.org/doc/html/rfc6960
+fn verify_ocsp_response(
+    path: &webpki::VerifiedPath<'_>,
+    response: &[u8],
+    now: UnixTime,
+    max_age: Duration,
+    unknown_status_policy: UnknownStatusPolicy,
+    supported: &WebPkiSupportedAlgorithms,
+    cert_id_hashes: &CertIdHashes,
+) -> Result<(), Error> {
+    let response = OcspResponse::from_der(response).ok_or(CertificateError::InvalidOcspResponse)?;
+
+    // The end-entity certificate is issued by the first intermediate, or the trust anchor.
+    let issuer_der;
+    let (issuer_spki, issuer) = match path.intermediate_certificates().next() {
+        Some(cert) => {
+            issuer_der = cert.der();
+            (
+                cert.subject_public_key_info(),
+                webpki::anchor_from_trusted_cert(&issuer_der).map_err(pki_error)?,
+            )
+        }
+        None => {
+            let anchor = path.anchor();
+            (
+                SubjectPublicKeyInfoDer::from(x509::wrap_in_sequence(
+                    &anchor.subject_public_key_info,
+                )),
+                anchor.clone(),
+            )
+        }
+    };
+
+    let signed_by = |spki: &SubjectPublicKeyInfoDer<'_>| {
+        webpki::RawPublicKeyEntity::try_from(spki).is_ok_and(|key| {
+            supported
+                .all
+                .iter()
+                .filter(|alg| alg.signature_alg_id().as_ref() == response.signature_algorithm)
+                .any(|alg| {
+                    key.verify_signature(*alg, response.tbs_response_data, response.signature)
+                        .is_ok()
+                })
+        })
+    };
+
+    let signed = signed_by(&issuer_spki)
+        || response.certs().any(|responder| {
+            let responder = CertificateDer::from(responder);
+            let Ok(responder) = webpki::EndEntityCert::try_from(&responder) else {
+                return false;
+            };
+
+            // "OCSP signing delegation SHALL be designated by the inclusion of
+            // id-kp-OCSPSigning in an extended key usage certificate extension
+            // included in the OCSP response signer's certificate" - RFC 6960 section 4.2.2.2
+            responder
+                .verify_for_usage(
+                    supported.all,
+                    slice::from_ref(&issuer),
+                    &[],
+                    now,
+                    webpki::KeyUsage::required(OCSP_SIGNING_OID),
+                    None,
+                    None,
+                )
+                .is_ok()
+                && signed_by(&responder.subject_public_key_info())
+        });
+    if !signed {
+        return Err(CertificateError::InvalidOcspResponse.into());
+    }
+
+    // "The issuerNameHash is the hash of the issuer's distinguished name ... The
+    // issuerKeyHash is the hash of the issuer's public key." - RFC 6960 section 4.1.1
+    let issuer_name = x509::TbsCertificate::from_cert(&path.end_entity().der())
+        .map(|tbs| x509::wrap_in_sequence(tbs.issuer))
+        .ok_or(CertificateError::InvalidOcspResponse)?;
+    let issuer_key =
+        x509::subject_public_key(&issuer_spki).ok_or(CertificateError::InvalidOcspResponse)?;
+
+    let single = response
+        .responses()
+        .find(|single| {
+            single.serial == path.end_entity().serial()
+                && cert_id_hashes.matches(single, &issuer_name, issuer_key)
+        })
+        .ok_or(CertificateError::InvalidOcspResponse)?;
+
+    let now = now.as_secs();
+    let next_update = single.next_update.unwrap_or_else(|| {
+        single
+            .this_update
+            .saturating_add(max_age.as_secs())
+    });
+    if single.this_update > now.saturating_add(OCSP_CLOCK_SKEW.as_secs()) || next_update < now {
+        return Err(CertificateError::InvalidOcspResponse.into());
+    }
+
+    match (single.status, unknown_status_policy) {
+        (OcspCertStatus::Good, _) | (OcspCertStatus::Unknown, UnknownStatusPolicy::Allow) => Ok(()),
+        (OcspCertStatus::Revoked, _) => Err(CertificateError::Revoked.into()),
+        (OcspCertStatus::Unknown, _) => Err(CertificateError::UnknownRevocationStatus.into()),
+    }
+}
+
+/// The hash functions available to match the `CertID` of OCSP responses.
+///
+/// A `CertID` using any other hash function cannot be matched, except SHA-1.
+#[derive(Clone, Default)]
+pub(crate) struct CertIdHashes(pub(crate) Vec<&'static dyn Hash>);
+
+impl CertIdHashes {
+    /// Whether `single` is for a certificate issued by the issuer with the DER-encoded
+    /// name `issuer_name` and public key `issuer_key`.
+    fn matches(
+        &self,
+        single: &x509::SingleResponse<'_>,
+        issuer_name: &[u8],
+        issuer_key: &[u8],
+    ) -> bool {
+        let algorithm = match single.hash_algorithm {
+            SHA1_OID => HashAlgorithm::SHA1,
+            SHA256_OID => HashAlgorithm::SHA256,
+            SHA384_OID => HashAlgorithm::SHA384,
+            SHA512_OID => HashAlgorithm::SHA512,
+            _ => return false,
+        };
+        let Some(hash) = self
+            .0
+            .iter()
+            .find(|hash| hash.algorithm() == algorithm)
+        else {
+            // Most responders use SHA-1 `CertID`s (RFC 5019 section 2.1.1), but SHA-1 is
+            // rarely available.  The `CertID` only identifies the certificate, and the
+            // response is already verified to be signed for its issuer, so this leaves
+            // the serial number to match.
+            return algorithm == HashAlgorithm::SHA1;
+        };
+
+        hash.hash(issuer_name).as_ref() == single.issuer_name_hash
+            && hash.hash(issuer_key).as_ref() == single.issuer_key_hash
+    }
+}
+
+impl fmt::Debug for CertIdHashes {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_list()
+            .entries(
+                self.0
+                    .iter()
+                    .map(|hash| hash.algorithm()),
+            )
+            .finish()
+    }
+}
+
+/// How long a response without a nextUpdate time is current for, by default.
+pub(crate) const DEFAULT_OCSP_MAX_AGE: Duration = Duration::from_secs(7 * 24 * 60 * 60);
+
+/// How far in the future a response's thisUpdate time may be, to allow for clock skew
+/// between the responder and us.
+const OCSP_CLOCK_SKEW: Duration = Duration::from_secs(5 * 60);
+
+/// id-kp-OCSPSigning: 1.3.6.1.5.5.7.3.9
+const OCSP_SIGNING_OID: &[u8] = &[0x2b, 0x06, 0x01, 0x05, 0x05, 0x07, 0x03, 0x09];
+
+/// id-sha1: 1.3.14.3.2.26
+const SHA1_OID: &[u8] = &[0x2b, 0x0e, 0x03, 0x02, 0x1a];
+
+/// id-sha256: 2.16.840.1.101.3.4.2.1
+const SHA256_OID: &[u8] = &[0x60, 0x86, 0x48, 0x01, 0x65, 0x03, 0x04, 0x02, 0x01];
+
+/// id-sha384: 2.16.840.1.101.3.4.2.2
+const SHA384_OID: &[u8] = &[0x60, 0x86, 0x48, 0x01, 0x65, 0x03, 0x04, 0x02, 0x02];
+
+/// id-sha512: 2.16.840.1.101.3.4.2.3
+const SHA512_OID: &[u8] = &[0x60, 0x86, 0x48, 0x01, 0x65, 0x03, 0x04, 0x02, 0x03];
@@ -1,16 +1,22 @@
 use alloc::vec::Vec;
+use core::time::Duration;
 
 use pki_types::{CertificateDer, CertificateRevocationListDer, ServerName, UnixTime};
 use webpki::{CertRevocationList, ExpirationPolicy, RevocationCheckDepth, UnknownStatusPolicy};
 
+use crate::crypto::hash::{Hash, HashAlgorithm};
 use crate::crypto::{CryptoProvider, WebPkiSupportedAlgorithms};
+use crate::delegated_credential::DelegatedCredential;
+use crate::sct::SignedCertificateTimestamp;
 use crate::sync::Arc;
 use crate::verify::{
     DigitallySignedStruct, HandshakeSignatureValid, ServerCertVerified, ServerCertVerifier,
 };
+use crate::webpki::ct::{CtPolicy, CtVerifier};
+use crate::webpki::ocsp::{CertIdHashes, DEFAULT_OCSP_MAX_AGE, StapledOcspPolicy};
 use crate::webpki::verify::{
-    ParsedCertificate, verify_server_cert_signed_by_trust_anchor_impl, verify_tls12_signature,
-    verify_tls13_signature,
+    ParsedCertificate, verify_delegated_credential, verify_server_cert_signed_by_trust_anchor_impl,
+    verify_tls12_signature, verify_tls13_signature, verify_tls13_signature_with_raw_key,
 };
 use crate::webpki::{VerifierBuilderError, parse_crls, verify_server_name};
 #[cfg(doc)]
@@ -28,13 +34,22 @@ pub struct ServerCertVerifierBuilder {
     unknown_revocation_policy: UnknownStatusPolicy,
     revocation_expiration_policy: ExpirationPolicy,
     supported_algs: WebPkiSupportedAlgorithms,
+    stapled_ocsp_policy: StapledOcspPolicy,
+    ocsp_max_age: Duration,
+    ocsp_hashes: CertIdHashes,
+    ct: CtVerifier,
 }
 
 impl ServerCertVerifierBuilder {
     pub(crate) fn new(
         roots: Arc<RootCertStore>,
         supported_algs: WebPkiSupportedAlgorithms,
+        hashes: Vec<&'static dyn Hash>,
     ) -> Self {
+        let sha256 = hashes
+            .iter()
+            .copied()
+            .find(|hash| hash.algorithm() == HashAlgorithm::SHA256);
         Self {
             roots,
             crls: Vec::new(),
@@ -42,6 +57,10 @@ impl ServerCertVerifierBuilder {
             unknown_revocation_policy: UnknownStatusPolicy::Deny,
             revocation_expiration_policy: ExpirationPolicy::Ignore,
             supported_algs,
+            stapled_ocsp_policy: StapledOcspPolicy::Ignore,
+            ocsp_max_age: DEFAULT_OCSP_MAX_AGE,
+            ocsp_hashes: CertIdHashes(hashes),
+            ct: CtVerifier::new(sha256),
         }
     }
 
@@ -70,14 +89,16 @@ impl ServerCertVerifierBuilder {
         self
     }
 
-    /// Allow unknown certificate revocation status when using CRLs.
+    /// Allow unknown certificate revocation status when using CRLs or stapled OCSP responses.
     ///
     /// If CRLs are provided with [`with_crls`][Self::with_crls] and it isn't possible to
     /// determine the revocation status of a certificate, do not treat it as an error condition.
+    /// Likewise, if [`check_stapled_ocsp_responses`][Self::check_stapled_ocsp_responses] is
+    /// used, accept a stapled OCSP response that reports an "unknown" status.
     /// Overrides the default behavior where unknown revocation status is considered an error.
     ///
-    /// If no CRLs are provided then this setting has no effect as revocation status checks
-    /// are not performed.
+    /// If no CRLs are provided and stapled OCSP responses are not checked then this setting
+    /// has no effect as revocation status checks are not performed.
     pub fn allow_unknown_revocation_status(mut self) -> Self {
         self.unknown_revocation_policy = UnknownStatusPolicy::Allow;
         self
@@ -96,6 +117,77 @@ impl ServerCertVerifierBuilder {
         self
     }
 
+    /// Check the revocation status of the server's end entity certificate using a stapled
+    /// OCSP response, if the server provides one.
+    ///
+    /// The built verifier asks servers for a stapled OCSP response.  If one is received, it
+    /// must be a valid [RFC 6960] response for the end entity certificate: signed by its
+    /// issuer, or by a responder certificate the issuer authorized for OCSP signing, and
+    /// current at the verification time.  Otherwise verification fails with
+    /// [`CertificateError::InvalidOcspResponse`].  A "revoked" status fails verification
+    /// with [`CertificateError::Revoked`].
+    ///
+    /// The response's `CertID` for the end entity certificate is matched by its serial
+    /// number, and the hashes of its issuer's name and public key.  These hashes are
+    /// computed with the hash functions of the [`crypto::CryptoProvider`]'s cipher suites.
+    /// A SHA-1 `CertID`, as used by most responders, is matched by its serial number alone:
+    /// the response is already known to be signed for the issuer.  A `CertID` using
+    /// any other hash function is not matched.
+    ///
+    /// Servers that do not provide a stapled OCSP response are accepted, unless
+    /// [`enforce_ocsp_must_staple`][Self::enforce_ocsp_must_staple] is used.
+    ///
+    /// [RFC 6960]: https://datatracker.ietf.org/doc/html/rfc6960
+    /// [`CertificateError::InvalidOcspResponse`]: crate::CertificateError::InvalidOcspResponse
+    /// [`CertificateError::Revoked`]: crate::CertificateError::Revoked
+    pub fn check_stapled_ocsp_responses(mut self) -> Self {
+        if self.stapled_ocsp_policy == StapledOcspPolicy::Ignore {
+            self.stapled_ocsp_policy = StapledOcspPolicy::Check;
+        }
+        self
+    }
+
+    /// Require a stapled OCSP response for end entity certificates with the OCSP
+    /// Must-Staple TLS feature ([RFC 7633]).
+    ///
+    /// Verification of such a certificate fails with
+    /// [`CertificateError::OcspResponseRequired`] if the server does not provide a stapled
+    /// OCSP response.  This implies [`check_stapled_ocsp_responses`][Self::check_stapled_ocsp_responses].
+    ///
+    /// [RFC 7633]: https://datatracker.ietf.org/doc/html/rfc7633
+    /// [`CertificateError::OcspResponseRequired`]: crate::CertificateError::OcspResponseRequired
+    pub fn enforce_ocsp_must_staple(mut self) -> Self {
+        self.stapled_ocsp_policy = StapledOcspPolicy::RequireForMustStaple;
+        self
+    }
+
+    /// Accept stapled OCSP responses that have no nextUpdate time for `max_age` after
+    /// their thisUpdate time.
+    ///
+    /// Such responses otherwise fail verification with
+    /// [`CertificateError::InvalidOcspResponse`] once they are seven days old.
+    ///
+    /// [`CertificateError::InvalidOcspResponse`]: crate::CertificateError::InvalidOcspResponse
+    pub fn with_ocsp_max_age(mut self, max_age: Duration) -> Self {
+        self.ocsp_max_age = max_age;
+        self
+    }
+
+    /// Require server certificates to comply with a Certificate Transparency `policy`.
+    ///
+    /// The built verifier asks servers for signed certificate timestamps (SCTs), and
+    /// fails verification with [`CertificateError::CertificateTransparencyRequired`]
+    /// unless the SCTs received with the server's certificate satisfy `policy`.
+    ///
+    /// Verifying SCTs embedded in certificates needs SHA-256, which is taken from
+    /// the cipher suites of the [`crypto::CryptoProvider`] used.
+    ///
+    /// [`CertificateError::CertificateTransparencyRequired`]: crate::CertificateError::CertificateTransparencyRequired
+    pub fn with_ct_policy(mut self, policy: CtPolicy) -> Self {
+        self.ct.policy = Some(policy);
+        self
+    }
+
     /// Build a server certificate verifier, allowing control over the root certificates to use as
     /// trust anchors, and to control how server certificate revocation checking is performed.
     ///
@@ -110,11 +202,17 @@ impl ServerCertVerifierBuilder {
     /// This function will return a [`VerifierBuilderError`] if:
     /// 1. No trust anchors have been provided.
     /// 2. DER encoded CRLs have been provided that can not be parsed successfully.
+    /// 3. A log given to [`with_ct_policy`][Self::with_ct_policy] has an ID that is not
+    ///    the SHA-256 hash of its key.
     pub fn build(self) -> Result<Arc<WebPkiServerVerifier>, VerifierBuilderError> {
         if self.roots.is_empty() {
             return Err(VerifierBuilderError::NoRootAnchors);
         }
 
+        if !self.ct.log_ids_match_keys() {
+            return Err(VerifierBuilderError::InvalidCtLogId);
+        }
+
         Ok(WebPkiServerVerifier::new(
             self.roots,
             parse_crls(self.crls)?,
@@ -122,6 +220,10 @@ impl ServerCertVerifierBuilder {
             self.unknown_revocation_policy,
             self.revocation_expiration_policy,
             self.supported_algs,
+            self.stapled_ocsp_policy,
+            self.ocsp_max_age,
+            self.ocsp_hashes,
+            self.ct,
         )
         .into())
     }
@@ -137,6 +239,10 @@ pub struct WebPkiServerVerifier {
     unknown_revocation_policy: UnknownStatusPolicy,
     revocation_expiration_policy: ExpirationPolicy,
     supported: WebPkiSupportedAlgorithms,
+    stapled_ocsp_policy: StapledOcspPolicy,
+    ocsp_max_age: Duration,
+    ocsp_hashes: CertIdHashes,
+    ct: CtVerifier,
 }
 
 #[allow(unreachable_pub)]
@@ -168,7 +274,20 @@ impl WebPkiServerVerifier {
         roots: Arc<RootCertStore>,
         provider: Arc<CryptoProvider>,
     ) -> ServerCertVerifierBuilder {
-        ServerCertVerifierBuilder::new(roots, provider.signature_verification_algorithms)
+        let mut hashes = Vec::<&'static dyn Hash>::new();
+        for hash in provider
+            .cipher_suites
+            .iter()
+            .map(|suite| suite.hash_provider())
+        {
+            if !hashes
+                .iter()
+                .any(|seen| seen.algorithm() == hash.algorithm())
+            {
+                hashes.push(hash);
+            }
+        }
+        ServerCertVerifierBuilder::new(roots, provider.signature_verification_algorithms, hashes)
     }
 
     /// Short-cut for creating a `WebPkiServerVerifier` that does not perform certificate revocation
@@ -184,6 +303,10 @@ impl WebPkiServerVerifier {
             UnknownStatusPolicy::Allow,
             ExpirationPolicy::Ignore,
             supported_algs,
+            StapledOcspPolicy::Ignore,
+            DEFAULT_OCSP_MAX_AGE,
+            CertIdHashes::default(),
+            CtVerifier::default(),
         )
     }
 
@@ -198,6 +321,10 @@ impl WebPkiServerVerifier {
     ///   are handled when `crls` are provided.
     /// * `supported` is the set of supported algorithms that will be used for
     ///   certificate verification and TLS handshake signature verification.
+    /// * `stapled_ocsp_policy` controls whether stapled OCSP responses are requested and checked.
+    /// * `ocsp_max_age` is how long stapled OCSP responses without a nextUpdate time are current.
+    /// * `ocsp_hashes` are the hash functions available to match stapled OCSP responses.
+    /// * `ct` is the Certificate Transparency policy to enforce, if any.
     pub(crate) fn new(
         roots: impl Into<Arc<RootCertStore>>,
         crls: Vec<CertRevocationList<'static>>,
@@ -205,6 +332,10 @@ impl WebPkiServerVerifier {
         unknown_revocation_policy: UnknownStatusPolicy,
         revocation_expiration_policy: ExpirationPolicy,
         supported: WebPkiSupportedAlgorithms,
+        stapled_ocsp_policy: StapledOcspPolicy,
+        ocsp_max_age: Duration,
+        ocsp_hashes: CertIdHashes,
+        ct: CtVerifier,
     ) -> Self {
         Self {
             roots: roots.into(),
@@ -213,6 +344,10 @@ impl WebPkiServerVerifier {
             unknown_revocation_policy,
             revocation_expiration_policy,
             supported,
+            stapled_ocsp_policy,
+            ocsp_max_age,
+            ocsp_hashes,
+            ct,
         }
     }
 }
@@ -228,12 +363,15 @@ impl ServerCertVerifier for WebPkiServerVerifier {
     /// each certificate in the chain to a root CA (excluding the root itself), or only the
     /// end entity certificate. Similarly, unknown revocation status may be treated as an error
     /// or allowed based on configuration.
+    ///
+    /// If configured, the end entity certificate's revocation status is also checked using the
+    /// stapled `ocsp_response`.
     fn verify_server_cert(
         &self,
         end_entity: &CertificateDer<'_>,
         intermediates: &[CertificateDer<'_>],
         server_name: &ServerName<'_>,
-        _ocsp_response: &[u8],
+        ocsp_response: &[u8],
         now: UnixTime,
     ) -> Result<ServerCertVerified, Error> {
         let cert = ParsedCertificate::try_from(end_entity)?;
@@ -259,7 +397,7 @@ impl ServerCertVerifier for WebPkiServerVerifier {
 
         // Note: we use the crate-internal `_impl` fn here in order to provide revocation
         // checking information, if applicable.
-        verify_server_cert_signed_by_trust_anchor_impl(
+        let path = verify_server_cert_signed_by_trust_anchor_impl(
             &cert,
             &self.roots,
             intermediates,
@@ -269,6 +407,16 @@ impl ServerCertVerifier for WebPkiServerVerifier {
         )?;
 
         verify_server_name(&cert, server_name)?;
+
+        self.stapled_ocsp_policy.verify(
+            &path,
+            ocsp_response,
+            now,
+            self.ocsp_max_age,
+            self.unknown_revocation_policy,
+            &self.supported,
+            &self.ocsp_hashes,
+        )?;
         Ok(ServerCertVerified::assertion())
     }
 
@@ -294,8 +442,51 @@ impl ServerCertVerifier for WebPkiServerVerifier {
         self.supported.supported_schemes()
     }
 
+    /// Will verify the delegated credential in the ways described by
+    /// [`verify_delegated_credential`].
+    fn verify_delegated_credential(
+        &self,
+        end_entity: &CertificateDer<'_>,
+        credential: &DelegatedCredential,
+        now: UnixTime,
+    ) -> Result<HandshakeSignatureValid, Error> {
+        verify_delegated_credential(end_entity, credential, now, &self.supported)
+    }
+
+    fn verify_tls13_signature_with_delegated_credential(
+        &self,
+        message: &[u8],
+        credential: &DelegatedCredential,
+        dss: &DigitallySignedStruct,
+    ) -> Result<HandshakeSignatureValid, Error> {
+        verify_tls13_signature_with_raw_key(message, &credential.public_key(), dss, &self.supported)
+    }
+
     fn request_ocsp_response(&self) -> bool {
-        false
+        self.stapled_ocsp_policy != StapledOcspPolicy::Ignore
+    }
+
+    fn request_scts(&self) -> bool {
+        self.ct.policy.is_some()
+    }
+
+    /// Will enforce the [`CtPolicy`] given to [`ServerCertVerifierBuilder::with_ct_policy`],
+    /// if any.
+    fn verify_signed_certificate_timestamps(
+        &self,
+        end_entity: &CertificateDer<'_>,
+        intermediates: &[CertificateDer<'_>],
+        scts: &[SignedCertificateTimestamp],
+        now: UnixTime,
+    ) -> Result<(), Error> {
+        self.ct.verify(
+            end_entity,
+            intermediates,
+            &self.roots,
+            scts,
+            now,
+            &self.supported,
+        )
     }
 }
 
@@ -1,5 +1,6 @@
 use alloc::vec::Vec;
 use core::fmt;
+use core::time::Duration;
 
 use pki_types::{
     CertificateDer, ServerName, SignatureVerificationAlgorithm, SubjectPublicKeyInfoDer, UnixTime,
@@ -7,9 +8,11 @@ use pki_types::{
 
 use super::anchors::RootCertStore;
 use super::pki_error;
+use crate::delegated_credential::DelegatedCredential;
 use crate::enums::SignatureScheme;
-use crate::error::{Error, PeerMisbehaved};
+use crate::error::{CertificateError, Error, PeerMisbehaved};
 use crate::verify::{DigitallySignedStruct, HandshakeSignatureValid};
+use crate::x509::DelegationDetails;
 
 /// Verify that the end-entity certificate `end_entity` is a valid server cert
 /// and chains to at least one of the trust anchors in the `roots` [RootCertStore].
@@ -38,6 +41,7 @@ pub fn verify_server_cert_signed_by_trust_anchor(
         now,
         supported_algs,
     )
+    .map(|_| ())
 }
 
 /// Verify that the `end_entity` has an alternative name matching the `server_name`.
@@ -92,7 +96,7 @@ impl WebPkiSupportedAlgorithms {
     }
 
     /// Return the first item in `mapping` that matches `scheme`.
-    fn convert_scheme(
+    pub(super) fn convert_scheme(
         &self,
         scheme: SignatureScheme,
     ) -> Result<&[&'static dyn SignatureVerificationAlgorithm], Error> {
@@ -225,6 +229,53 @@ pub fn verify_tls13_signature_with_raw_key(
         .map(|_| HandshakeSignatureValid::assertion())
 }
 
+/// Verify a delegated credential sent with the end-entity certificate `end_entity`,
+/// as described in [RFC 9345 section 4.2].
+///
+/// This checks that `end_entity` has the `DelegationUsage` extension and the
+/// `digitalSignature` key usage, that the credential is valid at `now` and expires
+/// no more than seven days later, and the signature of `end_entity`'s key over the
+/// credential.  `end_entity` must already have been validated.
+///
+/// [RFC 9345 section 4.2]: https://datatracker.ietf.org/doc/html/rfc9345#section-4.2
+pub fn verify_delegated_credential(
+    end_entity: &CertificateDer<'_>,
+    credential: &DelegatedCredential,
+    now: UnixTime,
+    supported_schemes: &WebPkiSupportedAlgorithms,
+) -> Result<HandshakeSignatureValid, Error> {
+    let details = DelegationDetails::from_cert(end_entity)
+        .ok_or(Error::InvalidCertificate(CertificateError::BadEncoding))?;
+    if !details.delegation_usage || !details.digital_signature {
+        return Err(PeerMisbehaved::InvalidDelegatedCredential.into());
+    }
+
+    let expiry = details
+        .not_before
+        .saturating_add(u64::from(credential.valid_time()));
+    if now.as_secs() >= expiry {
+        return Err(CertificateError::ExpiredContext {
+            time: now,
+            not_after: UnixTime::since_unix_epoch(Duration::from_secs(expiry)),
+        }
+        .into());
+    }
+
+    if expiry - now.as_secs() > MAX_DELEGATED_CREDENTIAL_VALIDITY {
+        return Err(PeerMisbehaved::InvalidDelegatedCredential.into());
+    }
+
+    verify_tls13_signature(
+        &credential.signed_message(end_entity),
+        end_entity,
+        &DigitallySignedStruct::new(credential.algorithm(), credential.signature().to_vec()),
+        supported_schemes,
+    )
+}
+
+/// The longest a delegated credential may remain valid: seven days (RFC 9345 section 4.2).
+const MAX_DELEGATED_CREDENTIAL_VALIDITY: u64 = 7 * 24 * 60 * 60;
+
 /// Verify that the end-entity certificate `end_entity` is a valid server cert
 /// and chains to at least one of the trust anchors in the `roots` [RootCertStore].
 ///
@@ -238,27 +289,25 @@ pub fn verify_tls13_signature_with_raw_key(
 /// and differs only in providing a `Option<webpki::RevocationOptions>` argument. We
 /// can't include this argument in `verify_server_cert_signed_by_trust_anchor` because
 /// it will leak the webpki types into Rustls' public API.
-pub(crate) fn verify_server_cert_signed_by_trust_anchor_impl(
-    cert: &ParsedCertificate<'_>,
-    roots: &RootCertStore,
-    intermediates: &[CertificateDer<'_>],
+pub(crate) fn verify_server_cert_signed_by_trust_anchor_impl<'p>(
+    cert: &'p ParsedCertificate<'p>,
+    roots: &'p RootCertStore,
+    intermediates: &'p [CertificateDer<'p>],
     revocation: Option<webpki::RevocationOptions<'_>>,
     now: UnixTime,
     supported_algs: &[&dyn SignatureVerificationAlgorithm],
-) -> Result<(), Error> {
-    let result = cert.0.verify_for_usage(
-        supported_algs,
-        &roots.roots,
-        intermediates,
-        now,
-        webpki::KeyUsage::server_auth(),
-        revocation,
-        None,
-    );
-    match result {
-        Ok(_) => Ok(()),
-        Err(e) => Err(pki_error(e)),
-    }
+) -> Result<webpki::VerifiedPath<'p>, Error> {
+    cert.0
+        .verify_for_usage(
+            supported_algs,
+            &roots.roots,
+            intermediates,
+            now,
+            webpki::KeyUsage::server_auth(),
+            revocation,
+            None,
+        )
+        .map_err(pki_error)
 }
 
 #[cfg(test)]
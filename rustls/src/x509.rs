@@ -1,6 +1,7 @@
 // Additional x509/asn1 functions to those provided in webpki/ring.
 
 use alloc::vec::Vec;
+use core::iter;
 
 /// Prepend stuff to `bytes` to put it in a DER SEQUENCE.
 pub(crate) fn wrap_in_sequence(bytes: &[u8]) -> Vec<u8> {
@@ -57,9 +58,537 @@ fn asn1_wrap(tag: u8, bytes_a: &[u8], bytes_b: &[u8]) -> Vec<u8> {
     }
 }
 
+/// The parts of a DER-encoded certificate's `TBSCertificate` that rustls inspects itself.
+///
+/// Certificates are validated by webpki; this is only used to look at extensions and fields
+/// that webpki does not expose.
+pub(crate) struct TbsCertificate<'a> {
+    /// The encoding of the fields before `extensions`.
+    fields: &'a [u8],
+    /// The value of the `issuer` name.
+    pub(crate) issuer: &'a [u8],
+    /// The `notBefore` time, in seconds since the UNIX epoch.
+    pub(crate) not_before: u64,
+    /// The value of the `subject` name.
+    pub(crate) subject: &'a [u8],
+    /// The encoding of the `subjectPublicKeyInfo`.
+    pub(crate) subject_public_key_info: &'a [u8],
+    /// The value of the `extensions` SEQUENCE, or empty if there are none.
+    extensions: &'a [u8],
+}
+
+impl<'a> TbsCertificate<'a> {
+    /// Extract the `TBSCertificate` from a DER-encoded certificate.
+    ///
+    /// Returns `None` if the certificate is not well-formed enough to do so.
+    pub(crate) fn from_cert(cert: &'a [u8]) -> Option<Self> {
+        // Certificate  ::=  SEQUENCE  {
+        //      tbsCertificate       TBSCertificate,
+        //      ... }
+        let (cert, _) = read_tlv(cert, DER_SEQUENCE_TAG)?;
+        let (tbs, _) = read_tlv(cert, DER_SEQUENCE_TAG)?;
+
+        // TBSCertificate  ::=  SEQUENCE  {
+        //      version         [0]  EXPLICIT Version DEFAULT v1,
+        //      serialNumber         CertificateSerialNumber,
+        //      signature            AlgorithmIdentifier,
+        //      issuer               Name,
+        //      validity             Validity,
+        //      subject              Name,
+        //      subjectPublicKeyInfo SubjectPublicKeyInfo,
+        //      issuerUniqueID  [1]  IMPLICIT UniqueIdentifier OPTIONAL,
+        //      subjectUniqueID [2]  IMPLICIT UniqueIdentifier OPTIONAL,
+        //      extensions      [3]  EXPLICIT Extensions OPTIONAL }
+        let (_, rest) = read_tlv(tbs, DER_CONTEXT_0_TAG).unwrap_or((&[], tbs));
+        let (_, rest) = read_tlv(rest, DER_INTEGER_TAG)?;
+        let (_, rest) = read_tlv(rest, DER_SEQUENCE_TAG)?;
+        let (issuer, rest) = read_tlv(rest, DER_SEQUENCE_TAG)?;
+        let (validity, rest) = read_tlv(rest, DER_SEQUENCE_TAG)?;
+        let (subject, spki) = read_tlv(rest, DER_SEQUENCE_TAG)?;
+        let (_, mut rest) = read_tlv(spki, DER_SEQUENCE_TAG)?;
+        let subject_public_key_info = &spki[..spki.len() - rest.len()];
+
+        let mut fields = tbs;
+        let mut extensions: &[u8] = &[];
+        while let Some((tag, value, remain)) = read_any(rest) {
+            if tag == DER_CONTEXT_3_TAG {
+                fields = &tbs[..tbs.len() - rest.len()];
+                (extensions, _) = read_tlv(value, DER_SEQUENCE_TAG)?;
+            }
+            rest = remain;
+        }
+
+        Some(Self {
+            fields,
+            issuer,
+            not_before: read_time(validity)?,
+            subject,
+            subject_public_key_info,
+            extensions,
+        })
+    }
+
+    /// Return the value of the extension with the given `oid`, if present.
+    pub(crate) fn extension(&self, oid: &[u8]) -> Option<&'a [u8]> {
+        Extensions(self.extensions)
+            .find(|ext| ext.oid == oid)
+            .map(|ext| ext.value)
+    }
+
+    /// Return the embedded `SignedCertificateTimestampList`, if present.
+    ///
+    /// See [RFC 6962 section 3.3](https://datatracker.ietf.org/doc/html/rfc6962#section-3.3).
+    pub(crate) fn sct_list(&self) -> Option<&'a [u8]> {
+        let (list, _) = read_tlv(self.extension(SCT_LIST_OID)?, DER_OCTET_STRING_TAG)?;
+        Some(list)
+    }
+
+    /// Whether the certificate's TLS Feature extension requires the `status_request`
+    /// extension, so that a valid OCSP response must be stapled ("OCSP Must-Staple").
+    ///
+    /// See [RFC 7633 section 4.2](https://datatracker.ietf.org/doc/html/rfc7633#section-4.2).
+    pub(crate) fn must_staple(&self) -> bool {
+        // Features ::= SEQUENCE OF INTEGER
+        let Some((mut features, _)) = self
+            .extension(TLS_FEATURE_OID)
+            .and_then(|value| read_tlv(value, DER_SEQUENCE_TAG))
+        else {
+            return false;
+        };
+
+        while let Some((feature, rest)) = read_tlv(features, DER_INTEGER_TAG) {
+            if feature == [STATUS_REQUEST_FEATURE] {
+                return true;
+            }
+            features = rest;
+        }
+        false
+    }
+
+    /// Return the encoding of the `TBSCertificate` of the precertificate this
+    /// certificate was issued from.
+    ///
+    /// That is the same as this certificate's, without the embedded SCT list
+    /// (see [RFC 6962 section 3.2](https://datatracker.ietf.org/doc/html/rfc6962#section-3.2)).
+    pub(crate) fn precertificate_tbs(&self) -> Vec<u8> {
+        let mut extensions = Vec::with_capacity(self.extensions.len());
+        for ext in Extensions(self.extensions).filter(|ext| ext.oid != SCT_LIST_OID) {
+            extensions.extend_from_slice(ext.encoding);
+        }
+
+        let extensions = asn1_wrap(DER_CONTEXT_3_TAG, &wrap_in_sequence(&extensions), &[]);
+        asn1_wrap(DER_SEQUENCE_TAG, self.fields, &extensions)
+    }
+}
+
+/// The parts of an end-entity certificate needed to validate a delegated credential.
+///
+/// See [RFC 9345 section 4.2](https://datatracker.ietf.org/doc/html/rfc9345#section-4.2).
+pub(crate) struct DelegationDetails {
+    /// The certificate's `notBefore` time, in seconds since the UNIX epoch.
+    pub(crate) not_before: u64,
+    /// Whether the certificate has the `DelegationUsage` extension.
+    pub(crate) delegation_usage: bool,
+    /// Whether the certificate has the `digitalSignature` key usage.
+    pub(crate) digital_signature: bool,
+}
+
+impl DelegationDetails {
+    /// Extract the details from a DER-encoded certificate.
+    ///
+    /// Returns `None` if the certificate is not well-formed enough to do so.
+    pub(crate) fn from_cert(cert: &[u8]) -> Option<Self> {
+        let tbs = TbsCertificate::from_cert(cert)?;
+
+        let digital_signature = match tbs.extension(KEY_USAGE_OID) {
+            // KeyUsage ::= BIT STRING { digitalSignature (0), ... }
+            Some(value) => {
+                let (bits, _) = read_tlv(value, DER_BIT_STRING_TAG)?;
+                bits.get(1)
+                    .is_some_and(|first| first & 0x80 != 0)
+            }
+            None => false,
+        };
+
+        Some(Self {
+            not_before: tbs.not_before,
+            delegation_usage: tbs
+                .extension(DELEGATION_USAGE_OID)
+                .is_some(),
+            digital_signature,
+        })
+    }
+}
+
+/// The parts of a DER-encoded OCSP response that rustls uses to validate it.
+///
+/// See [RFC 6960 section 4.2.1](https://datatracker.ietf.org/doc/html/rfc6960#section-4.2.1).
+pub(crate) struct OcspResponse<'a> {
+    /// The encoding of the signed `tbsResponseData`.
+    pub(crate) tbs_response_data: &'a [u8],
+    /// The value of the `signatureAlgorithm` AlgorithmIdentifier.
+    pub(crate) signature_algorithm: &'a [u8],
+    /// The signature over `tbs_response_data`.
+    pub(crate) signature: &'a [u8],
+    /// The contents of the `responses` SEQUENCE.
+    responses: &'a [u8],
+    /// The contents of the `certs` SEQUENCE, or empty if there are none.
+    certs: &'a [u8],
+}
+
+impl<'a> OcspResponse<'a> {
+    /// Parse a successful `OCSPResponse` of the basic response type.
+    ///
+    /// Returns `None` if the response is malformed, unsuccessful, or of another type.
+    pub(crate) fn from_der(response: &'a [u8]) -> Option<Self> {
+        // OCSPResponse ::= SEQUENCE {
+        //      responseStatus         OCSPResponseStatus,
+        //      responseBytes      [0] EXPLICIT ResponseBytes OPTIONAL }
+        //
+        // ResponseBytes ::= SEQUENCE {
+        //      responseType   OBJECT IDENTIFIER,
+        //      response       OCTET STRING }
+        let (response, _) = read_tlv(response, DER_SEQUENCE_TAG)?;
+        let (status, rest) = read_tlv(response, DER_ENUMERATED_TAG)?;
+        if status != [OCSP_SUCCESSFUL] {
+            return None;
+        }
+        let (bytes, _) = read_tlv(rest, DER_CONTEXT_0_TAG)?;
+        let (bytes, _) = read_tlv(bytes, DER_SEQUENCE_TAG)?;
+        let (response_type, rest) = read_tlv(bytes, DER_OID_TAG)?;
+        if response_type != OCSP_BASIC_OID {
+            return None;
+        }
+        let (basic, _) = read_tlv(rest, DER_OCTET_STRING_TAG)?;
+
+        // BasicOCSPResponse ::= SEQUENCE {
+        //      tbsResponseData      ResponseData,
+        //      signatureAlgorithm   AlgorithmIdentifier,
+        //      signature            BIT STRING,
+        //      certs            [0] EXPLICIT SEQUENCE OF Certificate OPTIONAL }
+        let (basic, _) = read_tlv(basic, DER_SEQUENCE_TAG)?;
+        let (data, rest) = read_tlv(basic, DER_SEQUENCE_TAG)?;
+        let tbs_response_data = &basic[..basic.len() - rest.len()];
+        let (signature_algorithm, rest) = read_tlv(rest, DER_SEQUENCE_TAG)?;
+        let (signature, rest) = read_tlv(rest, DER_BIT_STRING_TAG)?;
+        // No unused bits.
+        let signature = signature.strip_prefix(&[0])?;
+        let certs = match read_tlv(rest, DER_CONTEXT_0_TAG) {
+            Some((certs, _)) => read_tlv(certs, DER_SEQUENCE_TAG)?.0,
+            None => &[],
+        };
+
+        // ResponseData ::= SEQUENCE {
+        //      version              [0] EXPLICIT Version DEFAULT v1,
+        //      responderID              ResponderID,
+        //      producedAt               GeneralizedTime,
+        //      responses                SEQUENCE OF SingleResponse,
+        //      responseExtensions   [1] EXPLICIT Extensions OPTIONAL }
+        let (_, rest) = read_tlv(data, DER_CONTEXT_0_TAG).unwrap_or((&[], data));
+        let (_, _, rest) = read_any(rest)?;
+        let (_, rest) = read_tlv(rest, DER_GENERALIZED_TIME_TAG)?;
+        let (responses, _) = read_tlv(rest, DER_SEQUENCE_TAG)?;
+
+        Some(Self {
+            tbs_response_data,
+            signature_algorithm,
+            signature,
+            responses,
+            certs,
+        })
+    }
+
+    /// Iterate over the `SingleResponse`s, stopping at the first malformed one.
+    pub(crate) fn responses(&self) -> impl Iterator<Item = SingleResponse<'a>> {
+        let mut responses = self.responses;
+        iter::from_fn(move || {
+            let (single, rest) = read_tlv(responses, DER_SEQUENCE_TAG)?;
+            responses = rest;
+            SingleResponse::from_der(single)
+        })
+    }
+
+    /// Iterate over the DER-encoded certificates included to help verify the signature.
+    pub(crate) fn certs(&self) -> impl Iterator<Item = &'a [u8]> {
+        let mut certs = self.certs;
+        iter::from_fn(move || {
+            let (_, _, rest) = read_any(certs)?;
+            let cert = &certs[..certs.len() - rest.len()];
+            certs = rest;
+            Some(cert)
+        })
+    }
+}
+
+/// The status of one certificate in an OCSP response.
+pub(crate) struct SingleResponse<'a> {
+    /// The `CertID` hash algorithm's OID.
+    pub(crate) hash_algorithm: &'a [u8],
+    /// The hash of the issuer's DER-encoded name.
+    pub(crate) issuer_name_hash: &'a [u8],
+    /// The hash of the issuer's public key.
+    pub(crate) issuer_key_hash: &'a [u8],
+    /// The value of the certificate's `serialNumber`.
+    pub(crate) serial: &'a [u8],
+    /// The certificate's revocation status.
+    pub(crate) status: OcspCertStatus,
+    /// The `thisUpdate` time, in seconds since the UNIX epoch.
+    pub(crate) this_update: u64,
+    /// The `nextUpdate` time, in seconds since the UNIX epoch, if present.
+    pub(crate) next_update: Option<u64>,
+    /// The value of the `singleExtensions` SEQUENCE, or empty if there are none.
+    extensions: &'a [u8],
+}
+
+impl<'a> SingleResponse<'a> {
+    fn from_der(single: &'a [u8]) -> Option<Self> {
+        // SingleResponse ::= SEQUENCE {
+        //      certID                       CertID,
+        //      certStatus                   CertStatus,
+        //      thisUpdate                   GeneralizedTime,
+        //      nextUpdate         [0]       EXPLICIT GeneralizedTime OPTIONAL,
+        //      singleExtensions   [1]       EXPLICIT Extensions OPTIONAL }
+        //
+        // CertID ::= SEQUENCE {
+        //      hashAlgorithm       AlgorithmIdentifier,
+        //      issuerNameHash      OCTET STRING,
+        //      issuerKeyHash       OCTET STRING,
+        //      serialNumber        CertificateSerialNumber }
+        let (cert_id, rest) = read_tlv(single, DER_SEQUENCE_TAG)?;
+        let (hash_algorithm, cert_id) = read_tlv(cert_id, DER_SEQUENCE_TAG)?;
+        let (hash_algorithm, _) = read_tlv(hash_algorithm, DER_OID_TAG)?;
+        let (issuer_name_hash, cert_id) = read_tlv(cert_id, DER_OCTET_STRING_TAG)?;
+        let (issuer_key_hash, cert_id) = read_tlv(cert_id, DER_OCTET_STRING_TAG)?;
+        let (serial, _) = read_tlv(cert_id, DER_INTEGER_TAG)?;
+
+        // CertStatus ::= CHOICE {
+        //      good        [0]     IMPLICIT NULL,
+        //      revoked     [1]     IMPLICIT RevokedInfo,
+        //      unknown     [2]     IMPLICIT UnknownInfo }
+        let (tag, _, rest) = read_any(rest)?;
+        let status = match tag {
+            0x80 => OcspCertStatus::Good,
+            0xa1 => OcspCertStatus::Revoked,
+            0x82 => OcspCertStatus::Unknown,
+            _ => return None,
+        };
+
+        let this_update = read_time(rest)?;
+        let (_, mut rest) = read_tlv(rest, DER_GENERALIZED_TIME_TAG)?;
+        let mut next_update = None;
+        let mut extensions: &[u8] = &[];
+        while let Some((tag, value, remain)) = read_any(rest) {
+            match tag {
+                DER_CONTEXT_0_TAG => next_update = Some(read_time(value)?),
+                DER_CONTEXT_1_TAG => (extensions, _) = read_tlv(value, DER_SEQUENCE_TAG)?,
+                _ => {}
+            }
+            rest = remain;
+        }
+
+        Some(Self {
+            hash_algorithm,
+            issuer_name_hash,
+            issuer_key_hash,
+            serial,
+            status,
+            this_update,
+            next_update,
+            extensions,
+        })
+    }
+
+    /// Return the value of the single extension with the given `oid`, if present.
+    fn extension(&self, oid: &[u8]) -> Option<&'a [u8]> {
+        Extensions(self.extensions)
+            .find(|ext| ext.oid == oid)
+            .map(|ext| ext.value)
+    }
+}
+
+/// Return the `subjectPublicKey` of a DER-encoded `SubjectPublicKeyInfo`, without
+/// the BIT STRING's unused bits octet.
+pub(crate) fn subject_public_key(spki: &[u8]) -> Option<&[u8]> {
+    // SubjectPublicKeyInfo  ::=  SEQUENCE  {
+    //      algorithm            AlgorithmIdentifier,
+    //      subjectPublicKey     BIT STRING  }
+    let (spki, _) = read_tlv(spki, DER_SEQUENCE_TAG)?;
+    let (_, rest) = read_tlv(spki, DER_SEQUENCE_TAG)?;
+    let (key, _) = read_tlv(rest, DER_BIT_STRING_TAG)?;
+    key.split_first().map(|(_, key)| key)
+}
+
+/// A certificate's revocation status in an OCSP response.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) enum OcspCertStatus {
+    Good,
+    Revoked,
+    Unknown,
+}
+
+/// Return the `SignedCertificateTimestampList` in the `singleExtensions` of a
+/// DER-encoded OCSP response, if present.
+///
+/// See [RFC 6962 section 3.3](https://datatracker.ietf.org/doc/html/rfc6962#section-3.3).
+pub(crate) fn ocsp_sct_list(response: &[u8]) -> Option<&[u8]> {
+    OcspResponse::from_der(response)?
+        .responses()
+        .find_map(|single| {
+            let (list, _) = read_tlv(single.extension(OCSP_SCT_LIST_OID)?, DER_OCTET_STRING_TAG)?;
+            Some(list)
+        })
+}
+
+/// Iterates over the contents of an `Extensions` SEQUENCE.
+///
+/// Iteration stops early at the first malformed extension.
+struct Extensions<'a>(&'a [u8]);
+
+impl<'a> Iterator for Extensions<'a> {
+    type Item = Extension<'a>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        // Extension  ::=  SEQUENCE  {
+        //      extnID      OBJECT IDENTIFIER,
+        //      critical    BOOLEAN DEFAULT FALSE,
+        //      extnValue   OCTET STRING }
+        let input = core::mem::take(&mut self.0);
+        let (extension, rest) = read_tlv(input, DER_SEQUENCE_TAG)?;
+        let (oid, extension) = read_tlv(extension, DER_OID_TAG)?;
+        let (_, extension) = read_tlv(extension, DER_BOOLEAN_TAG).unwrap_or((&[], extension));
+        let (value, _) = read_tlv(extension, DER_OCTET_STRING_TAG)?;
+
+        self.0 = rest;
+        Some(Extension {
+            oid,
+            value,
+            encoding: &input[..input.len() - rest.len()],
+        })
+    }
+}
+
+struct Extension<'a> {
+    oid: &'a [u8],
+    value: &'a [u8],
+    encoding: &'a [u8],
+}
+
+/// Split the first element off `input`, returning its tag, value and the remaining input.
+fn read_any(input: &[u8]) -> Option<(u8, &[u8], &[u8])> {
+    let (&tag, rest) = input.split_first()?;
+    let (&first, rest) = rest.split_first()?;
+
+    let (len, rest) = match first {
+        0..=0x7f => (usize::from(first), rest),
+        0x81..=0x84 => {
+            let (len_bytes, rest) = split_at(rest, usize::from(first & 0x7f))?;
+            let len = len_bytes
+                .iter()
+                .fold(0usize, |acc, b| (acc << 8) | usize::from(*b));
+            (len, rest)
+        }
+        _ => return None,
+    };
+
+    let (value, rest) = split_at(rest, len)?;
+    Some((tag, value, rest))
+}
+
+/// Split the first element off `input`, which must have the tag `expected`.
+fn read_tlv(input: &[u8], expected: u8) -> Option<(&[u8], &[u8])> {
+    match read_any(input)? {
+        (tag, value, rest) if tag == expected => Some((value, rest)),
+        _ => None,
+    }
+}
+
+/// Read the first `Time` from `input`, as seconds since the UNIX epoch.
+fn read_time(input: &[u8]) -> Option<u64> {
+    let (tag, value, _) = read_any(input)?;
+    let (year, rest) = match tag {
+        // YYMMDDHHMMSSZ
+        DER_UTC_TIME_TAG => {
+            let (year, rest) = split_at(value, 2)?;
+            let year = read_digits(year)?;
+            (if year >= 50 { 1900 + year } else { 2000 + year }, rest)
+        }
+        // YYYYMMDDHHMMSSZ
+        DER_GENERALIZED_TIME_TAG => {
+            let (year, rest) = split_at(value, 4)?;
+            (read_digits(year)?, rest)
+        }
+        _ => return None,
+    };
+
+    if rest.len() != 11 || rest[10] != b'Z' {
+        return None;
+    }
+
+    let month = read_digits(&rest[0..2])?;
+    let day = read_digits(&rest[2..4])?;
+    let hours = read_digits(&rest[4..6])?;
+    let minutes = read_digits(&rest[6..8])?;
+    let seconds = read_digits(&rest[8..10])?;
+    if year < 1970 || !(1..=12).contains(&month) || !(1..=31).contains(&day) {
+        return None;
+    }
+
+    // Days since the epoch, from <http://howardhinnant.github.io/date_algorithms.html>.
+    let (y, m) = match month {
+        1 | 2 => (year - 1, month + 9),
+        _ => (year, month - 3),
+    };
+    let era = y / 400;
+    let year_of_era = y - era * 400;
+    let day_of_year = (153 * m + 2) / 5 + day - 1;
+    let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+    let days = era * 146_097 + day_of_era - 719_468;
+
+    Some(days * 86_400 + hours * 3_600 + minutes * 60 + seconds)
+}
+
+fn split_at(input: &[u8], mid: usize) -> Option<(&[u8], &[u8])> {
+    Some((input.get(..mid)?, input.get(mid..)?))
+}
+
+fn read_digits(digits: &[u8]) -> Option<u64> {
+    digits
+        .iter()
+        .try_fold(0u64, |acc, d| match d {
+            b'0'..=b'9' => Some(acc * 10 + u64::from(d - b'0')),
+            _ => None,
+        })
+}
+
+const DER_BOOLEAN_TAG: u8 = 0x01;
+const DER_INTEGER_TAG: u8 = 0x02;
 const DER_SEQUENCE_TAG: u8 = 0x30;
 const DER_BIT_STRING_TAG: u8 = 0x03;
 const DER_OCTET_STRING_TAG: u8 = 0x04;
+const DER_OID_TAG: u8 = 0x06;
+const DER_ENUMERATED_TAG: u8 = 0x0a;
+const DER_UTC_TIME_TAG: u8 = 0x17;
+const DER_GENERALIZED_TIME_TAG: u8 = 0x18;
+const DER_CONTEXT_0_TAG: u8 = 0xa0;
+const DER_CONTEXT_1_TAG: u8 = 0xa1;
+const DER_CONTEXT_3_TAG: u8 = 0xa3;
+
+/// id-ce-keyUsage: 2.5.29.15
+const KEY_USAGE_OID: &[u8] = &[0x55, 0x1d, 0x0f];
+/// id-pe-delegationUsage: 1.3.6.1.4.1.44363.44 (RFC 9345 section 4.2)
+const DELEGATION_USAGE_OID: &[u8] = &[0x2b, 0x06, 0x01, 0x04, 0x01, 0x82, 0xda, 0x4b, 0x2c];
+/// Embedded SCT list: 1.3.6.1.4.1.11129.2.4.2 (RFC 6962 section 3.3)
+const SCT_LIST_OID: &[u8] = &[0x2b, 0x06, 0x01, 0x04, 0x01, 0xd6, 0x79, 0x02, 0x04, 0x02];
+/// OCSP SCT list: 1.3.6.1.4.1.11129.2.4.5 (RFC 6962 section 3.3)
+const OCSP_SCT_LIST_OID: &[u8] = &[0x2b, 0x06, 0x01, 0x04, 0x01, 0xd6, 0x79, 0x02, 0x04, 0x05];
+/// id-pe-tlsfeature: 1.3.6.1.5.5.7.1.24 (RFC 7633 section 6)
+const TLS_FEATURE_OID: &[u8] = &[0x2b, 0x06, 0x01, 0x05, 0x05, 0x07, 0x01, 0x18];
+/// The `status_request` TLS extension, as a TLS feature (RFC 7633 section 4.2)
+const STATUS_REQUEST_FEATURE: u8 = 5;
+/// OCSPResponseStatus `successful` (RFC 6960 section 4.2.1)
+const OCSP_SUCCESSFUL: u8 = 0;
+/// id-pkix-ocsp-basic: 1.3.6.1.5.5.7.48.1.1 (RFC 6960 section 4.2.1)
+const OCSP_BASIC_OID: &[u8] = &[0x2b, 0x06, 0x01, 0x05, 0x05, 0x07, 0x30, 0x01, 0x01];
 
 #[cfg(test)]
 mod tests {
@@ -144,4 +673,113 @@ mod tests {
         // ^ tag   ^ len   ^ no unused bits    ^ value
         assert_eq!(wrap_in_bit_string(&[0x55u8]), vec![0x03, 0x02, 0x00, 0x55]);
     }
+
+    #[test]
+    fn test_delegation_details_of_test_ca_cert() {
+        use pki_types::pem::PemObject;
+
+        let cert = pki_types::CertificateDer::from_pem_slice(include_bytes!(
+            "../../test-ca/ecdsa-p256/end.cert"
+        ))
+        .unwrap();
+        let details = DelegationDetails::from_cert(&cert).unwrap();
+        assert!(!details.delegation_usage);
+        // 1975-01-01T00:00:00Z
+        assert_eq!(details.not_before, 157_766_400);
+    }
+
+    #[test]
+    fn test_ocsp_response() {
+        let response = sample_ocsp_response();
+        let response = OcspResponse::from_der(&response).unwrap();
+        assert_eq!(response.signature_algorithm, b"alg");
+        assert_eq!(response.signature, b"sig");
+        assert_eq!(response.certs().collect::<Vec<_>>(), [&b"\x30\x00"[..]]);
+
+        let singles = response.responses().collect::<Vec<_>>();
+        assert_eq!(singles.len(), 1);
+        assert_eq!(singles[0].hash_algorithm, b"hash");
+        assert_eq!(singles[0].issuer_name_hash, b"name");
+        assert_eq!(singles[0].issuer_key_hash, b"key");
+        assert_eq!(singles[0].serial, [0x12, 0x34]);
+        assert_eq!(singles[0].status, OcspCertStatus::Revoked);
+        // 2024-01-01T00:00:00Z and 2024-01-02T00:00:00Z
+        assert_eq!(singles[0].this_update, 1_704_067_200);
+        assert_eq!(singles[0].next_update, Some(1_704_153_600));
+
+        // tryLater, with no responseBytes
+        assert!(
+            OcspResponse::from_der(&wrap_in_sequence(&[DER_ENUMERATED_TAG, 0x01, 0x03])).is_none()
+        );
+    }
+
+    #[test]
+    fn test_ocsp_sct_list() {
+        assert_eq!(ocsp_sct_list(&sample_ocsp_response()), Some(&b"scts"[..]));
+    }
+
+    fn sample_ocsp_response() -> Vec<u8> {
+        let extension = wrap_concat_in_sequence(
+            &asn1_wrap(DER_OID_TAG, OCSP_SCT_LIST_OID, &[]),
+            &wrap_in_octet_string(&wrap_in_octet_string(b"scts")),
+        );
+        let cert_id = [
+            // hashAlgorithm, issuerNameHash, issuerKeyHash, serialNumber
+            &wrap_in_sequence(&asn1_wrap(DER_OID_TAG, b"hash", &[]))[..],
+            &wrap_in_octet_string(b"name"),
+            &wrap_in_octet_string(b"key"),
+            &[DER_INTEGER_TAG, 0x02, 0x12, 0x34],
+        ]
+        .concat();
+        let single_response = [
+            // certID, certStatus: revoked, thisUpdate, nextUpdate, singleExtensions
+            &wrap_in_sequence(&cert_id)[..],
+            &asn1_wrap(0xa1, b"\x18\x0f20231201000000Z", &[]),
+            b"\x18\x0f20240101000000Z",
+            &asn1_wrap(DER_CONTEXT_0_TAG, b"\x18\x0f20240102000000Z", &[]),
+            &asn1_wrap(DER_CONTEXT_1_TAG, &wrap_in_sequence(&extension), &[]),
+        ]
+        .concat();
+        let response_data = [
+            // responderID: byKey, producedAt, responses
+            &asn1_wrap(0xa2, &[], &[])[..],
+            b"\x18\x0f20240101000000Z",
+            &wrap_in_sequence(&wrap_in_sequence(&single_response)),
+        ]
+        .concat();
+        let basic = wrap_in_sequence(
+            &[
+                // tbsResponseData, signatureAlgorithm, signature, certs
+                &wrap_in_sequence(&response_data)[..],
+                &wrap_in_sequence(b"alg"),
+                &wrap_in_bit_string(b"sig"),
+                &asn1_wrap(
+                    DER_CONTEXT_0_TAG,
+                    &wrap_in_sequence(&wrap_in_sequence(&[])),
+                    &[],
+                ),
+            ]
+            .concat(),
+        );
+        let response_bytes = wrap_concat_in_sequence(
+            &asn1_wrap(DER_OID_TAG, OCSP_BASIC_OID, &[]),
+            &wrap_in_octet_string(&basic),
+        );
+        wrap_concat_in_sequence(
+            &asn1_wrap(DER_ENUMERATED_TAG, &[OCSP_SUCCESSFUL], &[]),
+            &asn1_wrap(DER_CONTEXT_0_TAG, &response_bytes, &[]),
+        )
+    }
+
+    #[test]
+    fn test_read_time() {
+        // UTCTime 1970-01-01T00:00:00Z
+        assert_eq!(read_time(b"\x17\x0d700101000000Z"), Some(0));
+        // UTCTime 2024-02-29T12:34:56Z
+        assert_eq!(read_time(b"\x17\x0d240229123456Z"), Some(1_709_210_096));
+        // GeneralizedTime 2050-01-01T00:00:00Z
+        assert_eq!(read_time(b"\x18\x0f20500101000000Z"), Some(2_524_608_000));
+        assert_eq!(read_time(b"\x17\x0d240229123456X"), None);
+        assert_eq!(read_time(b"\x17\x0d2402291234"), None);
+    }
 }
@@ -9,32 +9,44 @@ use std::sync::Mutex;
 use std::sync::atomic::{AtomicUsize, Ordering};
 use std::{fmt, mem};
 
-use pki_types::{CertificateDer, DnsName, IpAddr, ServerName, SubjectPublicKeyInfoDer, UnixTime};
-use rustls::client::{ResolvesClientCert, Resumption, verify_server_cert_signed_by_trust_anchor};
+use pki_types::{
+    CertificateDer, DnsName, IpAddr, PrivatePkcs8KeyDer, ServerName, SubjectPublicKeyInfoDer,
+    UnixTime,
+};
+use rustls::client::{
+    CtLog, CtPolicy, ResolvesClientCert, Resumption, VerifierBuilderError, WebPkiServerVerifier,
+    verify_server_cert_signed_by_trust_anchor,
+};
 use rustls::crypto::{ActiveKeyExchange, CryptoProvider, SharedSecret, SupportedKxGroup};
 use rustls::internal::msgs::base::Payload;
 use rustls::internal::msgs::codec::Codec;
 use rustls::internal::msgs::enums::{AlertLevel, ExtensionType};
 use rustls::internal::msgs::message::{Message, MessagePayload, PlainMessage};
-use rustls::server::{CertificateType, ClientHello, ParsedCertificate, ResolvesServerCert};
+use rustls::server::{
+    CertificateType, ClientHello, ClientHelloBloomFilter, ParsedCertificate, RecordsClientHellos,
+    ResolvesServerCert, ResolvesServerPsk, ServerSessionCache,
+};
+use rustls::time_provider::TimeProvider;
 use rustls::version::TLS12;
 use rustls::{
-    AlertDescription, CertificateError, CipherSuite, ClientConfig, ClientConnection,
-    ConnectionCommon, ConnectionTrafficSecrets, ContentType, DistinguishedName, Error,
-    ExtendedKeyPurpose, HandshakeKind, HandshakeType, InconsistentKeys, InvalidMessage, KeyLog,
-    NamedGroup, PeerIncompatible, PeerMisbehaved, ProtocolVersion, RootCertStore, ServerConfig,
-    ServerConnection, SideData, SignatureScheme, Stream, StreamOwned, SupportedCipherSuite,
-    SupportedProtocolVersion, sign,
+    AlertDescription, AuthenticatorRequest, CertificateError, CipherSuite, ClientConfig,
+    ClientConnection, ConnectionCommon, ConnectionTrafficSecrets, ContentType, DistinguishedName,
+    Error, ExtendedKeyPurpose, ExternalPsk, HandshakeKind, HandshakeType, InconsistentKeys,
+    InvalidMessage, KeyLog, MaxFragmentLength, NamedGroup, PeerIncompatible, PeerMisbehaved,
+    ProtocolVersion, PskKexMode, RootCertStore, ServerConfig, ServerConnection, SideData,
+    SignatureScheme, Stream, StreamOwned, SupportedCipherSuite, SupportedProtocolVersion,
+    TicketKey, TicketKeySet, Tls13CipherSuite, sign,
 };
 #[cfg(feature = "aws-lc-rs")]
 use rustls::{
-    client::{EchConfig, EchGreaseConfig, EchMode},
+    client::{EchConfig, EchGreaseConfig, EchMode, EchStatus},
     crypto::aws_lc_rs::hpke::ALL_SUPPORTED_SUITES,
     internal::msgs::base::PayloadU16,
     internal::msgs::handshake::{
         EchConfigContents, EchConfigPayload, HpkeKeyConfig, HpkeSymmetricCipherSuite,
     },
     pki_types::EchConfigListBytes,
+    server::{Acceptor, EchForwarder, EchKeyPair, EchSplitMode, EchStatus as ServerEchStatus},
 };
 use webpki::anchor_from_trusted_cert;
 
@@ -1926,6 +1938,87 @@ fn client_cert_resolve_server_added_hint() {
     }
 }
 
+/// Run the handshake until the client pauses to choose its certificate.
+fn complete_until_client_cert_requested(
+    client: &mut ClientConnection,
+    server: &mut ServerConnection,
+) {
+    loop {
+        transfer(client, server);
+        server.process_new_packets().unwrap();
+        transfer(server, client);
+        if client
+            .process_new_packets()
+            .unwrap()
+            .awaiting_client_cert()
+        {
+            return;
+        }
+    }
+}
+
+#[test]
+fn client_cert_resolve_asynchronously() {
+    let provider = provider::default_provider();
+    let kt = KeyType::Rsa2048;
+    let server_config = Arc::new(make_server_config_with_mandatory_client_auth(kt, &provider));
+
+    for version in rustls::ALL_VERSIONS {
+        let mut client_config = make_client_config_with_versions(kt, &[version], &provider);
+        client_config.client_auth_cert_resolver = Arc::new(AsyncClientCertResolver);
+        let (mut client, mut server) =
+            make_pair_for_arc_configs(&Arc::new(client_config), &server_config);
+        complete_until_client_cert_requested(&mut client, &mut server);
+        assert!(!client.wants_read());
+
+        let request = client.pending_client_cert().unwrap();
+        assert_eq!(
+            request
+                .root_hint_subjects()
+                .iter()
+                .map(|subject| subject.as_ref())
+                .collect::<Vec<_>>(),
+            vec![kt.ca_distinguished_name()]
+        );
+        assert_eq!(
+            request.sigschemes(),
+            default_signature_schemes(version.version())
+        );
+
+        let certified_key =
+            sign::CertifiedKey::from_der(kt.get_client_chain(), kt.get_client_key(), &provider)
+                .unwrap();
+        client
+            .complete_client_cert(Some(Arc::new(certified_key)))
+            .unwrap();
+        assert_eq!(do_handshake_until_error(&mut client, &mut server), Ok(()));
+        assert_eq!(server.peer_certificates(), Some(&kt.get_client_chain()[..]));
+    }
+}
+
+#[test]
+fn client_cert_declined_asynchronously() {
+    let provider = provider::default_provider();
+    let kt = KeyType::Rsa2048;
+    let server_config = Arc::new(make_server_config_with_mandatory_client_auth(kt, &provider));
+
+    for version in rustls::ALL_VERSIONS {
+        let mut client_config = make_client_config_with_versions(kt, &[version], &provider);
+        client_config.client_auth_cert_resolver = Arc::new(AsyncClientCertResolver);
+        let (mut client, mut server) =
+            make_pair_for_arc_configs(&Arc::new(client_config), &server_config);
+        complete_until_client_cert_requested(&mut client, &mut server);
+
+        client
+            .complete_client_cert(None)
+            .unwrap();
+        assert_eq!(
+            do_handshake_until_error(&mut client, &mut server),
+            Err(ErrorFromPeer::Server(Error::NoCertificatesPresented))
+        );
+    }
+}
+
 #[test]
 fn client_auth_works() {
     let provider = provider::default_provider();
@@ -1944,6 +2037,394 @@ fn client_auth_works() {
     }
 }
 
+fn post_handshake_auth_pair(
+    kt: KeyType,
+    client_config: ClientConfig,
+    provider: &CryptoProvider,
+) -> (ClientConnection, ServerConnection) {
+    let mut client_config = client_config;
+    client_config.enable_post_handshake_auth = true;
+    let (mut client, mut server) =
+        make_pair_for_configs(client_config, make_server_config(kt, provider));
+    do_handshake(&mut client, &mut server);
+    assert!(server.peer_certificates().is_none());
+    (client, server)
+}
+
+fn do_post_handshake_auth(
+    client: &mut ClientConnection,
+    server: &mut ServerConnection,
+) -> Result<(), ErrorFromPeer> {
+    transfer(server, client);
+    client
+        .process_new_packets()
+        .map_err(ErrorFromPeer::Client)?;
+    transfer(client, server);
+    server
+        .process_new_packets()
+        .map_err(ErrorFromPeer::Server)?;
+    Ok(())
+}
+
+#[test]
+fn post_handshake_client_auth_works() {
+    let provider = provider::default_provider();
+    for kt in KeyType::all_for_provider(&provider) {
+        let (mut client, mut server) =
+            post_handshake_auth_pair(*kt, make_client_config_with_auth(*kt, &provider), &provider);
+
+        let verifier = webpki_client_verifier_builder(get_client_root_store(*kt), &provider)
+            .build()
+            .unwrap();
+        server
+            .request_client_certificate(verifier.clone())
+            .unwrap();
+
+        // only one request may be outstanding
+        assert!(matches!(
+            server.request_client_certificate(verifier.clone()),
+            Err(Error::General(_))
+        ));
+
+        do_post_handshake_auth(&mut client, &mut server).unwrap();
+        assert_eq!(server.peer_certificates(), Some(&kt.get_client_chain()[..]));
+
+        // and another, which needs a different context
+        server
+            .request_client_certificate(verifier)
+            .unwrap();
+        do_post_handshake_auth(&mut client, &mut server).unwrap();
+        assert_eq!(server.peer_certificates(), Some(&kt.get_client_chain()[..]));
+
+        // data continues to flow
+        client
+            .writer()
+            .write_all(b"hello")
+            .unwrap();
+        transfer(&mut client, &mut server);
+        server.process_new_packets().unwrap();
+        check_read(&mut server.reader(), b"hello");
+    }
+}
+
+#[test]
+fn post_handshake_client_auth_declined() {
+    let provider = provider::default_provider();
+    let kt = KeyType::Rsa2048;
+
+    let (mut client, mut server) =
+        post_handshake_auth_pair(kt, make_client_config(kt, &provider), &provider);
+    let verifier = webpki_client_verifier_builder(get_client_root_store(kt), &provider)
+        .allow_unauthenticated()
+        .build()
+        .unwrap();
+    server
+        .request_client_certificate(verifier)
+        .unwrap();
+    do_post_handshake_auth(&mut client, &mut server).unwrap();
+    assert!(server.peer_certificates().is_none());
+
+    let (mut client, mut server) =
+        post_handshake_auth_pair(kt, make_client_config(kt, &provider), &provider);
+    let verifier = webpki_client_verifier_builder(get_client_root_store(kt), &provider)
+        .build()
+        .unwrap();
+    server
+        .request_client_certificate(verifier)
+        .unwrap();
+    assert_eq!(
+        do_post_handshake_auth(&mut client, &mut server),
+        Err(ErrorFromPeer::Server(Error::NoCertificatesPresented))
+    );
+}
+
+#[test]
+fn post_handshake_client_auth_requires_client_support() {
+    let provider = provider::default_provider();
+    let kt = KeyType::Rsa2048;
+    let verifier = webpki_client_verifier_builder(get_client_root_store(kt), &provider)
+        .build()
+        .unwrap();
+
+    for version in rustls::ALL_VERSIONS {
+        let (mut client, mut server) = make_pair_for_configs(
+            make_client_config_with_versions_with_auth(kt, &[version], &provider),
+            make_server_config(kt, &provider),
+        );
+        assert_eq!(
+            server.request_client_certificate(verifier.clone()),
+            Err(Error::HandshakeNotComplete)
+        );
+
+        do_handshake(&mut client, &mut server);
+        assert_eq!(
+            server.request_client_certificate(verifier.clone()),
+            Err(PeerIncompatible::PostHandshakeAuthNotOffered.into())
+        );
+    }
+}
+
+#[test]
+fn exported_authenticator_from_server() {
+    let provider = provider::default_provider();
+    for kt in KeyType::all_for_provider(&provider) {
+        let (mut client, mut server) = make_pair(*kt, &provider);
+        do_handshake(&mut client, &mut server);
+
+        let verifier = webpki_server_verifier_builder(get_client_root_store(*kt), &provider)
+            .build()
+            .unwrap();
+        let request = client
+            .exported_authenticator_request(b"request-1".to_vec(), verifier.as_ref())
+            .unwrap();
+
+        let received = AuthenticatorRequest::try_from(request.as_bytes()).unwrap();
+        assert_eq!(received.context(), b"request-1");
+        assert_eq!(received.signature_schemes(), request.signature_schemes());
+        let certified_key = kt
+            .certified_key_with_cert_chain(&provider)
+            .unwrap();
+        let authenticator = server
+            .exported_authenticator(&received, Some(&certified_key))
+            .unwrap();
+
+        let chain = client
+            .validate_exported_authenticator(
+                &request,
+                &authenticator,
+                &server_name("localhost"),
+                verifier.as_ref(),
+                UnixTime::now(),
+            )
+            .unwrap();
+        assert_eq!(chain, Some(kt.get_chain()));
+
+        assert_eq!(
+            client.validate_exported_authenticator(
+                &request,
+                &authenticator,
+                &server_name("example.com"),
+                verifier.as_ref(),
+                UnixTime::now(),
+            ),
+            Err(Error::InvalidCertificate(
+                CertificateError::NotValidForNameContext {
+                    expected: server_name("example.com"),
+                    presented: vec![
+                        "DnsName(\"testserver.com\")".into(),
+                        "DnsName(\"second.testserver.com\")".into(),
+                        "DnsName(\"localhost\")".into(),
+                        "IpAddress(198.51.100.1)".into(),
+                        "IpAddress(2001:db8::1)".into(),
+                    ]
+                }
+            ))
+        );
+
+        // an empty authenticator declines the request
+        let authenticator = server
+            .exported_authenticator(&received, None)
+            .unwrap();
+        assert_eq!(
+            client.validate_exported_authenticator(
+                &request,
+                &authenticator,
+                &server_name("localhost"),
+                verifier.as_ref(),
+                UnixTime::now(),
+            ),
+            Ok(None)
+        );
+    }
+}
+
+#[test]
+fn exported_authenticator_from_client() {
+    let provider = provider::default_provider();
+    for kt in KeyType::all_for_provider(&provider) {
+        let (mut client, mut server) = make_pair(*kt, &provider);
+        do_handshake(&mut client, &mut server);
+
+        let verifier = webpki_client_verifier_builder(get_client_root_store(*kt), &provider)
+            .build()
+            .unwrap();
+        let request = server
+            .exported_authenticator_request(b"request-1".to_vec(), verifier.as_ref())
+            .unwrap();
+
+        let received = AuthenticatorRequest::try_from(request.as_bytes()).unwrap();
+        assert!(
+            !received
+                .certificate_authorities()
+                .is_empty()
+        );
+        let certified_key = sign::CertifiedKey::new(
+            kt.get_client_chain(),
+            provider
+                .key_provider
+                .load_private_key(kt.get_client_key())
+                .unwrap(),
+        )
+        .unwrap();
+        let authenticator = client
+            .exported_authenticator(&received, Some(&certified_key))
+            .unwrap();
+
+        let chain = server
+            .validate_exported_authenticator(
+                &request,
+                &authenticator,
+                verifier.as_ref(),
+                UnixTime::now(),
+            )
+            .unwrap();
+        assert_eq!(chain, Some(kt.get_client_chain()));
+
+        // the client can decline, which is only allowed if client auth is optional
+        let authenticator = client
+            .exported_authenticator(&received, None)
+            .unwrap();
+        assert_eq!(
+            server.validate_exported_authenticator(
+                &request,
+                &authenticator,
+                verifier.as_ref(),
+                UnixTime::now(),
+            ),
+            Err(Error::NoCertificatesPresented)
+        );
+
+        let optional_verifier =
+            webpki_client_verifier_builder(get_client_root_store(*kt), &provider)
+                .allow_unauthenticated()
+                .build()
+                .unwrap();
+        assert_eq!(
+            server.validate_exported_authenticator(
+                &request,
+                &authenticator,
+                optional_verifier.as_ref(),
+                UnixTime::now(),
+            ),
+            Ok(None)
+        );
+    }
+}
+
+#[test]
+fn exported_authenticator_is_bound_to_connection() {
+    let provider = provider::default_provider();
+    let kt = KeyType::Rsa2048;
+    let verifier = webpki_client_verifier_builder(get_client_root_store(kt), &provider)
+        .build()
+        .unwrap();
+    let certified_key = sign::CertifiedKey::new(
+        kt.get_client_chain(),
+        provider
+            .key_provider
+            .load_private_key(kt.get_client_key())
+            .unwrap(),
+    )
+    .unwrap();
+
+    let (mut client, mut server) = make_pair(kt, &provider);
+    do_handshake(&mut client, &mut server);
+    let (mut other_client, mut other_server) = make_pair(kt, &provider);
+    do_handshake(&mut other_client, &mut other_server);
+
+    let request = server
+        .exported_authenticator_request(b"request-1".to_vec(), verifier.as_ref())
+        .unwrap();
+    let received = AuthenticatorRequest::try_from(request.as_bytes()).unwrap();
+
+    // made on a different connection
+    let authenticator = other_client
+        .exported_authenticator(&received, Some(&certified_key))
+        .unwrap();
+    assert_eq!(
+        server.validate_exported_authenticator(
+            &request,
+            &authenticator,
+            verifier.as_ref(),
+            UnixTime::now(),
+        ),
+        Err(Error::DecryptError)
+    );
+
+    // in reply to a different request
+    let other_request = server
+        .exported_authenticator_request(b"request-2".to_vec(), verifier.as_ref())
+        .unwrap();
+    let authenticator = client
+        .exported_authenticator(&received, Some(&certified_key))
+        .unwrap();
+    assert_eq!(
+        server.validate_exported_authenticator(
+            &other_request,
+            &authenticator,
+            verifier.as_ref(),
+            UnixTime::now(),
+        ),
+        Err(PeerMisbehaved::IncorrectCertificateRequestContext.into())
+    );
+
+    // tampered with
+    let mut tampered = authenticator.clone();
+    let last = tampered.len() - 1;
+    tampered[last] ^= 1;
+    assert_eq!(
+        server.validate_exported_authenticator(
+            &request,
+            &tampered,
+            verifier.as_ref(),
+            UnixTime::now(),
+        ),
+        Err(Error::DecryptError)
+    );
+
+    // a server cannot answer its own kind of request
+    assert_eq!(
+        server.exported_authenticator(&received, None),
+        Err(Error::InappropriateHandshakeMessage {
+            expect_types: vec![HandshakeType::ClientCertificateRequest],
+            got_type: HandshakeType::CertificateRequest,
+        })
+    );
+}
+
+#[test]
+fn exported_authenticator_requires_tls13() {
+    let provider = provider::default_provider();
+    let kt = KeyType::Rsa2048;
+    let verifier = webpki_client_verifier_builder(get_client_root_store(kt), &provider)
+        .build()
+        .unwrap();
+
+    let (mut client, mut server) = make_pair(kt, &provider);
+    assert_eq!(
+        server
+            .exported_authenticator_request(b"request-1".to_vec(), verifier.as_ref())
+            .unwrap_err(),
+        Error::HandshakeNotComplete
+    );
+    do_handshake(&mut client, &mut server);
+    assert!(
+        server
+            .exported_authenticator_request(vec![0; 256], verifier.as_ref())
+            .is_err()
+    );
+
+    let (mut client, mut server) = make_pair_for_configs(
+        make_client_config_with_versions(kt, &[&rustls::version::TLS12], &provider),
+        make_server_config(kt, &provider),
+    );
+    do_handshake(&mut client, &mut server);
+    assert!(matches!(
+        server.exported_authenticator_request(b"request-1".to_vec(), verifier.as_ref()),
+        Err(Error::General(_))
+    ));
+}
+
 #[test]
 fn client_mandatory_auth_client_revocation_works() {
     let provider = provider::default_provider();
@@ -4485,21 +4966,204 @@ fn tls13_stateful_resumption() {
 }
 
 #[test]
-fn tls13_stateless_resumption() {
+fn resumption_with_server_session_cache() {
     let kt = KeyType::Rsa2048;
     let provider = provider::default_provider();
-    let client_config = make_client_config_with_versions(kt, &[&rustls::version::TLS13], &provider);
-    let client_config = Arc::new(client_config);
+    let client_config = Arc::new(make_client_config(kt, &provider));
 
     let mut server_config = make_server_config(kt, &provider);
-    server_config.ticketer = provider::Ticketer::new().unwrap();
-    let storage = Arc::new(ServerStorage::new());
-    server_config.session_storage = storage.clone();
+    let cache = ServerSessionCache::new(
+        16,
+        core::time::Duration::from_secs(3600),
+        server_config.time_provider.clone(),
+    );
+    server_config.session_storage = cache.clone();
     let server_config = Arc::new(server_config);
 
-    // full handshake
     let (mut client, mut server) = make_pair_for_arc_configs(&client_config, &server_config);
-    let (full_c2s, full_s2c) = do_handshake(&mut client, &mut server);
+    do_handshake(&mut client, &mut server);
+    assert_eq!(server.handshake_kind(), Some(HandshakeKind::Full));
+
+    let (mut client, mut server) = make_pair_for_arc_configs(&client_config, &server_config);
+    do_handshake(&mut client, &mut server);
+    assert_eq!(server.handshake_kind(), Some(HandshakeKind::Resumed));
+
+    let stats = cache.stats();
+    assert_eq!(stats.hits, 1);
+    assert_eq!(stats.evictions, 0);
+}
+
+#[test]
+fn server_session_cache_uses_config_time_and_advertises_its_lifetime() {
+    let kt = KeyType::Rsa2048;
+    let provider = provider::default_provider();
+    let client_time = Arc::new(FakeTime::now());
+    let mut client_config = make_client_config(kt, &provider);
+    client_config.time_provider = client_time.clone();
+    let client_config = Arc::new(client_config);
+
+    let server_time = Arc::new(FakeTime::now());
+    let mut server_config = make_server_config(kt, &provider);
+    server_config.time_provider = server_time.clone();
+    let cache = ServerSessionCache::new(
+        16,
+        core::time::Duration::from_secs(3600),
+        server_time.clone(),
+    );
+    server_config.session_storage = cache.clone();
+    let server_config = Arc::new(server_config);
+
+    let (mut client, mut server) = make_pair_for_arc_configs(&client_config, &server_config);
+    do_handshake(&mut client, &mut server);
+    assert_eq!(server.handshake_kind(), Some(HandshakeKind::Full));
+
+    // the client still offers its ticket, but the cache has expired it
+    server_time.advance(3601);
+    let (mut client, mut server) = make_pair_for_arc_configs(&client_config, &server_config);
+    do_handshake(&mut client, &mut server);
+    assert_eq!(server.handshake_kind(), Some(HandshakeKind::Full));
+    assert_eq!(cache.stats().misses, 1);
+
+    // the client knows its new tickets expire with the cache, so does not offer them
+    client_time.advance(3601);
+    let (mut client, mut server) = make_pair_for_arc_configs(&client_config, &server_config);
+    do_handshake(&mut client, &mut server);
+    assert_eq!(server.handshake_kind(), Some(HandshakeKind::Full));
+    assert_eq!(cache.stats().hits, 0);
+    assert_eq!(cache.stats().misses, 1);
+}
+
+#[test]
+fn default_server_session_storage_expires_sessions() {
+    let kt = KeyType::Rsa2048;
+    let provider = provider::default_provider();
+    let client_config = Arc::new(make_client_config(kt, &provider));
+
+    let server_time = Arc::new(FakeTime::now());
+    let server_config = Arc::new(finish_server_config(
+        kt,
+        ServerConfig::builder_with_details(provider.into(), server_time.clone())
+            .with_safe_default_protocol_versions()
+            .unwrap(),
+    ));
+
+    let (mut client, mut server) = make_pair_for_arc_configs(&client_config, &server_config);
+    do_handshake(&mut client, &mut server);
+    assert_eq!(server.handshake_kind(), Some(HandshakeKind::Full));
+
+    let (mut client, mut server) = make_pair_for_arc_configs(&client_config, &server_config);
+    do_handshake(&mut client, &mut server);
+    assert_eq!(server.handshake_kind(), Some(HandshakeKind::Resumed));
+
+    server_time.advance(24 * 60 * 60 + 1);
+    let (mut client, mut server) = make_pair_for_arc_configs(&client_config, &server_config);
+    do_handshake(&mut client, &mut server);
+    assert_eq!(server.handshake_kind(), Some(HandshakeKind::Full));
+}
+
+#[derive(Debug)]
+struct FakeTime(Mutex<u64>);
+
+impl FakeTime {
+    fn now() -> Self {
+        Self(Mutex::new(UnixTime::now().as_secs()))
+    }
+
+    fn advance(&self, secs: u64) {
+        *self.0.lock().unwrap() += secs;
+    }
+}
+
+impl rustls::time_provider::TimeProvider for FakeTime {
+    fn current_time(&self) -> Option<UnixTime> {
+        Some(UnixTime::since_unix_epoch(core::time::Duration::from_secs(
+            *self.0.lock().unwrap(),
+        )))
+    }
+}
+
+#[test]
+fn tls13_stateless_resumption_with_shared_ticket_keys() {
+    let kt = KeyType::Rsa2048;
+    let provider = provider::default_provider();
+    let client_config = make_client_config_with_versions(kt, &[&rustls::version::TLS13], &provider);
+    let client_config = Arc::new(client_config);
+
+    let key_len = if provider_is_ring() { 32 } else { 64 };
+    let key = |n: u8| TicketKey::new([n; 16], &vec![n; key_len]);
+    let keys = TicketKeySet {
+        current: key(1),
+        next: Some(key(2)),
+        previous: vec![],
+    };
+
+    // two servers, sharing ticket keys but not session storage
+    let mut server_configs = Vec::new();
+    let mut ticketers = Vec::new();
+    for _ in 0..2 {
+        let ticketer = provider::Ticketer::new_with_keys(3600, keys.clone()).unwrap();
+        let mut server_config = make_server_config(kt, &provider);
+        server_config.ticketer = ticketer.clone();
+        server_config.session_storage = Arc::new(ServerStorage::new());
+        server_configs.push(Arc::new(server_config));
+        ticketers.push(ticketer);
+    }
+
+    let (mut client, mut server) = make_pair_for_arc_configs(&client_config, &server_configs[0]);
+    do_handshake(&mut client, &mut server);
+    assert_eq!(server.handshake_kind(), Some(HandshakeKind::Full));
+
+    // the ticket issued by the first server is accepted by the second
+    let (mut client, mut server) = make_pair_for_arc_configs(&client_config, &server_configs[1]);
+    do_handshake(&mut client, &mut server);
+    assert_eq!(server.handshake_kind(), Some(HandshakeKind::Resumed));
+
+    // and after the second server rotates, by the first
+    ticketers[1]
+        .update(TicketKeySet {
+            current: key(2),
+            next: None,
+            previous: vec![key(1)],
+        })
+        .unwrap();
+    let (mut client, mut server) = make_pair_for_arc_configs(&client_config, &server_configs[1]);
+    do_handshake(&mut client, &mut server);
+    assert_eq!(server.handshake_kind(), Some(HandshakeKind::Resumed));
+    let (mut client, mut server) = make_pair_for_arc_configs(&client_config, &server_configs[0]);
+    do_handshake(&mut client, &mut server);
+    assert_eq!(server.handshake_kind(), Some(HandshakeKind::Resumed));
+
+    // once a key is withdrawn, its tickets are no longer accepted
+    for ticketer in &ticketers {
+        ticketer
+            .update(TicketKeySet {
+                current: key(3),
+                next: None,
+                previous: vec![],
+            })
+            .unwrap();
+    }
+    let (mut client, mut server) = make_pair_for_arc_configs(&client_config, &server_configs[0]);
+    do_handshake(&mut client, &mut server);
+    assert_eq!(server.handshake_kind(), Some(HandshakeKind::Full));
+}
+
+#[test]
+fn tls13_stateless_resumption() {
+    let kt = KeyType::Rsa2048;
+    let provider = provider::default_provider();
+    let client_config = make_client_config_with_versions(kt, &[&rustls::version::TLS13], &provider);
+    let client_config = Arc::new(client_config);
+
+    let mut server_config = make_server_config(kt, &provider);
+    server_config.ticketer = provider::Ticketer::new().unwrap();
+    let storage = Arc::new(ServerStorage::new());
+    server_config.session_storage = storage.clone();
+    let server_config = Arc::new(server_config);
+
+    // full handshake
+    let (mut client, mut server) = make_pair_for_arc_configs(&client_config, &server_config);
+    let (full_c2s, full_s2c) = do_handshake(&mut client, &mut server);
     assert_eq!(storage.puts(), 0);
     assert_eq!(storage.gets(), 0);
     assert_eq!(storage.takes(), 0);
@@ -4553,118 +5217,534 @@ fn early_data_not_available() {
     assert!(client.early_data().is_none());
 }
 
-fn early_data_configs() -> (Arc<ClientConfig>, Arc<ServerConfig>) {
+#[derive(Debug)]
+struct ServerPsks(Vec<Arc<ExternalPsk>>);
+
+impl ResolvesServerPsk for ServerPsks {
+    fn resolve(&self, identity: &[u8]) -> Option<Arc<ExternalPsk>> {
+        self.0
+            .iter()
+            .find(|psk| psk.identity() == identity)
+            .cloned()
+    }
+}
+
+fn external_psk_configs(
+    client_psk: ExternalPsk,
+    server_psk: ExternalPsk,
+    kex_modes: &[PskKexMode],
+) -> (ClientConfig, ServerConfig) {
     let kt = KeyType::Rsa2048;
     let provider = provider::default_provider();
-    let mut client_config = make_client_config(kt, &provider);
-    client_config.enable_early_data = true;
-    client_config.resumption = Resumption::store(Arc::new(ClientStorage::new()));
+    let mut client_config =
+        make_client_config_with_versions(kt, &[&rustls::version::TLS13], &provider);
+    client_config.external_psks = vec![Arc::new(client_psk)];
+    client_config.psk_kex_modes = kex_modes.to_vec();
 
     let mut server_config = make_server_config(kt, &provider);
-    server_config.max_early_data_size = 1234;
-    (Arc::new(client_config), Arc::new(server_config))
+    server_config.psk_resolver = Arc::new(ServerPsks(vec![Arc::new(server_psk)]));
+    server_config.psk_kex_modes = kex_modes.to_vec();
+    (client_config, server_config)
+}
+
+fn tls13_suite(suite: SupportedCipherSuite) -> &'static Tls13CipherSuite {
+    suite.tls13().unwrap()
 }
 
 #[test]
-fn early_data_is_available_on_resumption() {
-    let (client_config, server_config) = early_data_configs();
+fn tls13_external_psk_with_dhe() {
+    let suite = tls13_suite(cipher_suite::TLS13_AES_256_GCM_SHA384);
+    let (client_config, server_config) = external_psk_configs(
+        ExternalPsk::new(*b"client1", [0x11; 32], suite).unwrap(),
+        ExternalPsk::new(*b"client1", [0x11; 32], suite).unwrap(),
+        &[PskKexMode::PskWithDhe],
+    );
 
-    let (mut client, mut server) = make_pair_for_arc_configs(&client_config, &server_config);
+    let (mut client, mut server) = make_pair_for_configs(client_config, server_config);
     do_handshake(&mut client, &mut server);
-
-    let (mut client, mut server) = make_pair_for_arc_configs(&client_config, &server_config);
-    assert!(client.early_data().is_some());
-    assert_eq!(
+    assert_eq!(client.handshake_kind(), Some(HandshakeKind::ExternalPsk));
+    assert_eq!(server.handshake_kind(), Some(HandshakeKind::ExternalPsk));
+    assert_eq!(server.external_psk_identity(), Some(&b"client1"[..]));
+    assert!(client.peer_certificates().is_none());
+    assert!(
         client
-            .early_data()
-            .unwrap()
-            .bytes_left(),
-        1234
+            .negotiated_key_exchange_group()
+            .is_some()
     );
+
     client
-        .early_data()
-        .unwrap()
-        .flush()
+        .writer()
+        .write_all(b"hello")
         .unwrap();
-    assert_eq!(
-        client
-            .early_data()
-            .unwrap()
-            .write(b"hello")
-            .unwrap(),
-        5
+    transfer(&mut client, &mut server);
+    server.process_new_packets().unwrap();
+    check_read(&mut server.reader(), b"hello");
+}
+
+#[test]
+fn tls13_external_psk_without_dhe() {
+    let suite = tls13_suite(cipher_suite::TLS13_AES_256_GCM_SHA384);
+    let (client_config, server_config) = external_psk_configs(
+        ExternalPsk::new(*b"client1", [0x22; 32], suite).unwrap(),
+        ExternalPsk::new(*b"client1", [0x22; 32], suite).unwrap(),
+        &[PskKexMode::PskOnly],
     );
-    do_handshake(&mut client, &mut server);
 
-    let mut received_early_data = [0u8; 5];
-    assert_eq!(
+    let (mut client, mut server) = make_pair_for_configs(client_config, server_config);
+    do_handshake(&mut client, &mut server);
+    assert_eq!(client.handshake_kind(), Some(HandshakeKind::ExternalPsk));
+    assert_eq!(server.handshake_kind(), Some(HandshakeKind::ExternalPsk));
+    assert!(
+        client
+            .negotiated_key_exchange_group()
+            .is_none()
+    );
+    assert!(
         server
-            .early_data()
-            .expect("early_data didn't happen")
-            .read(&mut received_early_data)
-            .expect("early_data failed unexpectedly"),
-        5
+            .negotiated_key_exchange_group()
+            .is_none()
     );
-    assert_eq!(&received_early_data[..], b"hello");
+
+    client
+        .writer()
+        .write_all(b"hello")
+        .unwrap();
+    transfer(&mut client, &mut server);
+    server.process_new_packets().unwrap();
+    check_read(&mut server.reader(), b"hello");
 }
 
-#[test]
-fn early_data_not_available_on_server_before_client_hello() {
-    let mut server = ServerConnection::new(Arc::new(make_server_config(
-        KeyType::Rsa2048,
-        &provider::default_provider(),
-    )))
-    .unwrap();
-    assert!(server.early_data().is_none());
+/// Resolves no certificate, after checking the client sent no key shares.
+#[derive(Debug)]
+struct NoCertificateNorKeyShare;
+
+impl ResolvesServerCert for NoCertificateNorKeyShare {
+    fn resolve(&self, client_hello: &ClientHello) -> Option<Arc<sign::CertifiedKey>> {
+        assert!(
+            client_hello
+                .extensions()
+                .all(|(typ, body)| typ != u16::from(ExtensionType::KeyShare) || body == [0, 0])
+        );
+        None
+    }
 }
 
 #[test]
-fn early_data_can_be_rejected_by_server() {
-    let (client_config, server_config) = early_data_configs();
+fn tls13_external_psk_without_certificate_or_key_share() {
+    let suite = tls13_suite(cipher_suite::TLS13_AES_256_GCM_SHA384);
+    let (client_config, mut server_config) = external_psk_configs(
+        ExternalPsk::new(*b"device1", [0x28; 32], suite).unwrap(),
+        ExternalPsk::new(*b"device1", [0x28; 32], suite).unwrap(),
+        &[PskKexMode::PskOnly],
+    );
+    server_config.cert_resolver = Arc::new(NoCertificateNorKeyShare);
 
-    let (mut client, mut server) = make_pair_for_arc_configs(&client_config, &server_config);
+    let (mut client, mut server) = make_pair_for_configs(client_config, server_config);
     do_handshake(&mut client, &mut server);
-
-    let (mut client, mut server) = make_pair_for_arc_configs(&client_config, &server_config);
-    assert!(client.early_data().is_some());
-    assert_eq!(
-        client
-            .early_data()
-            .unwrap()
-            .bytes_left(),
-        1234
+    assert_eq!(client.handshake_kind(), Some(HandshakeKind::ExternalPsk));
+    assert_eq!(server.handshake_kind(), Some(HandshakeKind::ExternalPsk));
+    assert_eq!(server.external_psk_identity(), Some(&b"device1"[..]));
+    assert!(
+        server
+            .negotiated_key_exchange_group()
+            .is_none()
     );
+
     client
-        .early_data()
-        .unwrap()
-        .flush()
+        .writer()
+        .write_all(b"hello")
         .unwrap();
-    assert_eq!(
-        client
-            .early_data()
-            .unwrap()
-            .write(b"hello")
-            .unwrap(),
-        5
+    transfer(&mut client, &mut server);
+    server.process_new_packets().unwrap();
+    check_read(&mut server.reader(), b"hello");
+}
+
+#[test]
+fn tls13_external_psk_without_certificate_for_unknown_identity() {
+    let suite = tls13_suite(cipher_suite::TLS13_AES_256_GCM_SHA384);
+    let (client_config, mut server_config) = external_psk_configs(
+        ExternalPsk::new(*b"device1", [0x29; 32], suite).unwrap(),
+        ExternalPsk::new(*b"device2", [0x29; 32], suite).unwrap(),
+        &[PskKexMode::PskOnly],
     );
-    server.reject_early_data();
-    do_handshake(&mut client, &mut server);
+    server_config.cert_resolver = Arc::new(NoCertificateNorKeyShare);
 
-    assert!(!client.is_early_data_accepted());
+    let (mut client, mut server) = make_pair_for_configs(client_config, server_config);
+    assert_eq!(
+        do_handshake_until_error(&mut client, &mut server),
+        Err(ErrorFromPeer::Server(Error::General(
+            "no server certificate chain resolved".into()
+        )))
+    );
 }
 
 #[test]
-fn early_data_is_limited_on_client() {
-    let (client_config, server_config) = early_data_configs();
+fn tls13_psk_only_client_retries_with_key_share_for_unknown_identity() {
+    let suite = tls13_suite(cipher_suite::TLS13_AES_256_GCM_SHA384);
+    let (client_config, server_config) = external_psk_configs(
+        ExternalPsk::new(*b"device1", [0x2a; 32], suite).unwrap(),
+        ExternalPsk::new(*b"device2", [0x2a; 32], suite).unwrap(),
+        &[PskKexMode::PskOnly],
+    );
 
-    // warm up
-    let (mut client, mut server) = make_pair_for_arc_configs(&client_config, &server_config);
+    let (mut client, mut server) = make_pair_for_configs(client_config, server_config);
     do_handshake(&mut client, &mut server);
-
-    let (mut client, mut server) = make_pair_for_arc_configs(&client_config, &server_config);
-    assert!(client.early_data().is_some());
     assert_eq!(
-        client
+        client.handshake_kind(),
+        Some(HandshakeKind::FullWithHelloRetryRequest)
+    );
+    assert_eq!(
+        server.handshake_kind(),
+        Some(HandshakeKind::FullWithHelloRetryRequest)
+    );
+    assert!(client.peer_certificates().is_some());
+}
+
+#[test]
+fn tls13_external_psk_server_prefers_dhe() {
+    let suite = tls13_suite(cipher_suite::TLS13_AES_256_GCM_SHA384);
+    let (mut client_config, mut server_config) = external_psk_configs(
+        ExternalPsk::new(*b"client1", [0x33; 32], suite).unwrap(),
+        ExternalPsk::new(*b"client1", [0x33; 32], suite).unwrap(),
+        &[],
+    );
+    client_config.psk_kex_modes = vec![PskKexMode::PskOnly, PskKexMode::PskWithDhe];
+    server_config.psk_kex_modes = vec![PskKexMode::PskWithDhe, PskKexMode::PskOnly];
+
+    let (mut client, mut server) = make_pair_for_configs(client_config, server_config);
+    do_handshake(&mut client, &mut server);
+    assert_eq!(server.handshake_kind(), Some(HandshakeKind::ExternalPsk));
+    assert!(
+        client
+            .negotiated_key_exchange_group()
+            .is_some()
+    );
+}
+
+#[test]
+fn tls13_imported_psk() {
+    let base = ExternalPsk::new(
+        *b"base",
+        [0x44; 32],
+        tls13_suite(cipher_suite::TLS13_AES_128_GCM_SHA256),
+    )
+    .unwrap();
+    let target = tls13_suite(cipher_suite::TLS13_AES_256_GCM_SHA384);
+    let imported = base.import(b"context", target).unwrap();
+    assert_eq!(
+        imported.identity(),
+        &b"\x00\x04base\x00\x07context\x03\x04\x00\x02"[..]
+    );
+    assert_eq!(imported.suite().common.suite, target.common.suite);
+
+    let (client_config, server_config) = external_psk_configs(
+        imported,
+        base.import(b"context", target).unwrap(),
+        &[PskKexMode::PskWithDhe],
+    );
+
+    let (mut client, mut server) = make_pair_for_configs(client_config, server_config);
+    do_handshake(&mut client, &mut server);
+    assert_eq!(server.handshake_kind(), Some(HandshakeKind::ExternalPsk));
+    assert_eq!(
+        client.negotiated_cipher_suite(),
+        Some(cipher_suite::TLS13_AES_256_GCM_SHA384)
+    );
+
+    // A different context yields a different identity.
+    assert_ne!(
+        base.import(b"other", target)
+            .unwrap()
+            .identity(),
+        base.import(b"context", target)
+            .unwrap()
+            .identity()
+    );
+}
+
+#[test]
+fn tls13_external_psk_with_incorrect_secret() {
+    let suite = tls13_suite(cipher_suite::TLS13_AES_256_GCM_SHA384);
+    let (client_config, server_config) = external_psk_configs(
+        ExternalPsk::new(*b"client1", [0x55; 32], suite).unwrap(),
+        ExternalPsk::new(*b"client1", [0x66; 32], suite).unwrap(),
+        &[PskKexMode::PskWithDhe],
+    );
+
+    let (mut client, mut server) = make_pair_for_configs(client_config, server_config);
+    assert_eq!(
+        do_handshake_until_error(&mut client, &mut server),
+        Err(ErrorFromPeer::Server(
+            PeerMisbehaved::IncorrectBinder.into()
+        ))
+    );
+}
+
+#[test]
+fn tls13_external_psk_with_unknown_identity() {
+    let suite = tls13_suite(cipher_suite::TLS13_AES_256_GCM_SHA384);
+    let (client_config, server_config) = external_psk_configs(
+        ExternalPsk::new(*b"client1", [0x77; 32], suite).unwrap(),
+        ExternalPsk::new(*b"client2", [0x77; 32], suite).unwrap(),
+        &[PskKexMode::PskWithDhe],
+    );
+
+    let (mut client, mut server) = make_pair_for_configs(client_config, server_config);
+    do_handshake(&mut client, &mut server);
+    assert_eq!(client.handshake_kind(), Some(HandshakeKind::Full));
+    assert_eq!(server.handshake_kind(), Some(HandshakeKind::Full));
+    assert_eq!(server.external_psk_identity(), None);
+    assert!(client.peer_certificates().is_some());
+}
+
+#[test]
+fn external_psk_requires_identity() {
+    let suite = tls13_suite(cipher_suite::TLS13_AES_128_GCM_SHA256);
+    assert!(ExternalPsk::new(*b"", [0x88; 32], suite).is_err());
+}
+
+fn early_data_configs() -> (Arc<ClientConfig>, Arc<ServerConfig>) {
+    let kt = KeyType::Rsa2048;
+    let provider = provider::default_provider();
+    let mut client_config = make_client_config(kt, &provider);
+    client_config.enable_early_data = true;
+    client_config.resumption = Resumption::store(Arc::new(ClientStorage::new()));
+
+    let mut server_config = make_server_config(kt, &provider);
+    server_config.max_early_data_size = 1234;
+    (Arc::new(client_config), Arc::new(server_config))
+}
+
+#[test]
+fn early_data_is_available_on_resumption() {
+    let (client_config, server_config) = early_data_configs();
+
+    let (mut client, mut server) = make_pair_for_arc_configs(&client_config, &server_config);
+    do_handshake(&mut client, &mut server);
+
+    let (mut client, mut server) = make_pair_for_arc_configs(&client_config, &server_config);
+    assert!(client.early_data().is_some());
+    assert_eq!(
+        client
+            .early_data()
+            .unwrap()
+            .bytes_left(),
+        1234
+    );
+    client
+        .early_data()
+        .unwrap()
+        .flush()
+        .unwrap();
+    assert_eq!(
+        client
+            .early_data()
+            .unwrap()
+            .write(b"hello")
+            .unwrap(),
+        5
+    );
+    do_handshake(&mut client, &mut server);
+
+    let mut received_early_data = [0u8; 5];
+    assert_eq!(
+        server
+            .early_data()
+            .expect("early_data didn't happen")
+            .read(&mut received_early_data)
+            .expect("early_data failed unexpectedly"),
+        5
+    );
+    assert_eq!(&received_early_data[..], b"hello");
+}
+
+#[test]
+fn early_data_not_available_on_server_before_client_hello() {
+    let mut server = ServerConnection::new(Arc::new(make_server_config(
+        KeyType::Rsa2048,
+        &provider::default_provider(),
+    )))
+    .unwrap();
+    assert!(server.early_data().is_none());
+}
+
+#[test]
+fn early_data_can_be_rejected_by_server() {
+    let (client_config, server_config) = early_data_configs();
+
+    let (mut client, mut server) = make_pair_for_arc_configs(&client_config, &server_config);
+    do_handshake(&mut client, &mut server);
+
+    let (mut client, mut server) = make_pair_for_arc_configs(&client_config, &server_config);
+    assert!(client.early_data().is_some());
+    assert_eq!(
+        client
+            .early_data()
+            .unwrap()
+            .bytes_left(),
+        1234
+    );
+    client
+        .early_data()
+        .unwrap()
+        .flush()
+        .unwrap();
+    assert_eq!(
+        client
+            .early_data()
+            .unwrap()
+            .write(b"hello")
+            .unwrap(),
+        5
+    );
+    server.reject_early_data();
+    do_handshake(&mut client, &mut server);
+
+    assert!(!client.is_early_data_accepted());
+}
+
+fn early_data_configs_with_stateless_tickets(
+    anti_replay: bool,
+) -> (Arc<ClientConfig>, Arc<ServerConfig>) {
+    let (client_config, server_config) = early_data_configs();
+    let mut server_config = Arc::into_inner(server_config).unwrap();
+    server_config.ticketer = provider::Ticketer::new().unwrap();
+    if anti_replay {
+        server_config.anti_replay = Arc::new(ClientHelloBloomFilter::new(
+            core::time::Duration::from_secs(10),
+            1000,
+        ));
+    }
+    (client_config, Arc::new(server_config))
+}
+
+/// Resume a session from `client_config`, write some early data, and return the client's first flight.
+fn client_first_flight_with_early_data(client_config: &Arc<ClientConfig>) -> Vec<u8> {
+    let mut client =
+        ClientConnection::new(client_config.clone(), server_name("localhost")).unwrap();
+    assert_eq!(
+        client
+            .early_data()
+            .unwrap()
+            .write(b"hello")
+            .unwrap(),
+        5
+    );
+
+    let mut flight = Vec::new();
+    while client.wants_write() {
+        client.write_tls(&mut flight).unwrap();
+    }
+    flight
+}
+
+fn server_receives(server_config: &Arc<ServerConfig>, mut flight: &[u8]) -> ServerConnection {
+    let mut server = ServerConnection::new(server_config.clone()).unwrap();
+    while !flight.is_empty() {
+        server.read_tls(&mut flight).unwrap();
+        server.process_new_packets().unwrap();
+    }
+    server
+}
+
+#[test]
+fn early_data_with_stateless_tickets_requires_anti_replay() {
+    for anti_replay in [false, true] {
+        let (client_config, server_config) = early_data_configs_with_stateless_tickets(anti_replay);
+
+        let (mut client, mut server) = make_pair_for_arc_configs(&client_config, &server_config);
+        do_handshake(&mut client, &mut server);
+
+        let (mut client, mut server) = make_pair_for_arc_configs(&client_config, &server_config);
+        assert_eq!(client.early_data().is_some(), anti_replay);
+        if let Some(mut early_data) = client.early_data() {
+            early_data.write_all(b"hello").unwrap();
+        }
+        do_handshake(&mut client, &mut server);
+        assert_eq!(server.handshake_kind(), Some(HandshakeKind::Resumed));
+        assert_eq!(client.is_early_data_accepted(), anti_replay);
+    }
+}
+
+#[test]
+fn replayed_early_data_is_rejected() {
+    let (client_config, server_config) = early_data_configs_with_stateless_tickets(true);
+
+    let (mut client, mut server) = make_pair_for_arc_configs(&client_config, &server_config);
+    do_handshake(&mut client, &mut server);
+
+    let flight = client_first_flight_with_early_data(&client_config);
+
+    let mut server = server_receives(&server_config, &flight);
+    let mut received_early_data = [0u8; 5];
+    server
+        .early_data()
+        .expect("early_data didn't happen")
+        .read_exact(&mut received_early_data)
+        .unwrap();
+    assert_eq!(&received_early_data[..], b"hello");
+
+    // the replayed ClientHello is accepted, but its early data is not
+    let mut server = server_receives(&server_config, &flight);
+    assert!(server.early_data().is_none());
+    assert!(server.wants_write());
+
+    // while a new ClientHello from the same client is fine
+    let flight = client_first_flight_with_early_data(&client_config);
+    let mut server = server_receives(&server_config, &flight);
+    assert!(server.early_data().is_some());
+}
+
+#[test]
+fn replayed_early_data_is_rejected_after_it_is_forgotten() {
+    let window = core::time::Duration::from_secs(10);
+    let (client_config, server_config) = early_data_configs_with_stateless_tickets(true);
+    let client_time = Arc::new(FakeTime::now());
+    let mut client_config = Arc::into_inner(client_config).unwrap();
+    client_config.time_provider = client_time.clone();
+    let client_config = Arc::new(client_config);
+
+    let server_time = Arc::new(FakeTime::now());
+    let anti_replay = Arc::new(ClientHelloBloomFilter::new(window, 1000));
+    let mut server_config = Arc::into_inner(server_config).unwrap();
+    server_config.time_provider = server_time.clone();
+    server_config.anti_replay = anti_replay.clone();
+    let server_config = Arc::new(server_config);
+
+    let (mut client, mut server) = make_pair_for_arc_configs(&client_config, &server_config);
+    do_handshake(&mut client, &mut server);
+
+    // the filter's current window starts now
+    let now = server_time.current_time().unwrap();
+    assert!(anti_replay.record(&[0; 32], now));
+
+    // the client's view of the ticket age is ahead by half the window
+    server_time.advance(9);
+    client_time.advance(14);
+    let flight = client_first_flight_with_early_data(&client_config);
+    let mut server = server_receives(&server_config, &flight);
+    assert!(server.early_data().is_some());
+
+    // after one and a half windows, the filter has forgotten the ClientHello,
+    // but the ticket age is too far from the server's to accept its early data
+    server_time.advance(15);
+    let mut server = server_receives(&server_config, &flight);
+    assert!(server.early_data().is_none());
+    assert!(server.wants_write());
+}
+
+#[test]
+fn early_data_is_limited_on_client() {
+    let (client_config, server_config) = early_data_configs();
+
+    // warm up
+    let (mut client, mut server) = make_pair_for_arc_configs(&client_config, &server_config);
+    do_handshake(&mut client, &mut server);
+
+    let (mut client, mut server) = make_pair_for_arc_configs(&client_config, &server_config);
+    assert!(client.early_data().is_some());
+    assert_eq!(
+        client
             .early_data()
             .unwrap()
             .bytes_left(),
@@ -5658,14 +6738,299 @@ fn test_client_sends_helloretryrequest() {
     ));
 }
 
+fn make_configs_for_stateless_retry(
+    provider: &CryptoProvider,
+) -> (Arc<ClientConfig>, Arc<ServerConfig>) {
+    // client sends a secp384r1 key share, but server only accepts x25519
+    let client_config = make_client_config_with_kx_groups(
+        KeyType::Rsa2048,
+        vec![provider::kx_group::SECP384R1, provider::kx_group::X25519],
+        provider,
+    );
+    let mut server_config = make_server_config_with_kx_groups(
+        KeyType::Rsa2048,
+        vec![provider::kx_group::X25519],
+        provider,
+    );
+    server_config.hello_retry_cookies = provider::Ticketer::new().unwrap();
+    (Arc::new(client_config), Arc::new(server_config))
+}
+
 #[test]
-fn test_client_attempts_to_use_unsupported_kx_group() {
-    // common to both client configs
-    let shared_storage = Arc::new(ClientStorage::new());
+fn test_server_stateless_hello_retry_request() {
     let provider = provider::default_provider();
+    let (client_config, server_config) = make_configs_for_stateless_retry(&provider);
 
-    // first, client sends a secp-256 share and server agrees. secp-256 is inserted
-    //   into kx group cache.
+    let (mut client, mut server) = make_pair_for_arc_configs(&client_config, &server_config);
+    transfer(&mut client, &mut server);
+    server.process_new_packets().unwrap();
+    transfer(&mut server, &mut client);
+    client.process_new_packets().unwrap();
+    drop(server);
+
+    // a fresh connection continues from the client's second hello
+    let mut server = ServerConnection::new(server_config).unwrap();
+    do_handshake(&mut client, &mut server);
+
+    assert_eq!(
+        client.handshake_kind(),
+        Some(HandshakeKind::FullWithHelloRetryRequest)
+    );
+    assert_eq!(
+        server.handshake_kind(),
+        Some(HandshakeKind::FullWithHelloRetryRequest)
+    );
+    assert_eq!(
+        server.server_name(),
+        Some(&DnsName::try_from("localhost").unwrap())
+    );
+    assert_eq!(
+        client
+            .negotiated_key_exchange_group()
+            .unwrap()
+            .name(),
+        NamedGroup::X25519
+    );
+}
+
+#[test]
+fn test_server_stateless_hello_retry_request_with_acceptor() {
+    use rustls::server::Acceptor;
+
+    let provider = provider::default_provider();
+    let (client_config, server_config) = make_configs_for_stateless_retry(&provider);
+
+    let (mut client, mut server) = make_pair_for_arc_configs(&client_config, &server_config);
+    transfer(&mut client, &mut server);
+    server.process_new_packets().unwrap();
+    transfer(&mut server, &mut client);
+    client.process_new_packets().unwrap();
+
+    let mut buf = Vec::new();
+    client.write_tls(&mut buf).unwrap();
+    let mut acceptor = Acceptor::default();
+    acceptor
+        .read_tls(&mut buf.as_slice())
+        .unwrap();
+    let accepted = acceptor.accept().unwrap().unwrap();
+    let mut server = accepted
+        .into_connection(server_config)
+        .unwrap();
+    do_handshake(&mut client, &mut server);
+
+    assert_eq!(
+        server.handshake_kind(),
+        Some(HandshakeKind::FullWithHelloRetryRequest)
+    );
+}
+
+#[test]
+fn test_acceptor_rejects_ccs_without_hello_retry_cookies() {
+    use rustls::server::Acceptor;
+
+    let provider = provider::default_provider();
+    let (client_config, server_config) = make_configs_for_stateless_retry(&provider);
+
+    let (mut client, mut server) = make_pair_for_arc_configs(&client_config, &server_config);
+    transfer(&mut client, &mut server);
+    server.process_new_packets().unwrap();
+    transfer(&mut server, &mut client);
+    client.process_new_packets().unwrap();
+
+    let mut buf = Vec::new();
+    client.write_tls(&mut buf).unwrap();
+    let mut acceptor = Acceptor::default();
+    acceptor
+        .read_tls(&mut buf.as_slice())
+        .unwrap();
+    let accepted = acceptor.accept().unwrap().unwrap();
+
+    // a config without cookies did not send the HelloRetryRequest the CCS follows
+    let other_config = make_server_config_with_kx_groups(
+        KeyType::Rsa2048,
+        vec![provider::kx_group::X25519],
+        &provider,
+    );
+    let (err, mut alert) = accepted
+        .into_connection(Arc::new(other_config))
+        .unwrap_err();
+    assert_eq!(
+        err,
+        Error::InappropriateMessage {
+            expect_types: vec![ContentType::Handshake],
+            got_type: ContentType::ChangeCipherSpec,
+        }
+    );
+
+    let mut alert_bytes = Vec::new();
+    alert
+        .write_all(&mut alert_bytes)
+        .unwrap();
+    assert_eq!(
+        alert_bytes,
+        [
+            0x15,
+            0x03,
+            0x03,
+            0x00,
+            0x02,
+            0x02,
+            u8::from(AlertDescription::UnexpectedMessage)
+        ]
+    );
+}
+
+#[test]
+fn test_server_stateful_hello_retry_request_with_cookie() {
+    let provider = provider::default_provider();
+    let (client_config, server_config) = make_configs_for_stateless_retry(&provider);
+
+    // the connection which sent the cookie can still complete the handshake
+    let (mut client, mut server) = make_pair_for_arc_configs(&client_config, &server_config);
+    do_handshake(&mut client, &mut server);
+    assert_eq!(
+        server.handshake_kind(),
+        Some(HandshakeKind::FullWithHelloRetryRequest)
+    );
+}
+
+#[test]
+fn test_server_rejects_invalid_hello_retry_request_cookie() {
+    let provider = provider::default_provider();
+    let (client_config, server_config) = make_configs_for_stateless_retry(&provider);
+
+    let (mut client, mut server) = make_pair_for_arc_configs(&client_config, &server_config);
+    transfer(&mut client, &mut server);
+    server.process_new_packets().unwrap();
+    transfer(&mut server, &mut client);
+    client.process_new_packets().unwrap();
+
+    // a server with different cookie keys cannot continue
+    let mut other_config = (*server_config).clone();
+    other_config.hello_retry_cookies = provider::Ticketer::new().unwrap();
+    let mut server = ServerConnection::new(Arc::new(other_config)).unwrap();
+    transfer(&mut client, &mut server);
+    assert_eq!(
+        server.process_new_packets().err(),
+        Some(Error::PeerMisbehaved(
+            PeerMisbehaved::InvalidHelloRetryRequestCookie
+        ))
+    );
+}
+
+#[test]
+fn test_server_rejects_wrong_key_share_group_after_stateless_retry() {
+    let provider = provider::default_provider();
+    let (client_config, server_config) = make_configs_for_stateless_retry(&provider);
+
+    let (mut client, mut server) = make_pair_for_arc_configs(&client_config, &server_config);
+    transfer(&mut client, &mut server);
+    server.process_new_packets().unwrap();
+    transfer(&mut server, &mut client);
+    client.process_new_packets().unwrap();
+
+    // the retry asked for an x25519 key share: relabel it as a secp384r1 one, which
+    // a server supporting secp384r1 would otherwise select
+    let mut second_hello = Vec::new();
+    client
+        .write_tls(&mut second_hello)
+        .unwrap();
+    let x25519_share = [0x00, 0x1d, 0x00, 0x20];
+    let at = second_hello
+        .windows(x25519_share.len())
+        .position(|window| window == x25519_share)
+        .unwrap();
+    second_hello[at + 1] = 0x18;
+
+    let mut other_config = make_server_config_with_kx_groups(
+        KeyType::Rsa2048,
+        vec![provider::kx_group::SECP384R1, provider::kx_group::X25519],
+        &provider,
+    );
+    other_config.hello_retry_cookies = server_config
+        .hello_retry_cookies
+        .clone();
+    let mut server = ServerConnection::new(Arc::new(other_config)).unwrap();
+    server
+        .read_tls(&mut &second_hello[..])
+        .unwrap();
+    assert_eq!(
+        server.process_new_packets().err(),
+        Some(Error::PeerMisbehaved(PeerMisbehaved::WrongGroupForKeyShare))
+    );
+}
+
+#[test]
+fn test_client_sends_multiple_initial_key_shares() {
+    let provider = provider::default_provider();
+    let server_config = Arc::new(make_server_config_with_kx_groups(
+        KeyType::Rsa2048,
+        vec![provider::kx_group::SECP256R1],
+        &provider,
+    ));
+
+    for (initial_key_shares, expected) in [
+        (1, HandshakeKind::FullWithHelloRetryRequest),
+        (2, HandshakeKind::Full),
+    ] {
+        let mut client_config = make_client_config(KeyType::Rsa2048, &provider);
+        client_config.initial_key_shares = initial_key_shares;
+
+        let (mut client, mut server) =
+            make_pair_for_arc_configs(&Arc::new(client_config), &server_config);
+        do_handshake(&mut client, &mut server);
+        assert_eq!(client.handshake_kind(), Some(expected));
+        assert_eq!(
+            client
+                .negotiated_key_exchange_group()
+                .unwrap()
+                .name(),
+            NamedGroup::secp256r1
+        );
+    }
+}
+
+#[test]
+fn test_client_key_share_groups_per_server_name() {
+    let provider = provider::default_provider();
+    let mut client_config = make_client_config_with_kx_groups(
+        KeyType::Rsa2048,
+        vec![provider::kx_group::SECP384R1, provider::kx_group::X25519],
+        &provider,
+    );
+    let storage = Arc::new(rustls::client::ClientSessionMemoryCache::new(32));
+    storage.set_key_share_groups(server_name("localhost"), vec![NamedGroup::X25519]);
+    client_config.resumption = Resumption::store(storage);
+    let client_config = Arc::new(client_config);
+
+    let server_config = Arc::new(make_server_config_with_kx_groups(
+        KeyType::Rsa2048,
+        vec![provider::kx_group::X25519],
+        &provider,
+    ));
+
+    for (name, expected) in [
+        ("localhost", HandshakeKind::Full),
+        (
+            "second.testserver.com",
+            HandshakeKind::FullWithHelloRetryRequest,
+        ),
+    ] {
+        let mut client = ClientConnection::new(client_config.clone(), server_name(name)).unwrap();
+        let mut server = ServerConnection::new(server_config.clone()).unwrap();
+        do_handshake(&mut client, &mut server);
+        assert_eq!(client.handshake_kind(), Some(expected), "{name}");
+    }
+}
+
+#[test]
+fn test_client_attempts_to_use_unsupported_kx_group() {
+    // common to both client configs
+    let shared_storage = Arc::new(ClientStorage::new());
+    let provider = provider::default_provider();
+
+    // first, client sends a secp-256 share and server agrees. secp-256 is inserted
+    //   into kx group cache.
     let mut client_config_1 = make_client_config_with_kx_groups(
         KeyType::Rsa2048,
         vec![provider::kx_group::SECP256R1],
@@ -5834,128 +7199,1302 @@ fn test_tls13_client_resumption_does_not_reuse_tickets() {
     assert!(matches!(ops[0], ClientStorageOp::TakeTls13Ticket(_, false)));
 }
 
+fn client_session_file_path() -> std::path::PathBuf {
+    std::env::temp_dir().join(format!(
+        "rustls-client-sessions-{}-{:?}",
+        std::process::id(),
+        std::thread::current().id()
+    ))
+}
+
 #[test]
-fn test_client_mtu_reduction() {
-    struct CollectWrites {
-        writevs: Vec<Vec<usize>>,
-    }
+fn test_client_session_file_cache_resumes_in_new_instance() {
+    use rustls::client::ClientSessionFileCache;
 
-    impl io::Write for CollectWrites {
-        fn write(&mut self, _: &[u8]) -> io::Result<usize> {
-            panic!()
-        }
-        fn flush(&mut self) -> io::Result<()> {
-            panic!()
-        }
-        fn write_vectored(&mut self, b: &[io::IoSlice<'_>]) -> io::Result<usize> {
-            let writes = b
-                .iter()
-                .map(|slice| slice.len())
-                .collect::<Vec<usize>>();
-            let len = writes.iter().sum();
-            self.writevs.push(writes);
-            Ok(len)
-        }
-    }
+    let provider = provider::default_provider();
+    let path = client_session_file_path();
+    let _ = std::fs::remove_file(&path);
 
-    fn collect_write_lengths(client: &mut ClientConnection) -> Vec<usize> {
-        let mut collector = CollectWrites { writevs: vec![] };
+    let mut client_config = make_client_config(KeyType::Rsa2048, &provider);
+    client_config.resumption = Resumption::store(Arc::new(ClientSessionFileCache::new(
+        &path,
+        16,
+        &client_config,
+    )));
+    let server_config = Arc::new(make_server_config(KeyType::Rsa2048, &provider));
 
-        client
-            .write_tls(&mut collector)
-            .unwrap();
-        assert_eq!(collector.writevs.len(), 1);
-        collector.writevs[0].clone()
-    }
+    let (mut client, mut server) =
+        make_pair_for_arc_configs(&Arc::new(client_config.clone()), &server_config);
+    do_handshake(&mut client, &mut server);
+    assert_eq!(client.handshake_kind(), Some(HandshakeKind::Full));
+
+    // a new store reading the same file, as in a later process
+    client_config.resumption = Resumption::store(Arc::new(ClientSessionFileCache::new(
+        &path,
+        16,
+        &client_config,
+    )));
+    let (mut client, mut server) =
+        make_pair_for_arc_configs(&Arc::new(client_config), &server_config);
+    do_handshake(&mut client, &mut server);
+    assert_eq!(client.handshake_kind(), Some(HandshakeKind::Resumed));
+
+    std::fs::remove_file(&path).unwrap();
+}
+
+#[test]
+fn test_client_session_file_cache_tickets_are_single_use() {
+    use rustls::client::{ClientSessionFileCache, ClientSessionStore};
 
     let provider = provider::default_provider();
-    for kt in KeyType::all_for_provider(&provider) {
-        let mut client_config = make_client_config(*kt, &provider);
-        client_config.max_fragment_size = Some(64);
-        let mut client =
-            ClientConnection::new(Arc::new(client_config), server_name("localhost")).unwrap();
-        let writes = collect_write_lengths(&mut client);
-        println!("writes at mtu=64: {writes:?}");
-        assert!(writes.iter().all(|x| *x <= 64));
-        assert!(writes.len() > 1);
-    }
+    let path = client_session_file_path();
+    let _ = std::fs::remove_file(&path);
+
+    let mut client_config = make_client_config(KeyType::Rsa2048, &provider);
+    let store = Arc::new(ClientSessionFileCache::new(&path, 16, &client_config));
+    client_config.resumption = Resumption::store(store.clone());
+    let client_config = Arc::new(client_config);
+    let mut server_config = make_server_config(KeyType::Rsa2048, &provider);
+    server_config.send_tls13_tickets = 3;
+    let server_config = Arc::new(server_config);
+
+    let (mut client, mut server) = make_pair_for_arc_configs(&client_config, &server_config);
+    do_handshake(&mut client, &mut server);
+    assert!(
+        store
+            .kx_hint(&server_name("localhost"))
+            .is_some()
+    );
+
+    // tickets taken through one instance are gone from the other
+    let other = ClientSessionFileCache::new(&path, 16, &client_config);
+    assert!(
+        store
+            .take_tls13_ticket(&server_name("localhost"))
+            .is_some()
+    );
+    assert!(
+        other
+            .take_tls13_ticket(&server_name("localhost"))
+            .is_some()
+    );
+    assert!(
+        store
+            .take_tls13_ticket(&server_name("localhost"))
+            .is_some()
+    );
+    assert!(
+        other
+            .take_tls13_ticket(&server_name("localhost"))
+            .is_none()
+    );
+    assert!(
+        store
+            .take_tls13_ticket(&server_name("localhost"))
+            .is_none()
+    );
+
+    std::fs::remove_file(&path).unwrap();
+}
+
+#[cfg(unix)]
+#[test]
+fn test_client_session_file_cache_times_out_on_stale_lock_it_cannot_remove() {
+    use rustls::client::{ClientSessionFileCache, ClientSessionStore};
+
+    let provider = provider::default_provider();
+    let path = client_session_file_path();
+    let mut lock_path = path.clone().into_os_string();
+    lock_path.push(".lock");
+
+    // a directory is a lock file that can be neither read nor removed
+    std::fs::create_dir(&lock_path).unwrap();
+    std::fs::File::open(&lock_path)
+        .unwrap()
+        .set_modified(std::time::SystemTime::now() - core::time::Duration::from_secs(60))
+        .unwrap();
+
+    let client_config = make_client_config(KeyType::Rsa2048, &provider);
+    let store = ClientSessionFileCache::new(&path, 16, &client_config);
+    let (tx, rx) = std::sync::mpsc::channel();
+    std::thread::spawn(move || {
+        tx.send(store.take_tls13_ticket(&server_name("localhost")))
+            .unwrap();
+    });
+    assert!(
+        rx.recv_timeout(core::time::Duration::from_secs(30))
+            .unwrap()
+            .is_none()
+    );
+
+    std::fs::remove_dir(&lock_path).unwrap();
+}
+
+#[test]
+fn test_client_session_file_cache_keeps_tickets_it_cannot_remove() {
+    use rustls::client::{ClientSessionFileCache, ClientSessionStore};
+
+    let provider = provider::default_provider();
+    let path = client_session_file_path();
+    let _ = std::fs::remove_file(&path);
+    let mut tmp_path = path.clone().into_os_string();
+    tmp_path.push(".tmp");
+
+    let mut client_config = make_client_config(KeyType::Rsa2048, &provider);
+    let store = Arc::new(ClientSessionFileCache::new(&path, 16, &client_config));
+    client_config.resumption = Resumption::store(store.clone());
+    let server_config = Arc::new(make_server_config(KeyType::Rsa2048, &provider));
+    let (mut client, mut server) =
+        make_pair_for_arc_configs(&Arc::new(client_config), &server_config);
+    do_handshake(&mut client, &mut server);
+
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        let mode = std::fs::metadata(&path)
+            .unwrap()
+            .permissions()
+            .mode();
+        assert_eq!(mode & 0o777, 0o600);
+    }
+
+    // a directory in the way of the temporary file makes saving fail
+    std::fs::create_dir(&tmp_path).unwrap();
+    assert!(
+        store
+            .take_tls13_ticket(&server_name("localhost"))
+            .is_none()
+    );
+    std::fs::remove_dir(&tmp_path).unwrap();
+    assert!(
+        store
+            .take_tls13_ticket(&server_name("localhost"))
+            .is_some()
+    );
+
+    std::fs::remove_file(&path).unwrap();
+}
+
+#[test]
+fn test_client_session_file_cache_replaces_stale_lock() {
+    use rustls::client::{ClientSessionFileCache, ClientSessionStore};
+
+    let provider = provider::default_provider();
+    let path = client_session_file_path();
+    let _ = std::fs::remove_file(&path);
+    let mut lock_path = path.clone().into_os_string();
+    lock_path.push(".lock");
+
+    let stale = std::fs::File::create(&lock_path).unwrap();
+    stale
+        .set_modified(std::time::SystemTime::now() - core::time::Duration::from_secs(60))
+        .unwrap();
+    drop(stale);
+
+    let client_config = make_client_config(KeyType::Rsa2048, &provider);
+    let store = ClientSessionFileCache::new(&path, 16, &client_config);
+    store.set_kx_hint(server_name("localhost"), rustls::NamedGroup::X25519);
+    assert_eq!(
+        store.kx_hint(&server_name("localhost")),
+        Some(rustls::NamedGroup::X25519)
+    );
+    assert!(!std::path::Path::new(&lock_path).exists());
+
+    std::fs::remove_file(&path).unwrap();
+}
+
+#[test]
+fn test_client_session_file_cache_verifies_stored_sessions() {
+    use rustls::client::{ClientSessionFileCache, ClientSessionStore};
+
+    let provider = provider::default_provider();
+    let path = client_session_file_path();
+    let _ = std::fs::remove_file(&path);
+
+    // sessions stored by a process that accepts any server certificate
+    let mut client_config = make_client_config(KeyType::Rsa2048, &provider);
+    client_config
+        .dangerous()
+        .set_certificate_verifier(Arc::new(MockServerVerifier::accepts_anything()));
+    let store = Arc::new(ClientSessionFileCache::new(&path, 16, &client_config));
+    client_config.resumption = Resumption::store(store.clone());
+    let server_config = Arc::new(make_server_config(KeyType::EcdsaP256, &provider));
+    let (mut client, mut server) =
+        make_pair_for_arc_configs(&Arc::new(client_config), &server_config);
+    do_handshake(&mut client, &mut server);
+
+    // are not used by a process whose verifier rejects the server's certificate
+    let verifying_config = make_client_config(KeyType::Rsa2048, &provider);
+    let verifying = ClientSessionFileCache::new(&path, 16, &verifying_config);
+    assert!(
+        verifying
+            .take_tls13_ticket(&server_name("localhost"))
+            .is_none()
+    );
+    let name = server_name("localhost");
+    let encoded = store
+        .take_tls13_ticket(&name)
+        .unwrap()
+        .encode();
+    assert!(
+        rustls::client::Tls13ClientSessionValue::decode(&encoded, &name, &verifying_config)
+            .is_err()
+    );
+
+    // nor by one without their cipher suite, which leaves them for others
+    let suite = client
+        .negotiated_cipher_suite()
+        .unwrap();
+    let other_suites = CryptoProvider {
+        cipher_suites: provider
+            .cipher_suites
+            .iter()
+            .copied()
+            .filter(|scs| *scs != suite)
+            .collect(),
+        ..provider.clone()
+    };
+    let other_suites_config = make_client_config(KeyType::Rsa2048, &other_suites);
+    let other_suites_store = ClientSessionFileCache::new(&path, 16, &other_suites_config);
+    assert!(
+        other_suites_store
+            .take_tls13_ticket(&name)
+            .is_none()
+    );
+    assert!(store.take_tls13_ticket(&name).is_some());
+
+    std::fs::remove_file(&path).unwrap();
+}
+
+#[test]
+fn test_client_session_values_encode_and_decode() {
+    use rustls::client::{ClientSessionStore, Tls12ClientSessionValue, Tls13ClientSessionValue};
+
+    let provider = provider::default_provider();
+    for version in [&rustls::version::TLS12, &rustls::version::TLS13] {
+        let storage = Arc::new(ClientStorage::new());
+        let mut client_config =
+            make_client_config_with_versions(KeyType::Rsa2048, &[version], &provider);
+        client_config.resumption = Resumption::store(storage.clone());
+        let client_config = Arc::new(client_config);
+        let server_config = Arc::new(make_server_config(KeyType::Rsa2048, &provider));
+
+        let (mut client, mut server) = make_pair_for_arc_configs(&client_config, &server_config);
+        do_handshake(&mut client, &mut server);
+
+        let name = server_name("localhost");
+        if version.version() == ProtocolVersion::TLSv1_2 {
+            let value = storage.tls12_session(&name).unwrap();
+            let encoded = value.encode();
+            let decoded = Tls12ClientSessionValue::decode(&encoded, &name, &client_config).unwrap();
+            assert_eq!(decoded.encode(), encoded);
+            assert!(Tls13ClientSessionValue::decode(&encoded, &name, &client_config).is_err());
+        } else {
+            let value = storage
+                .take_tls13_ticket(&name)
+                .unwrap();
+            let encoded = value.encode();
+            let decoded = Tls13ClientSessionValue::decode(&encoded, &name, &client_config).unwrap();
+            assert_eq!(decoded.encode(), encoded);
+            assert!(Tls12ClientSessionValue::decode(&encoded, &name, &client_config).is_err());
+
+            // an unknown encoding version is rejected
+            let mut future = encoded.clone();
+            future[0] = 0xff;
+            assert!(Tls13ClientSessionValue::decode(&future, &name, &client_config).is_err());
+        }
+    }
+}
+
+#[test]
+fn test_client_mtu_reduction() {
+    struct CollectWrites {
+        writevs: Vec<Vec<usize>>,
+    }
+
+    impl io::Write for CollectWrites {
+        fn write(&mut self, _: &[u8]) -> io::Result<usize> {
+            panic!()
+        }
+        fn flush(&mut self) -> io::Result<()> {
+            panic!()
+        }
+        fn write_vectored(&mut self, b: &[io::IoSlice<'_>]) -> io::Result<usize> {
+            let writes = b
+                .iter()
+                .map(|slice| slice.len())
+                .collect::<Vec<usize>>();
+            let len = writes.iter().sum();
+            self.writevs.push(writes);
+            Ok(len)
+        }
+    }
+
+    fn collect_write_lengths(client: &mut ClientConnection) -> Vec<usize> {
+        let mut collector = CollectWrites { writevs: vec![] };
+
+        client
+            .write_tls(&mut collector)
+            .unwrap();
+        assert_eq!(collector.writevs.len(), 1);
+        collector.writevs[0].clone()
+    }
+
+    let provider = provider::default_provider();
+    for kt in KeyType::all_for_provider(&provider) {
+        let mut client_config = make_client_config(*kt, &provider);
+        client_config.max_fragment_size = Some(64);
+        let mut client =
+            ClientConnection::new(Arc::new(client_config), server_name("localhost")).unwrap();
+        let writes = collect_write_lengths(&mut client);
+        println!("writes at mtu=64: {writes:?}");
+        assert!(writes.iter().all(|x| *x <= 64));
+        assert!(writes.len() > 1);
+    }
+}
+
+#[test]
+fn test_server_mtu_reduction() {
+    let provider = provider::default_provider();
+    let mut server_config = make_server_config(KeyType::Rsa2048, &provider);
+    server_config.max_fragment_size = Some(64);
+    server_config.send_half_rtt_data = true;
+    let (mut client, mut server) = make_pair_for_configs(
+        make_client_config(KeyType::Rsa2048, &provider),
+        server_config,
+    );
+
+    let big_data = [0u8; 2048];
+    server
+        .writer()
+        .write_all(&big_data)
+        .unwrap();
+
+    let encryption_overhead = 20; // FIXME: see issue #991
+
+    transfer(&mut client, &mut server);
+    server.process_new_packets().unwrap();
+    {
+        let mut pipe = OtherSession::new(&mut client);
+        server.write_tls(&mut pipe).unwrap();
+
+        assert_eq!(pipe.writevs.len(), 1);
+        assert!(
+            pipe.writevs[0]
+                .iter()
+                .all(|x| *x <= 64 + encryption_overhead)
+        );
+    }
+
+    client.process_new_packets().unwrap();
+    transfer(&mut client, &mut server);
+    server.process_new_packets().unwrap();
+    {
+        let mut pipe = OtherSession::new(&mut client);
+        server.write_tls(&mut pipe).unwrap();
+        assert_eq!(pipe.writevs.len(), 1);
+        assert!(
+            pipe.writevs[0]
+                .iter()
+                .all(|x| *x <= 64 + encryption_overhead)
+        );
+    }
+
+    client.process_new_packets().unwrap();
+    check_read(&mut client.reader(), &big_data);
+}
+
+fn check_client_max_fragment_size(size: usize) -> Option<Error> {
+    let provider = provider::default_provider();
+    let mut client_config = make_client_config(KeyType::Ed25519, &provider);
+    client_config.max_fragment_size = Some(size);
+    ClientConnection::new(Arc::new(client_config), server_name("localhost")).err()
+}
+
+#[test]
+fn bad_client_max_fragment_sizes() {
+    assert_eq!(
+        check_client_max_fragment_size(31),
+        Some(Error::BadMaxFragmentSize)
+    );
+    assert_eq!(check_client_max_fragment_size(32), None);
+    assert_eq!(check_client_max_fragment_size(64), None);
+    assert_eq!(check_client_max_fragment_size(1460), None);
+    assert_eq!(check_client_max_fragment_size(0x4000), None);
+    assert_eq!(check_client_max_fragment_size(0x4005), None);
+    assert_eq!(
+        check_client_max_fragment_size(0x4006),
+        Some(Error::BadMaxFragmentSize)
+    );
+    assert_eq!(
+        check_client_max_fragment_size(0xffff),
+        Some(Error::BadMaxFragmentSize)
+    );
+}
+
+#[test]
+fn delegated_credential_is_used() {
+    let provider = provider::default_provider();
+    let pki = DelegationPki::new(true, &provider);
+    let credential = pki.credential(2 * 24 * 60 * 60);
+
+    let mut client_config = pki.client_config(&provider);
+    client_config.enable_delegated_credentials = true;
+    let (mut client, mut server) =
+        make_pair_for_configs(client_config, pki.server_config(credential, &provider));
+    do_handshake(&mut client, &mut server);
+    assert_eq!(client.handshake_kind(), Some(HandshakeKind::Full));
+}
+
+#[test]
+fn delegated_credential_requires_client_support() {
+    let provider = provider::default_provider();
+    let pki = DelegationPki::new(true, &provider);
+    let credential = pki.credential(2 * 24 * 60 * 60);
+
+    // the certificate's own key cannot sign, so this fails if the server
+    // does not use the credential.
+    let (mut client, mut server) = make_pair_for_configs(
+        pki.client_config(&provider),
+        pki.server_config(credential, &provider),
+    );
+    assert_eq!(
+        do_handshake_until_error(&mut client, &mut server),
+        Err(ErrorFromPeer::Server(Error::PeerIncompatible(
+            PeerIncompatible::NoSignatureSchemesInCommon
+        )))
+    );
+}
+
+#[test]
+fn delegated_credential_rejected_without_delegation_usage() {
+    let provider = provider::default_provider();
+    let pki = DelegationPki::new(false, &provider);
+    let credential = pki.credential(2 * 24 * 60 * 60);
+
+    let mut client_config = pki.client_config(&provider);
+    client_config.enable_delegated_credentials = true;
+    let (mut client, mut server) =
+        make_pair_for_configs(client_config, pki.server_config(credential, &provider));
+    assert_eq!(
+        do_handshake_until_error(&mut client, &mut server),
+        Err(ErrorFromPeer::Client(Error::PeerMisbehaved(
+            PeerMisbehaved::InvalidDelegatedCredential
+        )))
+    );
+}
+
+#[test]
+fn delegated_credential_rejected_when_expired() {
+    let provider = provider::default_provider();
+    let pki = DelegationPki::new(true, &provider);
+    // the certificate became valid one day ago
+    let credential = pki.credential(60 * 60);
+
+    let mut client_config = pki.client_config(&provider);
+    client_config.enable_delegated_credentials = true;
+    let (mut client, mut server) =
+        make_pair_for_configs(client_config, pki.server_config(credential, &provider));
+    assert!(matches!(
+        do_handshake_until_error(&mut client, &mut server),
+        Err(ErrorFromPeer::Client(Error::InvalidCertificate(
+            CertificateError::ExpiredContext { .. }
+        )))
+    ));
+}
+
+#[test]
+fn delegated_credential_rejected_when_valid_too_long() {
+    let provider = provider::default_provider();
+    let pki = DelegationPki::new(true, &provider);
+    let credential = pki.credential(10 * 24 * 60 * 60);
+
+    let mut client_config = pki.client_config(&provider);
+    client_config.enable_delegated_credentials = true;
+    let (mut client, mut server) =
+        make_pair_for_configs(client_config, pki.server_config(credential, &provider));
+    assert_eq!(
+        do_handshake_until_error(&mut client, &mut server),
+        Err(ErrorFromPeer::Client(Error::PeerMisbehaved(
+            PeerMisbehaved::InvalidDelegatedCredential
+        )))
+    );
+}
+
+#[test]
+fn delegated_key_must_match_credential() {
+    let provider = provider::default_provider();
+    let pki = DelegationPki::new(true, &provider);
+    let credential = pki.credential(2 * 24 * 60 * 60);
+
+    assert_eq!(
+        sign::DelegatedKey::new(credential, pki.end_entity_key.clone()).err(),
+        Some(Error::InconsistentKeys(InconsistentKeys::KeyMismatch))
+    );
+}
+
+/// A CA and an end-entity certificate valid since one day ago, for testing
+/// delegated credentials.
+struct DelegationPki {
+    root: CertificateDer<'static>,
+    end_entity: CertificateDer<'static>,
+    end_entity_key: Arc<dyn sign::SigningKey>,
+    delegated_key: Arc<dyn sign::SigningKey>,
+}
+
+impl DelegationPki {
+    fn new(delegation_usage: bool, provider: &CryptoProvider) -> Self {
+        use rcgen::{
+            BasicConstraints, CertificateParams, CustomExtension, IsCa, Issuer, KeyPair,
+            KeyUsagePurpose,
+        };
+
+        let mut ca_params = CertificateParams::new(Vec::new()).unwrap();
+        ca_params.is_ca = IsCa::Ca(BasicConstraints::Unconstrained);
+        ca_params.key_usages = vec![KeyUsagePurpose::KeyCertSign];
+        let ca_key = KeyPair::generate().unwrap();
+        let root = ca_params.self_signed(&ca_key).unwrap();
+        let issuer = Issuer::new(ca_params, ca_key);
+
+        let mut ee_params = CertificateParams::new(vec!["localhost".into()]).unwrap();
+        ee_params.not_before = time::OffsetDateTime::now_utc() - time::Duration::days(1);
+        ee_params.key_usages = vec![KeyUsagePurpose::DigitalSignature];
+        if delegation_usage {
+            // id-pe-delegationUsage, with a NULL value
+            ee_params
+                .custom_extensions
+                .push(CustomExtension::from_oid_content(
+                    &[1, 3, 6, 1, 4, 1, 44363, 44],
+                    vec![0x05, 0x00],
+                ));
+        }
+        let ee_key = KeyPair::generate().unwrap();
+        let end_entity = ee_params
+            .signed_by(&ee_key, &issuer)
+            .unwrap();
+
+        let load_key = |key: KeyPair| {
+            provider
+                .key_provider
+                .load_private_key(PrivatePkcs8KeyDer::from(key.serialize_der()).into())
+                .unwrap()
+        };
+
+        Self {
+            root: root.der().clone(),
+            end_entity: end_entity.der().clone(),
+            end_entity_key: load_key(ee_key),
+            delegated_key: load_key(KeyPair::generate().unwrap()),
+        }
+    }
+
+    fn credential(&self, valid_time: u32) -> sign::DelegatedCredential {
+        let signer = self
+            .end_entity_key
+            .choose_scheme(&[SignatureScheme::ECDSA_NISTP256_SHA256])
+            .unwrap();
+        sign::DelegatedCredential::new(
+            &self.end_entity,
+            &*signer,
+            valid_time,
+            SignatureScheme::ECDSA_NISTP256_SHA256,
+            self.delegated_key.public_key().unwrap(),
+        )
+        .unwrap()
+    }
+
+    fn client_config(&self, provider: &CryptoProvider) -> ClientConfig {
+        let mut roots = RootCertStore::empty();
+        roots.add(self.root.clone()).unwrap();
+        client_config_builder_with_versions(&[&rustls::version::TLS13], provider)
+            .with_root_certificates(roots)
+            .with_no_client_auth()
+    }
+
+    /// A server config whose certificate's own key cannot sign, so that
+    /// handshakes only succeed using `credential`.
+    fn server_config(
+        &self,
+        credential: sign::DelegatedCredential,
+        provider: &CryptoProvider,
+    ) -> ServerConfig {
+        let mut certified_key = sign::CertifiedKey::new_unchecked(
+            vec![self.end_entity.clone()],
+            Arc::new(SigningKeyWithoutSchemes(self.end_entity_key.clone())),
+        );
+        certified_key.delegated_credential =
+            Some(sign::DelegatedKey::new(credential, self.delegated_key.clone()).unwrap());
+
+        server_config_builder(provider)
+            .with_no_client_auth()
+            .with_cert_resolver(Arc::new(sign::SingleCertAndKey::from(certified_key)))
+    }
+}
+
+/// A SigningKey that cannot sign with any scheme.
+#[derive(Debug)]
+struct SigningKeyWithoutSchemes(Arc<dyn sign::SigningKey>);
+
+impl sign::SigningKey for SigningKeyWithoutSchemes {
+    fn choose_scheme(&self, _offered: &[SignatureScheme]) -> Option<Box<dyn sign::Signer>> {
+        None
+    }
+
+    fn public_key(&self) -> Option<SubjectPublicKeyInfoDer<'_>> {
+        self.0.public_key()
+    }
+
+    fn algorithm(&self) -> rustls::SignatureAlgorithm {
+        self.0.algorithm()
+    }
+}
+
+#[test]
+fn ct_policy_accepts_scts_from_tls_extension() {
+    let provider = provider::default_provider();
+    let kt = KeyType::EcdsaP256;
+    let logs = [TestCtLog::new(&provider), TestCtLog::new(&provider)];
+    let scts = logs
+        .iter()
+        .map(|log| log.x509_sct(&kt.get_chain()[0]))
+        .collect::<Vec<_>>();
+
+    for version in [&rustls::version::TLS12, &rustls::version::TLS13] {
+        let policy = CtPolicy::new([logs[0].log("a"), logs[1].log("b")]);
+        let (mut client, mut server) = make_pair_for_configs(
+            ct_client_config(kt.ca_cert(), policy, version, &provider),
+            ct_server_config(kt, Some(sct_list(&scts)), &provider),
+        );
+        do_handshake(&mut client, &mut server);
+    }
+}
+
+#[test]
+fn ct_policy_rejects_missing_scts() {
+    let provider = provider::default_provider();
+    let kt = KeyType::EcdsaP256;
+    let logs = [TestCtLog::new(&provider), TestCtLog::new(&provider)];
+
+    for version in [&rustls::version::TLS12, &rustls::version::TLS13] {
+        let policy = CtPolicy::new([logs[0].log("a"), logs[1].log("b")]);
+        let (mut client, mut server) = make_pair_for_configs(
+            ct_client_config(kt.ca_cert(), policy, version, &provider),
+            ct_server_config(kt, None, &provider),
+        );
+        assert_eq!(
+            do_handshake_until_error(&mut client, &mut server),
+            Err(ErrorFromPeer::Client(Error::InvalidCertificate(
+                CertificateError::CertificateTransparencyRequired
+            )))
+        );
+    }
+}
+
+#[test]
+fn ct_policy_requires_distinct_operators() {
+    let provider = provider::default_provider();
+    let kt = KeyType::EcdsaP256;
+    let logs = [TestCtLog::new(&provider), TestCtLog::new(&provider)];
+    let scts = logs
+        .iter()
+        .map(|log| log.x509_sct(&kt.get_chain()[0]))
+        .collect::<Vec<_>>();
+
+    let policy = CtPolicy::new([logs[0].log("a"), logs[1].log("a")]);
+    let (mut client, mut server) = make_pair_for_configs(
+        ct_client_config(
+            kt.ca_cert(),
+            policy.clone(),
+            &rustls::version::TLS13,
+            &provider,
+        ),
+        ct_server_config(kt, Some(sct_list(&scts)), &provider),
+    );
+    assert_eq!(
+        do_handshake_until_error(&mut client, &mut server),
+        Err(ErrorFromPeer::Client(Error::InvalidCertificate(
+            CertificateError::CertificateTransparencyRequired
+        )))
+    );
+
+    let (mut client, mut server) = make_pair_for_configs(
+        ct_client_config(
+            kt.ca_cert(),
+            policy.with_min_operators(1),
+            &rustls::version::TLS13,
+            &provider,
+        ),
+        ct_server_config(kt, Some(sct_list(&scts)), &provider),
+    );
+    do_handshake(&mut client, &mut server);
+}
+
+#[test]
+fn ct_policy_ignores_scts_from_unknown_logs() {
+    let provider = provider::default_provider();
+    let kt = KeyType::EcdsaP256;
+    let logs = [TestCtLog::new(&provider), TestCtLog::new(&provider)];
+    let scts = logs
+        .iter()
+        .map(|log| log.x509_sct(&kt.get_chain()[0]))
+        .collect::<Vec<_>>();
+
+    let policy = CtPolicy::new([logs[0].log("a")]).with_min_operators(1);
+    let (mut client, mut server) = make_pair_for_configs(
+        ct_client_config(kt.ca_cert(), policy, &rustls::version::TLS13, &provider),
+        ct_server_config(kt, Some(sct_list(&scts)), &provider),
+    );
+    assert_eq!(
+        do_handshake_until_error(&mut client, &mut server),
+        Err(ErrorFromPeer::Client(Error::InvalidCertificate(
+            CertificateError::CertificateTransparencyRequired
+        )))
+    );
+}
+
+#[test]
+fn ct_policy_accepts_embedded_scts() {
+    use rcgen::{
+        BasicConstraints, CertificateParams, CustomExtension, IsCa, Issuer, KeyPair, PublicKeyData,
+        SerialNumber,
+    };
+
+    let provider = provider::default_provider();
+    let logs = [TestCtLog::new(&provider), TestCtLog::new(&provider)];
+
+    let mut ca_params = CertificateParams::new(Vec::new()).unwrap();
+    ca_params.is_ca = IsCa::Ca(BasicConstraints::Unconstrained);
+    let ca_key = KeyPair::generate().unwrap();
+    let root = ca_params.self_signed(&ca_key).unwrap();
+    let issuer_key_hash = cipher_suite::TLS13_AES_128_GCM_SHA256
+        .tls13()
+        .unwrap()
+        .common
+        .hash_provider
+        .hash(&ca_key.subject_public_key_info());
+    let issuer = Issuer::new(ca_params, ca_key);
+
+    // The SCTs sign the precertificate's TBSCertificate, which is that of the
+    // final certificate without the SCTs.
+    let mut ee_params = CertificateParams::new(vec!["localhost".into()]).unwrap();
+    ee_params.serial_number = Some(SerialNumber::from(1234u64));
+    let ee_key = KeyPair::generate().unwrap();
+    let precert = ee_params
+        .signed_by(&ee_key, &issuer)
+        .unwrap();
+    let scts = logs
+        .iter()
+        .map(|log| log.precert_sct(issuer_key_hash.as_ref(), tbs_certificate(precert.der())))
+        .collect::<Vec<_>>();
+
+    // id-ce-signedCertificateTimestampList
+    ee_params
+        .custom_extensions
+        .push(CustomExtension::from_oid_content(
+            &[1, 3, 6, 1, 4, 1, 11129, 2, 4, 2],
+            der_octet_string(&sct_list(&scts)),
+        ));
+    let end_entity = ee_params
+        .signed_by(&ee_key, &issuer)
+        .unwrap();
+
+    let server_config = server_config_builder(&provider)
+        .with_no_client_auth()
+        .with_single_cert(
+            vec![end_entity.der().clone()],
+            PrivatePkcs8KeyDer::from(ee_key.serialize_der()).into(),
+        )
+        .unwrap();
+    let policy = CtPolicy::new([logs[0].log("a"), logs[1].log("b")]);
+    let (mut client, mut server) = make_pair_for_configs(
+        ct_client_config(
+            root.der().clone(),
+            policy,
+            &rustls::version::TLS13,
+            &provider,
+        ),
+        server_config,
+    );
+    do_handshake(&mut client, &mut server);
+}
+
+#[test]
+fn ct_policy_rejects_log_id_not_matching_key() {
+    let provider = provider::default_provider();
+    let log = TestCtLog::new(&provider);
+    let mut id = log.id;
+    id[0] ^= 1;
+    let policy = CtPolicy::new([CtLog::new(
+        id,
+        log.key
+            .public_key()
+            .unwrap()
+            .into_owned(),
+        "a",
+    )]);
+
+    let mut roots = RootCertStore::empty();
+    roots
+        .add(KeyType::EcdsaP256.ca_cert())
+        .unwrap();
+    let result =
+        WebPkiServerVerifier::builder_with_provider(Arc::new(roots), Arc::new(provider.clone()))
+            .with_ct_policy(policy)
+            .build();
+    assert!(matches!(result, Err(VerifierBuilderError::InvalidCtLogId)));
+}
+
+fn ct_client_config(
+    root: CertificateDer<'_>,
+    policy: CtPolicy,
+    version: &'static SupportedProtocolVersion,
+    provider: &CryptoProvider,
+) -> ClientConfig {
+    let mut roots = RootCertStore::empty();
+    roots.add(root.into_owned()).unwrap();
+    let verifier =
+        WebPkiServerVerifier::builder_with_provider(Arc::new(roots), Arc::new(provider.clone()))
+            .with_ct_policy(policy)
+            .build()
+            .unwrap();
+    client_config_builder_with_versions(&[version], provider)
+        .with_webpki_verifier(verifier)
+        .with_no_client_auth()
+}
+
+fn ct_server_config(
+    kt: KeyType,
+    sct_list: Option<Vec<u8>>,
+    provider: &CryptoProvider,
+) -> ServerConfig {
+    let mut certified_key = sign::CertifiedKey::clone(
+        &kt.certified_key_with_cert_chain(provider)
+            .unwrap(),
+    );
+    certified_key.sct_list = sct_list;
+    server_config_builder(provider)
+        .with_no_client_auth()
+        .with_cert_resolver(Arc::new(sign::SingleCertAndKey::from(certified_key)))
+}
+
+/// A Certificate Transparency log, issuing SCTs timestamped a minute ago.
+struct TestCtLog {
+    id: [u8; 32],
+    key: Arc<dyn sign::SigningKey>,
+}
+
+impl TestCtLog {
+    fn new(provider: &CryptoProvider) -> Self {
+        let key = rcgen::KeyPair::generate().unwrap();
+        let key = provider
+            .key_provider
+            .load_private_key(PrivatePkcs8KeyDer::from(key.serialize_der()).into())
+            .unwrap();
+        // the log ID is the hash of its key
+        let id = cipher_suite::TLS13_AES_128_GCM_SHA256
+            .tls13()
+            .unwrap()
+            .common
+            .hash_provider
+            .hash(key.public_key().unwrap().as_ref());
+        Self {
+            id: id.as_ref().try_into().unwrap(),
+            key,
+        }
+    }
+
+    fn log(&self, operator: &str) -> CtLog {
+        CtLog::new(
+            self.id,
+            self.key
+                .public_key()
+                .unwrap()
+                .into_owned(),
+            operator,
+        )
+    }
+
+    /// An SCT for a certificate, delivered outside the certificate.
+    fn x509_sct(&self, end_entity: &[u8]) -> Vec<u8> {
+        let mut entry = vec![0, 0];
+        entry.extend_from_slice(&(end_entity.len() as u32).to_be_bytes()[1..]);
+        entry.extend_from_slice(end_entity);
+        self.sct(&entry)
+    }
+
+    /// An SCT for a precertificate, embedded in the final certificate.
+    fn precert_sct(&self, issuer_key_hash: &[u8], tbs: &[u8]) -> Vec<u8> {
+        let mut entry = vec![0, 1];
+        entry.extend_from_slice(issuer_key_hash);
+        entry.extend_from_slice(&(tbs.len() as u32).to_be_bytes()[1..]);
+        entry.extend_from_slice(tbs);
+        self.sct(&entry)
+    }
+
+    /// `entry` is the encoding of the `LogEntryType` and the entry itself.
+    fn sct(&self, entry: &[u8]) -> Vec<u8> {
+        let timestamp = (UnixTime::now().as_secs() - 60) * 1000;
+        let mut message = vec![0, 0];
+        message.extend_from_slice(&timestamp.to_be_bytes());
+        message.extend_from_slice(entry);
+        message.extend_from_slice(&[0, 0]);
+        let signature = self
+            .key
+            .choose_scheme(&[SignatureScheme::ECDSA_NISTP256_SHA256])
+            .unwrap()
+            .sign(&message)
+            .unwrap();
+
+        let mut sct = vec![0];
+        sct.extend_from_slice(&self.id);
+        sct.extend_from_slice(&timestamp.to_be_bytes());
+        // no extensions, then an ECDSA_NISTP256_SHA256 signature
+        sct.extend_from_slice(&[0, 0, 0x04, 0x03]);
+        sct.extend_from_slice(&(signature.len() as u16).to_be_bytes());
+        sct.extend_from_slice(&signature);
+        sct
+    }
+}
+
+/// The TLS encoding of a `SignedCertificateTimestampList`.
+fn sct_list(scts: &[Vec<u8>]) -> Vec<u8> {
+    let mut list = Vec::new();
+    for sct in scts {
+        list.extend_from_slice(&(sct.len() as u16).to_be_bytes());
+        list.extend_from_slice(sct);
+    }
+    let mut encoded = (list.len() as u16)
+        .to_be_bytes()
+        .to_vec();
+    encoded.extend_from_slice(&list);
+    encoded
+}
+
+/// The encoding of the `TBSCertificate` of a DER-encoded certificate.
+fn tbs_certificate(cert: &[u8]) -> &[u8] {
+    let tbs = &cert[der_header(cert).0..];
+    let (header_len, len) = der_header(tbs);
+    &tbs[..header_len + len]
+}
+
+/// The length of a DER header, and of the value following it.
+fn der_header(der: &[u8]) -> (usize, usize) {
+    match der[1] {
+        len @ 0..=0x7f => (2, len as usize),
+        0x81 => (3, der[2] as usize),
+        0x82 => (4, u16::from_be_bytes([der[2], der[3]]) as usize),
+        _ => panic!("unsupported DER length"),
+    }
+}
+
+fn der_octet_string(value: &[u8]) -> Vec<u8> {
+    let mut encoded = match value.len() {
+        len @ 0..=0x7f => vec![0x04, len as u8],
+        len @ 0x80..=0xff => vec![0x04, 0x81, len as u8],
+        len => {
+            let len = u16::try_from(len)
+                .unwrap()
+                .to_be_bytes();
+            vec![0x04, 0x82, len[0], len[1]]
+        }
+    };
+    encoded.extend_from_slice(value);
+    encoded
+}
+
+#[test]
+fn record_size_limit_is_negotiated() {
+    let provider = provider::default_provider();
+    for (version, protocol_max) in [
+        (&rustls::version::TLS12, 0x4000),
+        (&rustls::version::TLS13, 0x4001),
+    ] {
+        let mut client_config =
+            make_client_config_with_versions(KeyType::Rsa2048, &[version], &provider);
+        client_config.record_size_limit = Some(256);
+        let (mut client, mut server) = make_pair_for_configs(
+            client_config,
+            make_server_config(KeyType::Rsa2048, &provider),
+        );
+        do_handshake(&mut client, &mut server);
+
+        assert_eq!(server.peer_record_size_limit(), Some(256));
+        assert_eq!(client.peer_record_size_limit(), Some(protocol_max));
+
+        // header, explicit nonce and tag
+        let record_overhead = 5 + 8 + 16;
+        let big_data = [0u8; 2048];
+        server
+            .writer()
+            .write_all(&big_data)
+            .unwrap();
+        {
+            let mut pipe = OtherSession::new(&mut client);
+            server.write_tls(&mut pipe).unwrap();
+            assert_eq!(pipe.writevs.len(), 1);
+            assert!(pipe.writevs[0].len() >= 8);
+            assert!(
+                pipe.writevs[0]
+                    .iter()
+                    .all(|x| *x <= 256 + record_overhead)
+            );
+        }
+        client.process_new_packets().unwrap();
+        check_read(&mut client.reader(), &big_data);
+
+        client
+            .writer()
+            .write_all(&big_data)
+            .unwrap();
+        transfer(&mut client, &mut server);
+        server.process_new_packets().unwrap();
+        check_read(&mut server.reader(), &big_data);
+    }
+}
+
+#[test]
+fn record_size_limit_requires_client_extension() {
+    let provider = provider::default_provider();
+    let mut server_config = make_server_config(KeyType::Rsa2048, &provider);
+    server_config.record_size_limit = Some(256);
+    let (mut client, mut server) = make_pair_for_configs(
+        make_client_config(KeyType::Rsa2048, &provider),
+        server_config,
+    );
+    do_handshake(&mut client, &mut server);
+
+    assert_eq!(client.peer_record_size_limit(), None);
+    assert_eq!(server.peer_record_size_limit(), None);
+}
+
+#[test]
+fn server_rejects_records_over_record_size_limit() {
+    use rustls::crypto::cipher::{OutboundChunks, OutboundPlainMessage};
+
+    let provider = provider::default_provider();
+    let mut client_config =
+        make_client_config_with_versions(KeyType::Rsa2048, &[&rustls::version::TLS13], &provider);
+    client_config.record_size_limit = Some(1024);
+    client_config.enable_secret_extraction = true;
+    let mut server_config = make_server_config(KeyType::Rsa2048, &provider);
+    server_config.record_size_limit = Some(256);
+    let (mut client, mut server) = make_pair_for_configs(client_config, server_config);
+    do_handshake(&mut client, &mut server);
+
+    // a protected record over the limit, which the client itself would not send
+    let Some(SupportedCipherSuite::Tls13(suite)) = client.negotiated_cipher_suite() else {
+        unreachable!();
+    };
+    let (seq, secrets) = client
+        .dangerous_extract_secrets()
+        .unwrap()
+        .tx;
+    let (key, iv) = match secrets {
+        ConnectionTrafficSecrets::Aes128Gcm { key, iv }
+        | ConnectionTrafficSecrets::Aes256Gcm { key, iv }
+        | ConnectionTrafficSecrets::Chacha20Poly1305 { key, iv } => (key, iv),
+        _ => unreachable!(),
+    };
+    let oversized = suite
+        .aead_alg
+        .encrypter(key, iv)
+        .encrypt(
+            OutboundPlainMessage {
+                typ: ContentType::ApplicationData,
+                version: ProtocolVersion::TLSv1_2,
+                payload: OutboundChunks::Single(&[0u8; 256]),
+            },
+            seq,
+        )
+        .unwrap()
+        .encode();
+
+    server
+        .read_tls(&mut oversized.as_slice())
+        .unwrap();
+    assert_eq!(
+        server.process_new_packets().err(),
+        Some(Error::PeerSentOversizedRecord)
+    );
 }
 
 #[test]
-fn test_server_mtu_reduction() {
+fn record_size_limit_does_not_apply_to_unprotected_records() {
     let provider = provider::default_provider();
-    let mut server_config = make_server_config(KeyType::Rsa2048, &provider);
-    server_config.max_fragment_size = Some(64);
-    server_config.send_half_rtt_data = true;
-    let (mut client, mut server) = make_pair_for_configs(
-        make_client_config(KeyType::Rsa2048, &provider),
-        server_config,
-    );
+    let mut client_config =
+        make_client_config_with_versions(KeyType::Rsa4096, &[&rustls::version::TLS12], &provider);
+    client_config.record_size_limit = Some(256);
+    let mut server_config = make_server_config(KeyType::Rsa4096, &provider);
+    server_config.record_size_limit = Some(256);
+    let (mut client, mut server) = make_pair_for_configs(client_config, server_config);
 
-    let big_data = [0u8; 2048];
-    server
-        .writer()
-        .write_all(&big_data)
+    transfer(&mut client, &mut server);
+    server.process_new_packets().unwrap();
+
+    // the rest of the server's plaintext flight after its ServerHello, including
+    // its certificate chain, in a single record as other implementations send it
+    let mut flight = Vec::new();
+    while server.wants_write() {
+        server.write_tls(&mut flight).unwrap();
+    }
+    let server_hello_len = 5 + u16::from_be_bytes([flight[3], flight[4]]) as usize;
+    let (server_hello, mut records) = flight.split_at(server_hello_len);
+    client
+        .read_tls(&mut &server_hello[..])
         .unwrap();
+    client.process_new_packets().unwrap();
+    assert_eq!(client.peer_record_size_limit(), Some(256));
+
+    let mut handshake = Vec::new();
+    while !records.is_empty() {
+        assert_eq!(records[0], 0x16);
+        let len = u16::from_be_bytes([records[3], records[4]]) as usize;
+        handshake.extend_from_slice(&records[5..5 + len]);
+        records = &records[5 + len..];
+    }
+    assert!(handshake.len() > 256);
+    let mut record = vec![0x16, 0x03, 0x03];
+    record.extend_from_slice(&(handshake.len() as u16).to_be_bytes());
+    record.extend_from_slice(&handshake);
+
+    let mut record = record.as_slice();
+    while !record.is_empty() {
+        client.read_tls(&mut record).unwrap();
+        client.process_new_packets().unwrap();
+    }
+    do_handshake(&mut client, &mut server);
+    assert_eq!(client.peer_record_size_limit(), Some(256));
+    assert_eq!(server.peer_record_size_limit(), Some(256));
+}
 
-    let encryption_overhead = 20; // FIXME: see issue #991
+#[test]
+fn bad_record_size_limits() {
+    let provider = provider::default_provider();
+    let mut client_config = make_client_config(KeyType::Ed25519, &provider);
+    client_config.record_size_limit = Some(63);
+    assert_eq!(
+        ClientConnection::new(Arc::new(client_config), server_name("localhost")).err(),
+        Some(Error::BadMaxFragmentSize)
+    );
 
-    transfer(&mut client, &mut server);
-    server.process_new_packets().unwrap();
-    {
-        let mut pipe = OtherSession::new(&mut client);
-        server.write_tls(&mut pipe).unwrap();
+    let mut server_config = make_server_config(KeyType::Ed25519, &provider);
+    server_config.record_size_limit = Some(63);
+    assert_eq!(
+        ServerConnection::new(Arc::new(server_config)).err(),
+        Some(Error::BadMaxFragmentSize)
+    );
+}
 
-        assert_eq!(pipe.writevs.len(), 1);
-        assert!(
-            pipe.writevs[0]
-                .iter()
-                .all(|x| *x <= 64 + encryption_overhead)
+#[test]
+fn max_fragment_length_is_negotiated() {
+    let provider = provider::default_provider();
+    for version in rustls::ALL_VERSIONS {
+        let mut client_config =
+            make_client_config_with_versions(KeyType::Rsa2048, &[version], &provider);
+        client_config.max_fragment_length = Some(MaxFragmentLength::Length512);
+        let (mut client, mut server) = make_pair_for_configs(
+            client_config,
+            make_server_config(KeyType::Rsa2048, &provider),
         );
-    }
+        do_handshake(&mut client, &mut server);
 
-    client.process_new_packets().unwrap();
-    transfer(&mut client, &mut server);
-    server.process_new_packets().unwrap();
-    {
-        let mut pipe = OtherSession::new(&mut client);
-        server.write_tls(&mut pipe).unwrap();
-        assert_eq!(pipe.writevs.len(), 1);
-        assert!(
-            pipe.writevs[0]
-                .iter()
-                .all(|x| *x <= 64 + encryption_overhead)
+        assert_eq!(
+            client.max_fragment_length(),
+            Some(MaxFragmentLength::Length512)
+        );
+        assert_eq!(
+            server.max_fragment_length(),
+            Some(MaxFragmentLength::Length512)
         );
-    }
 
-    client.process_new_packets().unwrap();
-    check_read(&mut client.reader(), &big_data);
+        // header, explicit nonce and tag
+        let record_overhead = 5 + 8 + 16;
+        let big_data = [0u8; 2048];
+        server
+            .writer()
+            .write_all(&big_data)
+            .unwrap();
+        {
+            let mut pipe = OtherSession::new(&mut client);
+            server.write_tls(&mut pipe).unwrap();
+            assert_eq!(pipe.writevs.len(), 1);
+            assert!(pipe.writevs[0].len() >= 4);
+            assert!(
+                pipe.writevs[0]
+                    .iter()
+                    .all(|x| *x <= 512 + record_overhead)
+            );
+        }
+        client.process_new_packets().unwrap();
+        check_read(&mut client.reader(), &big_data);
+
+        client
+            .writer()
+            .write_all(&big_data)
+            .unwrap();
+        {
+            let mut pipe = OtherSession::new(&mut server);
+            client.write_tls(&mut pipe).unwrap();
+            assert!(
+                pipe.writevs[0]
+                    .iter()
+                    .all(|x| *x <= 512 + record_overhead)
+            );
+        }
+        server.process_new_packets().unwrap();
+        check_read(&mut server.reader(), &big_data);
+    }
 }
 
-fn check_client_max_fragment_size(size: usize) -> Option<Error> {
+#[test]
+fn max_fragment_length_is_ignored_with_record_size_limit() {
     let provider = provider::default_provider();
-    let mut client_config = make_client_config(KeyType::Ed25519, &provider);
-    client_config.max_fragment_size = Some(size);
-    ClientConnection::new(Arc::new(client_config), server_name("localhost")).err()
+    let mut client_config = make_client_config(KeyType::Rsa2048, &provider);
+    client_config.max_fragment_length = Some(MaxFragmentLength::Length512);
+    client_config.record_size_limit = Some(1024);
+    let (mut client, mut server) = make_pair_for_configs(
+        client_config,
+        make_server_config(KeyType::Rsa2048, &provider),
+    );
+    do_handshake(&mut client, &mut server);
+
+    assert_eq!(client.max_fragment_length(), None);
+    assert_eq!(server.max_fragment_length(), None);
+    assert_eq!(server.peer_record_size_limit(), Some(1024));
 }
 
 #[test]
-fn bad_client_max_fragment_sizes() {
-    assert_eq!(
-        check_client_max_fragment_size(31),
-        Some(Error::BadMaxFragmentSize)
+fn server_rejects_records_over_max_fragment_length() {
+    let provider = provider::default_provider();
+    let mut client_config =
+        make_client_config_with_versions(KeyType::Rsa2048, &[&rustls::version::TLS12], &provider);
+    client_config.max_fragment_length = Some(MaxFragmentLength::Length512);
+    let (mut client, mut server) = make_pair_for_configs(
+        client_config,
+        make_server_config(KeyType::Rsa2048, &provider),
     );
-    assert_eq!(check_client_max_fragment_size(32), None);
-    assert_eq!(check_client_max_fragment_size(64), None);
-    assert_eq!(check_client_max_fragment_size(1460), None);
-    assert_eq!(check_client_max_fragment_size(0x4000), None);
-    assert_eq!(check_client_max_fragment_size(0x4005), None);
+
+    transfer(&mut client, &mut server);
+    server.process_new_packets().unwrap();
+
+    let mut oversized = vec![0x16, 0x03, 0x03, 0x02, 0x01];
+    oversized.extend_from_slice(&[0u8; 513]);
+    server
+        .read_tls(&mut oversized.as_slice())
+        .unwrap();
     assert_eq!(
-        check_client_max_fragment_size(0x4006),
-        Some(Error::BadMaxFragmentSize)
+        server.process_new_packets().err(),
+        Some(Error::PeerSentOversizedRecord)
     );
+}
+
+#[test]
+fn bad_client_max_fragment_length() {
+    let provider = provider::default_provider();
+    let mut client_config = make_client_config(KeyType::Ed25519, &provider);
+    client_config.max_fragment_length = Some(MaxFragmentLength::Unknown(5));
     assert_eq!(
-        check_client_max_fragment_size(0xffff),
+        ClientConnection::new(Arc::new(client_config), server_name("localhost")).err(),
         Some(Error::BadMaxFragmentSize)
     );
 }
@@ -6282,6 +8821,282 @@ fn test_acceptor() {
     assert_eq!(alert_content, expected);
 }
 
+#[test]
+fn test_acceptor_received_tls() {
+    use rustls::server::Acceptor;
+
+    let provider = provider::default_provider();
+    let client_config = Arc::new(make_client_config(KeyType::Ed25519, &provider));
+    let mut client = ClientConnection::new(client_config, server_name("localhost")).unwrap();
+    let mut buf = Vec::new();
+    client.write_tls(&mut buf).unwrap();
+
+    let mut acceptor = Acceptor::default();
+    acceptor
+        .read_tls(&mut &buf[..10])
+        .unwrap();
+    assert!(acceptor.accept().unwrap().is_none());
+    assert_eq!(acceptor.received_tls(), &buf[..10]);
+
+    acceptor
+        .read_tls(&mut &buf[10..])
+        .unwrap();
+    let accepted = acceptor.accept().unwrap().unwrap();
+    assert_eq!(accepted.received_tls(), &buf[..]);
+    assert!(acceptor.received_tls().is_empty());
+
+    // the received records can be passed through to another server
+    let server_config = Arc::new(make_server_config(KeyType::Ed25519, &provider));
+    let mut server = ServerConnection::new(server_config).unwrap();
+    server
+        .read_tls(&mut accepted.received_tls())
+        .unwrap();
+    server.process_new_packets().unwrap();
+    do_handshake(&mut client, &mut server);
+}
+
+#[test]
+fn test_acceptor_client_hello_fingerprints() {
+    use rustls::server::Acceptor;
+
+    let provider = provider::default_provider();
+    let mut client_config = make_client_config(KeyType::Ed25519, &provider);
+    client_config.alpn_protocols = vec![b"h2".to_vec(), b"http/1.1".to_vec()];
+    let mut client = ClientConnection::new(client_config.into(), server_name("localhost")).unwrap();
+    let mut buf = Vec::new();
+    client.write_tls(&mut buf).unwrap();
+
+    let mut acceptor = Acceptor::default();
+    acceptor
+        .read_tls(&mut &buf[..])
+        .unwrap();
+    let accepted = acceptor.accept().unwrap().unwrap();
+    let hello = accepted.client_hello();
+
+    // the extensions are exactly those that were sent, in the order they were sent
+    let mut encoded = Vec::new();
+    for (typ, body) in hello.extensions() {
+        encoded.extend_from_slice(&typ.to_be_bytes());
+        encoded.extend_from_slice(&(body.len() as u16).to_be_bytes());
+        encoded.extend_from_slice(body);
+    }
+    assert!(buf.ends_with(&encoded));
+    assert!(
+        hello
+            .extensions()
+            .any(|(typ, _)| typ == 0x0000)
+    );
+
+    let join = |items: Vec<u16>| {
+        items
+            .iter()
+            .map(|i| i.to_string())
+            .collect::<Vec<_>>()
+            .join("-")
+    };
+    let expected_ja3 = format!(
+        "771,{},{},{},",
+        join(
+            hello
+                .cipher_suites()
+                .iter()
+                .map(|cs| u16::from(*cs))
+                .collect()
+        ),
+        join(
+            hello
+                .extensions()
+                .map(|(typ, _)| typ)
+                .collect()
+        ),
+        join(
+            provider
+                .kx_groups
+                .iter()
+                .map(|kx| u16::from(kx.name()))
+                .collect()
+        ),
+    );
+    let ja3 = hello.ja3();
+    assert!(ja3.starts_with(&expected_ja3), "{ja3} vs {expected_ja3}");
+
+    let ja4 = hello.ja4(&provider).unwrap();
+    let prefix = format!(
+        "t13d{:02}{:02}h2_",
+        hello.cipher_suites().len(),
+        hello.extensions().count()
+    );
+    assert!(ja4.starts_with(&prefix), "{ja4} vs {prefix}");
+    assert_eq!(ja4.len(), 36);
+    assert_eq!(ja4.as_bytes()[23], b'_');
+}
+
+#[test]
+fn test_client_sends_grease() {
+    use rustls::server::Acceptor;
+
+    let is_grease = |v: u16| v & 0x0f0f == 0x0a0a && v >> 8 == v & 0xff;
+    let provider = provider::default_provider();
+
+    for version in [&rustls::version::TLS12, &rustls::version::TLS13] {
+        let mut client_config = make_client_config(KeyType::Ed25519, &provider);
+        client_config.enable_grease = true;
+        client_config.alpn_protocols = vec![b"h2".to_vec()];
+        let mut client =
+            ClientConnection::new(client_config.into(), server_name("localhost")).unwrap();
+        let mut buf = Vec::new();
+        client.write_tls(&mut buf).unwrap();
+
+        let mut acceptor = Acceptor::default();
+        acceptor
+            .read_tls(&mut &buf[..])
+            .unwrap();
+        let accepted = acceptor.accept().unwrap().unwrap();
+        let hello = accepted.client_hello();
+
+        assert!(is_grease(u16::from(hello.cipher_suites()[0])));
+        assert!(is_grease(u16::from(hello.named_groups().unwrap()[0])));
+
+        let extensions = hello.extensions().collect::<Vec<_>>();
+        assert!(is_grease(extensions[0].0));
+        assert_eq!(extensions[0].1, &[]);
+        assert_eq!(
+            extensions
+                .iter()
+                .filter(|(typ, _)| is_grease(*typ))
+                .count(),
+            2
+        );
+
+        // each of these lists starts with a GREASE value, after its length prefix
+        let body = |typ: u16| {
+            extensions
+                .iter()
+                .find(|(t, _)| *t == typ)
+                .unwrap()
+                .1
+        };
+        let signature_algorithms = body(0x000d);
+        assert!(is_grease(u16::from_be_bytes([
+            signature_algorithms[2],
+            signature_algorithms[3]
+        ])));
+        let supported_versions = body(0x002b);
+        assert!(is_grease(u16::from_be_bytes([
+            supported_versions[1],
+            supported_versions[2]
+        ])));
+        let key_share = body(0x0033);
+        assert!(is_grease(u16::from_be_bytes([key_share[2], key_share[3]])));
+        assert_eq!(&key_share[4..7], &[0, 1, 0]);
+        let alpn = body(0x0010);
+        assert_eq!(alpn[2], 2);
+        assert!(is_grease(u16::from_be_bytes([alpn[3], alpn[4]])));
+
+        // a server ignores all of them
+        let mut server_config =
+            make_server_config_with_versions(KeyType::Ed25519, &[version], &provider);
+        server_config.alpn_protocols = vec![b"h2".to_vec()];
+        let mut server = ServerConnection::new(server_config.into()).unwrap();
+        server
+            .read_tls(&mut accepted.received_tls())
+            .unwrap();
+        server.process_new_packets().unwrap();
+        do_handshake(&mut client, &mut server);
+        assert_eq!(client.protocol_version(), Some(version.version()));
+        assert_eq!(client.alpn_protocol(), Some(&b"h2"[..]));
+    }
+}
+
+#[test]
+fn test_client_grease_with_hello_retry_request() {
+    let provider = provider::default_provider();
+    let mut client_config = make_client_config_with_kx_groups(
+        KeyType::Ed25519,
+        vec![provider::kx_group::SECP384R1, provider::kx_group::X25519],
+        &provider,
+    );
+    client_config.enable_grease = true;
+    let server_config = make_server_config_with_kx_groups(
+        KeyType::Ed25519,
+        vec![provider::kx_group::X25519],
+        &provider,
+    );
+
+    let (mut client, mut server) = make_pair_for_configs(client_config, server_config);
+    do_handshake(&mut client, &mut server);
+    assert_eq!(
+        client.handshake_kind(),
+        Some(HandshakeKind::FullWithHelloRetryRequest)
+    );
+}
+
+#[test]
+fn test_client_grease_with_resumption() {
+    let provider = provider::default_provider();
+    let mut client_config = make_client_config(KeyType::Ed25519, &provider);
+    client_config.enable_grease = true;
+    let client_config = Arc::new(client_config);
+    let server_config = Arc::new(make_server_config(KeyType::Ed25519, &provider));
+
+    for kind in [HandshakeKind::Full, HandshakeKind::Resumed] {
+        let (mut client, mut server) = make_pair_for_arc_configs(&client_config, &server_config);
+        do_handshake(&mut client, &mut server);
+        assert_eq!(client.handshake_kind(), Some(kind));
+    }
+}
+
+#[test]
+fn test_client_hello_padding() {
+    use rustls::client::ClientHelloPadding;
+
+    let provider = provider::default_provider();
+    for (padding, check) in [
+        (
+            ClientHelloPadding::ToLength(2000),
+            (|len| len == 2000) as fn(usize) -> bool,
+        ),
+        (ClientHelloPadding::ToMultipleOf(384), |len| len % 384 == 0),
+    ] {
+        let mut client_config = make_client_config(KeyType::Ed25519, &provider);
+        client_config.padding = padding;
+        let server_config = make_server_config(KeyType::Ed25519, &provider);
+        let (mut client, mut server) = make_pair_for_configs(client_config, server_config);
+
+        let mut buf = Vec::new();
+        client.write_tls(&mut buf).unwrap();
+        // one record, with a five byte header
+        assert!(check(buf.len() - 5), "{padding:?} gave {}", buf.len() - 5);
+
+        server.read_tls(&mut &buf[..]).unwrap();
+        server.process_new_packets().unwrap();
+        do_handshake(&mut client, &mut server);
+    }
+}
+
+#[test]
+fn test_client_hello_padding_with_resumption() {
+    use rustls::client::ClientHelloPadding;
+
+    let provider = provider::default_provider();
+    let mut client_config = make_client_config(KeyType::Ed25519, &provider);
+    client_config.padding = ClientHelloPadding::ToMultipleOf(512);
+    let client_config = Arc::new(client_config);
+    let server_config = Arc::new(make_server_config(KeyType::Ed25519, &provider));
+
+    for kind in [HandshakeKind::Full, HandshakeKind::Resumed] {
+        let (mut client, mut server) = make_pair_for_arc_configs(&client_config, &server_config);
+        let mut buf = Vec::new();
+        client.write_tls(&mut buf).unwrap();
+        assert_eq!((buf.len() - 5) % 512, 0);
+
+        server.read_tls(&mut &buf[..]).unwrap();
+        server.process_new_packets().unwrap();
+        do_handshake(&mut client, &mut server);
+        assert_eq!(client.handshake_kind(), Some(kind));
+    }
+}
+
 #[test]
 fn test_acceptor_rejected_handshake() {
     use rustls::server::Acceptor;
@@ -6983,6 +9798,415 @@ fn test_client_fips_service_indicator_includes_ech_hpke_suite() {
     }
 }
 
+#[cfg(feature = "aws-lc-rs")]
+fn make_ech_server_key(config_id: u8) -> EchKeyPair {
+    EchKeyPair::generate(
+        config_id,
+        DnsName::try_from("testserver.com").unwrap(),
+        ALL_SUPPORTED_SUITES[0],
+    )
+    .unwrap()
+}
+
+#[cfg(feature = "aws-lc-rs")]
+fn make_ech_client_config(key: &EchKeyPair, provider: CryptoProvider) -> ClientConfig {
+    let ech_config = EchConfig::new(key.config_list(), ALL_SUPPORTED_SUITES).unwrap();
+    let config = ClientConfig::builder_with_provider(provider.into())
+        .with_ech(EchMode::Enable(ech_config))
+        .unwrap();
+    finish_client_config(KeyType::Rsa2048, config)
+}
+
+#[cfg(feature = "aws-lc-rs")]
+#[test]
+fn test_server_accepts_ech() {
+    let key = make_ech_server_key(1);
+    let mut server_config = make_server_config(KeyType::Rsa2048, &provider::default_provider());
+    server_config.ech_keys = vec![key.clone()];
+    let client_config = make_ech_client_config(&key, provider::default_provider());
+
+    let (mut client, mut server) = make_pair_for_configs(client_config, server_config);
+    do_handshake(&mut client, &mut server);
+
+    assert_eq!(client.ech_status(), EchStatus::Accepted);
+    assert_eq!(server.ech_status(), ServerEchStatus::Accepted);
+    // The server sees the name from the inner hello, not the public name.
+    assert_eq!(
+        server.server_name(),
+        Some(&DnsName::try_from("localhost").unwrap())
+    );
+    assert_eq!(server.handshake_kind(), Some(HandshakeKind::Full));
+}
+
+#[cfg(feature = "aws-lc-rs")]
+#[test]
+fn test_server_accepts_ech_with_hello_retry_request() {
+    let key = make_ech_server_key(1);
+    let mut server_config = make_server_config_with_kx_groups(
+        KeyType::Rsa2048,
+        vec![provider::kx_group::SECP384R1],
+        &provider::default_provider(),
+    );
+    server_config.ech_keys = vec![key.clone()];
+    let client_config = make_ech_client_config(
+        &key,
+        CryptoProvider {
+            kx_groups: vec![provider::kx_group::X25519, provider::kx_group::SECP384R1],
+            ..provider::default_provider()
+        },
+    );
+
+    let (mut client, mut server) = make_pair_for_configs(client_config, server_config);
+    do_handshake(&mut client, &mut server);
+
+    assert_eq!(client.ech_status(), EchStatus::Accepted);
+    assert_eq!(server.ech_status(), ServerEchStatus::Accepted);
+    assert_eq!(
+        server.server_name(),
+        Some(&DnsName::try_from("localhost").unwrap())
+    );
+    assert_eq!(
+        server.handshake_kind(),
+        Some(HandshakeKind::FullWithHelloRetryRequest)
+    );
+}
+
+#[cfg(feature = "aws-lc-rs")]
+#[test]
+fn test_server_accepts_ech_with_padding() {
+    use rustls::client::ClientHelloPadding;
+
+    let key = make_ech_server_key(1);
+    let mut server_config = make_server_config(KeyType::Rsa2048, &provider::default_provider());
+    server_config.ech_keys = vec![key.clone()];
+    let mut client_config = make_ech_client_config(&key, provider::default_provider());
+    client_config.padding = ClientHelloPadding::ToMultipleOf(512);
+
+    let (mut client, mut server) = make_pair_for_configs(client_config, server_config);
+    let mut buf = Vec::new();
+    client.write_tls(&mut buf).unwrap();
+    // the outer hello is padded
+    assert_eq!((buf.len() - 5) % 512, 0);
+
+    server.read_tls(&mut &buf[..]).unwrap();
+    server.process_new_packets().unwrap();
+    do_handshake(&mut client, &mut server);
+    assert_eq!(client.ech_status(), EchStatus::Accepted);
+    assert_eq!(server.ech_status(), ServerEchStatus::Accepted);
+}
+
+#[cfg(feature = "aws-lc-rs")]
+#[test]
+fn test_acceptor_accepts_ech() {
+    use rustls::server::Acceptor;
+
+    let key = make_ech_server_key(1);
+    let client_config = Arc::new(make_ech_client_config(&key, provider::default_provider()));
+    let mut client = ClientConnection::new(client_config, server_name("localhost")).unwrap();
+    let mut buf = Vec::new();
+    client.write_tls(&mut buf).unwrap();
+
+    let mut acceptor = Acceptor::default();
+    acceptor
+        .read_tls(&mut buf.as_slice())
+        .unwrap();
+    let accepted = acceptor.accept().unwrap().unwrap();
+    // Before the config is available, only the outer hello can be inspected.
+    assert_eq!(
+        accepted.client_hello().server_name(),
+        Some(&DnsName::try_from("testserver.com").unwrap())
+    );
+
+    let mut server_config = make_server_config(KeyType::Rsa2048, &provider::default_provider());
+    server_config.ech_keys = vec![key];
+    let mut server = accepted
+        .into_connection(Arc::new(server_config))
+        .unwrap();
+    do_handshake(&mut client, &mut server);
+
+    assert_eq!(client.ech_status(), EchStatus::Accepted);
+    assert_eq!(server.ech_status(), ServerEchStatus::Accepted);
+    assert_eq!(
+        server.server_name(),
+        Some(&DnsName::try_from("localhost").unwrap())
+    );
+}
+
+/// Accept `client`'s hello on a client-facing server, and decrypt it using `ech_keys`.
+#[cfg(feature = "aws-lc-rs")]
+fn ech_split_mode_accept(client: &mut ClientConnection, ech_keys: &[EchKeyPair]) -> EchSplitMode {
+    let mut buf = Vec::new();
+    client.write_tls(&mut buf).unwrap();
+
+    let mut acceptor = Acceptor::default();
+    acceptor
+        .read_tls(&mut buf.as_slice())
+        .unwrap();
+    let accepted = acceptor.accept().unwrap().unwrap();
+    accepted.decrypt_ech(ech_keys).unwrap()
+}
+
+/// Complete a handshake between `client` and `backend`, relayed by `forwarder`.
+#[cfg(feature = "aws-lc-rs")]
+fn do_ech_split_mode_handshake(
+    client: &mut ClientConnection,
+    forwarder: &mut EchForwarder,
+    backend: &mut ServerConnection,
+) {
+    while client.is_handshaking() || backend.is_handshaking() {
+        let mut buf = Vec::new();
+        while forwarder.wants_write() {
+            forwarder.write_tls(&mut buf).unwrap();
+        }
+        let mut rd = buf.as_slice();
+        while !rd.is_empty() {
+            backend.read_tls(&mut rd).unwrap();
+            backend.process_new_packets().unwrap();
+        }
+
+        transfer(backend, client);
+        client.process_new_packets().unwrap();
+
+        let mut buf = Vec::new();
+        while client.wants_write() {
+            client.write_tls(&mut buf).unwrap();
+        }
+        forwarder.forward(&buf).unwrap();
+    }
+}
+
+#[cfg(feature = "aws-lc-rs")]
+#[test]
+fn test_ech_split_mode() {
+    let key = make_ech_server_key(1);
+    let client_config = Arc::new(make_ech_client_config(&key, provider::default_provider()));
+    let mut client = ClientConnection::new(client_config, server_name("localhost")).unwrap();
+
+    let EchSplitMode::Forward(mut forwarder) = ech_split_mode_accept(&mut client, &[key]) else {
+        panic!("ECH offer not decrypted");
+    };
+    assert_eq!(
+        forwarder.server_name(),
+        Some(&DnsName::try_from("localhost").unwrap())
+    );
+
+    // The backend has no ECH keys, and does not need them.
+    let mut backend_config = make_server_config(KeyType::Rsa2048, &provider::default_provider());
+    backend_config.ech_backend = true;
+    let mut backend = ServerConnection::new(Arc::new(backend_config)).unwrap();
+    do_ech_split_mode_handshake(&mut client, &mut forwarder, &mut backend);
+
+    assert_eq!(client.ech_status(), EchStatus::Accepted);
+    assert_eq!(backend.ech_status(), ServerEchStatus::Forwarded);
+    assert_eq!(
+        backend.server_name(),
+        Some(&DnsName::try_from("localhost").unwrap())
+    );
+}
+
+#[cfg(feature = "aws-lc-rs")]
+#[test]
+fn test_ech_split_mode_alerts_client_when_forwarding_fails() {
+    let key = make_ech_server_key(1);
+    let client_config = Arc::new(make_ech_client_config(&key, provider::default_provider()));
+    let mut client = ClientConnection::new(client_config, server_name("localhost")).unwrap();
+
+    // the hello is followed by a record too large to forward
+    let mut buf = Vec::new();
+    client.write_tls(&mut buf).unwrap();
+    buf.extend_from_slice(&[0x17, 0x03, 0x03, 0xff, 0xff]);
+
+    let mut acceptor = Acceptor::default();
+    acceptor
+        .read_tls(&mut buf.as_slice())
+        .unwrap();
+    let accepted = acceptor.accept().unwrap().unwrap();
+    let (err, mut alert) = accepted
+        .decrypt_ech(&[key])
+        .unwrap_err();
+    assert_eq!(err, Error::InvalidMessage(InvalidMessage::MessageTooLarge));
+
+    let mut alert_bytes = Vec::new();
+    alert
+        .write_all(&mut alert_bytes)
+        .unwrap();
+    assert_eq!(
+        alert_bytes,
+        [
+            0x15,
+            0x03,
+            0x03,
+            0x00,
+            0x02,
+            0x02,
+            u8::from(AlertDescription::DecodeError)
+        ]
+    );
+}
+
+#[cfg(feature = "aws-lc-rs")]
+#[test]
+fn test_ech_split_mode_with_hello_retry_request() {
+    let key = make_ech_server_key(1);
+    let client_config = Arc::new(make_ech_client_config(
+        &key,
+        CryptoProvider {
+            kx_groups: vec![provider::kx_group::X25519, provider::kx_group::SECP384R1],
+            ..provider::default_provider()
+        },
+    ));
+    let mut client = ClientConnection::new(client_config, server_name("localhost")).unwrap();
+
+    let EchSplitMode::Forward(mut forwarder) = ech_split_mode_accept(&mut client, &[key]) else {
+        panic!("ECH offer not decrypted");
+    };
+
+    let mut backend_config = make_server_config_with_kx_groups(
+        KeyType::Rsa2048,
+        vec![provider::kx_group::SECP384R1],
+        &provider::default_provider(),
+    );
+    backend_config.ech_backend = true;
+    let mut backend = ServerConnection::new(Arc::new(backend_config)).unwrap();
+    do_ech_split_mode_handshake(&mut client, &mut forwarder, &mut backend);
+
+    assert_eq!(client.ech_status(), EchStatus::Accepted);
+    assert_eq!(backend.ech_status(), ServerEchStatus::Forwarded);
+    assert_eq!(
+        backend.handshake_kind(),
+        Some(HandshakeKind::FullWithHelloRetryRequest)
+    );
+}
+
+#[cfg(feature = "aws-lc-rs")]
+#[test]
+fn test_ech_forwarded_inner_hello_requires_ech_backend() {
+    let key = make_ech_server_key(1);
+    let client_config = Arc::new(make_ech_client_config(&key, provider::default_provider()));
+
+    let forwarded_hello = |key: EchKeyPair| {
+        let mut client =
+            ClientConnection::new(client_config.clone(), server_name("localhost")).unwrap();
+        let EchSplitMode::Forward(mut forwarder) = ech_split_mode_accept(&mut client, &[key])
+        else {
+            panic!("ECH offer not decrypted");
+        };
+        let mut buf = Vec::new();
+        while forwarder.wants_write() {
+            forwarder.write_tls(&mut buf).unwrap();
+        }
+        buf
+    };
+
+    // Without `ech_backend`, the inner hello's ECH extension is ignored...
+    let mut server = ServerConnection::new(Arc::new(make_server_config(
+        KeyType::Rsa2048,
+        &provider::default_provider(),
+    )))
+    .unwrap();
+    server
+        .read_tls(&mut forwarded_hello(key.clone()).as_slice())
+        .unwrap();
+    server.process_new_packets().unwrap();
+    assert_eq!(server.ech_status(), ServerEchStatus::NotOffered);
+
+    // ...or rejected, if the server has ECH keys, even with `ech_backend`.
+    let mut server_config = make_server_config(KeyType::Rsa2048, &provider::default_provider());
+    server_config.ech_keys = vec![key.clone()];
+    server_config.ech_backend = true;
+    let mut server = ServerConnection::new(Arc::new(server_config)).unwrap();
+    server
+        .read_tls(&mut forwarded_hello(key).as_slice())
+        .unwrap();
+    assert_eq!(
+        server
+            .process_new_packets()
+            .unwrap_err(),
+        Error::PeerMisbehaved(PeerMisbehaved::InvalidEncryptedClientHello)
+    );
+}
+
+#[cfg(feature = "aws-lc-rs")]
+#[test]
+fn test_ech_split_mode_terminates_rejected_offer() {
+    let key = make_ech_server_key(1);
+    let client_config = Arc::new(make_ech_client_config(
+        &make_ech_server_key(2),
+        provider::default_provider(),
+    ));
+    let mut client = ClientConnection::new(client_config, server_name("localhost")).unwrap();
+
+    let EchSplitMode::Terminate(accepted) =
+        ech_split_mode_accept(&mut client, core::slice::from_ref(&key))
+    else {
+        panic!("unexpectedly decrypted ECH offer");
+    };
+
+    let mut server_config = make_server_config(KeyType::Rsa2048, &provider::default_provider());
+    server_config.ech_keys = vec![key];
+    let mut server = accepted
+        .into_connection(Arc::new(server_config))
+        .unwrap();
+    let err = do_handshake_until_error(&mut client, &mut server);
+
+    assert_eq!(server.ech_status(), ServerEchStatus::Rejected);
+    assert!(matches!(
+        err,
+        Err(ErrorFromPeer::Client(Error::PeerIncompatible(
+            PeerIncompatible::ServerRejectedEncryptedClientHello(Some(_))
+        )))
+    ));
+}
+
+#[cfg(feature = "aws-lc-rs")]
+#[test]
+fn test_server_rejects_ech_with_unknown_config() {
+    let server_key = make_ech_server_key(1);
+    let mut server_config = make_server_config(KeyType::Rsa2048, &provider::default_provider());
+    server_config.ech_keys = vec![server_key.clone()];
+    let client_config =
+        make_ech_client_config(&make_ech_server_key(2), provider::default_provider());
+
+    let (mut client, mut server) = make_pair_for_configs(client_config, server_config);
+    let err = do_handshake_until_error(&mut client, &mut server);
+
+    // The server continues with the outer hello, and sends its configuration for the
+    // client to retry with.
+    assert_eq!(server.ech_status(), ServerEchStatus::Rejected);
+    assert_eq!(
+        server.server_name(),
+        Some(&DnsName::try_from("testserver.com").unwrap())
+    );
+    let Err(ErrorFromPeer::Client(Error::PeerIncompatible(
+        PeerIncompatible::ServerRejectedEncryptedClientHello(Some(retry_configs)),
+    ))) = err
+    else {
+        panic!("unexpected result {err:?}");
+    };
+    let mut retry_config_list = Vec::new();
+    retry_configs.encode(&mut retry_config_list);
+    assert_eq!(retry_config_list, server_key.config_list().to_vec());
+}
+
+#[cfg(feature = "aws-lc-rs")]
+#[test]
+fn test_server_ignores_ech_without_keys() {
+    let key = make_ech_server_key(1);
+    let server_config = make_server_config(KeyType::Rsa2048, &provider::default_provider());
+    let client_config = make_ech_client_config(&key, provider::default_provider());
+
+    let (mut client, mut server) = make_pair_for_configs(client_config, server_config);
+    let err = do_handshake_until_error(&mut client, &mut server);
+
+    assert_eq!(server.ech_status(), ServerEchStatus::NotOffered);
+    assert!(matches!(
+        err,
+        Err(ErrorFromPeer::Client(Error::PeerIncompatible(
+            PeerIncompatible::ServerRejectedEncryptedClientHello(None)
+        )))
+    ));
+}
+
 #[test]
 fn test_complete_io_errors_if_close_notify_received_too_early() {
     let mut server = ServerConnection::new(Arc::new(make_server_config(
@@ -10,11 +10,12 @@ use common::{
     Arc, ErrorFromPeer, KeyType, MockClientVerifier, do_handshake_until_both_error,
     do_handshake_until_error, make_client_config_with_versions,
     make_client_config_with_versions_with_auth, make_pair_for_arc_configs, server_config_builder,
-    server_name,
+    server_name, transfer,
 };
 use rustls::server::danger::ClientCertVerified;
 use rustls::{
-    AlertDescription, ClientConnection, Error, InvalidMessage, ServerConfig, ServerConnection,
+    AlertDescription, CertificateError, ClientConnection, Error, InvalidMessage, IoState,
+    ServerConfig, ServerConnection,
 };
 
 // Client is authorized!
@@ -62,6 +63,78 @@ fn client_verifier_works() {
     }
 }
 
+#[test]
+fn client_verifier_works_asynchronously() {
+    let provider = provider::default_provider();
+    let kt = KeyType::Rsa2048;
+    let mut client_verifier = MockClientVerifier::new(ver_unreachable, kt, &provider);
+    client_verifier.asynchronous = true;
+    let server_config = Arc::new(server_config_with_verifier(kt, client_verifier));
+
+    for version in rustls::ALL_VERSIONS {
+        let client_config = make_client_config_with_versions_with_auth(kt, &[version], &provider);
+        let (mut client, mut server) =
+            make_pair_for_arc_configs(&Arc::new(client_config), &server_config);
+        let io_state = complete_until_server_verifies(&mut client, &mut server);
+        assert!(io_state.awaiting_verification());
+        assert!(!server.wants_read());
+
+        let pending = server.pending_verification().unwrap();
+        assert_eq!(pending.end_entity(), &kt.get_client_chain()[0]);
+        assert_eq!(pending.server_name(), None);
+
+        server
+            .complete_verification(Ok(()))
+            .unwrap();
+        assert_eq!(do_handshake_until_error(&mut client, &mut server), Ok(()));
+        assert_eq!(server.peer_certificates(), Some(&kt.get_client_chain()[..]));
+    }
+}
+
+#[test]
+fn client_verifier_fails_asynchronously() {
+    let provider = provider::default_provider();
+    let kt = KeyType::Rsa2048;
+    let mut client_verifier = MockClientVerifier::new(ver_unreachable, kt, &provider);
+    client_verifier.asynchronous = true;
+    let server_config = Arc::new(server_config_with_verifier(kt, client_verifier));
+
+    for version in rustls::ALL_VERSIONS {
+        let client_config = make_client_config_with_versions_with_auth(kt, &[version], &provider);
+        let (mut client, mut server) =
+            make_pair_for_arc_configs(&Arc::new(client_config), &server_config);
+        complete_until_server_verifies(&mut client, &mut server);
+
+        assert_eq!(
+            server.complete_verification(Err(CertificateError::Revoked.into())),
+            Err(Error::InvalidCertificate(CertificateError::Revoked))
+        );
+        transfer(&mut server, &mut client);
+        assert_eq!(
+            client
+                .process_new_packets()
+                .unwrap_err(),
+            Error::AlertReceived(AlertDescription::CertificateRevoked)
+        );
+    }
+}
+
+/// Run the handshake until the server pauses to verify the client's certificate.
+fn complete_until_server_verifies(
+    client: &mut ClientConnection,
+    server: &mut ServerConnection,
+) -> IoState {
+    loop {
+        transfer(client, server);
+        let io_state = server.process_new_packets().unwrap();
+        if io_state.awaiting_verification() {
+            return io_state;
+        }
+        transfer(server, client);
+        client.process_new_packets().unwrap();
+    }
+}
+
 // Server offers no verification schemes
 #[test]
 fn client_verifier_no_schemes() {
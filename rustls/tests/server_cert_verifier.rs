@@ -7,19 +7,27 @@ use super::*;
 mod common;
 
 use common::{
-    Arc, ErrorFromPeer, KeyType, MockServerVerifier, client_config_builder, do_handshake,
-    do_handshake_until_both_error, do_handshake_until_error, make_client_config_with_versions,
-    make_pair_for_arc_configs, make_server_config, server_config_builder,
+    Arc, ErrorFromPeer, KeyType, MockServerVerifier, client_config_builder,
+    client_config_builder_with_versions, do_handshake, do_handshake_until_both_error,
+    do_handshake_until_error, make_client_config_with_versions, make_pair_for_arc_configs,
+    make_pair_for_configs, make_server_config, server_config_builder, transfer,
+};
+use pki_types::{CertificateDer, PrivateKeyDer, PrivatePkcs8KeyDer, ServerName};
+use rcgen::{
+    BasicConstraints, CertificateParams, CustomExtension, ExtendedKeyUsagePurpose, IsCa, Issuer,
+    KeyPair, SerialNumber, SigningKey,
 };
-use pki_types::{CertificateDer, ServerName};
 use rustls::client::WebPkiServerVerifier;
 use rustls::client::danger::{HandshakeSignatureValid, ServerCertVerified, ServerCertVerifier};
+use rustls::crypto::CryptoProvider;
 use rustls::server::{ClientHello, ResolvesServerCert};
 use rustls::sign::CertifiedKey;
 use rustls::{
-    AlertDescription, CertificateError, DigitallySignedStruct, DistinguishedName, Error,
-    InvalidMessage, RootCertStore,
+    AlertDescription, CertificateError, ClientConfig, DigitallySignedStruct, DistinguishedName,
+    Error, InvalidMessage, RootCertStore, ServerConfig, SupportedProtocolVersion,
 };
+use time::{Duration, OffsetDateTime};
+use x509_parser::certificate::X509Certificate;
 use x509_parser::prelude::FromDer;
 use x509_parser::x509::X509Name;
 
@@ -171,6 +179,106 @@ fn client_can_override_certificate_verification_and_offer_no_signature_schemes()
     }
 }
 
+#[test]
+fn client_can_verify_server_certificate_asynchronously() {
+    let provider = provider::default_provider();
+    let kt = KeyType::Rsa2048;
+    let server_config = Arc::new(make_server_config(kt, &provider));
+
+    for version in rustls::ALL_VERSIONS {
+        let verifier = Arc::new(MockServerVerifier::accepts_anything_asynchronously());
+        let mut client_config = make_client_config_with_versions(kt, &[version], &provider);
+        client_config
+            .dangerous()
+            .set_certificate_verifier(verifier.clone());
+
+        let (mut client, mut server) =
+            make_pair_for_arc_configs(&Arc::new(client_config), &server_config);
+        transfer(&mut client, &mut server);
+        server.process_new_packets().unwrap();
+        transfer(&mut server, &mut client);
+        let io_state = client.process_new_packets().unwrap();
+        assert!(io_state.awaiting_verification());
+        assert!(!client.wants_read());
+        assert!(client.is_handshaking());
+
+        let pending = client.pending_verification().unwrap();
+        assert_eq!(pending.end_entity(), &kt.get_chain()[0]);
+        assert_eq!(pending.intermediates(), &kt.get_chain()[1..]);
+        assert_eq!(
+            pending.server_name(),
+            Some(&ServerName::try_from("localhost").unwrap())
+        );
+        verifier
+            .verify_server_cert(
+                pending.end_entity(),
+                pending.intermediates(),
+                pending.server_name().unwrap(),
+                pending.ocsp_response(),
+                pending.now(),
+            )
+            .unwrap();
+
+        let io_state = client
+            .complete_verification(Ok(()))
+            .unwrap();
+        assert!(!io_state.awaiting_verification());
+        assert!(client.pending_verification().is_none());
+        do_handshake(&mut client, &mut server);
+        assert_eq!(client.protocol_version(), Some(version.version()));
+    }
+}
+
+#[test]
+fn client_can_reject_server_certificate_asynchronously() {
+    let provider = provider::default_provider();
+    let kt = KeyType::Rsa2048;
+    let server_config = Arc::new(make_server_config(kt, &provider));
+
+    for version in rustls::ALL_VERSIONS {
+        let mut client_config = make_client_config_with_versions(kt, &[version], &provider);
+        client_config
+            .dangerous()
+            .set_certificate_verifier(Arc::new(
+                MockServerVerifier::accepts_anything_asynchronously(),
+            ));
+
+        let (mut client, mut server) =
+            make_pair_for_arc_configs(&Arc::new(client_config), &server_config);
+        transfer(&mut client, &mut server);
+        server.process_new_packets().unwrap();
+        transfer(&mut server, &mut client);
+        assert!(
+            client
+                .process_new_packets()
+                .unwrap()
+                .awaiting_verification()
+        );
+
+        let err = client
+            .complete_verification(Err(CertificateError::UnknownIssuer.into()))
+            .unwrap_err();
+        assert_eq!(
+            err,
+            Error::InvalidCertificate(CertificateError::UnknownIssuer)
+        );
+        assert_eq!(
+            client
+                .process_new_packets()
+                .unwrap_err(),
+            err
+        );
+
+        transfer(&mut client, &mut server);
+        assert_eq!(
+            server
+                .process_new_packets()
+                .unwrap_err(),
+            Error::AlertReceived(AlertDescription::UnknownCA)
+        );
+    }
+}
+
 #[test]
 fn client_can_request_certain_trusted_cas() {
     let provider = provider::default_provider();
@@ -257,6 +365,562 @@ fn client_can_request_certain_trusted_cas() {
     assert_eq!(cas_unaware_error_count, key_types.len() - 1);
 }
 
+#[test]
+fn client_checks_stapled_ocsp_response() {
+    let provider = provider::default_provider();
+    let ca = OcspTestCa::new();
+    let (end_entity, key) = ca.end_entity(false);
+    let response = ocsp_response(&ca, GOOD, in_an_hour(), ca.issuer.key(), &[]);
+
+    for version in rustls::ALL_VERSIONS {
+        let (mut client, mut server) = make_pair_for_configs(
+            ocsp_client_config(&ca, false, version, &provider),
+            ocsp_server_config(&end_entity, &key, Some(response.clone()), &provider),
+        );
+        do_handshake(&mut client, &mut server);
+    }
+}
+
+#[test]
+fn client_rejects_revoked_stapled_ocsp_status() {
+    let provider = provider::default_provider();
+    let ca = OcspTestCa::new();
+    let (end_entity, key) = ca.end_entity(false);
+    let revoked = der(0xa1, &generalized_time(OffsetDateTime::now_utc()));
+    let response = ocsp_response(&ca, &revoked, in_an_hour(), ca.issuer.key(), &[]);
+
+    for version in rustls::ALL_VERSIONS {
+        assert_ocsp_error(
+            ocsp_client_config(&ca, false, version, &provider),
+            ocsp_server_config(&end_entity, &key, Some(response.clone()), &provider),
+            CertificateError::Revoked,
+        );
+    }
+}
+
+#[test]
+fn client_rejects_unknown_stapled_ocsp_status() {
+    let provider = provider::default_provider();
+    let ca = OcspTestCa::new();
+    let (end_entity, key) = ca.end_entity(false);
+    let response = ocsp_response(&ca, UNKNOWN, in_an_hour(), ca.issuer.key(), &[]);
+
+    assert_ocsp_error(
+        ocsp_client_config(&ca, false, &rustls::version::TLS13, &provider),
+        ocsp_server_config(&end_entity, &key, Some(response), &provider),
+        CertificateError::UnknownRevocationStatus,
+    );
+}
+
+#[test]
+fn client_rejects_expired_stapled_ocsp_response() {
+    let provider = provider::default_provider();
+    let ca = OcspTestCa::new();
+    let (end_entity, key) = ca.end_entity(false);
+    let next_update = OffsetDateTime::now_utc() - Duration::seconds(30);
+    let response = ocsp_response(&ca, GOOD, next_update, ca.issuer.key(), &[]);
+
+    assert_ocsp_error(
+        ocsp_client_config(&ca, false, &rustls::version::TLS13, &provider),
+        ocsp_server_config(&end_entity, &key, Some(response), &provider),
+        CertificateError::InvalidOcspResponse,
+    );
+}
+
+#[test]
+fn client_limits_age_of_stapled_ocsp_response_without_next_update() {
+    let provider = provider::default_provider();
+    let ca = OcspTestCa::new();
+    let (end_entity, key) = ca.end_entity(false);
+    let now = OffsetDateTime::now_utc();
+
+    let recent = ocsp_response_with_times(
+        &ca,
+        GOOD,
+        now - Duration::days(6),
+        None,
+        ca.issuer.key(),
+        &[],
+    );
+    let (mut client, mut server) = make_pair_for_configs(
+        ocsp_client_config(&ca, false, &rustls::version::TLS13, &provider),
+        ocsp_server_config(&end_entity, &key, Some(recent), &provider),
+    );
+    do_handshake(&mut client, &mut server);
+
+    let stale = ocsp_response_with_times(
+        &ca,
+        GOOD,
+        now - Duration::days(8),
+        None,
+        ca.issuer.key(),
+        &[],
+    );
+    assert_ocsp_error(
+        ocsp_client_config(&ca, false, &rustls::version::TLS13, &provider),
+        ocsp_server_config(&end_entity, &key, Some(stale.clone()), &provider),
+        CertificateError::InvalidOcspResponse,
+    );
+
+    // The maximum age is configurable.
+    let mut roots = RootCertStore::empty();
+    roots.add(ca.root.clone()).unwrap();
+    let verifier =
+        WebPkiServerVerifier::builder_with_provider(Arc::new(roots), Arc::new(provider.clone()))
+            .check_stapled_ocsp_responses()
+            .with_ocsp_max_age(core::time::Duration::from_secs(30 * 24 * 60 * 60))
+            .build()
+            .unwrap();
+    let client_config = client_config_builder_with_versions(&[&rustls::version::TLS13], &provider)
+        .with_webpki_verifier(verifier)
+        .with_no_client_auth();
+    let (mut client, mut server) = make_pair_for_configs(
+        client_config,
+        ocsp_server_config(&end_entity, &key, Some(stale), &provider),
+    );
+    do_handshake(&mut client, &mut server);
+}
+
+#[test]
+fn client_allows_clock_skew_for_stapled_ocsp_response() {
+    let provider = provider::default_provider();
+    let ca = OcspTestCa::new();
+    let (end_entity, key) = ca.end_entity(false);
+    let now = OffsetDateTime::now_utc();
+
+    let skewed = ocsp_response_with_times(
+        &ca,
+        GOOD,
+        now + Duration::minutes(2),
+        Some(in_an_hour()),
+        ca.issuer.key(),
+        &[],
+    );
+    let (mut client, mut server) = make_pair_for_configs(
+        ocsp_client_config(&ca, false, &rustls::version::TLS13, &provider),
+        ocsp_server_config(&end_entity, &key, Some(skewed), &provider),
+    );
+    do_handshake(&mut client, &mut server);
+
+    let future = ocsp_response_with_times(
+        &ca,
+        GOOD,
+        now + Duration::minutes(10),
+        Some(in_an_hour()),
+        ca.issuer.key(),
+        &[],
+    );
+    assert_ocsp_error(
+        ocsp_client_config(&ca, false, &rustls::version::TLS13, &provider),
+        ocsp_server_config(&end_entity, &key, Some(future), &provider),
+        CertificateError::InvalidOcspResponse,
+    );
+}
+
+#[test]
+fn client_rejects_stapled_ocsp_response_for_another_issuer() {
+    let provider = provider::default_provider();
+    let ca = OcspTestCa::new();
+    let (end_entity, key) = ca.end_entity(false);
+    // A response for another issuer's certificate with the same serial number.
+    let other = OcspTestCa::new();
+    let response = ocsp_response(&other, GOOD, in_an_hour(), ca.issuer.key(), &[]);
+
+    assert_ocsp_error(
+        ocsp_client_config(&ca, false, &rustls::version::TLS13, &provider),
+        ocsp_server_config(&end_entity, &key, Some(response), &provider),
+        CertificateError::InvalidOcspResponse,
+    );
+}
+
+#[test]
+fn client_accepts_stapled_ocsp_response_with_sha1_cert_id() {
+    let provider = provider::default_provider();
+    let ca = OcspTestCa::new();
+    let (end_entity, key) = ca.end_entity(false);
+    let response = ocsp_response_for_cert_id(
+        &sha1_cert_id(SERIAL),
+        GOOD,
+        OffsetDateTime::now_utc() - Duration::minutes(1),
+        Some(in_an_hour()),
+        ca.issuer.key(),
+        &[],
+    );
+
+    let (mut client, mut server) = make_pair_for_configs(
+        ocsp_client_config(&ca, false, &rustls::version::TLS13, &provider),
+        ocsp_server_config(&end_entity, &key, Some(response), &provider),
+    );
+    do_handshake(&mut client, &mut server);
+}
+
+#[test]
+fn client_rejects_stapled_ocsp_response_with_sha1_cert_id_for_another_serial() {
+    let provider = provider::default_provider();
+    let ca = OcspTestCa::new();
+    let (end_entity, key) = ca.end_entity(false);
+    let response = ocsp_response_for_cert_id(
+        &sha1_cert_id(&[0x56, 0x78]),
+        GOOD,
+        OffsetDateTime::now_utc() - Duration::minutes(1),
+        Some(in_an_hour()),
+        ca.issuer.key(),
+        &[],
+    );
+
+    assert_ocsp_error(
+        ocsp_client_config(&ca, false, &rustls::version::TLS13, &provider),
+        ocsp_server_config(&end_entity, &key, Some(response), &provider),
+        CertificateError::InvalidOcspResponse,
+    );
+}
+
+#[test]
+fn client_rejects_stapled_ocsp_response_from_wrong_signer() {
+    let provider = provider::default_provider();
+    let ca = OcspTestCa::new();
+    let (end_entity, key) = ca.end_entity(false);
+    let other = KeyPair::generate().unwrap();
+    let response = ocsp_response(&ca, GOOD, in_an_hour(), &other, &[]);
+
+    assert_ocsp_error(
+        ocsp_client_config(&ca, false, &rustls::version::TLS13, &provider),
+        ocsp_server_config(&end_entity, &key, Some(response), &provider),
+        CertificateError::InvalidOcspResponse,
+    );
+}
+
+#[test]
+fn client_accepts_stapled_ocsp_response_from_delegated_responder() {
+    let provider = provider::default_provider();
+    let ca = OcspTestCa::new();
+    let (end_entity, key) = ca.end_entity(false);
+    let (responder, responder_key) = ca.responder(true);
+    let response = ocsp_response(&ca, GOOD, in_an_hour(), &responder_key, &[&responder]);
+
+    let (mut client, mut server) = make_pair_for_configs(
+        ocsp_client_config(&ca, false, &rustls::version::TLS13, &provider),
+        ocsp_server_config(&end_entity, &key, Some(response), &provider),
+    );
+    do_handshake(&mut client, &mut server);
+}
+
+#[test]
+fn client_rejects_stapled_ocsp_response_from_unauthorized_responder() {
+    let provider = provider::default_provider();
+    let ca = OcspTestCa::new();
+    let (end_entity, key) = ca.end_entity(false);
+    let (responder, responder_key) = ca.responder(false);
+    let response = ocsp_response(&ca, GOOD, in_an_hour(), &responder_key, &[&responder]);
+
+    assert_ocsp_error(
+        ocsp_client_config(&ca, false, &rustls::version::TLS13, &provider),
+        ocsp_server_config(&end_entity, &key, Some(response), &provider),
+        CertificateError::InvalidOcspResponse,
+    );
+}
+
+#[test]
+fn client_enforces_ocsp_must_staple() {
+    let provider = provider::default_provider();
+    let ca = OcspTestCa::new();
+    let (end_entity, key) = ca.end_entity(true);
+
+    for version in rustls::ALL_VERSIONS {
+        assert_ocsp_error(
+            ocsp_client_config(&ca, true, version, &provider),
+            ocsp_server_config(&end_entity, &key, None, &provider),
+            CertificateError::OcspResponseRequired,
+        );
+
+        // Must-Staple is not enforced unless asked for.
+        let (mut client, mut server) = make_pair_for_configs(
+            ocsp_client_config(&ca, false, version, &provider),
+            ocsp_server_config(&end_entity, &key, None, &provider),
+        );
+        do_handshake(&mut client, &mut server);
+
+        let response = ocsp_response(&ca, GOOD, in_an_hour(), ca.issuer.key(), &[]);
+        let (mut client, mut server) = make_pair_for_configs(
+            ocsp_client_config(&ca, true, version, &provider),
+            ocsp_server_config(&end_entity, &key, Some(response), &provider),
+        );
+        do_handshake(&mut client, &mut server);
+    }
+}
+
+fn assert_ocsp_error(
+    client_config: ClientConfig,
+    server_config: ServerConfig,
+    expected: CertificateError,
+) {
+    let (mut client, mut server) = make_pair_for_configs(client_config, server_config);
+    assert_eq!(
+        do_handshake_until_error(&mut client, &mut server),
+        Err(ErrorFromPeer::Client(Error::InvalidCertificate(expected)))
+    );
+}
+
+fn ocsp_client_config(
+    ca: &OcspTestCa,
+    enforce_must_staple: bool,
+    version: &'static SupportedProtocolVersion,
+    provider: &CryptoProvider,
+) -> ClientConfig {
+    let mut roots = RootCertStore::empty();
+    roots.add(ca.root.clone()).unwrap();
+    let mut builder =
+        WebPkiServerVerifier::builder_with_provider(Arc::new(roots), Arc::new(provider.clone()))
+            .check_stapled_ocsp_responses();
+    if enforce_must_staple {
+        builder = builder.enforce_ocsp_must_staple();
+    }
+    client_config_builder_with_versions(&[version], provider)
+        .with_webpki_verifier(builder.build().unwrap())
+        .with_no_client_auth()
+}
+
+fn ocsp_server_config(
+    end_entity: &CertificateDer<'static>,
+    key: &PrivateKeyDer<'static>,
+    ocsp: Option<Vec<u8>>,
+    provider: &CryptoProvider,
+) -> ServerConfig {
+    let builder = server_config_builder(provider).with_no_client_auth();
+    let chain = vec![end_entity.clone()];
+    match ocsp {
+        Some(ocsp) => builder.with_single_cert_with_ocsp(chain, key.clone_key(), ocsp),
+        None => builder.with_single_cert(chain, key.clone_key()),
+    }
+    .unwrap()
+}
+
+/// A root CA issuing server certificates, and OCSP responses for them.
+struct OcspTestCa {
+    root: CertificateDer<'static>,
+    issuer: Issuer<'static, KeyPair>,
+}
+
+impl OcspTestCa {
+    fn new() -> Self {
+        let mut params = CertificateParams::new(Vec::new()).unwrap();
+        params.is_ca = IsCa::Ca(BasicConstraints::Unconstrained);
+        let key = KeyPair::generate().unwrap();
+        Self {
+            root: params
+                .self_signed(&key)
+                .unwrap()
+                .der()
+                .clone(),
+            issuer: Issuer::new(params, key),
+        }
+    }
+
+    /// A certificate for "localhost" with serial number `SERIAL`, optionally
+    /// with the OCSP Must-Staple TLS feature.
+    fn end_entity(&self, must_staple: bool) -> (CertificateDer<'static>, PrivateKeyDer<'static>) {
+        let mut params = CertificateParams::new(vec!["localhost".into()]).unwrap();
+        params.serial_number = Some(SerialNumber::from(SERIAL.to_vec()));
+        if must_staple {
+            // id-pe-tlsfeature, containing status_request
+            params
+                .custom_extensions
+                .push(CustomExtension::from_oid_content(
+                    &[1, 3, 6, 1, 5, 5, 7, 1, 24],
+                    der(0x30, &der(0x02, &[5])),
+                ));
+        }
+        let key = KeyPair::generate().unwrap();
+        let cert = params
+            .signed_by(&key, &self.issuer)
+            .unwrap();
+        (
+            cert.der().clone(),
+            PrivatePkcs8KeyDer::from(key.serialize_der()).into(),
+        )
+    }
+
+    /// An OCSP responder certificate, optionally authorized for OCSP signing.
+    fn responder(&self, ocsp_signing: bool) -> (CertificateDer<'static>, KeyPair) {
+        let mut params = CertificateParams::new(Vec::new()).unwrap();
+        if ocsp_signing {
+            params.extended_key_usages = vec![ExtendedKeyUsagePurpose::OcspSigning];
+        }
+        let key = KeyPair::generate().unwrap();
+        let cert = params
+            .signed_by(&key, &self.issuer)
+            .unwrap();
+        (cert.der().clone(), key)
+    }
+}
+
+/// A successful OCSP response giving `cert_status` for the certificate with serial
+/// number `SERIAL` issued by `ca`, signed by `signer` and including `certs`.
+fn ocsp_response(
+    ca: &OcspTestCa,
+    cert_status: &[u8],
+    next_update: OffsetDateTime,
+    signer: &KeyPair,
+    certs: &[&CertificateDer<'_>],
+) -> Vec<u8> {
+    ocsp_response_with_times(
+        ca,
+        cert_status,
+        OffsetDateTime::now_utc() - Duration::minutes(1),
+        Some(next_update),
+        signer,
+        certs,
+    )
+}
+
+fn ocsp_response_with_times(
+    ca: &OcspTestCa,
+    cert_status: &[u8],
+    this_update: OffsetDateTime,
+    next_update: Option<OffsetDateTime>,
+    signer: &KeyPair,
+    certs: &[&CertificateDer<'_>],
+) -> Vec<u8> {
+    let sha256 = &provider::cipher_suite::TLS13_AES_128_GCM_SHA256
+        .tls13()
+        .unwrap()
+        .common
+        .hash_provider;
+    let (_, root) = X509Certificate::from_der(&ca.root).unwrap();
+    let cert_id = [
+        der(0x30, &[der(0x06, SHA256_OID), der(0x05, &[])].concat()),
+        der(
+            0x04,
+            sha256
+                .hash(root.tbs_certificate.subject.as_raw())
+                .as_ref(),
+        ),
+        der(
+            0x04,
+            sha256
+                .hash(ca.issuer.key().public_key_raw())
+                .as_ref(),
+        ),
+        der(0x02, SERIAL),
+    ]
+    .concat();
+    ocsp_response_for_cert_id(
+        &cert_id,
+        cert_status,
+        this_update,
+        next_update,
+        signer,
+        certs,
+    )
+}
+
+/// A SHA-1 `CertID` for the certificate with serial number `serial`.
+///
+/// The issuer name and key hashes are placeholders: SHA-1 is not available to compute
+/// them, nor to check them.
+fn sha1_cert_id(serial: &[u8]) -> Vec<u8> {
+    [
+        der(0x30, &[der(0x06, SHA1_OID), der(0x05, &[])].concat()),
+        der(0x04, &[0xaa; 20]),
+        der(0x04, &[0xbb; 20]),
+        der(0x02, serial),
+    ]
+    .concat()
+}
+
+fn ocsp_response_for_cert_id(
+    cert_id: &[u8],
+    cert_status: &[u8],
+    this_update: OffsetDateTime,
+    next_update: Option<OffsetDateTime>,
+    signer: &KeyPair,
+    certs: &[&CertificateDer<'_>],
+) -> Vec<u8> {
+    let now = OffsetDateTime::now_utc();
+    let mut single_response = [
+        der(0x30, cert_id),
+        cert_status.to_vec(),
+        generalized_time(this_update),
+    ]
+    .concat();
+    if let Some(next_update) = next_update {
+        single_response.extend(der(0xa0, &generalized_time(next_update)));
+    }
+    let tbs_response_data = der(
+        0x30,
+        &[
+            der(0xa2, &der(0x04, &[0; 20])),
+            generalized_time(now),
+            der(0x30, &der(0x30, &single_response)),
+        ]
+        .concat(),
+    );
+
+    let signature = signer.sign(&tbs_response_data).unwrap();
+    let mut basic = [
+        tbs_response_data,
+        der(0x30, &der(0x06, ECDSA_WITH_SHA256_OID)),
+        der(0x03, &[&[0][..], &signature].concat()),
+    ]
+    .concat();
+    if !certs.is_empty() {
+        let certs = certs
+            .iter()
+            .flat_map(|cert| cert.iter().copied())
+            .collect::<Vec<_>>();
+        basic.extend(der(0xa0, &der(0x30, &certs)));
+    }
+
+    let response_bytes = [der(0x06, OCSP_BASIC_OID), der(0x04, &der(0x30, &basic))].concat();
+    der(
+        0x30,
+        &[der(0x0a, &[0]), der(0xa0, &der(0x30, &response_bytes))].concat(),
+    )
+}
+
+fn in_an_hour() -> OffsetDateTime {
+    OffsetDateTime::now_utc() + Duration::hours(1)
+}
+
+fn generalized_time(time: OffsetDateTime) -> Vec<u8> {
+    let time = format!(
+        "{:04}{:02}{:02}{:02}{:02}{:02}Z",
+        time.year(),
+        u8::from(time.month()),
+        time.day(),
+        time.hour(),
+        time.minute(),
+        time.second()
+    );
+    der(0x18, time.as_bytes())
+}
+
+fn der(tag: u8, value: &[u8]) -> Vec<u8> {
+    let mut encoded = vec![tag];
+    match value.len() {
+        len @ 0..=0x7f => encoded.push(len as u8),
+        len @ 0x80..=0xff => encoded.extend([0x81, len as u8]),
+        len => {
+            encoded.push(0x82);
+            encoded.extend(
+                u16::try_from(len)
+                    .unwrap()
+                    .to_be_bytes(),
+            );
+        }
+    }
+    encoded.extend_from_slice(value);
+    encoded
+}
+
+const SERIAL: &[u8] = &[0x12, 0x34];
+const GOOD: &[u8] = &[0x80, 0x00];
+const UNKNOWN: &[u8] = &[0x82, 0x00];
+const SHA1_OID: &[u8] = &[0x2b, 0x0e, 0x03, 0x02, 0x1a];
+const SHA256_OID: &[u8] = &[0x60, 0x86, 0x48, 0x01, 0x65, 0x03, 0x04, 0x02, 0x01];
+const ECDSA_WITH_SHA256_OID: &[u8] = &[0x2a, 0x86, 0x48, 0xce, 0x3d, 0x04, 0x03, 0x02];
+const OCSP_BASIC_OID: &[u8] = &[0x2b, 0x06, 0x01, 0x05, 0x05, 0x07, 0x30, 0x01, 0x01];
+
 #[derive(Debug, Clone)]
 pub struct ResolvesCertChainByCaName(Vec<(DistinguishedName, Arc<CertifiedKey>)>);
 
@@ -2,9 +2,14 @@
 
 use std::num::NonZeroUsize;
 
+use pki_types::{DnsName, SubjectPublicKeyInfoDer};
 use rustls::client::{ClientConnectionData, EarlyDataError, UnbufferedClientConnection};
 use rustls::crypto::CryptoProvider;
-use rustls::server::{ServerConnectionData, UnbufferedServerConnection};
+use rustls::server::danger::ClientCertVerified;
+use rustls::server::{
+    ServerConnectionData, UnbufferedAcceptStatus, UnbufferedAcceptor, UnbufferedServerConnection,
+};
+use rustls::sign::{CertifiedKey, Signer, SigningKey, SingleCertAndKey};
 use rustls::unbuffered::{
     ConnectionState, EncodeError, EncryptError, InsufficientSizeError, ReadTraffic,
     UnbufferedConnectionCommon, UnbufferedStatus, WriteTraffic,
@@ -12,7 +17,7 @@ use rustls::unbuffered::{
 use rustls::version::TLS13;
 use rustls::{
     AlertDescription, CertificateError, ClientConfig, ConnectionTrafficSecrets, Error,
-    InvalidMessage, ServerConfig, SideData,
+    InvalidMessage, PeerIncompatible, ServerConfig, SideData, SignatureAlgorithm, SignatureScheme,
 };
 
 use super::*;
@@ -292,16 +297,30 @@ fn run(
     server_config: Arc<ServerConfig>,
     server_actions: &mut Actions,
 ) -> Outcome {
-    let mut outcome = Outcome::default();
+    let client = UnbufferedClientConnection::new(client_config, server_name("localhost")).unwrap();
+    let server = UnbufferedServerConnection::new(server_config).unwrap();
+    run_connections(
+        client,
+        client_actions,
+        server,
+        server_actions,
+        BothBuffers::default(),
+        Outcome::default(),
+    )
+}
+
+fn run_connections(
+    mut client: UnbufferedClientConnection,
+    client_actions: &mut Actions,
+    mut server: UnbufferedServerConnection,
+    server_actions: &mut Actions,
+    mut buffers: BothBuffers,
+    mut outcome: Outcome,
+) -> Outcome {
     let mut count = 0;
     let mut client_handshake_done = false;
     let mut server_handshake_done = false;
 
-    let mut client =
-        UnbufferedClientConnection::new(client_config.clone(), server_name("localhost")).unwrap();
-    let mut server = UnbufferedServerConnection::new(server_config.clone()).unwrap();
-    let mut buffers = BothBuffers::default();
-
     while !(client_handshake_done
         && server_handshake_done
         && client_actions.finished()
@@ -357,7 +376,7 @@ fn run(
             State::PeerClosed => {
                 outcome.client_saw_peer_closed_state = true;
             }
-            State::Closed => {}
+            State::Closed | State::Signed | State::Verified | State::ClientCertChosen => {}
             state => unreachable!("{state:?}"),
         }
 
@@ -413,7 +432,7 @@ fn run(
             State::PeerClosed => {
                 outcome.server_saw_peer_closed_state = true;
             }
-            State::Closed => {}
+            State::Closed | State::Signed | State::Verified | State::ClientCertChosen => {}
         }
 
         count += 1;
@@ -1018,6 +1037,431 @@ fn read_traffic_not_consumed_too_early() {
     );
 }
 
+#[test]
+fn server_signs_asynchronously() {
+    let provider = provider::default_provider();
+    for version in rustls::ALL_VERSIONS {
+        eprintln!("{version:?}");
+        let key = provider
+            .key_provider
+            .load_private_key(KeyType::Rsa2048.get_key())
+            .unwrap();
+        let mut server_config =
+            make_server_config_with_versions(KeyType::Rsa2048, &[version], &provider);
+        server_config.cert_resolver = Arc::new(SingleCertAndKey::from(
+            CertifiedKey::new(
+                KeyType::Rsa2048.get_chain(),
+                Arc::new(AsyncSigningKey(key.clone())),
+            )
+            .unwrap(),
+        ));
+        let client_config = make_client_config(KeyType::Rsa2048, &provider);
+
+        let mut server_actions = Actions {
+            signing_key: Some(&*key),
+            ..NO_ACTIONS
+        };
+
+        let outcome = run(
+            Arc::new(client_config),
+            &mut NO_ACTIONS.clone(),
+            Arc::new(server_config),
+            &mut server_actions,
+        );
+
+        assert!(
+            outcome
+                .server_transcript
+                .iter()
+                .any(|state| state == "SignatureRequired")
+        );
+        assert_eq!(
+            outcome
+                .server
+                .unwrap()
+                .protocol_version(),
+            Some(version.version())
+        );
+    }
+}
+
+#[test]
+fn client_signs_asynchronously() {
+    let provider = provider::default_provider();
+    for version in rustls::ALL_VERSIONS {
+        eprintln!("{version:?}");
+        let key = provider
+            .key_provider
+            .load_private_key(KeyType::Rsa2048.get_client_key())
+            .unwrap();
+        let server_config =
+            make_server_config_with_mandatory_client_auth(KeyType::Rsa2048, &provider);
+        let mut client_config =
+            make_client_config_with_versions(KeyType::Rsa2048, &[version], &provider);
+        client_config.client_auth_cert_resolver = Arc::new(SingleCertAndKey::from(
+            CertifiedKey::new(
+                KeyType::Rsa2048.get_client_chain(),
+                Arc::new(AsyncSigningKey(key.clone())),
+            )
+            .unwrap(),
+        ));
+
+        let mut client_actions = Actions {
+            signing_key: Some(&*key),
+            ..NO_ACTIONS
+        };
+
+        let outcome = run(
+            Arc::new(client_config),
+            &mut client_actions,
+            Arc::new(server_config),
+            &mut NO_ACTIONS.clone(),
+        );
+
+        assert!(
+            outcome
+                .client_transcript
+                .iter()
+                .any(|state| state == "SignatureRequired")
+        );
+        assert!(
+            !outcome
+                .server_transcript
+                .iter()
+                .any(|state| state == "SignatureRequired")
+        );
+        assert!(
+            outcome
+                .server
+                .unwrap()
+                .peer_certificates()
+                .is_some()
+        );
+    }
+}
+
+#[test]
+fn synchronous_signers_are_not_deferred() {
+    let outcome = handshake_config(&rustls::version::TLS13, |client, _| {
+        *client = make_client_config_with_auth(KeyType::Rsa2048, &provider::default_provider());
+    });
+
+    assert!(
+        !outcome
+            .client_transcript
+            .iter()
+            .chain(&outcome.server_transcript)
+            .any(|state| state == "SignatureRequired")
+    );
+}
+
+#[test]
+fn client_verifies_asynchronously() {
+    let provider = provider::default_provider();
+    for version in rustls::ALL_VERSIONS {
+        eprintln!("{version:?}");
+        let server_config =
+            make_server_config_with_versions(KeyType::Rsa2048, &[version], &provider);
+        let mut client_config = make_client_config(KeyType::Rsa2048, &provider);
+        client_config
+            .dangerous()
+            .set_certificate_verifier(Arc::new(
+                MockServerVerifier::accepts_anything_asynchronously(),
+            ));
+
+        let outcome = run(
+            Arc::new(client_config),
+            &mut NO_ACTIONS.clone(),
+            Arc::new(server_config),
+            &mut NO_ACTIONS.clone(),
+        );
+
+        assert!(
+            outcome
+                .client_transcript
+                .iter()
+                .any(|state| state == "VerificationRequired")
+        );
+        assert!(
+            outcome
+                .client
+                .unwrap()
+                .peer_certificates()
+                .is_some()
+        );
+    }
+}
+
+#[test]
+fn server_verifies_asynchronously() {
+    let provider = provider::default_provider();
+    for version in rustls::ALL_VERSIONS {
+        eprintln!("{version:?}");
+        let mut client_verifier = MockClientVerifier::new(
+            || Ok(ClientCertVerified::assertion()),
+            KeyType::Rsa2048,
+            &provider,
+        );
+        client_verifier.asynchronous = true;
+        let server_config = server_config_builder(&provider)
+            .with_client_cert_verifier(Arc::new(client_verifier))
+            .with_single_cert(KeyType::Rsa2048.get_chain(), KeyType::Rsa2048.get_key())
+            .unwrap();
+        let client_config =
+            make_client_config_with_versions_with_auth(KeyType::Rsa2048, &[version], &provider);
+
+        let outcome = run(
+            Arc::new(client_config),
+            &mut NO_ACTIONS.clone(),
+            Arc::new(server_config),
+            &mut NO_ACTIONS.clone(),
+        );
+
+        assert!(
+            outcome
+                .server_transcript
+                .iter()
+                .any(|state| state == "VerificationRequired")
+        );
+        assert!(
+            outcome
+                .server
+                .unwrap()
+                .peer_certificates()
+                .is_some()
+        );
+    }
+}
+
+#[test]
+fn client_chooses_certificate_asynchronously() {
+    let provider = provider::default_provider();
+    let kt = KeyType::Rsa2048;
+    let certified_key = Arc::new(
+        CertifiedKey::from_der(kt.get_client_chain(), kt.get_client_key(), &provider).unwrap(),
+    );
+    for version in rustls::ALL_VERSIONS {
+        eprintln!("{version:?}");
+        let server_config = make_server_config_with_mandatory_client_auth(kt, &provider);
+        let mut client_config = make_client_config_with_versions(kt, &[version], &provider);
+        client_config.client_auth_cert_resolver = Arc::new(AsyncClientCertResolver);
+
+        let mut client_actions = Actions {
+            client_cert: Some(&certified_key),
+            ..NO_ACTIONS
+        };
+
+        let outcome = run(
+            Arc::new(client_config),
+            &mut client_actions,
+            Arc::new(server_config),
+            &mut NO_ACTIONS.clone(),
+        );
+
+        assert!(
+            outcome
+                .client_transcript
+                .iter()
+                .any(|state| state == "ClientCertRequired")
+        );
+        assert_eq!(
+            outcome
+                .server
+                .unwrap()
+                .peer_certificates(),
+            Some(&kt.get_client_chain()[..])
+        );
+    }
+}
+
+#[test]
+fn server_accepts_with_unbuffered_acceptor() {
+    let provider = provider::default_provider();
+    for version in rustls::ALL_VERSIONS {
+        eprintln!("{version:?}");
+        let server_config =
+            make_server_config_with_versions(KeyType::Rsa2048, &[version], &provider);
+        let client_config = make_client_config(KeyType::Rsa2048, &provider);
+
+        let client =
+            UnbufferedClientConnection::new(Arc::new(client_config), server_name("localhost"))
+                .unwrap();
+        let (client, mut buffers, outcome) = send_client_hello(client);
+
+        // an incomplete record
+        let mut acceptor = UnbufferedAcceptor::default();
+        let UnbufferedAcceptStatus {
+            discard, accepted, ..
+        } = acceptor.accept(&mut buffers.server.incoming.filled()[..3]);
+        assert_eq!(discard, 0);
+        assert!(accepted.unwrap().is_none());
+
+        let UnbufferedAcceptStatus {
+            discard, accepted, ..
+        } = acceptor.accept(buffers.server.incoming.filled());
+        buffers.server.incoming.discard(discard);
+        let accepted = accepted.unwrap().unwrap();
+        assert_eq!(
+            accepted.client_hello().server_name(),
+            Some(&DnsName::try_from("localhost").unwrap())
+        );
+
+        // reusing an acceptor is not allowed
+        let UnbufferedAcceptStatus { accepted: err, .. } =
+            acceptor.accept(buffers.server.incoming.filled());
+        assert_eq!(
+            err.unwrap_err().0,
+            Error::General("Acceptor polled after completion".into())
+        );
+
+        let server = accepted
+            .into_connection(Arc::new(server_config))
+            .unwrap();
+        let outcome = run_connections(
+            client,
+            &mut NO_ACTIONS.clone(),
+            server,
+            &mut NO_ACTIONS.clone(),
+            buffers,
+            outcome,
+        );
+        assert_eq!(
+            outcome
+                .server
+                .unwrap()
+                .protocol_version(),
+            Some(version.version())
+        );
+    }
+}
+
+#[test]
+fn unbuffered_acceptor_reports_alerts() {
+    let provider = provider::default_provider();
+
+    // an invalid record (length = 32k bytes)
+    let mut acceptor = UnbufferedAcceptor::default();
+    let UnbufferedAcceptStatus { accepted, .. } =
+        acceptor.accept(&mut [0x16, 0x03, 0x01, 0x80, 0x00]);
+    let (err, mut alert) = accepted.unwrap_err();
+    assert_eq!(err, Error::InvalidMessage(InvalidMessage::MessageTooLarge));
+    let mut outgoing_tls = [0u8; 64];
+    assert!(matches!(
+        alert.encode(&mut outgoing_tls[..1]),
+        Err(EncodeError::InsufficientSize(InsufficientSizeError {
+            required_size: 7,
+            ..
+        }))
+    ));
+    let written = alert.encode(&mut outgoing_tls).unwrap();
+    assert_eq!(
+        &outgoing_tls[..written],
+        &[
+            0x15,
+            0x03,
+            0x03,
+            0x00,
+            0x02,
+            0x02,
+            u8::from(AlertDescription::DecodeError)
+        ]
+    );
+    assert_eq!(alert.encode(&mut outgoing_tls).unwrap(), 0);
+
+    // a ClientHello unacceptable to the chosen config
+    let server_config =
+        make_server_config_with_versions(KeyType::Rsa2048, &[&rustls::version::TLS12], &provider);
+    let client_config = make_client_config_with_versions(KeyType::Rsa2048, &[&TLS13], &provider);
+    let client =
+        UnbufferedClientConnection::new(Arc::new(client_config), server_name("localhost")).unwrap();
+    let (_, mut buffers, _) = send_client_hello(client);
+
+    let mut acceptor = UnbufferedAcceptor::default();
+    let UnbufferedAcceptStatus { accepted, .. } = acceptor.accept(buffers.server.incoming.filled());
+    let Err((err, mut alert)) = accepted
+        .unwrap()
+        .unwrap()
+        .into_connection(Arc::new(server_config))
+    else {
+        panic!("unexpected success");
+    };
+    assert_eq!(
+        err,
+        Error::PeerIncompatible(PeerIncompatible::Tls12NotOfferedOrEnabled)
+    );
+    let written = alert.encode(&mut outgoing_tls).unwrap();
+    assert_eq!(
+        &outgoing_tls[..written],
+        &[
+            0x15,
+            0x03,
+            0x03,
+            0x00,
+            0x02,
+            0x02,
+            u8::from(AlertDescription::ProtocolVersion)
+        ]
+    );
+}
+
+/// Run `client` until its first flight is in the server's incoming buffer.
+fn send_client_hello(
+    mut client: UnbufferedClientConnection,
+) -> (UnbufferedClientConnection, BothBuffers, Outcome) {
+    let mut buffers = BothBuffers::default();
+    let mut outcome = Outcome::default();
+    loop {
+        let state = advance_client(
+            &mut client,
+            &mut buffers.client,
+            NO_ACTIONS,
+            &mut outcome.client_transcript,
+        );
+        if let State::TransmitTlsData { .. } = state {
+            buffers.client_send();
+            return (client, buffers, outcome);
+        }
+    }
+}
+
+/// A `SigningKey` whose signatures are made by the application, via
+/// `ConnectionState::SignatureRequired`.
+#[derive(Debug)]
+struct AsyncSigningKey(Arc<dyn SigningKey>);
+
+impl SigningKey for AsyncSigningKey {
+    fn choose_scheme(&self, offered: &[SignatureScheme]) -> Option<Box<dyn Signer>> {
+        self.0
+            .choose_scheme(offered)
+            .map(|signer| Box::new(AsyncSigner(signer)) as Box<dyn Signer>)
+    }
+
+    fn public_key(&self) -> Option<SubjectPublicKeyInfoDer<'_>> {
+        self.0.public_key()
+    }
+
+    fn algorithm(&self) -> SignatureAlgorithm {
+        self.0.algorithm()
+    }
+}
+
+#[derive(Debug)]
+struct AsyncSigner(Box<dyn Signer>);
+
+impl Signer for AsyncSigner {
+    fn sign(&self, _message: &[u8]) -> Result<Vec<u8>, Error> {
+        panic!("unbuffered connections must not call sign() for asynchronous signers");
+    }
+
+    fn scheme(&self) -> SignatureScheme {
+        self.0.scheme()
+    }
+
+    fn is_async(&self) -> bool {
+        true
+    }
+}
+
 fn write_traffic<T: SideData, R, F: FnMut(WriteTraffic<T>) -> R>(
     status: UnbufferedStatus<'_, '_, T>,
     mut f: F,
@@ -1116,12 +1560,17 @@ enum State {
         sent_app_data: bool,
         sent_close_notify: bool,
     },
+    Signed,
+    Verified,
+    ClientCertChosen,
 }
 
 const NO_ACTIONS: Actions = Actions {
     app_data_to_send: None,
     early_data_to_send: None,
     send_close_notify: false,
+    signing_key: None,
+    client_cert: None,
 };
 
 #[derive(Clone, Copy, Debug)]
@@ -1129,6 +1578,8 @@ struct Actions<'a> {
     app_data_to_send: Option<&'a [u8]>,
     early_data_to_send: Option<&'a [u8]>,
     send_close_notify: bool,
+    signing_key: Option<&'a dyn SigningKey>,
+    client_cert: Option<&'a Arc<CertifiedKey>>,
 }
 
 impl Actions<'_> {
@@ -1325,6 +1776,37 @@ fn handle_state<Data>(
             State::ReceivedAppData { records }
         }
 
+        ConnectionState::SignatureRequired(state) => {
+            let signer = actions
+                .signing_key
+                .expect("no signing key for SignatureRequired")
+                .choose_scheme(&[state.scheme()])
+                .unwrap();
+            let signature = signer.sign(state.message()).unwrap();
+            state.done(signature).unwrap();
+            State::Signed
+        }
+
+        ConnectionState::VerificationRequired(state) => {
+            // the verifiers used with this accept anything
+            assert!(
+                !state
+                    .verification()
+                    .end_entity()
+                    .is_empty()
+            );
+            state.done(Ok(())).unwrap();
+            State::Verified
+        }
+
+        ConnectionState::ClientCertRequired(state) => {
+            assert!(!state.request().sigschemes().is_empty());
+            state
+                .done(actions.client_cert.cloned())
+                .unwrap();
+            State::ClientCertChosen
+        }
+
         ConnectionState::PeerClosed => State::PeerClosed,
         ConnectionState::Closed => State::Closed,
 